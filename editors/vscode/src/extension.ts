@@ -1,57 +1,90 @@
-/* --------------------------------------------------------------------------------------------
- * Copyright (c) Microsoft Corporation. All rights reserved.
- * Licensed under the MIT License. See License.txt in the project root for license information.
- * ------------------------------------------------------------------------------------------ */
-
-import * as path from 'path';
-import { workspace, ExtensionContext } from 'vscode';
-
-import {
-	LanguageClient,
-	LanguageClientOptions,
-	ServerOptions,
-	TransportKind
-} from 'vscode-languageclient/node';
-
-let client: LanguageClient;
-
-export function activate(context: ExtensionContext) {
-	const run = {
-        command: "test-lsp",
-        args: [],
-        options: { env: Object.assign({}, process.env, { RUST_BACKTRACE: "1" }) },
-    };
-    console.log("use arguments", run);
-    const serverOptions: ServerOptions = {
-        run,
-        debug: run,
-    };
-
-	// Options to control the language client
-	const clientOptions: LanguageClientOptions = {
-		// Register the server for plain text documents
-		documentSelector: [{ scheme: 'file', language: 'plaintext' }],
-		synchronize: {
-			// Notify the server about file changes to '.clientrc files contained in the workspace
-			fileEvents: workspace.createFileSystemWatcher('**/.txt')
-		}
-	};
-
-	// Create the language client and start the client.
-	client = new LanguageClient(
-		'languageServerExample',
-		'Language Server Example',
-		serverOptions,
-		clientOptions
-	);
-
-	// Start the client. This will also launch the server
-	client.start();
-}
-
-export function deactivate(): Thenable<void> | undefined {
-	if (!client) {
-		return undefined;
-	}
-	return client.stop();
-}
+/* --------------------------------------------------------------------------------------------
+ * Copyright (c) Microsoft Corporation. All rights reserved.
+ * Licensed under the MIT License. See License.txt in the project root for license information.
+ * ------------------------------------------------------------------------------------------ */
+
+import * as path from 'path';
+import { workspace, ExtensionContext, TextDocumentContentProvider, Uri } from 'vscode';
+
+import {
+	ClientCapabilities,
+	LanguageClient,
+	LanguageClientOptions,
+	ServerOptions,
+	StaticFeature,
+	TransportKind
+} from 'vscode-languageclient/node';
+
+let client: LanguageClient;
+
+// Read-only documents the server makes on demand, like `testlsp:stats`
+const virtualDocuments: TextDocumentContentProvider = {
+	async provideTextDocumentContent(uri: Uri): Promise<string> {
+		const document = await client.sendRequest<{ text: string }>(
+			'testLsp/readVirtualDocument',
+			{ uri: uri.toString() }
+		);
+		return document.text;
+	}
+};
+
+// Tells the server `testlsp:` documents can be shown
+const virtualDocumentsFeature: StaticFeature = {
+	fillClientCapabilities(capabilities: ClientCapabilities) {
+		capabilities.experimental = {
+			...capabilities.experimental,
+			testLsp: { virtualDocuments: true }
+		};
+	},
+	initialize() {},
+	getState() {
+		return { kind: 'static' };
+	},
+	dispose() {}
+};
+
+export function activate(context: ExtensionContext) {
+	const run = {
+        command: "test-lsp",
+        args: [],
+        options: { env: Object.assign({}, process.env, { RUST_BACKTRACE: "1" }) },
+    };
+    console.log("use arguments", run);
+    const serverOptions: ServerOptions = {
+        run,
+        debug: run,
+    };
+
+	// Options to control the language client
+	const clientOptions: LanguageClientOptions = {
+		// Register the server for plain text documents
+		documentSelector: [{ scheme: 'file', language: 'plaintext' }],
+		synchronize: {
+			// Notify the server about file changes to '.clientrc files contained in the workspace
+			fileEvents: workspace.createFileSystemWatcher('**/.txt')
+		}
+	};
+
+	// Create the language client and start the client.
+	client = new LanguageClient(
+		'languageServerExample',
+		'Language Server Example',
+		serverOptions,
+		clientOptions
+	);
+
+	client.registerFeature(virtualDocumentsFeature);
+	context.subscriptions.push(
+		workspace.registerTextDocumentContentProvider('testlsp', virtualDocuments)
+	);
+
+	// Start the client. This will also launch the server
+	client.start();
+}
+
+export function deactivate(): Thenable<void> | undefined {
+	if (!client) {
+		return undefined;
+	}
+	return client.stop();
+}
@@ -0,0 +1,172 @@
+//! Just enough of BibTeX to complete citation keys and tell what they cite.
+
+use itertools::Itertools;
+use lsp_types::Url;
+use std::collections::HashMap;
+
+/// The languages citing with `@key`.
+pub const CITING_LANGUAGES: &[&str] = &["markdown", "latex"];
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+}
+
+impl Entry {
+    /// The title and authors, as hovers show them.
+    pub fn describe(&self) -> String {
+        let title = self.title.as_deref().unwrap_or("Untitled");
+        match &self.author {
+            Some(author) => format!("**{title}**\n\n{author}"),
+            None => format!("**{title}**"),
+        }
+    }
+}
+
+/// The entries of the workspace `.bib` files, by file.
+#[derive(Debug, Default)]
+pub struct Bibliography {
+    files: HashMap<Url, Vec<Entry>>,
+}
+
+impl Bibliography {
+    /// Reparses `uri` if it's a `.bib` file.
+    pub fn update(&mut self, uri: &Url, text: &str) {
+        if is_bib(uri) {
+            self.files.insert(uri.clone(), parse(text));
+        }
+    }
+
+    pub fn set(&mut self, uri: Url, entries: Vec<Entry>) {
+        self.files.insert(uri, entries);
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.files
+            .values()
+            .flatten()
+            .map(|entry| entry.key.as_str())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.files.values().flatten().find(|entry| entry.key == key)
+    }
+}
+
+pub fn is_bib(uri: &Url) -> bool {
+    uri.path().ends_with(".bib")
+}
+
+/// The entries of a `.bib` file, skipping `@string`, `@preamble` and `@comment`.
+pub fn parse(text: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut rest = text;
+    while let Some(at) = rest.find('@') {
+        rest = &rest[at + 1..];
+        let kind_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        let kind = rest[..kind_len].to_ascii_lowercase();
+        let Some(body) = rest[kind_len..].trim_start().strip_prefix(['{', '(']) else {
+            continue;
+        };
+        let end = closing(body).unwrap_or(body.len());
+        rest = &body[end..];
+        if matches!(kind.as_str(), "string" | "preamble" | "comment") {
+            continue;
+        }
+        let body = &body[..end];
+        let Some((key, fields)) = body.split_once(',') else {
+            continue;
+        };
+        let mut entry = Entry {
+            key: key.trim().to_string(),
+            title: None,
+            author: None,
+        };
+        for (name, value) in fields_of(fields) {
+            match name.to_ascii_lowercase().as_str() {
+                "title" => entry.title = Some(value),
+                "author" => entry.author = Some(value),
+                _ => {}
+            }
+        }
+        if !entry.key.is_empty() {
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+/// Where the `@key` being typed at the end of `line` starts, after the `@`.
+pub fn citation_start(line: &str) -> Option<usize> {
+    let start = line.rfind(|c: char| !is_key_char(c)).map_or(0, |i| {
+        i + line[i..].chars().next().map_or(1, char::len_utf8)
+    });
+    let before = line[..start].strip_suffix('@')?;
+    // Not an email address
+    before
+        .chars()
+        .next_back()
+        .is_none_or(|c| c.is_whitespace() || matches!(c, '[' | ';' | '-'))
+        .then_some(start)
+}
+
+/// The cited key around `offset` in `text`.
+pub fn citation_at(text: &str, offset: usize) -> Option<&str> {
+    let end = text[offset..]
+        .find(|c: char| !is_key_char(c))
+        .map_or(text.len(), |i| offset + i);
+    let start = citation_start(&text[..offset])?;
+    // Punctuation ending a key ends the sentence instead
+    let key = text[start..end].trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_');
+    Some(key).filter(|key| !key.is_empty())
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '.' | '/')
+}
+
+/// The length of `body` up to the brace closing it, braces nesting.
+fn closing(body: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in body.char_indices() {
+        match c {
+            '{' | '(' => depth += 1,
+            '}' | ')' if depth == 0 => return Some(i),
+            '}' | ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The `name = value` pairs of an entry, values without their delimiters and with
+/// their whitespace collapsed.
+fn fields_of(mut fields: &str) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    while let Some((name, rest)) = fields.split_once('=') {
+        let name = name.trim().trim_start_matches(',').trim().to_string();
+        let rest = rest.trim_start();
+        let (value, len) = match rest.chars().next() {
+            Some('{') => {
+                let end = closing(&rest[1..]).unwrap_or(rest.len() - 1);
+                (&rest[1..end + 1], end + 2)
+            }
+            Some('"') => {
+                let end = rest[1..].find('"').unwrap_or(rest.len() - 1);
+                (&rest[1..end + 1], end + 2)
+            }
+            _ => {
+                let end = rest.find(',').unwrap_or(rest.len());
+                (&rest[..end], end)
+            }
+        };
+        let value = value.replace(['{', '}'], "");
+        found.push((name, value.split_whitespace().join(" ")));
+        fields = &rest[len.min(rest.len())..];
+    }
+    found
+}
@@ -0,0 +1,747 @@
+//! Where completion candidates come from. Each source is a [`CandidateProvider`], and
+//! [`Providers`] asks them in order, the first provider of a word being the one it's
+//! shown as.
+
+use crate::bibtex::{self, Bibliography};
+use crate::commit;
+use crate::config::{CompletionConfig, ProviderConfig};
+use crate::context;
+use crate::environment;
+use crate::error::ServerError;
+use crate::experiment::Arm;
+use crate::ext::COMPLETION_ACCEPTED;
+use crate::git;
+use crate::index::Index;
+use crate::line_index::LineIndex;
+use crate::markdown::{self, LinkTarget, Markdown};
+use crate::plugin::{self, PluginResult, Unanswered, Worker};
+use crate::session::Session;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use lsp_types::{CompletionItemKind, Position, Url};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+/// How many word starts keep the plugin completions that arrived late.
+const LATE_COMPLETIONS: usize = 64;
+
+/// How many of the last commit messages commit messages are completed from.
+const RECENT_COMMITS: usize = 50;
+
+type Result<T> = std::result::Result<T, ServerError>;
+
+type CompletionKey = (Url, Position);
+
+/// Where a completion comes from, shown to the user as its kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// Used with the same punctuation as at the cursor.
+    Context,
+    Line,
+    Plugin,
+    Index,
+    Snippet,
+    /// Headings and link reference definitions of the markdown document.
+    Anchor,
+    /// Keys of the workspace bibliography.
+    Citation,
+    Environment,
+    /// The staged changes and the recent messages, in commit messages.
+    Git,
+    /// What the client said was copied recently.
+    Clipboard,
+    /// `completion.dictionary`, like the words of rule packs.
+    Dictionary,
+}
+
+impl Source {
+    pub fn kind(self) -> CompletionItemKind {
+        match self {
+            Self::Context => CompletionItemKind::FIELD,
+            Self::Line => CompletionItemKind::TEXT,
+            Self::Plugin => CompletionItemKind::VALUE,
+            Self::Index => CompletionItemKind::REFERENCE,
+            Self::Snippet => CompletionItemKind::SNIPPET,
+            Self::Anchor | Self::Citation => CompletionItemKind::REFERENCE,
+            Self::Environment => CompletionItemKind::VARIABLE,
+            Self::Git | Self::Clipboard | Self::Dictionary => CompletionItemKind::TEXT,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Context => "context",
+            Self::Line => "line",
+            Self::Plugin => "plugin",
+            Self::Index => "index",
+            Self::Snippet => "snippet",
+            Self::Anchor => "anchor",
+            Self::Citation => "citation",
+            Self::Environment => "environment",
+            Self::Git => "git",
+            Self::Clipboard => "clipboard",
+            Self::Dictionary => "dictionary",
+        }
+    }
+
+    /// Reports the item as accepted once the client inserts it, for the experiment arm
+    /// of its document if any.
+    pub fn accepted(self, arm: Option<Arm>) -> lsp_types::Command {
+        let arguments = [Some(self.name()), arm.map(Arm::name)]
+            .into_iter()
+            .flatten()
+            .map(serde_json::Value::from)
+            .collect();
+        lsp_types::Command::new(
+            "Accept completion".to_string(),
+            COMPLETION_ACCEPTED.to_string(),
+            Some(arguments),
+        )
+    }
+}
+
+/// The word being completed, and what providers may look at to complete it.
+pub struct Query<'a> {
+    /// As the server keys documents.
+    pub uri: &'a Url,
+    pub text: &'a str,
+    pub language: &'a str,
+    pub position: Position,
+    pub word_start: Position,
+    /// The line up to the cursor.
+    pub before: &'a str,
+    pub prefix: &'a str,
+    /// The words of `completion.context_window`, the line up to the cursor by default.
+    pub window_words: &'a [&'a str],
+    /// What plugins are given of the text, with `completion.context_window`.
+    pub window: Option<Range<usize>>,
+    /// Whether the words used with the punctuation at the cursor are wanted.
+    pub symbol_context: bool,
+    pub context_symbols: &'a [char],
+    pub index: &'a Index,
+    pub bibliography: &'a Bibliography,
+    /// From `testLsp/clipboardHint`, the latest first.
+    pub clipboard: &'a VecDeque<String>,
+}
+
+impl Query<'_> {
+    fn key(&self) -> CompletionKey {
+        (self.uri.clone(), self.word_start)
+    }
+}
+
+pub enum Candidates {
+    Ready(Vec<String>),
+    /// The only words that make sense at the cursor, the other providers not being asked.
+    /// They replace the line up to the cursor from byte `from`, rather than the word.
+    Only {
+        words: Vec<String>,
+        from: usize,
+    },
+    /// Still being looked for on another thread.
+    Pending(Receiver<PluginResult<Vec<String>>>),
+}
+
+pub trait CandidateProvider: Send {
+    /// What `completion.providers` knows it as.
+    fn name(&self) -> &str;
+
+    fn source(&self) -> Source;
+
+    /// Starts looking for the candidates of `query`, which slow providers do off the
+    /// message loop.
+    fn candidates(&mut self, query: &Query) -> Result<Candidates>;
+
+    /// Takes back the candidates that missed their budget, to use them when the same
+    /// word is completed again.
+    fn missed(&mut self, _query: &Query, _pending: Receiver<PluginResult<Vec<String>>>) {}
+}
+
+/// The providers in the order they're asked.
+pub struct Providers {
+    providers: Vec<Box<dyn CandidateProvider>>,
+    config: HashMap<String, ProviderConfig>,
+    deadline: Duration,
+    /// `completion.stop_words`, lowercase.
+    stop_words: HashSet<String>,
+    slo: Slo,
+}
+
+/// What the providers came up with.
+pub struct Collected {
+    /// Each word once, where the provider ranking it highest put it, with every source
+    /// that came up with it, that one first.
+    pub words: IndexMap<String, Vec<Source>>,
+    /// Some provider missed its budget.
+    pub incomplete: bool,
+    /// Where the words replace the line up to the cursor from, when a provider knew the
+    /// only words that make sense there.
+    pub replacing: Option<usize>,
+    /// The providers just disabled for missing their budget too many times in a row.
+    pub disabled: Vec<String>,
+}
+
+/// Disables the providers that keep missing their budget for `completion.cooldown`
+/// seconds, so that a slow one doesn't make every completion wait for it.
+struct Slo {
+    max_misses: u32,
+    cooldown: Duration,
+    /// Budgets missed in a row, by provider.
+    misses: HashMap<String, u32>,
+    /// Until when each disabled provider is skipped.
+    disabled: HashMap<String, Instant>,
+}
+
+impl Slo {
+    fn skips(&mut self, name: &str, now: Instant) -> bool {
+        match self.disabled.get(name) {
+            Some(&until) if now < until => true,
+            Some(_) => {
+                log::info!("enabling {name} again");
+                self.disabled.remove(name);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the provider is disabled now, having missed its budget once too often.
+    fn record(&mut self, name: &str, missed: bool) -> bool {
+        if !missed {
+            self.misses.remove(name);
+            return false;
+        }
+        let misses = self.misses.entry(name.to_string()).or_default();
+        *misses += 1;
+        if self.max_misses == 0 || *misses < self.max_misses {
+            return false;
+        }
+        log::warn!("{name} missed its budget {misses} times in a row, disabling it");
+        self.misses.remove(name);
+        self.disabled
+            .insert(name.to_string(), Instant::now() + self.cooldown);
+        true
+    }
+}
+
+impl Providers {
+    /// The built-in providers and one per plugin, the slow plugins being waited on for
+    /// up to `completion.deadline` unless they have a budget of their own.
+    pub fn new(plugins: &[Worker], config: &CompletionConfig) -> Self {
+        let mut providers: Vec<Box<dyn CandidateProvider>> = vec![
+            Box::new(AnchorProvider),
+            Box::new(CitationProvider),
+            Box::new(EnvironmentProvider),
+            Box::new(CommitProvider::default()),
+            Box::new(ClipboardProvider),
+            Box::new(ContextProvider),
+            Box::new(LineProvider),
+        ];
+        for plugin in plugins {
+            providers.push(Box::new(PluginProvider::new(plugin.clone())));
+        }
+        providers.push(Box::new(IndexProvider));
+        if !config.dictionary.is_empty() {
+            providers.push(Box::new(DictionaryProvider(config.dictionary.clone())));
+        }
+        for name in config.providers.keys() {
+            if !providers.iter().any(|provider| provider.name() == name) {
+                log::warn!("no completion provider is called `{name}`");
+            }
+        }
+        Self {
+            providers,
+            config: config.providers.clone(),
+            deadline: Duration::from_millis(config.deadline),
+            stop_words: config
+                .stop_words
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect(),
+            slo: Slo {
+                max_misses: config.max_misses,
+                cooldown: Duration::from_secs(config.cooldown),
+                misses: HashMap::new(),
+                disabled: HashMap::new(),
+            },
+        }
+    }
+
+    /// Asks every provider for `query`, waiting on the pending ones until their budget is
+    /// spent or `cancelled` says the request was.
+    pub fn collect(
+        &mut self,
+        query: &Query,
+        started: Instant,
+        session: &mut Session,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Collected {
+        let Self {
+            providers,
+            config,
+            deadline,
+            stop_words,
+            slo,
+        } = self;
+        let mut disabled = Vec::new();
+        // Every provider starts before any is waited on, so the slow ones work meanwhile
+        let mut asked = Vec::new();
+        for provider in providers.iter_mut() {
+            let config = config.get(provider.name()).cloned().unwrap_or_default();
+            if !config.enabled || slo.skips(provider.name(), started) {
+                continue;
+            }
+            let budget = config.budget.map_or(*deadline, Duration::from_millis);
+            let asked_at = Instant::now();
+            let candidates = provider.candidates(query);
+            match candidates {
+                Ok(Candidates::Only { words, from }) => {
+                    session.record_latency(provider.name(), asked_at.elapsed());
+                    slo.record(provider.name(), false);
+                    let source = provider.source();
+                    return Collected {
+                        words: words.into_iter().map(|word| (word, vec![source])).collect(),
+                        incomplete: false,
+                        replacing: Some(from),
+                        disabled,
+                    };
+                }
+                Ok(Candidates::Ready(_)) => {
+                    let latency = asked_at.elapsed();
+                    session.record_latency(provider.name(), latency);
+                    if slo.record(provider.name(), latency > budget) {
+                        disabled.push(provider.name().to_string());
+                    }
+                }
+                _ => {}
+            }
+            asked.push((provider, asked_at, started + budget, candidates));
+        }
+
+        let mut words = IndexMap::new();
+        // Case variants are one candidate, spelled as first found
+        let mut spellings = HashMap::new();
+        let mut incomplete = false;
+        for (provider, asked_at, due, candidates) in asked {
+            let name = provider.name().to_string();
+            let found = match candidates {
+                Ok(Candidates::Ready(found) | Candidates::Only { words: found, .. }) => found,
+                Ok(Candidates::Pending(pending)) => {
+                    match plugin::wait(&pending, Some(due), cancelled) {
+                        Ok(Ok(found)) => {
+                            session.record_latency(&name, asked_at.elapsed());
+                            slo.record(&name, false);
+                            found
+                        }
+                        Ok(Err(source)) => {
+                            let err = ServerError::Plugin { name, source };
+                            log::error!("failed to complete: {err}");
+                            continue;
+                        }
+                        Err(Unanswered::Timeout) => {
+                            incomplete = true;
+                            if slo.record(&name, true) {
+                                disabled.push(name);
+                            }
+                            provider.missed(query, pending);
+                            continue;
+                        }
+                        // Not the provider's fault, so its words are still kept for later
+                        Err(Unanswered::Cancelled) => {
+                            incomplete = true;
+                            provider.missed(query, pending);
+                            continue;
+                        }
+                        Err(Unanswered::Disconnected) => {
+                            log::error!("{name} stopped");
+                            continue;
+                        }
+                    }
+                }
+                Err(err) => {
+                    log::error!("failed to complete: {err}");
+                    continue;
+                }
+            };
+            let source = provider.source();
+            for word in found {
+                if !stop_words.is_empty() && stop_words.contains(&word.to_lowercase()) {
+                    continue;
+                }
+                let folded = query.index.collator().fold(&word);
+                let word = spellings.entry(folded).or_insert(word).clone();
+                let sources: &mut Vec<Source> = words.entry(word).or_default();
+                if !sources.contains(&source) {
+                    sources.push(source);
+                }
+            }
+        }
+        Collected {
+            words,
+            incomplete,
+            replacing: None,
+            disabled,
+        }
+    }
+}
+
+/// The heading anchors after `](#` and the link reference labels after `][`, in
+/// markdown documents.
+struct AnchorProvider;
+
+impl CandidateProvider for AnchorProvider {
+    fn name(&self) -> &str {
+        "anchors"
+    }
+
+    fn source(&self) -> Source {
+        Source::Anchor
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        if query.language != "markdown" {
+            return Ok(Candidates::Ready(Vec::new()));
+        }
+        let typed = [query.before, query.prefix].concat();
+        let Some((target, from)) = markdown::link_target(&typed) else {
+            return Ok(Candidates::Ready(Vec::new()));
+        };
+        let markdown = Markdown::parse(query.text);
+        let found = match target {
+            LinkTarget::Anchor => markdown
+                .headings
+                .into_iter()
+                .map(|heading| heading.anchor)
+                .collect(),
+            LinkTarget::Reference => markdown
+                .link_definitions
+                .into_iter()
+                .map(|definition| definition.label)
+                .collect(),
+        };
+        Ok(Candidates::Only { words: found, from })
+    }
+}
+
+/// The variables defined earlier in the document and the ones of the server's
+/// environment after `$` and `${`, in the languages expanding them.
+struct EnvironmentProvider;
+
+impl CandidateProvider for EnvironmentProvider {
+    fn name(&self) -> &str {
+        "environment"
+    }
+
+    fn source(&self) -> Source {
+        Source::Environment
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        let expanding = environment::EXPANDING_LANGUAGES.contains(&query.language);
+        if !expanding || !environment::expands(query.before) {
+            return Ok(Candidates::Ready(Vec::new()));
+        }
+        let offset = LineIndex::new(query.text)
+            .offset(query.position)
+            .unwrap_or(query.text.len());
+        Ok(Candidates::Only {
+            words: environment::variables(query.text, offset),
+            from: query.before.len(),
+        })
+    }
+}
+
+/// The words of the staged changes and of the recent commit messages, in commit messages.
+#[derive(Default)]
+struct CommitProvider {
+    /// By work tree, and when they were asked for.
+    words: Option<(PathBuf, Instant, Vec<String>)>,
+}
+
+impl CommitProvider {
+    fn words(&mut self, work_tree: PathBuf) -> Result<&[String]> {
+        let fresh = self.words.as_ref().is_some_and(|(cached, asked_at, _)| {
+            *cached == work_tree && asked_at.elapsed() < git::REFRESH
+        });
+        if !fresh {
+            let staged = git::staged_words(&work_tree)?;
+            let messages = git::message_words(&work_tree, RECENT_COMMITS)?;
+            let words = staged.into_iter().chain(messages).unique().collect();
+            self.words = Some((work_tree, Instant::now(), words));
+        }
+        Ok(self.words.as_ref().map_or(&[], |(_, _, words)| words))
+    }
+}
+
+impl CandidateProvider for CommitProvider {
+    fn name(&self) -> &str {
+        "git"
+    }
+
+    fn source(&self) -> Source {
+        Source::Git
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        let work_tree = query
+            .uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| git::work_tree(&path));
+        let Some(work_tree) = work_tree.filter(|_| query.language == commit::LANGUAGE) else {
+            return Ok(Candidates::Ready(Vec::new()));
+        };
+        let words = self.words(work_tree)?;
+        Ok(Candidates::Ready(
+            words
+                .iter()
+                .filter(|word| word.starts_with(query.prefix))
+                .cloned()
+                .collect(),
+        ))
+    }
+}
+
+/// The keys of the workspace `.bib` files after `@`, in the languages citing with it.
+struct CitationProvider;
+
+impl CandidateProvider for CitationProvider {
+    fn name(&self) -> &str {
+        "citations"
+    }
+
+    fn source(&self) -> Source {
+        Source::Citation
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        if !bibtex::CITING_LANGUAGES.contains(&query.language) {
+            return Ok(Candidates::Ready(Vec::new()));
+        }
+        let typed = [query.before, query.prefix].concat();
+        let Some(from) = bibtex::citation_start(&typed) else {
+            return Ok(Candidates::Ready(Vec::new()));
+        };
+        let words = query.bibliography.keys().map(str::to_string).collect();
+        Ok(Candidates::Only { words, from })
+    }
+}
+
+/// The recently copied strings starting with the prefix, the latest first.
+struct ClipboardProvider;
+
+impl CandidateProvider for ClipboardProvider {
+    fn name(&self) -> &str {
+        "clipboard"
+    }
+
+    fn source(&self) -> Source {
+        Source::Clipboard
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        Ok(Candidates::Ready(
+            query
+                .clipboard
+                .iter()
+                .filter(|copied| copied.starts_with(query.prefix) && *copied != query.prefix)
+                .cloned()
+                .collect(),
+        ))
+    }
+}
+
+/// The indexed words used right after the punctuation at the cursor.
+struct ContextProvider;
+
+impl CandidateProvider for ContextProvider {
+    fn name(&self) -> &str {
+        "context"
+    }
+
+    fn source(&self) -> Source {
+        Source::Context
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        let Some(symbol) = query
+            .symbol_context
+            .then(|| context::symbol_context(query.before, query.context_symbols))
+            .flatten()
+        else {
+            return Ok(Candidates::Ready(Vec::new()));
+        };
+        let found = query
+            .index
+            .words_after(symbol, query.prefix)
+            .map_err(ServerError::index)?;
+        Ok(Candidates::Ready(
+            found.into_iter().map(|(w, _)| w).collect(),
+        ))
+    }
+}
+
+/// The words around the cursor, of the line up to it unless configured otherwise.
+struct LineProvider;
+
+impl CandidateProvider for LineProvider {
+    fn name(&self) -> &str {
+        "line"
+    }
+
+    fn source(&self) -> Source {
+        Source::Line
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        let words = query.window_words.iter().map(|w| w.to_string()).collect();
+        Ok(Candidates::Ready(words))
+    }
+}
+
+/// The words of the open documents and the workspace starting with the prefix.
+struct IndexProvider;
+
+impl CandidateProvider for IndexProvider {
+    fn name(&self) -> &str {
+        "index"
+    }
+
+    fn source(&self) -> Source {
+        Source::Index
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        let found = query
+            .index
+            .words_with_prefix(query.prefix)
+            .map_err(ServerError::index)?;
+        Ok(Candidates::Ready(
+            found.into_iter().map(|(w, _)| w).collect(),
+        ))
+    }
+}
+
+/// The words of `completion.dictionary` starting with the prefix in any case, after
+/// those of the documents.
+struct DictionaryProvider(Vec<String>);
+
+impl CandidateProvider for DictionaryProvider {
+    fn name(&self) -> &str {
+        "dictionary"
+    }
+
+    fn source(&self) -> Source {
+        Source::Dictionary
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        let prefix = query.prefix.to_lowercase();
+        let words = self
+            .0
+            .iter()
+            .filter(|word| word.to_lowercase().starts_with(&prefix))
+            .cloned()
+            .collect();
+        Ok(Candidates::Ready(words))
+    }
+}
+
+/// A plugin completion that missed its budget.
+struct LateCompletion {
+    /// The document and word start it was asked for.
+    key: CompletionKey,
+    /// The prefix it was asked for.
+    prefix: String,
+    receiver: Receiver<PluginResult<Vec<String>>>,
+}
+
+struct PluginProvider {
+    worker: Worker,
+    pending: Vec<LateCompletion>,
+    late: IndexMap<CompletionKey, (String, Vec<String>)>,
+}
+
+impl PluginProvider {
+    fn new(worker: Worker) -> Self {
+        Self {
+            worker,
+            pending: Vec::new(),
+            late: IndexMap::new(),
+        }
+    }
+
+    /// Caches the completions that missed their budget and have since arrived.
+    fn collect_late(&mut self) {
+        self.pending.retain(|late| match late.receiver.try_recv() {
+            Ok(Ok(candidates)) => {
+                let (key, prefix) = (late.key.clone(), late.prefix.clone());
+                self.late.insert(key, (prefix, candidates));
+                if self.late.len() > LATE_COMPLETIONS {
+                    self.late.shift_remove_index(0);
+                }
+                false
+            }
+            Ok(Err(err)) => {
+                log::error!("plugin {} failed to complete: {err}", self.worker.name());
+                false
+            }
+            Err(TryRecvError::Empty) => true,
+            Err(TryRecvError::Disconnected) => false,
+        });
+    }
+}
+
+impl CandidateProvider for PluginProvider {
+    fn name(&self) -> &str {
+        self.worker.name()
+    }
+
+    fn source(&self) -> Source {
+        Source::Plugin
+    }
+
+    fn candidates(&mut self, query: &Query) -> Result<Candidates> {
+        self.collect_late();
+        // Served once, and only while the word is still the one they were asked for, or
+        // more of it
+        if let Some((prefix, late)) = self.late.shift_remove(&query.key()) {
+            if query.prefix.starts_with(&prefix) {
+                let late = late
+                    .into_iter()
+                    .filter(|word| word.starts_with(query.prefix))
+                    .collect();
+                return Ok(Candidates::Ready(late));
+            }
+        }
+        let (text, position) = match &query.window {
+            Some(window) => {
+                let text = &query.text[window.clone()];
+                let offset = LineIndex::new(query.text)
+                    .offset(query.position)
+                    .unwrap_or(window.end);
+                let position = LineIndex::new(text)
+                    .position(offset.clamp(window.start, window.end) - window.start);
+                (text.to_string(), position)
+            }
+            None => (query.text.to_string(), query.position),
+        };
+        let pending = self
+            .worker
+            .call(move |plugin| plugin.complete(&text, position));
+        Ok(Candidates::Pending(pending))
+    }
+
+    fn missed(&mut self, query: &Query, pending: Receiver<PluginResult<Vec<String>>>) {
+        self.pending.push(LateCompletion {
+            key: query.key(),
+            prefix: query.prefix.to_string(),
+            receiver: pending,
+        });
+    }
+}
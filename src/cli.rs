@@ -0,0 +1,241 @@
+//! The subcommands doing once what the server does for an editor.
+
+use clap::{Args, ValueEnum};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use test_lsp::engine::{
+    Checker, CompletionEngine, Config, Diagnostic, DiagnosticSeverity, NumberOrString, Position,
+    Url,
+};
+use test_lsp::{conformance, sarif, soak};
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Sync + Send>>;
+
+#[derive(Args)]
+pub struct CompleteArgs {
+    #[arg(long)]
+    file: PathBuf,
+    /// 1-based.
+    #[arg(long)]
+    line: u32,
+    /// 1-based, in characters.
+    #[arg(long)]
+    col: u32,
+    /// Also complete from the words of the text files under this directory.
+    #[arg(long)]
+    workspace: Option<PathBuf>,
+    /// A JSON file of the settings clients send as `initializationOptions`.
+    #[arg(long)]
+    settings: Option<PathBuf>,
+}
+
+/// Prints the candidates for the word at the cursor of `args` as JSON.
+pub fn complete(args: CompleteArgs) -> Result<()> {
+    let mut engine = CompletionEngine::new(settings(args.settings.as_deref())?)?;
+    if let Some(root) = &args.workspace {
+        let indexed = engine.index_workspace(root);
+        log::info!("indexed {indexed} files under {}", root.display());
+    }
+    let text = std::fs::read_to_string(&args.file)?;
+    let uri = uri(&args.file)?;
+    engine.update(&uri, &text)?;
+    let position = position(&text, args.line, args.col)
+        .ok_or_else(|| format!("{} has no line {}", args.file.display(), args.line))?;
+    let completions = engine.complete(&uri, &text, position);
+    println!("{}", serde_json::to_string_pretty(&completions)?);
+    Ok(())
+}
+
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Files, or directories to check the text files of.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    format: Format,
+    /// A JSON file of the settings clients send as `initializationOptions`.
+    #[arg(long)]
+    settings: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// A line per diagnostic, like compilers print.
+    Human,
+    /// The diagnostics of each file, as LSP has them.
+    Json,
+    /// A SARIF 2.1.0 log, for code scanning.
+    Sarif,
+}
+
+/// Prints the diagnostics of the files of `args`, returning whether any is an error.
+pub fn check(args: CheckArgs) -> Result<bool> {
+    let checker = Checker::new(settings(args.settings.as_deref())?);
+    let mut checked = Vec::new();
+    for path in args.paths.iter().flat_map(|path| checker.files(path)) {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) => {
+                log::warn!("skipping {}: {err}", path.display());
+                continue;
+            }
+        };
+        let uri = uri(&path)?;
+        let diagnostics = checker.check(&uri, &text);
+        checked.push((uri, diagnostics));
+    }
+    let failed = checked
+        .iter()
+        .flat_map(|(_, diagnostics)| diagnostics)
+        .any(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR));
+    let cwd = std::env::current_dir()?;
+    match args.format {
+        Format::Human => {
+            for (uri, diagnostics) in &checked {
+                let path = uri.to_file_path().unwrap_or_default();
+                let path = path.strip_prefix(&cwd).unwrap_or(&path);
+                for diagnostic in diagnostics {
+                    println!("{}", human(path, diagnostic));
+                }
+            }
+        }
+        Format::Json => {
+            let files = checked
+                .iter()
+                .map(|(uri, diagnostics)| {
+                    serde_json::json!({ "uri": uri, "diagnostics": diagnostics })
+                })
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&files)?);
+        }
+        Format::Sarif => {
+            let log = sarif::log(&checked, Some(&uri(&cwd)?));
+            println!("{}", serde_json::to_string_pretty(&log)?);
+        }
+    }
+    Ok(failed)
+}
+
+/// Prints how the server fares in the conformance suite, returning whether any check
+/// failed.
+pub fn conformance() -> bool {
+    let outcomes = conformance::run();
+    for outcome in &outcomes {
+        match &outcome.failure {
+            None => println!("ok      {}", outcome.name),
+            Some(failure) => println!("FAILED  {}: {failure}", outcome.name),
+        }
+    }
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| outcome.failure.is_some())
+        .count();
+    println!("{} passed, {failed} failed", outcomes.len() - failed);
+    failed > 0
+}
+
+#[derive(Args)]
+pub struct SoakArgs {
+    /// How long to type for, in seconds.
+    #[arg(long, default_value_t = 60)]
+    duration: u64,
+    #[arg(long, default_value_t = 30)]
+    edits_per_second: u32,
+    /// Ask for completions after this many edits.
+    #[arg(long, default_value_t = 5)]
+    completion_every: u32,
+    /// Fail if the process ever holds more memory than this, in MiB.
+    #[arg(long)]
+    max_memory: Option<u64>,
+    /// Fail if the 99th percentile of the completion latencies is above this, in
+    /// milliseconds.
+    #[arg(long)]
+    max_latency: Option<u64>,
+    /// Seeds the edits, for a run to be replayed.
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+}
+
+/// Prints how the server held up to the soak run of `args`, returning whether it went
+/// over the bounds.
+pub fn soak(args: SoakArgs) -> bool {
+    let report = soak::run(&soak::Options {
+        duration: Duration::from_secs(args.duration),
+        edits_per_second: args.edits_per_second,
+        completion_every: args.completion_every,
+        max_memory: args.max_memory.map(|mib| mib * 1024 * 1024),
+        max_latency: args.max_latency.map(Duration::from_millis),
+        seed: args.seed,
+    });
+    println!("{} edits, {} completions", report.edits, report.completions);
+    if let (Some(p50), Some(p99), Some(max)) = (
+        report.percentile(50),
+        report.percentile(99),
+        report.latencies.last(),
+    ) {
+        println!("completion latency: p50 {p50:?}, p99 {p99:?}, max {max:?}");
+    }
+    if let Some(peak) = report.peak_memory {
+        println!("peak memory: {} MiB", peak / (1024 * 1024));
+    }
+    match &report.failure {
+        None => {
+            println!("ok");
+            false
+        }
+        Some(failure) => {
+            println!("FAILED  {failure}");
+            true
+        }
+    }
+}
+
+/// `path:line:column: severity[rule]: message`, with the line and column 1-based.
+fn human(path: &Path, diagnostic: &Diagnostic) -> String {
+    let severity = match diagnostic.severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) | None => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(_) => "hint",
+    };
+    let rule = match &diagnostic.code {
+        Some(NumberOrString::String(code)) => format!("[{code}]"),
+        Some(NumberOrString::Number(code)) => format!("[{code}]"),
+        None => String::new(),
+    };
+    let start = diagnostic.range.start;
+    format!(
+        "{}:{}:{}: {severity}{rule}: {}",
+        path.display(),
+        start.line + 1,
+        start.character + 1,
+        diagnostic.message
+    )
+}
+
+fn uri(path: &Path) -> Result<Url> {
+    let path = path.canonicalize()?;
+    Ok(Url::from_file_path(&path).map_err(|()| format!("{} has no uri", path.display()))?)
+}
+
+fn settings(path: Option<&Path>) -> Result<Config> {
+    let Some(path) = path else {
+        return Ok(Config::default());
+    };
+    let settings = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(Config::from_initialization_options(Some(settings)))
+}
+
+/// The LSP position of the 1-based `line` and `column`, the column clamped to the end
+/// of the line.
+fn position(text: &str, line: u32, column: u32) -> Option<Position> {
+    let line = line.checked_sub(1)?;
+    let content = text.lines().nth(line as usize)?;
+    let character = content
+        .chars()
+        .take(column.saturating_sub(1) as usize)
+        .map(char::len_utf16)
+        .sum::<usize>();
+    Some(Position::new(line, character as u32))
+}
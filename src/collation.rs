@@ -0,0 +1,122 @@
+//! Comparing words the way readers of a language expect, for listing candidates.
+//!
+//! Words compare by their letters first, then their accents, then their case, so
+//! `resume`, `Resume` and `résumé` sort together wherever the language's alphabet
+//! puts them. Locales with letters of their own, like the Swedish `ä` or the Spanish
+//! `ñ`, sort those where their alphabet does instead of as accented letters.
+
+use std::cmp::Ordering;
+use unicase::UniCase;
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+#[derive(Debug, Clone, Default)]
+pub struct Collator {
+    /// The letters of the alphabet sorted after another letter rather than as an
+    /// accented one, in order.
+    letters: &'static [(char, char)],
+    /// Whether `I` is the capital of `ı` rather than `i`, and `İ` that of `i`.
+    dotless_i: bool,
+}
+
+/// How a word sorts, compared field by field.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    letters: Vec<u32>,
+    /// The accents with the number of letters before them.
+    accents: Vec<(usize, char)>,
+    /// Whether each letter is uppercase, which sorts after lowercase.
+    uppercase: Vec<bool>,
+}
+
+impl Collator {
+    /// For a BCP 47 tag like `sv` or `tr-TR`, only looking at the language. Languages
+    /// without an alphabet of their own here sort in the root order.
+    pub fn new(locale: Option<&str>) -> Self {
+        let language = locale
+            .and_then(|locale| locale.split(['-', '_']).next())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let letters: &[_] = match language.as_str() {
+            "sv" | "fi" => &[('å', 'z'), ('ä', 'z'), ('ö', 'z')],
+            "da" | "nb" | "nn" | "no" => &[('æ', 'z'), ('ø', 'z'), ('å', 'z')],
+            "es" => &[('ñ', 'n')],
+            "pl" => &[
+                ('ą', 'a'),
+                ('ć', 'c'),
+                ('ę', 'e'),
+                ('ł', 'l'),
+                ('ń', 'n'),
+                ('ó', 'o'),
+                ('ś', 's'),
+                ('ź', 'z'),
+                ('ż', 'z'),
+            ],
+            "tr" | "az" => &[
+                ('ç', 'c'),
+                ('ğ', 'g'),
+                ('ı', 'h'),
+                ('ö', 'o'),
+                ('ş', 's'),
+                ('ü', 'u'),
+            ],
+            _ => &[],
+        };
+        Self {
+            letters,
+            dotless_i: matches!(language.as_str(), "tr" | "az"),
+        }
+    }
+
+    /// What the case variants of `word` have in common, to list them once.
+    pub fn fold(&self, word: &str) -> UniCase<String> {
+        UniCase::new(match self.dotless_i {
+            true => word.replace('I', "ı").replace('İ', "i"),
+            false => word.to_string(),
+        })
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.key(a).cmp(&self.key(b)).then_with(|| a.cmp(b))
+    }
+
+    fn key(&self, word: &str) -> Key {
+        let mut key = Key::default();
+        for c in word.chars() {
+            let uppercase = c.is_uppercase();
+            let lowercase = match c {
+                'I' if self.dotless_i => vec!['ı'],
+                'İ' if self.dotless_i => vec!['i'],
+                _ => c.to_lowercase().collect(),
+            };
+            for c in lowercase {
+                if let Some(weight) = self.letter(c) {
+                    key.letters.push(weight);
+                    key.uppercase.push(uppercase);
+                    continue;
+                }
+                decompose_canonical(c, |part| match is_combining_mark(part) {
+                    true => key.accents.push((key.letters.len(), part)),
+                    false => {
+                        key.letters.push(u32::from(part) * 16);
+                        key.uppercase.push(uppercase);
+                    }
+                });
+            }
+        }
+        key
+    }
+
+    /// The weight of a letter of the alphabet that sorts after `after`.
+    fn letter(&self, c: char) -> Option<u32> {
+        let (position, (_, after)) = self
+            .letters
+            .iter()
+            .enumerate()
+            .find(|(_, (letter, _))| *letter == c)?;
+        let before = self.letters[..position]
+            .iter()
+            .filter(|(_, other)| other == after)
+            .count();
+        Some(u32::from(*after) * 16 + before as u32 + 1)
+    }
+}
@@ -0,0 +1,44 @@
+//! Commit messages, as git asks for them in `COMMIT_EDITMSG`.
+
+use std::ops::Range;
+
+/// The language id of commit messages.
+pub const LANGUAGE: &str = "gitcommit";
+
+/// Columns of the subject line, which logs show on their own.
+#[cfg(feature = "diagnostics")]
+pub const SUBJECT_WIDTH: usize = 50;
+
+/// Columns the body is wrapped at.
+pub const BODY_WIDTH: usize = 72;
+
+/// The lines of `text` with their byte offsets, without git's `#` comments.
+#[cfg(feature = "diagnostics")]
+pub fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line.trim_end_matches(['\n', '\r'])))
+        })
+        .filter(|(_, line)| !line.starts_with('#'))
+}
+
+/// The body of the message: from its third line up to the first comment.
+pub fn body(text: &str) -> Range<usize> {
+    let start = text
+        .split_inclusive('\n')
+        .take(2)
+        .map(str::len)
+        .sum::<usize>();
+    let end = text[start..]
+        .split_inclusive('\n')
+        .scan(start, |offset, line| {
+            let line_start = *offset;
+            *offset += line.len();
+            Some((line_start, line))
+        })
+        .find(|(_, line)| line.starts_with('#'))
+        .map_or(text.len(), |(line_start, _)| line_start);
+    start..end
+}
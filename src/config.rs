@@ -0,0 +1,766 @@
+use lsp_types::{DiagnosticSeverity, FormattingOptions, FormattingProperty};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Server settings, read from the client's `initializationOptions`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub index: IndexConfig,
+    pub completion: CompletionConfig,
+    pub references: ReferencesConfig,
+    pub format: FormatConfig,
+    pub debug: DebugConfig,
+    pub diagnostics: DiagnosticsConfig,
+    pub tables: TablesConfig,
+    /// Severity overrides by rule id, for built-in and plugin diagnostics alike.
+    pub rules: HashMap<String, RuleLevel>,
+    pub plugins: Vec<PluginConfig>,
+    pub sidecar: Option<SidecarConfig>,
+    /// Leaves out what reaches the network, like the sidecar and the explanations of
+    /// `testLsp.explain` it gives.
+    pub offline: bool,
+    /// Where the data directories of the workspaces are, by default `test-lsp/workspaces`
+    /// in the platform data directory.
+    pub data_dir: Option<PathBuf>,
+    /// Files, directories or http(s) URLs of the TOML rule packs bundling a house style,
+    /// see [`crate::rule_pack`].
+    pub rule_packs: Vec<String>,
+    /// What acronyms stand for, besides what the workspace introduces them as, like
+    /// `Language Server Protocol (LSP)`.
+    pub acronyms: HashMap<String, String>,
+    pub responses: ResponsesConfig,
+}
+
+impl Config {
+    pub fn from_initialization_options(options: Option<serde_json::Value>) -> Self {
+        let Some(options) = options else {
+            return Self::default();
+        };
+        match serde_json::from_value(options) {
+            Ok(config) => config,
+            Err(err) => {
+                log::warn!("invalid initializationOptions, using defaults: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    /// Explains the settings asking for features this binary was built without, which
+    /// the server does without.
+    pub fn unsupported(&self) -> Vec<String> {
+        let mut unsupported = Vec::new();
+        if self.index.storage == StorageKind::Disk && !cfg!(feature = "sled") {
+            unsupported.push(
+                "`index.storage` is `disk`, but test-lsp was built without the `sled` feature, \
+                 so the index is kept in memory"
+                    .to_string(),
+            );
+        }
+        for plugin in &self.plugins {
+            let feature = match plugin.kind {
+                PluginKind::Wasm if !cfg!(feature = "wasm") => "wasm",
+                PluginKind::Lua if !cfg!(feature = "lua") => "lua",
+                _ => continue,
+            };
+            unsupported.push(format!(
+                "Plugin {} wasn't loaded, test-lsp was built without the `{feature}` feature",
+                plugin.path.display()
+            ));
+        }
+        if self.index.workspace && !cfg!(feature = "workspace") {
+            unsupported.push(
+                "`index.workspace` is set, but test-lsp was built without the `workspace` \
+                 feature, so only the open documents are indexed"
+                    .to_string(),
+            );
+        }
+        if !self.references.exclude.is_empty() && !cfg!(feature = "workspace") {
+            unsupported.push(
+                "`references.exclude` is set, but test-lsp was built without the `workspace` \
+                 feature, so no file is left out of references"
+                    .to_string(),
+            );
+        }
+        if !self.diagnostics.patterns.is_empty() && !cfg!(feature = "diagnostics") {
+            unsupported.push(
+                "`diagnostics.patterns` is set, but test-lsp was built without the \
+                 `diagnostics` feature, so only plugins diagnose documents"
+                    .to_string(),
+            );
+        }
+        if self.sidecar.is_some() && !cfg!(feature = "grpc") {
+            unsupported.push(
+                "`sidecar` is set, but test-lsp was built without the `grpc` feature, so \
+                 completions and diagnostics come from the built-in sources only"
+                    .to_string(),
+            );
+        }
+        unsupported
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLevel {
+    Off,
+    Hint,
+    Info,
+    Warning,
+    Error,
+}
+
+impl RuleLevel {
+    /// `None` when the rule is turned off.
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Self::Off => None,
+            Self::Hint => Some(DiagnosticSeverity::HINT),
+            Self::Info => Some(DiagnosticSeverity::INFORMATION),
+            Self::Warning => Some(DiagnosticSeverity::WARNING),
+            Self::Error => Some(DiagnosticSeverity::ERROR),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CompletionConfig {
+    /// Prefer the words previously used with the same punctuation as the cursor, e.g.
+    /// after a `.` or on a `#` heading line.
+    pub symbol_context: bool,
+    pub context_symbols: Vec<char>,
+    /// Milliseconds to wait for the slower sources before returning what's ready.
+    pub deadline: u64,
+    /// Candidates beyond this many are dropped and the list marked incomplete.
+    pub max_results: usize,
+    /// Snippet bodies by the abbreviation completing to them, in LSP snippet syntax.
+    pub snippets: HashMap<String, String>,
+    /// Ranks the completions of half the documents differently, see
+    /// [`crate::experiment`].
+    pub experiment: Option<ExperimentConfig>,
+    /// By the name of the provider, `anchors`, `citations`, `environment`, `git`,
+    /// `clipboard`, `context`, `line`, `index`, `dictionary` or a plugin's.
+    pub providers: HashMap<String, ProviderConfig>,
+    /// Rank the words of the lines changed since the last commit first, going by
+    /// `git diff`.
+    pub git_boost: bool,
+    /// What the `line` provider takes words from and what plugins are given of the
+    /// document, by default the line up to the cursor and the whole document.
+    pub context_window: Option<ContextWindow>,
+    /// Bytes before and after the cursor completions look at at most, for minified and
+    /// data files with lines megabytes long to complete as fast as the others.
+    pub column_window: usize,
+    /// Explain the ranking of each item in its `data`, and to `testLsp/explainRanking`.
+    pub debug: bool,
+    /// The BCP 47 tag of the language candidates are sorted and told apart by case
+    /// for, like `sv` or `tr`.
+    pub locale: Option<String>,
+    /// Words completed whether or not any document has them, like the product names
+    /// of a rule pack.
+    pub dictionary: Vec<String>,
+    /// Words never offered, whatever provider has them.
+    pub stop_words: Vec<String>,
+    /// Completions in a row a provider may miss its budget in before it's left out for
+    /// `cooldown` seconds, `0` never leaving providers out.
+    pub max_misses: u32,
+    pub cooldown: u64,
+    /// How the candidates are ranked, unless `strategies` has one for the language id.
+    pub strategy: RankingStrategy,
+    pub strategies: HashMap<String, RankingStrategy>,
+}
+
+/// How completion candidates are ranked, see [`crate::ranking::Ranker`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RankingStrategy {
+    /// The most frequent words of the workspace first.
+    Frequency,
+    /// The words of the files indexed last first.
+    Recency,
+    /// The words the prefix matches the most tightly first, for abbreviated typing.
+    FuzzyFirst,
+    /// As the providers found them, reordered by plugins backed by language models.
+    #[default]
+    ModelWeighted,
+}
+
+/// How much of the document around the cursor completions look at.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextWindow {
+    /// The line up to the cursor.
+    Line,
+    /// As many lines above and below the cursor's, like `{ "lines": 3 }`.
+    Lines(usize),
+    /// The paragraph around the cursor.
+    Paragraph,
+}
+
+impl Default for CompletionConfig {
+    fn default() -> Self {
+        Self {
+            symbol_context: false,
+            context_symbols: vec!['.', '#', '@', ':', '>'],
+            deadline: 100,
+            max_results: 100,
+            snippets: HashMap::new(),
+            experiment: None,
+            providers: HashMap::new(),
+            git_boost: false,
+            context_window: None,
+            column_window: 8 * 1024,
+            debug: false,
+            locale: None,
+            dictionary: Vec::new(),
+            stop_words: Vec::new(),
+            max_misses: 3,
+            cooldown: 60,
+            strategy: RankingStrategy::default(),
+            strategies: HashMap::new(),
+        }
+    }
+}
+
+impl CompletionConfig {
+    pub fn strategy(&self, language: &str) -> RankingStrategy {
+        self.strategies
+            .get(language)
+            .copied()
+            .unwrap_or(self.strategy)
+    }
+
+    /// Whether any document may be completed from symbol contexts, which the index then
+    /// has to count.
+    pub fn uses_symbol_context(&self) -> bool {
+        self.symbol_context
+            || self.experiment.as_ref().is_some_and(|experiment| {
+                experiment.control.symbol_context || experiment.treatment.symbol_context
+            })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProviderConfig {
+    pub enabled: bool,
+    /// Milliseconds to wait for the provider, instead of `completion.deadline`.
+    pub budget: Option<u64>,
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            budget: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExperimentConfig {
+    /// Also changes which arm each document is assigned to.
+    pub name: String,
+    pub control: RankingConfig,
+    pub treatment: RankingConfig,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct RankingConfig {
+    /// Overrides `completion.symbol_context`.
+    pub symbol_context: bool,
+    /// Let plugins rank the candidates.
+    pub plugin_ranking: bool,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            symbol_context: false,
+            plugin_ranking: true,
+        }
+    }
+}
+
+/// CSV and TSV files.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TablesConfig {
+    /// Files larger than this many bytes get no column hovers, symbols or alignment.
+    pub max_size: usize,
+}
+
+impl Default for TablesConfig {
+    fn default() -> Self {
+        Self { max_size: 1 << 20 }
+    }
+}
+
+/// The bytes responses may take, past which they're summarized, for slow clients not to
+/// lag rendering them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ResponsesConfig {
+    pub hover: usize,
+    pub completion_documentation: usize,
+    pub diagnostic_message: usize,
+}
+
+impl Default for ResponsesConfig {
+    fn default() -> Self {
+        Self {
+            hover: 16 * 1024,
+            completion_documentation: 4 * 1024,
+            diagnostic_message: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReferencesConfig {
+    /// Leave out uses inside string literals, in the languages whose syntax is known.
+    pub exclude_strings: bool,
+    /// Leave out uses inside comments, in the languages whose syntax is known.
+    pub exclude_comments: bool,
+    /// Gitignore-style globs, relative to the workspace root, of files never searched.
+    pub exclude: Vec<String>,
+    /// Locations beyond this many are dropped, with a message saying so.
+    pub max_results: usize,
+}
+
+impl Default for ReferencesConfig {
+    fn default() -> Self {
+        Self {
+            exclude_strings: false,
+            exclude_comments: false,
+            exclude: Vec::new(),
+            max_results: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FormatConfig {
+    /// Close brackets and curl double quotes as they're typed in prose documents.
+    pub auto_pair: bool,
+    /// languageIds whose documents get curly quotes and em dashes when formatted or
+    /// saved.
+    pub typographic: Vec<String>,
+    pub insert_final_newline: bool,
+    /// Leave a single newline where the text ends in several.
+    pub trim_final_newlines: bool,
+    pub line_ending: LineEnding,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            auto_pair: false,
+            typographic: Vec::new(),
+            insert_final_newline: true,
+            trim_final_newlines: true,
+            line_ending: LineEnding::Auto,
+        }
+    }
+}
+
+impl FormatConfig {
+    /// Overrides the settings the client sent along with a formatting request, the
+    /// line ending as the `lineEnding` extension property.
+    pub fn with_options(&self, options: &FormattingOptions) -> Self {
+        let line_ending = match options.properties.get("lineEnding") {
+            Some(FormattingProperty::String(line_ending)) => {
+                serde_json::from_value(line_ending.as_str().into())
+                    .inspect_err(|err| log::warn!("invalid lineEnding `{line_ending}`: {err}"))
+                    .unwrap_or(self.line_ending)
+            }
+            _ => self.line_ending,
+        };
+        Self {
+            insert_final_newline: options
+                .insert_final_newline
+                .unwrap_or(self.insert_final_newline),
+            trim_final_newlines: options
+                .trim_final_newlines
+                .unwrap_or(self.trim_final_newlines),
+            line_ending,
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// Whichever the text uses most.
+    #[default]
+    Auto,
+    Lf,
+    Crlf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DebugConfig {
+    /// Periodically check that open documents agree with their edits and the index,
+    /// resyncing the ones that don't.
+    pub consistency_check: bool,
+    /// Seconds between consistency checks.
+    pub consistency_interval: u64,
+    /// How many protocol anomalies `testLsp/recentErrors` keeps.
+    pub recent_errors: usize,
+    /// Log the optional `$/` notifications the server doesn't handle at info level rather
+    /// than debug.
+    pub log_unhandled: bool,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            consistency_check: false,
+            consistency_interval: 10,
+            recent_errors: 100,
+            log_unhandled: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DiagnosticsConfig {
+    /// languageIds whose documents get the prose rules.
+    pub prose_languages: Vec<String>,
+    /// Abbreviations after which a lowercase word doesn't start a new sentence.
+    pub capitalization_exceptions: Vec<String>,
+    /// Columns a line may take by languageId, only the languages listed being checked.
+    pub line_length: HashMap<String, usize>,
+    /// Milliseconds to wait for each provider, like a plugin, to check a document.
+    pub timeout: u64,
+    /// By the name of the provider, a built-in rule id, `patterns` or a plugin's name.
+    pub providers: HashMap<String, DiagnosticProviderConfig>,
+    /// Folder of the workspace whose markdown files have anchors unique across it, as
+    /// docs sites joining them into one page need.
+    pub docs_folder: Option<PathBuf>,
+    /// Grades prose paragraphs should read at, the others getting hints.
+    pub reading_level: Option<ReadingLevel>,
+    /// Regex rules, checked by the `patterns` provider.
+    pub patterns: Vec<PatternRule>,
+}
+
+/// Flags the matches of `pattern` with `message`, offering `replacement` as a fix.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatternRule {
+    /// The code of its diagnostics, which `rules` can configure like a built-in's.
+    pub id: String,
+    pub pattern: String,
+    pub message: String,
+    #[serde(default)]
+    pub replacement: Option<String>,
+    #[serde(default = "PatternRule::default_level")]
+    pub level: RuleLevel,
+}
+
+impl PatternRule {
+    fn default_level() -> RuleLevel {
+        RuleLevel::Warning
+    }
+}
+
+/// A band of Flesch-Kincaid grades, like `{ "min": 6, "max": 10 }`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReadingLevel {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            prose_languages: [
+                "plaintext",
+                "markdown",
+                "latex",
+                "restructuredtext",
+                "asciidoc",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            capitalization_exceptions: ["e.g.", "i.e.", "etc.", "vs.", "cf.", "approx."]
+                .map(str::to_string)
+                .to_vec(),
+            line_length: HashMap::new(),
+            timeout: 1000,
+            providers: HashMap::new(),
+            docs_folder: None,
+            reading_level: None,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DiagnosticProviderConfig {
+    pub enabled: bool,
+    /// languageIds of the documents checked, by default the prose languages for the
+    /// built-in rules and every language for plugins.
+    pub languages: Option<Vec<String>>,
+    /// Overrides `diagnostics.timeout`.
+    pub timeout: Option<u64>,
+}
+
+impl Default for DiagnosticProviderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            languages: None,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IndexConfig {
+    pub storage: StorageKind,
+    /// Where the on-disk index lives, defaults to `index` in the workspace data directory.
+    pub path: Option<PathBuf>,
+    /// Index every text file in the workspace, not just the open documents.
+    pub workspace: bool,
+    /// Share the workspace index with other instances running on the same workspace.
+    pub shared: bool,
+    /// Index the workspace files as queries need them, as they're opened and while the
+    /// server is idle, rather than all of them on startup.
+    pub lazy_workspace: bool,
+    /// File name suffixes, like `txt` or `min.js`, indexed without checking for binary
+    /// or minified content.
+    pub allow_extensions: Vec<String>,
+    /// File name suffixes never indexed.
+    pub deny_extensions: Vec<String>,
+    /// How hard to work at indexing, can be changed with `workspace/didChangeConfiguration`.
+    pub profile: IndexProfile,
+    /// How many parts the index is split into by document, updated and queried in
+    /// parallel.
+    pub shards: usize,
+    /// Days for the frequency of a word no longer indexed in any document to decay to
+    /// half, `null` to never decay.
+    pub decay_half_life_days: Option<f64>,
+}
+
+impl IndexConfig {
+    /// Whether the workspace files are indexed, which builds without the `workspace`
+    /// feature never do.
+    pub fn indexes_workspace(&self) -> bool {
+        self.workspace && cfg!(feature = "workspace")
+    }
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            storage: StorageKind::default(),
+            path: None,
+            workspace: cfg!(feature = "workspace"),
+            shared: false,
+            lazy_workspace: false,
+            allow_extensions: Vec::new(),
+            deny_extensions: ["min.js", "min.css", "map"].map(str::to_string).to_vec(),
+            profile: IndexProfile::default(),
+            shards: std::thread::available_parallelism()
+                .map_or(1, NonZeroUsize::get)
+                .min(8),
+            decay_half_life_days: Some(90.0),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexProfile {
+    Eager,
+    #[default]
+    Balanced,
+    /// For running on battery.
+    Lazy,
+}
+
+impl IndexProfile {
+    /// Threads scanning the workspace.
+    pub fn parallelism(self) -> usize {
+        let cores = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        match self {
+            Self::Eager => cores,
+            Self::Balanced => (cores / 2).max(1),
+            Self::Lazy => 1,
+        }
+    }
+
+    /// How long edits have to settle before a document is reindexed and diagnosed.
+    pub fn debounce(self) -> Duration {
+        match self {
+            Self::Eager => Duration::ZERO,
+            Self::Balanced => Duration::from_millis(150),
+            Self::Lazy => Duration::from_secs(1),
+        }
+    }
+
+    /// Whether the files the client hasn't opened are diagnosed as they're scanned.
+    pub fn diagnose_unopened(self) -> bool {
+        self == Self::Eager
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    #[default]
+    Memory,
+    Disk,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(any(feature = "wasm", feature = "lua")), allow(dead_code))]
+pub struct PluginConfig {
+    pub kind: PluginKind,
+    pub path: PathBuf,
+    /// Instructions a plugin may execute per call.
+    #[serde(default = "PluginConfig::default_fuel")]
+    pub fuel: u64,
+    /// Bytes of memory a plugin may allocate.
+    #[serde(default = "PluginConfig::default_max_memory")]
+    pub max_memory: usize,
+}
+
+impl PluginConfig {
+    fn default_fuel() -> u64 {
+        100_000_000
+    }
+
+    fn default_max_memory() -> usize {
+        64 << 20
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginKind {
+    Wasm,
+    Lua,
+}
+
+/// An external model server speaking `proto/sidecar.proto`.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub struct SidecarConfig {
+    pub endpoint: String,
+    #[serde(default)]
+    pub deadlines: SidecarDeadlines,
+}
+
+/// Milliseconds each sidecar call may take.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub struct SidecarDeadlines {
+    pub complete: u64,
+    pub rank: u64,
+    pub diagnose: u64,
+    pub rewrite: u64,
+    pub explain: u64,
+}
+
+impl Default for SidecarDeadlines {
+    fn default() -> Self {
+        Self {
+            complete: 200,
+            rank: 100,
+            diagnose: 2000,
+            rewrite: 10000,
+            explain: 10000,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Whether the settings are explained as needing `feature`.
+    fn needs(settings: serde_json::Value, feature: &str) -> bool {
+        Config::from_initialization_options(Some(settings))
+            .unsupported()
+            .iter()
+            .any(|message| message.contains(&format!("the `{feature}` feature")))
+    }
+
+    #[test]
+    fn defaults_need_no_feature() {
+        assert!(Config::default().unsupported().is_empty());
+    }
+
+    #[test]
+    fn disk_storage() {
+        let settings = json!({ "index": { "storage": "disk" } });
+        assert_eq!(needs(settings, "sled"), !cfg!(feature = "sled"));
+    }
+
+    #[test]
+    fn plugins() {
+        let settings = json!({ "plugins": [{ "kind": "wasm", "path": "plugin.wasm" }] });
+        assert_eq!(needs(settings, "wasm"), !cfg!(feature = "wasm"));
+        let settings = json!({ "plugins": [{ "kind": "lua", "path": "plugin.lua" }] });
+        assert_eq!(needs(settings, "lua"), !cfg!(feature = "lua"));
+    }
+
+    #[test]
+    fn sidecar() {
+        let settings = json!({ "sidecar": { "endpoint": "http://localhost:50051" } });
+        assert_eq!(needs(settings, "grpc"), !cfg!(feature = "grpc"));
+    }
+
+    #[test]
+    fn workspace() {
+        let settings = json!({ "index": { "workspace": true } });
+        assert_eq!(needs(settings, "workspace"), !cfg!(feature = "workspace"));
+        let settings = json!({ "references": { "exclude": ["vendor/"] } });
+        assert_eq!(needs(settings, "workspace"), !cfg!(feature = "workspace"));
+    }
+
+    #[test]
+    fn diagnostic_patterns() {
+        let rule = json!({ "id": "todo", "pattern": "TODO", "message": "Left to do" });
+        let settings = json!({ "diagnostics": { "patterns": [rule] } });
+        assert_eq!(
+            needs(settings, "diagnostics"),
+            !cfg!(feature = "diagnostics")
+        );
+    }
+
+    #[test]
+    fn every_missing_feature_at_once() {
+        let settings = json!({
+            "index": { "storage": "disk" },
+            "plugins": [
+                { "kind": "wasm", "path": "plugin.wasm" },
+                { "kind": "lua", "path": "plugin.lua" }
+            ],
+            "sidecar": { "endpoint": "http://localhost:50051" }
+        });
+        let built = [
+            cfg!(feature = "sled"),
+            cfg!(feature = "wasm"),
+            cfg!(feature = "lua"),
+            cfg!(feature = "grpc"),
+        ];
+        let missing = built.iter().filter(|&&built| !built).count();
+        let config = Config::from_initialization_options(Some(settings));
+        assert_eq!(config.unsupported().len(), missing);
+    }
+}
@@ -0,0 +1,179 @@
+//! A conformance suite exercising what the LSP spec requires of every server against
+//! one on an in-memory connection, run by `test-lsp conformance` to catch the handlers
+//! that break it as they're added.
+
+use crate::harness::{completion_params, Client};
+use crate::server::Stop;
+use lsp_server::{ErrorCode, Response};
+use lsp_types::notification::{Cancel, Exit, Notification as _};
+use lsp_types::request::{
+    Completion, RegisterCapability, Request as _, Shutdown, WorkDoneProgressCreate,
+    WorkspaceConfiguration,
+};
+use lsp_types::{CancelParams, NumberOrString, Position, Url};
+use serde_json::Value;
+
+/// A check of the suite and why it failed, if it did.
+pub struct Outcome {
+    pub name: &'static str,
+    pub failure: Option<String>,
+}
+
+type Check = fn(&mut Client) -> Result<(), String>;
+
+const CHECKS: &[(&str, Check)] = &[
+    (
+        "requests before initialize are refused",
+        refused_before_initialize,
+    ),
+    (
+        "initialize answers with the capabilities",
+        initialize_capabilities,
+    ),
+    ("unknown requests get MethodNotFound", unknown_request),
+    ("unknown notifications are ignored", unknown_notification),
+    ("cancelled requests are still answered", cancelled_request),
+    (
+        "server requests need the client capabilities",
+        capability_gating,
+    ),
+    ("shutdown refuses requests until exit", shutdown_exit),
+];
+
+/// Runs every check against a server of its own.
+pub fn run() -> Vec<Outcome> {
+    CHECKS
+        .iter()
+        .map(|&(name, check)| {
+            let mut client = Client::start();
+            let failure = check(&mut client).err();
+            client.stop();
+            Outcome { name, failure }
+        })
+        .collect()
+}
+
+fn expect_error(response: &Response, code: ErrorCode) -> Result<(), String> {
+    match &response.error {
+        Some(error) if error.code == code as i32 => Ok(()),
+        Some(error) => Err(format!(
+            "expected error {}, got {}: {}",
+            code as i32, error.code, error.message
+        )),
+        None => Err(format!("expected error {}, got a result", code as i32)),
+    }
+}
+
+fn expect_result(response: &Response) -> Result<&Value, String> {
+    match (&response.result, &response.error) {
+        (_, Some(error)) => Err(format!("got error {}: {}", error.code, error.message)),
+        (Some(result), None) => Ok(result),
+        (None, None) => Ok(&Value::Null),
+    }
+}
+
+fn document() -> Url {
+    Url::parse("untitled:conformance").expect("a valid uri")
+}
+
+fn refused_before_initialize(client: &mut Client) -> Result<(), String> {
+    let response = client.request(Shutdown::METHOD, ())?;
+    expect_error(&response, ErrorCode::ServerNotInitialized)?;
+    expect_result(&client.initialize()?)?;
+    Ok(())
+}
+
+fn initialize_capabilities(client: &mut Client) -> Result<(), String> {
+    let response = client.initialize()?;
+    let result = expect_result(&response)?;
+    if !result.get("capabilities").is_some_and(Value::is_object) {
+        return Err(format!("expected capabilities, got {result}"));
+    }
+    Ok(())
+}
+
+fn unknown_request(client: &mut Client) -> Result<(), String> {
+    expect_result(&client.initialize()?)?;
+    let response = client.request("testLsp/noSuchMethod", ())?;
+    expect_error(&response, ErrorCode::MethodNotFound)
+}
+
+fn unknown_notification(client: &mut Client) -> Result<(), String> {
+    expect_result(&client.initialize()?)?;
+    client.notify("$/noSuchNotification", ())?;
+    client.notify("testLsp/noSuchNotification", ())?;
+    // Still serving, without having answered the notifications
+    let uri = document();
+    client.open(&uri, "conformance")?;
+    let response = client.request(
+        Completion::METHOD,
+        completion_params(&uri, Position::new(0, 4)),
+    )?;
+    expect_result(&response)?;
+    Ok(())
+}
+
+fn cancelled_request(client: &mut Client) -> Result<(), String> {
+    expect_result(&client.initialize()?)?;
+    let uri = document();
+    client.open(&uri, "conformance")?;
+    let id = client.send_request(
+        Completion::METHOD,
+        completion_params(&uri, Position::new(0, 4)),
+    )?;
+    let cancelled = NumberOrString::Number(client.last_id());
+    client.notify(Cancel::METHOD, CancelParams { id: cancelled })?;
+    let response = client.response(id)?;
+    match &response.error {
+        Some(error) if error.code != ErrorCode::RequestCanceled as i32 => Err(format!(
+            "expected a result or error {}, got {}: {}",
+            ErrorCode::RequestCanceled as i32,
+            error.code,
+            error.message
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn capability_gating(client: &mut Client) -> Result<(), String> {
+    expect_result(&client.initialize()?)?;
+    let uri = document();
+    client.open(&uri, "conformance")?;
+    let mut params = completion_params(&uri, Position::new(0, 4));
+    params.work_done_progress_params.work_done_token =
+        Some(NumberOrString::String("conformance".to_string()));
+    expect_result(&client.request(Completion::METHOD, params)?)?;
+    // Asked of a client that declared none of them
+    let ungated = [
+        WorkDoneProgressCreate::METHOD,
+        RegisterCapability::METHOD,
+        WorkspaceConfiguration::METHOD,
+    ];
+    match client
+        .server_requests()
+        .iter()
+        .find(|method| ungated.contains(&method.as_str()))
+    {
+        Some(method) => Err(format!("sent {method} to a client not supporting it")),
+        None => Ok(()),
+    }
+}
+
+fn shutdown_exit(client: &mut Client) -> Result<(), String> {
+    expect_result(&client.initialize()?)?;
+    let response = client.request(Shutdown::METHOD, ())?;
+    if !expect_result(&response)?.is_null() {
+        return Err("expected a null result to shutdown".to_string());
+    }
+    let uri = document();
+    let response = client.request(
+        Completion::METHOD,
+        completion_params(&uri, Position::new(0, 0)),
+    )?;
+    expect_error(&response, ErrorCode::InvalidRequest)?;
+    client.notify(Exit::METHOD, ())?;
+    match client.stopped()? {
+        Stop::Exit => Ok(()),
+        Stop::ClientGone => Err("stopped for the client being gone".to_string()),
+    }
+}
@@ -0,0 +1,71 @@
+//! A debug check that the server's copy of each open document, and what the index
+//! holds for it, still agree with the edits the client sent.
+
+use crate::document::Document;
+use crate::index::WordCounts;
+use itertools::Itertools;
+
+/// How many disagreeing words a report lists.
+const REPORTED_WORDS: usize = 10;
+
+/// The length and line count of a text, which the edits applied to a document imply
+/// without looking at the result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shape {
+    pub len: usize,
+    pub lines: usize,
+}
+
+impl Shape {
+    pub fn of(text: &str) -> Self {
+        Self {
+            len: text.len(),
+            lines: text.matches('\n').count() + 1,
+        }
+    }
+}
+
+/// How `document` drifted from its edits or from the index, if it did. `counted` are
+/// the word counts of its text.
+pub fn check(
+    document: &Document,
+    indexed: Option<&WordCounts>,
+    counted: &WordCounts,
+) -> Option<String> {
+    let mut report = Vec::new();
+    let actual = Shape::of(&document.text);
+    if actual != document.shape {
+        report.push(format!(
+            "edits up to version {} imply {} bytes in {} lines, but the text has {} bytes in {} lines",
+            document.version, document.shape.len, document.shape.lines, actual.len, actual.lines
+        ));
+    }
+    match indexed {
+        None => report.push("the index has no entry for it".to_string()),
+        Some(indexed) => {
+            let words = indexed
+                .keys()
+                .chain(counted.keys())
+                .unique()
+                .filter(|word| indexed.get(*word) != counted.get(*word))
+                .sorted()
+                .collect_vec();
+            if !words.is_empty() {
+                let listed = words
+                    .iter()
+                    .take(REPORTED_WORDS)
+                    .map(|word| {
+                        let indexed = indexed.get(*word).copied().unwrap_or_default();
+                        let counted = counted.get(*word).copied().unwrap_or_default();
+                        format!("`{word}` indexed {indexed} times, used {counted}")
+                    })
+                    .join(", ");
+                report.push(format!(
+                    "the index disagrees on {} words: {listed}",
+                    words.len()
+                ));
+            }
+        }
+    }
+    (!report.is_empty()).then(|| report.join("\n"))
+}
@@ -0,0 +1,98 @@
+use crate::config::ContextWindow;
+use crate::{is_word_char, wrap, Token};
+use logos::Logos;
+use std::ops::Range;
+use std::time::Instant;
+
+/// Bytes lexed between looks at the deadline.
+const LEX_SLICE: usize = 16 * 1024;
+
+/// The part of `text` around `offset` that `window` takes in.
+pub fn window(text: &str, offset: usize, window: ContextWindow) -> Range<usize> {
+    let line_start = |offset: usize| text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    match window {
+        ContextWindow::Line => line_start(offset)..offset,
+        ContextWindow::Lines(lines) => {
+            let mut start = line_start(offset);
+            for _ in 0..lines {
+                if start == 0 {
+                    break;
+                }
+                start = line_start(start - 1);
+            }
+            let mut end = offset;
+            for _ in 0..=lines {
+                match text[end..].find('\n') {
+                    Some(i) => end += i + 1,
+                    None => {
+                        end = text.len();
+                        break;
+                    }
+                }
+            }
+            start..end
+        }
+        ContextWindow::Paragraph => {
+            wrap::paragraph(text, offset).unwrap_or_else(|| line_start(offset)..offset)
+        }
+    }
+}
+
+/// `range`, but for what's further than `max` bytes from `offset`.
+pub fn clamp(text: &str, range: Range<usize>, offset: usize, max: usize) -> Range<usize> {
+    let mut start = range.start.max(offset.saturating_sub(max));
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    let mut end = range.end.min(offset.saturating_add(max));
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    start..end.max(start)
+}
+
+/// The words of `text` in `range`, lexed a slice at a time from its end back for the
+/// words nearest the cursor to be there when `deadline` passes first.
+pub fn words(text: &str, range: Range<usize>, deadline: Instant) -> Vec<&str> {
+    let mut slices = Vec::new();
+    let mut end = range.end;
+    while end > range.start {
+        let mut start = end.saturating_sub(LEX_SLICE).max(range.start);
+        while !text.is_char_boundary(start) {
+            start -= 1;
+        }
+        // Words are left whole to the slice before
+        if start > range.start {
+            if let Some(i) = text[start..end].find(|c| !is_word_char(c)) {
+                start += i;
+            }
+        }
+        slices.push(
+            Token::lexer(&text[start..end])
+                .filter_map(|token| match token {
+                    Ok(Token::Word(word)) => Some(word),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        );
+        end = start;
+        if Instant::now() >= deadline {
+            log::debug!("lexed {} of the window by the deadline", range.end - end);
+            break;
+        }
+    }
+    slices.into_iter().rev().flatten().collect()
+}
+
+/// The punctuation a word is used with: the symbol right before it, as in `foo.bar`,
+/// or else the symbol its line starts with, as in `# Heading words`.
+///
+/// `before` is the text of the line up to the word.
+pub fn symbol_context(before: &str, symbols: &[char]) -> Option<char> {
+    let is_context = |c: &char| symbols.contains(c);
+    before
+        .chars()
+        .next_back()
+        .filter(is_context)
+        .or_else(|| before.trim_start().chars().next().filter(is_context))
+}
@@ -0,0 +1,31 @@
+//! Where the server keeps what it persists about a workspace, like the on-disk index
+//! and the shared index snapshot, out of the workspace itself.
+
+use lsp_types::Url;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The directory of the workspace at `root`, named by a hash of its uri under `base`,
+/// by default `test-lsp/workspaces` in the platform data directory.
+pub fn workspace(base: Option<&Path>, root: &Url) -> Option<PathBuf> {
+    let base = match base {
+        Some(base) => base.to_path_buf(),
+        None => dirs::data_dir()?.join("test-lsp").join("workspaces"),
+    };
+    let hash = root
+        .as_str()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    Some(base.join(format!("{hash:016x}")))
+}
+
+/// Removes `dir` and everything in it, if it exists.
+pub fn clear(dir: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(dir) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
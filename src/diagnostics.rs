@@ -0,0 +1,139 @@
+use crate::config::RuleLevel;
+use crate::document::Document;
+use lsp_types::{Diagnostic, NumberOrString};
+#[cfg(feature = "diagnostics")]
+use lsp_types::{DiagnosticRelatedInformation, Location, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+mod anchors;
+#[cfg(feature = "diagnostics")]
+mod balance;
+#[cfg(feature = "diagnostics")]
+mod capitalization;
+#[cfg(feature = "diagnostics")]
+mod commit_message;
+#[cfg(feature = "diagnostics")]
+mod delimiters;
+#[cfg(feature = "diagnostics")]
+mod invisible;
+#[cfg(feature = "diagnostics")]
+mod line_length;
+mod links;
+#[cfg(feature = "diagnostics")]
+mod patterns;
+mod provider;
+#[cfg(feature = "diagnostics")]
+mod reading_level;
+#[cfg(feature = "diagnostics")]
+mod repetition;
+mod suppression;
+
+pub use anchors::check_across as check_anchors_across;
+#[cfg(feature = "diagnostics")]
+pub use line_length::RULE as LINE_TOO_LONG;
+pub use links::{check as check_links, links, RULE as BROKEN_LINK};
+pub use provider::Providers;
+#[cfg(feature = "diagnostics")]
+pub use reading_level::RULE as READING_LEVEL;
+
+/// `source` of the diagnostics produced by the built-in rules.
+pub const SOURCE: &str = "test-lsp";
+
+/// Code action kind applying every automatic fix of a document.
+pub const FIX_ALL: &str = "source.fixAll.testLsp";
+
+/// Opening and closing delimiters, besides the straight double quote.
+pub const PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('“', '”'), ('«', '»')];
+
+/// Ids and descriptions of the built-in rules.
+pub const RULES: &[(&str, &str)] = &[
+    #[cfg(feature = "diagnostics")]
+    (capitalization::RULE, capitalization::DESCRIPTION),
+    #[cfg(feature = "diagnostics")]
+    (repetition::RULE, repetition::DESCRIPTION),
+    #[cfg(feature = "diagnostics")]
+    (delimiters::RULE, delimiters::DESCRIPTION),
+    #[cfg(feature = "diagnostics")]
+    (invisible::RULE, invisible::DESCRIPTION),
+    #[cfg(feature = "diagnostics")]
+    (line_length::RULE, line_length::DESCRIPTION),
+    #[cfg(feature = "diagnostics")]
+    (commit_message::RULE, commit_message::DESCRIPTION),
+    #[cfg(feature = "diagnostics")]
+    (balance::RULE, balance::DESCRIPTION),
+    (links::RULE, links::DESCRIPTION),
+    (anchors::RULE, anchors::DESCRIPTION),
+    #[cfg(feature = "diagnostics")]
+    (reading_level::RULE, reading_level::DESCRIPTION),
+    (suppression::RULE, suppression::DESCRIPTION),
+];
+
+/// An automatic fix for a diagnostic, carried in its `data` field: replace `range`,
+/// or else the diagnostic's range, with `replacement`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fix {
+    pub title: String,
+    pub replacement: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<lsp_types::Range>,
+}
+
+impl Fix {
+    pub fn of(diagnostic: &Diagnostic) -> Option<Self> {
+        if diagnostic.source.as_deref() != Some(SOURCE) {
+            return None;
+        }
+        serde_json::from_value(diagnostic.data.clone()?).ok()
+    }
+}
+
+/// Applies the suppression directives of `document` to diagnostics from any source.
+pub fn suppress(document: &Document, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    suppression::apply(&document.text, diagnostics)
+}
+
+/// Applies the configured severity of each rule, dropping the rules turned off.
+pub fn configure(
+    diagnostics: Vec<Diagnostic>,
+    rules: &HashMap<String, RuleLevel>,
+) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .filter_map(|mut diagnostic| {
+            let Some(NumberOrString::String(code)) = &diagnostic.code else {
+                return Some(diagnostic);
+            };
+            if let Some(level) = rules.get(code) {
+                diagnostic.severity = Some(level.severity()?);
+            }
+            Some(diagnostic)
+        })
+        .collect()
+}
+
+fn diagnostic(
+    rule: &str,
+    range: lsp_types::Range,
+    severity: lsp_types::DiagnosticSeverity,
+    message: String,
+    fix: Option<Fix>,
+) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(rule.to_string())),
+        source: Some(SOURCE.to_string()),
+        message,
+        data: fix.map(|fix| serde_json::to_value(fix).unwrap()),
+        ..Default::default()
+    }
+}
+
+#[cfg(feature = "diagnostics")]
+fn related(uri: &Url, range: lsp_types::Range, message: String) -> DiagnosticRelatedInformation {
+    DiagnosticRelatedInformation {
+        location: Location::new(uri.clone(), range),
+        message,
+    }
+}
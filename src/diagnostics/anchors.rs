@@ -0,0 +1,97 @@
+#[cfg(feature = "diagnostics")]
+use super::related;
+use super::{diagnostic, Fix};
+use crate::line_index::LineIndex;
+use crate::markdown::{slug, Markdown};
+use lsp_types::{Diagnostic, DiagnosticSeverity, Url};
+use std::collections::HashSet;
+
+pub const RULE: &str = "duplicate-anchor";
+pub const DESCRIPTION: &str =
+    "Markdown headings have anchors of their own, in the document and across the docs folder";
+
+/// Flags the headings whose anchor an earlier heading of the document already has,
+/// which links to it silently skip.
+#[cfg(feature = "diagnostics")]
+pub fn check(uri: &Url, text: &str, markdown: &Markdown) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    let taken = taken(markdown, []);
+    let mut diagnostics = Vec::new();
+    for (i, heading) in markdown.headings.iter().enumerate() {
+        let title = &text[heading.title.clone()];
+        let base = slug(title);
+        let Some(first) = markdown.headings[..i]
+            .iter()
+            .find(|earlier| slug(&text[earlier.title.clone()]) == base)
+        else {
+            continue;
+        };
+        diagnostics.push(Diagnostic {
+            related_information: Some(vec![related(
+                uri,
+                line_index.range(first.range.clone()),
+                format!("`#{base}` first taken here"),
+            )]),
+            ..diagnostic(
+                RULE,
+                line_index.range(heading.range.clone()),
+                DiagnosticSeverity::WARNING,
+                format!(
+                    "`#{base}` is taken, links reach this heading as `#{}`",
+                    heading.anchor
+                ),
+                suffix(title, heading.title.end, &line_index, &taken),
+            )
+        });
+    }
+    diagnostics
+}
+
+/// Flags the headings whose anchor `elsewhere` finds in another file of the docs folder.
+pub fn check_across(
+    text: &str,
+    markdown: &Markdown,
+    elsewhere: impl Fn(&str) -> Option<(Url, HashSet<String>)>,
+) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    markdown
+        .headings
+        .iter()
+        .filter_map(|heading| {
+            let (other, anchors) = elsewhere(&heading.anchor)?;
+            let name = other.path_segments()?.next_back()?.to_string();
+            let title = &text[heading.title.clone()];
+            Some(diagnostic(
+                RULE,
+                line_index.range(heading.range.clone()),
+                DiagnosticSeverity::WARNING,
+                format!("`#{}` is also an anchor of `{name}`", heading.anchor),
+                suffix(
+                    title,
+                    heading.title.end,
+                    &line_index,
+                    &taken(markdown, anchors),
+                ),
+            ))
+        })
+        .collect()
+}
+
+fn taken(markdown: &Markdown, also: impl IntoIterator<Item = String>) -> HashSet<String> {
+    markdown
+        .headings
+        .iter()
+        .map(|heading| heading.anchor.clone())
+        .chain(also)
+        .collect()
+}
+
+/// Numbers the title `2` or more, whichever gives an anchor nothing has.
+fn suffix(title: &str, end: usize, line_index: &LineIndex, taken: &HashSet<String>) -> Option<Fix> {
+    let n = (2..).find(|n| !taken.contains(&slug(&format!("{title} {n}"))))?;
+    Some(Fix {
+        title: format!("Rename to `{title} {n}`"),
+        replacement: format!(" {n}"),
+        range: Some(line_index.range(end..end)),
+    })
+}
@@ -0,0 +1,96 @@
+use super::{diagnostic, related};
+use crate::line_index::LineIndex;
+use lsp_types::{Diagnostic, DiagnosticSeverity, Url};
+
+pub const RULE: &str = "unbalanced-bracket";
+pub const DESCRIPTION: &str = "Brackets and quotes of code and data files are closed";
+
+const BRACKETS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Quotes ending with their line, unlike backticks.
+const LINE_QUOTES: &[char] = &['"', '\''];
+
+/// Flags the brackets never closed or closed by the wrong bracket, and the quotes left
+/// open. A closing bracket matching one further out closes the ones in between, so a
+/// single missing bracket is flagged once.
+pub fn check(uri: &Url, text: &str) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    let mut diagnostics = Vec::new();
+    let mut open: Vec<(usize, char)> = Vec::new();
+    let never_closed = |offset: usize, c: char| {
+        diagnostic(
+            RULE,
+            line_index.range(offset..offset + c.len_utf8()),
+            DiagnosticSeverity::WARNING,
+            format!("`{c}` is never closed"),
+            None,
+        )
+    };
+    let mut quote: Option<(usize, char)> = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if let Some((start, q)) = quote {
+            match c {
+                '\\' => _ = chars.next(),
+                '\n' if q != '`' => {
+                    diagnostics.push(never_closed(start, q));
+                    quote = None;
+                }
+                c if c == q => quote = None,
+                _ => {}
+            }
+            continue;
+        }
+        if c == '`' || (LINE_QUOTES.contains(&c) && !is_apostrophe(text, i)) {
+            quote = Some((i, c));
+        } else if BRACKETS.iter().any(|&(opening, _)| opening == c) {
+            open.push((i, c));
+        } else if let Some(&(opening, _)) = BRACKETS.iter().find(|&&(_, closing)| closing == c) {
+            let range = line_index.range(i..i + c.len_utf8());
+            match open.iter().rposition(|&(_, top)| top == opening) {
+                Some(matched) if matched + 1 == open.len() => _ = open.pop(),
+                // Whatever was opened since was left open
+                Some(matched) => {
+                    for (offset, skipped) in open.drain(matched + 1..) {
+                        diagnostics.push(never_closed(offset, skipped));
+                    }
+                    open.pop();
+                }
+                None => match open.last() {
+                    Some(&(offset, top)) => diagnostics.push(Diagnostic {
+                        related_information: Some(vec![related(
+                            uri,
+                            line_index.range(offset..offset + top.len_utf8()),
+                            format!("`{top}` opened here"),
+                        )]),
+                        ..diagnostic(
+                            RULE,
+                            range,
+                            DiagnosticSeverity::WARNING,
+                            format!("`{c}` doesn't close `{top}`"),
+                            None,
+                        )
+                    }),
+                    None => diagnostics.push(diagnostic(
+                        RULE,
+                        range,
+                        DiagnosticSeverity::WARNING,
+                        format!("`{c}` is never opened"),
+                        None,
+                    )),
+                },
+            }
+        }
+    }
+    if let Some((start, q)) = quote {
+        diagnostics.push(never_closed(start, q));
+    }
+    diagnostics.extend(open.into_iter().map(|(offset, c)| never_closed(offset, c)));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.range.start);
+    diagnostics
+}
+
+/// Single quotes after a letter, as in `don't` and `users'`, which comments are full of.
+fn is_apostrophe(text: &str, offset: usize) -> bool {
+    text[..offset].ends_with(|c: char| c.is_alphanumeric()) && text[offset..].starts_with('\'')
+}
@@ -0,0 +1,56 @@
+use super::{diagnostic, Fix};
+use crate::config::DiagnosticsConfig;
+use crate::line_index::LineIndex;
+use crate::markdown::Markdown;
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+
+pub const RULE: &str = "sentence-capitalization";
+pub const DESCRIPTION: &str = "Sentences start with a capital letter";
+
+/// Flags sentences starting with a lowercase letter after `.`, `?` or `!`, outside of
+/// code.
+pub fn check(text: &str, markdown: &Markdown, config: &DiagnosticsConfig) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    let mut diagnostics = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if !matches!(c, '.' | '?' | '!') || markdown.in_code(i) {
+            continue;
+        }
+        // An ellipsis doesn't necessarily end the sentence
+        if c == '.' && text[..i].ends_with('.') {
+            continue;
+        }
+        let before = &text[..=i];
+        if config.capitalization_exceptions.iter().any(|exception| {
+            before.len() >= exception.len()
+                && before.is_char_boundary(before.len() - exception.len())
+                && before[before.len() - exception.len()..].eq_ignore_ascii_case(exception)
+        }) {
+            continue;
+        }
+        let mut spaced = false;
+        while chars.next_if(|(_, next)| next.is_whitespace()).is_some() {
+            spaced = true;
+        }
+        let Some(&(start, first)) = chars.peek() else {
+            break;
+        };
+        if !spaced || !first.is_lowercase() || markdown.in_code(start) {
+            continue;
+        }
+        let end = start + first.len_utf8();
+        diagnostics.push(diagnostic(
+            RULE,
+            line_index.range(start..end),
+            DiagnosticSeverity::HINT,
+            "Sentence should start with a capital letter".to_string(),
+            Some(Fix {
+                title: "Capitalize the sentence".to_string(),
+                replacement: first.to_uppercase().collect(),
+                range: None,
+            }),
+        ));
+    }
+    diagnostics
+}
@@ -0,0 +1,75 @@
+use super::diagnostic;
+use crate::commit::{self, BODY_WIDTH, SUBJECT_WIDTH};
+use crate::line_index::LineIndex;
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+
+pub const RULE: &str = "commit-message";
+pub const DESCRIPTION: &str =
+    "Commit messages have a short imperative subject, a blank line and a wrapped body";
+
+/// Checks the subject line and the body of a commit message.
+pub fn check(text: &str) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    let mut diagnostics = Vec::new();
+    let mut lines = commit::lines(text);
+    let Some((start, subject)) = lines.next() else {
+        return diagnostics;
+    };
+    if let Some((overflow, _)) = subject.char_indices().nth(SUBJECT_WIDTH) {
+        let columns = subject.chars().count();
+        diagnostics.push(diagnostic(
+            RULE,
+            line_index.range(start + overflow..start + subject.len()),
+            DiagnosticSeverity::WARNING,
+            format!("Subject is {columns} characters long, keep it to {SUBJECT_WIDTH}"),
+            None,
+        ));
+    }
+    let first_word = subject.split_whitespace().next().unwrap_or_default();
+    if !is_imperative(first_word) {
+        let offset = start + subject.find(first_word).unwrap_or_default();
+        diagnostics.push(diagnostic(
+            RULE,
+            line_index.range(offset..offset + first_word.len()),
+            DiagnosticSeverity::HINT,
+            format!("Write the subject in the imperative mood, not `{first_word}`"),
+            None,
+        ));
+    }
+    if let Some((start, line)) = lines.next().filter(|(_, line)| !line.trim().is_empty()) {
+        diagnostics.push(diagnostic(
+            RULE,
+            line_index.range(start..start + line.len()),
+            DiagnosticSeverity::WARNING,
+            "Separate the subject from the body with a blank line".to_string(),
+            None,
+        ));
+    }
+    let body = commit::body(text);
+    for (start, line) in commit::lines(text).filter(|(start, _)| body.contains(start)) {
+        // Long URLs and the like can't be wrapped
+        let Some((overflow, _)) = line.char_indices().nth(BODY_WIDTH) else {
+            continue;
+        };
+        if line.trim().contains(char::is_whitespace) {
+            diagnostics.push(diagnostic(
+                RULE,
+                line_index.range(start + overflow..start + line.len()),
+                DiagnosticSeverity::INFORMATION,
+                format!("Wrap the body at {BODY_WIDTH} characters"),
+                None,
+            ));
+        }
+    }
+    diagnostics
+}
+
+/// Whether `word` reads as an order, `Add` rather than `Added`, `Adding` or `Adds`.
+fn is_imperative(word: &str) -> bool {
+    let word = word.to_lowercase();
+    let word = word.trim_end_matches([':', '.', ',']);
+    let past = word.ends_with("ed") && !word.ends_with("eed");
+    let gerund = word.ends_with("ing") && word.len() > 5;
+    let third_person = word.ends_with('s') && !word.ends_with("ss") && !word.ends_with("us");
+    word.len() < 4 || !(past || gerund || third_person)
+}
@@ -0,0 +1,87 @@
+use super::{diagnostic, related, PAIRS};
+use crate::line_index::LineIndex;
+use crate::markdown::Markdown;
+use lsp_types::{Diagnostic, DiagnosticSeverity, Url};
+
+pub const RULE: &str = "unbalanced-delimiter";
+pub const DESCRIPTION: &str = "Brackets and double quotes are closed within their paragraph";
+
+/// Flags brackets and double quotes left open at the end of their paragraph, closed
+/// by the wrong delimiter or never opened.
+pub fn check(uri: &Url, text: &str, markdown: &Markdown) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    let mut diagnostics = Vec::new();
+    let mut open: Vec<(usize, char)> = Vec::new();
+    let unclosed = |open: &mut Vec<(usize, char)>, diagnostics: &mut Vec<Diagnostic>| {
+        for (offset, c) in open.drain(..) {
+            diagnostics.push(diagnostic(
+                RULE,
+                line_index.range(offset..offset + c.len_utf8()),
+                DiagnosticSeverity::WARNING,
+                format!("`{c}` is never closed"),
+                None,
+            ));
+        }
+    };
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\n' && chars.peek().is_some_and(|&(_, next)| next == '\n') {
+            unclosed(&mut open, &mut diagnostics);
+            continue;
+        }
+        if markdown.in_code(i) {
+            continue;
+        }
+        let range = line_index.range(i..i + c.len_utf8());
+        if c == '"' {
+            match open.last() {
+                Some(&(_, '"')) => _ = open.pop(),
+                _ => open.push((i, c)),
+            }
+        } else if PAIRS.iter().any(|&(opening, _)| opening == c) {
+            open.push((i, c));
+        } else if let Some(&(opening, _)) = PAIRS.iter().find(|&&(_, closing)| closing == c) {
+            if open.last().map(|&(_, top)| top) != Some(opening) && is_marker(text, i) {
+                continue;
+            }
+            match open.pop() {
+                Some((_, top)) if top == opening => {}
+                Some((offset, top)) => diagnostics.push(Diagnostic {
+                    related_information: Some(vec![related(
+                        uri,
+                        line_index.range(offset..offset + top.len_utf8()),
+                        format!("`{top}` opened here"),
+                    )]),
+                    ..diagnostic(
+                        RULE,
+                        range,
+                        DiagnosticSeverity::WARNING,
+                        format!("`{c}` closes `{top}`"),
+                        None,
+                    )
+                }),
+                None => diagnostics.push(diagnostic(
+                    RULE,
+                    range,
+                    DiagnosticSeverity::WARNING,
+                    format!("`{c}` is never opened"),
+                    None,
+                )),
+            }
+        }
+    }
+    unclosed(&mut open, &mut diagnostics);
+    diagnostics
+}
+
+/// Closers used on their own: emoticons like `:)` and list markers like `1)`.
+fn is_marker(text: &str, offset: usize) -> bool {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let before = &text[line_start..offset];
+    before.ends_with([':', ';'])
+        || (!before.trim_start().is_empty()
+            && before
+                .trim_start()
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric()))
+}
@@ -0,0 +1,47 @@
+use super::{diagnostic, Fix};
+use crate::line_index::LineIndex;
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+
+pub const RULE: &str = "invisible-character";
+pub const DESCRIPTION: &str = "No zero width or bidirectional control characters hide in the text";
+
+/// Zero width joiners are left out, emoji sequences and some scripts need them.
+const INVISIBLE: &[(char, &str)] = &[
+    ('\u{200B}', "zero width space"),
+    ('\u{2060}', "word joiner"),
+    ('\u{FEFF}', "zero width no-break space"),
+    ('\u{200E}', "left-to-right mark"),
+    ('\u{200F}', "right-to-left mark"),
+    ('\u{202A}', "left-to-right embedding"),
+    ('\u{202B}', "right-to-left embedding"),
+    ('\u{202C}', "pop directional formatting"),
+    ('\u{202D}', "left-to-right override"),
+    ('\u{202E}', "right-to-left override"),
+    ('\u{2066}', "left-to-right isolate"),
+    ('\u{2067}', "right-to-left isolate"),
+    ('\u{2068}', "first strong isolate"),
+    ('\u{2069}', "pop directional isolate"),
+];
+
+/// Flags the characters that can't be seen but change how text is read or parsed.
+pub fn check(text: &str) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    text.char_indices()
+        // A leading U+FEFF is a byte order mark
+        .filter(|&(i, c)| !(i == 0 && c == '\u{FEFF}'))
+        .filter_map(|(i, c)| {
+            let (_, name) = INVISIBLE.iter().find(|(invisible, _)| *invisible == c)?;
+            Some(diagnostic(
+                RULE,
+                line_index.range(i..i + c.len_utf8()),
+                DiagnosticSeverity::WARNING,
+                format!("Invisible {name} (U+{:04X})", c as u32),
+                Some(Fix {
+                    title: format!("Remove the {name}"),
+                    replacement: String::new(),
+                    range: None,
+                }),
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,28 @@
+use super::diagnostic;
+use crate::line_index::LineIndex;
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+
+pub const RULE: &str = "line-too-long";
+pub const DESCRIPTION: &str = "Lines fit the column budget of their language";
+
+/// Flags the part of each line past `max` characters.
+pub fn check(text: &str, max: usize) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    let mut diagnostics = Vec::new();
+    let mut start = 0;
+    for line in text.split('\n') {
+        let content = line.trim_end_matches('\r');
+        if let Some((overflow, _)) = content.char_indices().nth(max) {
+            let columns = content.chars().count();
+            diagnostics.push(diagnostic(
+                RULE,
+                line_index.range(start + overflow..start + content.len()),
+                DiagnosticSeverity::INFORMATION,
+                format!("Line is {columns} characters long, more than {max}"),
+                None,
+            ));
+        }
+        start += line.len() + 1;
+    }
+    diagnostics
+}
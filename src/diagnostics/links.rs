@@ -0,0 +1,129 @@
+use super::{diagnostic, Fix};
+use crate::line_index::LineIndex;
+use crate::markdown::Markdown;
+use lsp_types::{Diagnostic, DiagnosticSeverity, Url};
+use std::ops::Range;
+
+pub const RULE: &str = "broken-link";
+pub const DESCRIPTION: &str =
+    "Relative links and images of markdown files lead to files and headings that exist";
+
+/// A relative link, `[text](path#anchor)`, `![alt](path)` or `[label]: path#anchor`.
+pub struct Link {
+    pub range: Range<usize>,
+    pub target: Url,
+    pub anchor: Option<String>,
+}
+
+/// The relative links of a markdown document outside of code, resolved against `uri`.
+pub fn links(uri: &Url, text: &str) -> Vec<Link> {
+    let markdown = Markdown::parse(text);
+    let inline = text.match_indices("](").filter_map(|(i, _)| {
+        let start = i + 2;
+        let rest = &text[start..];
+        let len = rest.find([')', ' ', '\n']).unwrap_or(rest.len());
+        Some(start..start + len).filter(|range| !markdown.in_code(range.start))
+    });
+    let definitions = markdown
+        .link_definitions
+        .iter()
+        .map(|definition| definition.destination.clone());
+    inline
+        .chain(definitions)
+        .filter_map(|range| {
+            let destination = &text[range.clone()];
+            // Other schemes, absolute paths and the document's own anchors
+            let relative = !destination.is_empty()
+                && !destination.contains("://")
+                && !destination.starts_with(['/', '#'])
+                && !destination.starts_with("mailto:");
+            if !relative {
+                return None;
+            }
+            let (path, anchor) = match destination.split_once('#') {
+                Some((path, anchor)) => (path, Some(anchor.to_string())),
+                None => (destination, None),
+            };
+            Some(Link {
+                range,
+                target: uri.join(path).ok()?,
+                anchor,
+            })
+        })
+        .collect()
+}
+
+/// Flags the links of `text` to files `read` doesn't find, or to headings their
+/// markdown files don't have. Links off by case or by an extension get fixed to the
+/// file of the names `list` gives for the directory of a link.
+pub fn check(
+    uri: &Url,
+    text: &str,
+    read: impl Fn(&Url) -> Option<String>,
+    list: impl Fn(&Url) -> Vec<String>,
+) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    links(uri, text)
+        .into_iter()
+        .filter_map(|link| {
+            let name = &text[link.range.clone()];
+            let mut fix = None;
+            let message = match (read(&link.target), &link.anchor) {
+                (None, _) => {
+                    fix = near_miss(name, &link.target, &list);
+                    format!("`{name}` doesn't exist")
+                }
+                (Some(target), Some(anchor))
+                    if link.target.path().ends_with(".md")
+                        && Markdown::parse(&target).heading(anchor).is_none() =>
+                {
+                    format!("`{name}` has no heading `#{anchor}`")
+                }
+                _ => return None,
+            };
+            Some(diagnostic(
+                RULE,
+                line_index.range(link.range),
+                DiagnosticSeverity::WARNING,
+                message,
+                fix,
+            ))
+        })
+        .collect()
+}
+
+/// The destination `name` with its file name replaced by the one of its directory
+/// differing only in case or in having an extension.
+fn near_miss(name: &str, target: &Url, list: impl Fn(&Url) -> Vec<String>) -> Option<Fix> {
+    let (path, anchor) = match name.split_once('#') {
+        Some((path, anchor)) => (path, Some(anchor)),
+        None => (name, None),
+    };
+    let (directory, file) = match path.trim_end_matches('/').rsplit_once('/') {
+        Some((directory, file)) => (Some(directory), file),
+        None => (None, path.trim_end_matches('/')),
+    };
+    let names = list(&target.join(".").ok()?);
+    let found = names
+        .iter()
+        .find(|candidate| candidate.eq_ignore_ascii_case(file))
+        .or_else(|| {
+            names.iter().find(|candidate| {
+                candidate
+                    .rsplit_once('.')
+                    .is_some_and(|(stem, _)| stem.eq_ignore_ascii_case(file))
+            })
+        })?;
+    let mut replacement = match directory {
+        Some(directory) => format!("{directory}/{found}"),
+        None => found.clone(),
+    };
+    if let Some(anchor) = anchor {
+        replacement = format!("{replacement}#{anchor}");
+    }
+    Some(Fix {
+        title: format!("Change to `{replacement}`"),
+        replacement,
+        range: None,
+    })
+}
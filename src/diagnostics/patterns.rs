@@ -0,0 +1,61 @@
+use super::{diagnostic, Fix};
+use crate::config::PatternRule;
+use crate::line_index::LineIndex;
+use crate::markdown::Markdown;
+use lsp_types::Diagnostic;
+use regex::Regex;
+
+/// What `diagnostics.providers` knows the regex rules as.
+pub const PROVIDER: &str = "patterns";
+
+/// A rule of `diagnostics.patterns` with its regex compiled.
+pub struct Compiled {
+    rule: PatternRule,
+    regex: Regex,
+}
+
+/// Compiles `rules`, logging and skipping those with an invalid pattern.
+pub fn compile(rules: &[PatternRule]) -> Vec<Compiled> {
+    rules
+        .iter()
+        .filter_map(|rule| match Regex::new(&rule.pattern) {
+            Ok(regex) => Some(Compiled {
+                rule: rule.clone(),
+                regex,
+            }),
+            Err(err) => {
+                log::error!("invalid pattern of rule {}: {err}", rule.id);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Flags the matches of every rule outside of code.
+pub fn check(text: &str, markdown: &Markdown, rules: &[Compiled]) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    let mut diagnostics = Vec::new();
+    for Compiled { rule, regex } in rules {
+        let Some(severity) = rule.level.severity() else {
+            continue;
+        };
+        for found in regex.find_iter(text) {
+            if found.is_empty() || markdown.in_code(found.start()) {
+                continue;
+            }
+            let fix = rule.replacement.as_ref().map(|replacement| Fix {
+                title: format!("Replace with `{replacement}`"),
+                replacement: replacement.clone(),
+                range: None,
+            });
+            diagnostics.push(diagnostic(
+                &rule.id,
+                line_index.range(found.range()),
+                severity,
+                rule.message.clone(),
+                fix,
+            ));
+        }
+    }
+    diagnostics
+}
@@ -0,0 +1,311 @@
+#[cfg(feature = "diagnostics")]
+use super::{
+    anchors, balance, capitalization, commit_message, delimiters, invisible, line_length, patterns,
+    reading_level, repetition,
+};
+use crate::config::{DiagnosticProviderConfig, DiagnosticsConfig};
+use crate::document::Document;
+use crate::error::ServerError;
+#[cfg(feature = "diagnostics")]
+use crate::markdown::Markdown;
+use crate::plugin::{PluginResult, Worker};
+#[cfg(feature = "diagnostics")]
+use crate::{commit, log_file};
+use lsp_types::{Diagnostic, Url};
+#[cfg(feature = "diagnostics")]
+use std::cell::OnceCell;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+type Result<T> = std::result::Result<T, ServerError>;
+
+/// The document being checked, and what providers may look at to check it.
+pub struct Subject<'a> {
+    #[cfg(feature = "diagnostics")]
+    pub uri: &'a Url,
+    pub document: &'a Document,
+    #[cfg(feature = "diagnostics")]
+    pub config: &'a DiagnosticsConfig,
+    #[cfg(feature = "diagnostics")]
+    markdown: OnceCell<Markdown>,
+}
+
+#[cfg(feature = "diagnostics")]
+impl Subject<'_> {
+    /// Where the code spans are, which prose rules skip.
+    pub fn markdown(&self) -> &Markdown {
+        self.markdown
+            .get_or_init(|| Markdown::parse(&self.document.text))
+    }
+}
+
+pub enum Diagnosis {
+    #[cfg(feature = "diagnostics")]
+    Ready(Vec<Diagnostic>),
+    /// Still being checked on another thread.
+    Pending(Receiver<PluginResult<Vec<Diagnostic>>>),
+}
+
+pub trait DiagnosticProvider: Send + Sync {
+    /// What `diagnostics.providers` knows it as.
+    fn name(&self) -> &str;
+
+    /// Whether it only checks `diagnostics.prose_languages` unless configured with
+    /// languages of its own.
+    fn prose(&self) -> bool;
+
+    /// Starts checking `subject`, which slow providers do off the message loop.
+    fn diagnose(&self, subject: &Subject) -> Result<Diagnosis>;
+}
+
+/// The built-in rules and plugins, which check a document concurrently.
+pub struct Providers {
+    providers: Vec<Box<dyn DiagnosticProvider>>,
+    config: DiagnosticsConfig,
+}
+
+impl Providers {
+    pub fn new(plugins: &[Worker], config: DiagnosticsConfig) -> Self {
+        #[cfg(feature = "diagnostics")]
+        let mut providers = Self::built_in(&config);
+        #[cfg(not(feature = "diagnostics"))]
+        let mut providers: Vec<Box<dyn DiagnosticProvider>> = Vec::new();
+        for plugin in plugins {
+            providers.push(Box::new(PluginProvider(plugin.clone())));
+        }
+        for name in config.providers.keys() {
+            if !providers.iter().any(|provider| provider.name() == name) {
+                log::warn!("no diagnostic provider is called `{name}`");
+            }
+        }
+        Self { providers, config }
+    }
+
+    /// The rules of test-lsp itself, and the `patterns` of `config`.
+    #[cfg(feature = "diagnostics")]
+    fn built_in(config: &DiagnosticsConfig) -> Vec<Box<dyn DiagnosticProvider>> {
+        let mut providers: Vec<Box<dyn DiagnosticProvider>> = vec![
+            Box::new(Rule {
+                id: capitalization::RULE,
+                prose: true,
+                check: |subject| {
+                    let text = &subject.document.text;
+                    capitalization::check(text, subject.markdown(), subject.config)
+                },
+            }),
+            Box::new(Rule {
+                id: repetition::RULE,
+                prose: true,
+                check: |subject| {
+                    repetition::check(subject.uri, &subject.document.text, subject.markdown())
+                },
+            }),
+            Box::new(Rule {
+                id: delimiters::RULE,
+                prose: true,
+                check: |subject| {
+                    delimiters::check(subject.uri, &subject.document.text, subject.markdown())
+                },
+            }),
+            // Worst hidden in code, so every language is checked
+            Box::new(Rule {
+                id: invisible::RULE,
+                prose: false,
+                check: |subject| invisible::check(&subject.document.text),
+            }),
+            Box::new(Rule {
+                id: line_length::RULE,
+                prose: false,
+                check: |subject| {
+                    let budget = subject.config.line_length.get(subject.document.language());
+                    budget.map_or_else(Vec::new, |&max| {
+                        line_length::check(&subject.document.text, max)
+                    })
+                },
+            }),
+            // Prose has `unbalanced-delimiter` instead, which knows about paragraphs
+            Box::new(Rule {
+                id: balance::RULE,
+                prose: false,
+                check: |subject| {
+                    let language = subject.document.language();
+                    let prose = &subject.config.prose_languages;
+                    // Free-form too
+                    let skipped = [commit::LANGUAGE, log_file::LANGUAGE].contains(&language)
+                        || prose.iter().any(|prose| prose == language);
+                    match skipped {
+                        true => Vec::new(),
+                        false => balance::check(subject.uri, &subject.document.text),
+                    }
+                },
+            }),
+            Box::new(Rule {
+                id: reading_level::RULE,
+                prose: true,
+                check: |subject| match &subject.config.reading_level {
+                    Some(target) => {
+                        reading_level::check(&subject.document.text, subject.markdown(), target)
+                    }
+                    None => Vec::new(),
+                },
+            }),
+            Box::new(Rule {
+                id: anchors::RULE,
+                prose: false,
+                check: |subject| match subject.document.language() {
+                    "markdown" => {
+                        anchors::check(subject.uri, &subject.document.text, subject.markdown())
+                    }
+                    _ => Vec::new(),
+                },
+            }),
+            Box::new(Rule {
+                id: commit_message::RULE,
+                prose: false,
+                check: |subject| match subject.document.language() {
+                    commit::LANGUAGE => commit_message::check(&subject.document.text),
+                    _ => Vec::new(),
+                },
+            }),
+        ];
+        if !config.patterns.is_empty() {
+            let compiled = patterns::compile(&config.patterns);
+            providers.push(Box::new(PatternProvider(compiled)));
+        }
+        providers
+    }
+
+    /// Runs the providers enabled for the language of `document`.
+    pub fn check(&self, uri: &Url, document: &Document) -> Vec<Diagnostic> {
+        let subject = Subject {
+            #[cfg(feature = "diagnostics")]
+            uri,
+            document,
+            #[cfg(feature = "diagnostics")]
+            config: &self.config,
+            #[cfg(feature = "diagnostics")]
+            markdown: OnceCell::new(),
+        };
+        let started = Instant::now();
+        // Every provider starts before any is waited on, so the slow ones work meanwhile
+        let mut asked = Vec::new();
+        for provider in &self.providers {
+            let config = self.provider_config(provider.name());
+            if !self.checks(provider.as_ref(), &config, document.language()) {
+                continue;
+            }
+            let timeout = config.timeout.unwrap_or(self.config.timeout);
+            let due = started + Duration::from_millis(timeout);
+            asked.push((provider, due, provider.diagnose(&subject)));
+        }
+
+        let mut diagnostics = Vec::new();
+        for (provider, due, diagnosis) in asked {
+            let name = provider.name();
+            match diagnosis {
+                #[cfg(feature = "diagnostics")]
+                Ok(Diagnosis::Ready(found)) => diagnostics.extend(found),
+                Ok(Diagnosis::Pending(pending)) => {
+                    match pending.recv_timeout(due.saturating_duration_since(Instant::now())) {
+                        Ok(Ok(found)) => diagnostics.extend(found),
+                        Ok(Err(source)) => {
+                            let name = name.to_string();
+                            let err = ServerError::Plugin { name, source };
+                            log::error!("failed to diagnose: {err}");
+                        }
+                        Err(RecvTimeoutError::Timeout) => {
+                            log::warn!("{name} took too long to diagnose {uri}")
+                        }
+                        Err(RecvTimeoutError::Disconnected) => log::error!("{name} stopped"),
+                    }
+                }
+                Err(err) => log::error!("failed to diagnose: {err}"),
+            }
+        }
+        diagnostics
+    }
+
+    fn provider_config(&self, name: &str) -> DiagnosticProviderConfig {
+        self.config.providers.get(name).cloned().unwrap_or_default()
+    }
+
+    fn checks(
+        &self,
+        provider: &dyn DiagnosticProvider,
+        config: &DiagnosticProviderConfig,
+        language: &str,
+    ) -> bool {
+        let languages = match &config.languages {
+            Some(languages) => languages,
+            None if provider.prose() => &self.config.prose_languages,
+            None => return config.enabled,
+        };
+        config.enabled && languages.iter().any(|checked| checked == language)
+    }
+}
+
+/// A built-in rule.
+#[cfg(feature = "diagnostics")]
+struct Rule {
+    id: &'static str,
+    prose: bool,
+    check: fn(&Subject) -> Vec<Diagnostic>,
+}
+
+#[cfg(feature = "diagnostics")]
+impl DiagnosticProvider for Rule {
+    fn name(&self) -> &str {
+        self.id
+    }
+
+    fn prose(&self) -> bool {
+        self.prose
+    }
+
+    fn diagnose(&self, subject: &Subject) -> Result<Diagnosis> {
+        Ok(Diagnosis::Ready((self.check)(subject)))
+    }
+}
+
+/// The regex rules of `diagnostics.patterns`, like those of rule packs.
+#[cfg(feature = "diagnostics")]
+struct PatternProvider(Vec<patterns::Compiled>);
+
+#[cfg(feature = "diagnostics")]
+impl DiagnosticProvider for PatternProvider {
+    fn name(&self) -> &str {
+        patterns::PROVIDER
+    }
+
+    fn prose(&self) -> bool {
+        true
+    }
+
+    fn diagnose(&self, subject: &Subject) -> Result<Diagnosis> {
+        let text = &subject.document.text;
+        Ok(Diagnosis::Ready(patterns::check(
+            text,
+            subject.markdown(),
+            &self.0,
+        )))
+    }
+}
+
+struct PluginProvider(Worker);
+
+impl DiagnosticProvider for PluginProvider {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn prose(&self) -> bool {
+        false
+    }
+
+    fn diagnose(&self, subject: &Subject) -> Result<Diagnosis> {
+        let text = subject.document.text.clone();
+        Ok(Diagnosis::Pending(
+            self.0.call(move |plugin| plugin.diagnose(&text)),
+        ))
+    }
+}
@@ -0,0 +1,34 @@
+use super::diagnostic;
+use crate::config::ReadingLevel;
+use crate::line_index::LineIndex;
+use crate::markdown::Markdown;
+use crate::readability;
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+
+pub const RULE: &str = "reading-level";
+pub const DESCRIPTION: &str = "Prose paragraphs read at the grade of `diagnostics.reading_level`";
+
+/// Hints at the paragraphs harder or easier to read than `target`.
+pub fn check(text: &str, markdown: &Markdown, target: &ReadingLevel) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    readability::paragraphs(text, markdown)
+        .into_iter()
+        .filter_map(|(paragraph, grade)| {
+            let side = match grade {
+                grade if grade > target.max => "above",
+                grade if grade < target.min => "below",
+                _ => return None,
+            };
+            Some(diagnostic(
+                RULE,
+                line_index.range(paragraph),
+                DiagnosticSeverity::HINT,
+                format!(
+                    "Reads at grade {grade:.1}, {side} the target of {}-{}",
+                    target.min, target.max
+                ),
+                None,
+            ))
+        })
+        .collect()
+}
@@ -0,0 +1,58 @@
+use super::{diagnostic, related, Fix};
+use crate::line_index::LineIndex;
+use crate::markdown::Markdown;
+use lsp_types::{Diagnostic, DiagnosticSeverity, Url};
+
+pub const RULE: &str = "repeated-word";
+pub const DESCRIPTION: &str = "A word isn't repeated by mistake, as in \"the the\"";
+
+/// Flags a word repeated right after itself, pointing back at the first occurrence.
+pub fn check(uri: &Url, text: &str, markdown: &Markdown) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(text);
+    let mut diagnostics = Vec::new();
+    let mut previous: Option<std::ops::Range<usize>> = None;
+    let mut i = 0;
+    while let Some(found) = text[i..].find(char::is_alphabetic) {
+        let start = i + found;
+        let end = text[start..]
+            .find(|c: char| !c.is_alphanumeric() && c != '\'')
+            .map_or(text.len(), |len| start + len);
+        i = end;
+        if markdown.in_code(start) {
+            previous = None;
+            continue;
+        }
+        let word = start..end;
+        let repeated = previous.take().filter(|first| {
+            let between = &text[first.end..start];
+            // Only whitespace in between, and no paragraph break
+            between.chars().all(char::is_whitespace)
+                && between.matches('\n').count() < 2
+                && text[first.clone()].to_lowercase() == text[start..end].to_lowercase()
+        });
+        if let Some(first) = repeated {
+            let word_text = &text[start..end];
+            diagnostics.push(Diagnostic {
+                related_information: Some(vec![related(
+                    uri,
+                    line_index.range(first.clone()),
+                    format!("First `{}`", &text[first.clone()]),
+                )]),
+                ..diagnostic(
+                    RULE,
+                    line_index.range(word.clone()),
+                    DiagnosticSeverity::WARNING,
+                    format!("`{word_text}` is repeated"),
+                    Some(Fix {
+                        title: format!("Remove the repeated `{word_text}`"),
+                        replacement: String::new(),
+                        range: Some(line_index.range(first.end..end)),
+                    }),
+                )
+            });
+            continue;
+        }
+        previous = Some(word);
+    }
+    diagnostics
+}
@@ -0,0 +1,124 @@
+use super::diagnostic;
+use crate::line_index::LineIndex;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+pub const RULE: &str = "unused-suppression";
+pub const DESCRIPTION: &str = "Suppression directives match some diagnostic";
+
+const PREFIX: &str = "test-lsp:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    NextLine,
+    File,
+}
+
+/// A `test-lsp:disable-next-line <rule>...` or `test-lsp:disable-file <rule>...`
+/// marker, in whatever comment syntax the document uses. Without rules it
+/// suppresses every diagnostic.
+#[derive(Debug)]
+struct Directive {
+    scope: Scope,
+    line: u32,
+    span: std::ops::Range<usize>,
+    rules: Vec<String>,
+    used: bool,
+}
+
+impl Directive {
+    fn matches(&self, diagnostic: &Diagnostic) -> bool {
+        if self.scope == Scope::NextLine && diagnostic.range.start.line != self.line + 1 {
+            return false;
+        }
+        self.rules.is_empty()
+            || matches!(&diagnostic.code, Some(NumberOrString::String(code)) if self.rules.contains(code))
+    }
+}
+
+/// Drops the diagnostics matched by a directive in `text`, and reports the directives
+/// that matched none.
+pub fn apply(text: &str, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    let mut directives = parse(text);
+    if directives.is_empty() {
+        return diagnostics;
+    }
+    let mut kept: Vec<Diagnostic> = diagnostics
+        .into_iter()
+        .filter(|diagnostic| {
+            let mut suppressed = false;
+            for directive in directives.iter_mut().filter(|d| d.matches(diagnostic)) {
+                directive.used = true;
+                suppressed = true;
+            }
+            !suppressed
+        })
+        .collect();
+    let line_index = LineIndex::new(text);
+    kept.extend(directives.into_iter().filter(|d| !d.used).map(|directive| {
+        let target = match directive.rules.as_slice() {
+            [] => "diagnostics".to_string(),
+            rules => rules
+                .iter()
+                .map(|rule| format!("`{rule}`"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        diagnostic(
+            RULE,
+            line_index.range(directive.span),
+            DiagnosticSeverity::INFORMATION,
+            format!("Suppression of {target} matches no diagnostic"),
+            None,
+        )
+    }));
+    kept
+}
+
+fn parse(text: &str) -> Vec<Directive> {
+    let mut directives = Vec::new();
+    let mut offset = 0;
+    for (line, content) in text.split_inclusive('\n').enumerate() {
+        let line_start = offset;
+        offset += content.len();
+        let Some(found) = content.find(PREFIX) else {
+            continue;
+        };
+        let rest = &content[found + PREFIX.len()..];
+        let (scope, keyword) = if rest.starts_with("disable-next-line") {
+            (Scope::NextLine, "disable-next-line")
+        } else if rest.starts_with("disable-file") {
+            (Scope::File, "disable-file")
+        } else {
+            continue;
+        };
+        let start = line_start + found;
+        let mut end = start + PREFIX.len() + keyword.len();
+        let mut rules = Vec::new();
+        // Rules run until whatever closes the comment, like `-->` or `*/`
+        loop {
+            let rest = &text[end..line_start + content.len()];
+            let word_start = rest.len() - rest.trim_start_matches([' ', '\t', ',']).len();
+            let word = rest[word_start..]
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .next()
+                .unwrap_or_default();
+            let is_rule = word.starts_with(|c: char| c.is_ascii_alphanumeric())
+                && word
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !is_rule {
+                break;
+            }
+            rules.push(word.to_string());
+            end += word_start + word.len();
+        }
+        directives.push(Directive {
+            scope,
+            line: line as u32,
+            span: start..end,
+            rules,
+            used: false,
+        });
+    }
+    directives
+}
@@ -0,0 +1,60 @@
+//! Completions the user keeps passing over, demoted for the rest of the session.
+
+use lsp_types::{Position, Url};
+use std::collections::HashMap;
+
+/// Times a suggestion is dismissed in one context before it's demoted.
+const DEMOTE_AFTER: u32 = 2;
+
+#[derive(Debug, Default)]
+pub struct Dismissals {
+    /// By the word before the completion, then the suggestion.
+    counts: HashMap<(String, String), u32>,
+    /// The top suggestion last served, to tell whether the user passed it over.
+    shown: Option<Shown>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Shown {
+    pub uri: Url,
+    pub word_start: Position,
+    /// The word before the completion, lowercased, empty when there's none.
+    pub context: String,
+    pub top: String,
+}
+
+impl Dismissals {
+    pub fn dismissed(&mut self, context: &str, word: &str) {
+        *self
+            .counts
+            .entry((context.to_string(), word.to_string()))
+            .or_default() += 1;
+    }
+
+    pub fn is_demoted(&self, context: &str, word: &str) -> bool {
+        self.counts
+            .get(&(context.to_string(), word.to_string()))
+            .is_some_and(|count| *count >= DEMOTE_AFTER)
+    }
+
+    /// Remembers the top suggestion of a completion. When it's served somewhere else
+    /// than the last one, that one counts as dismissed unless `typed_at` finds it was
+    /// the word written where it was shown.
+    pub fn served(
+        &mut self,
+        shown: Option<Shown>,
+        typed_at: impl Fn(&Url, Position) -> Option<String>,
+    ) {
+        let moved_on = |last: &Shown| {
+            shown
+                .as_ref()
+                .is_none_or(|shown| (&shown.uri, shown.word_start) != (&last.uri, last.word_start))
+        };
+        if let Some(last) = self.shown.take().filter(moved_on) {
+            if typed_at(&last.uri, last.word_start).is_some_and(|typed| typed != last.top) {
+                self.dismissed(&last.context, &last.top);
+            }
+        }
+        self.shown = shown;
+    }
+}
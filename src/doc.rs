@@ -0,0 +1,107 @@
+use lsp_types::{Documentation, MarkupContent, MarkupKind};
+use std::borrow::Cow;
+
+/// Renders documentation, written in markdown, in the format a client prefers.
+#[derive(Debug, Clone, Copy)]
+pub struct DocRenderer {
+    markdown: bool,
+    /// The bytes of markdown rendered before the rest is summarized away.
+    max_len: usize,
+}
+
+impl DocRenderer {
+    /// `formats` as advertised by the client, most preferred first. Clients that don't
+    /// say get plain text.
+    pub fn new(formats: Option<&[MarkupKind]>, max_len: usize) -> Self {
+        let markdown = formats.and_then(|formats| formats.first()) == Some(&MarkupKind::Markdown);
+        Self { markdown, max_len }
+    }
+
+    pub fn render(&self, markdown: &str) -> MarkupContent {
+        let markdown = summarize(markdown, self.max_len);
+        match self.markdown {
+            true => MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown.into_owned(),
+            },
+            false => MarkupContent {
+                kind: MarkupKind::PlainText,
+                value: plain_text(&markdown),
+            },
+        }
+    }
+
+    pub fn documentation(&self, markdown: &str) -> Documentation {
+        Documentation::MarkupContent(self.render(markdown))
+    }
+}
+
+/// `markdown` without fences, backticks, emphasis or heading markers.
+fn plain_text(markdown: &str) -> String {
+    let mut lines = Vec::new();
+    let mut fence: Option<(char, usize)> = None;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        let marker = trimmed.chars().next().filter(|&c| c == '`' || c == '~');
+        let len = marker.map_or(0, |c| trimmed.len() - trimmed.trim_start_matches(c).len());
+        match (fence, marker) {
+            (Some((c, open_len)), Some(marker))
+                if marker == c && len >= open_len && trimmed[len..].trim().is_empty() =>
+            {
+                fence = None;
+            }
+            (Some(_), _) => lines.push(line.to_string()),
+            (None, Some(c)) if len >= 3 => fence = Some((c, len)),
+            (None, _) => {
+                let line = line.trim_start_matches('#').trim_start_matches(' ');
+                lines.push(line.replace('`', "").replace("**", "").replace("__", ""));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// `markdown` cut down to the paragraphs that fit in `max_len` bytes, or to the words of
+/// the first that do, with a fence left open closed and a note of what was left out.
+pub fn summarize(markdown: &str, max_len: usize) -> Cow<'_, str> {
+    if markdown.len() <= max_len {
+        return Cow::Borrowed(markdown);
+    }
+    let paragraphs = markdown
+        .match_indices("\n\n")
+        .map(|(i, _)| i)
+        .take_while(|&end| end <= max_len)
+        .last();
+    let end = paragraphs.unwrap_or_else(|| {
+        let mut end = max_len;
+        while !markdown.is_char_boundary(end) {
+            end -= 1;
+        }
+        markdown[..end].rfind(char::is_whitespace).unwrap_or(end)
+    });
+    let kept = markdown[..end].trim_end();
+    let mut summary = kept.to_string();
+    let fences = kept
+        .lines()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count();
+    if fences % 2 == 1 {
+        summary.push_str("\n```");
+    }
+    let left_out = markdown[kept.len()..].trim().chars().count();
+    summary.push_str(&format!("\n\n… ({left_out} more characters left out)"));
+    Cow::Owned(summary)
+}
+
+/// `text` cut at a word to `max_len` bytes, ending in `…` when it is.
+pub fn truncate(text: &str, max_len: usize) -> Cow<'_, str> {
+    if text.len() <= max_len {
+        return Cow::Borrowed(text);
+    }
+    let mut end = max_len.saturating_sub('…'.len_utf8());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let end = text[..end].rfind(char::is_whitespace).unwrap_or(end);
+    Cow::Owned(format!("{}…", text[..end].trim_end()))
+}
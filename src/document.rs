@@ -0,0 +1,115 @@
+use crate::consistency::Shape;
+use crate::language;
+use crate::line_index::LineIndex;
+use lsp_types::TextDocumentContentChangeEvent;
+
+/// An open document, as last synced by the client.
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// As the client spells it, while the server keys documents by [`crate::uri::normalize`].
+    pub uri: lsp_types::Url,
+    pub text: String,
+    pub language_id: String,
+    pub version: i32,
+    /// Set with `testLsp.setDocumentLanguage` when the client's languageId is wrong.
+    pub language_override: Option<String>,
+    /// What the edits applied so far imply about the text, see [`crate::consistency`].
+    pub shape: Shape,
+}
+
+impl Document {
+    /// The language per-language settings are looked up with, which refines a generic
+    /// `language_id` from the text unless overridden.
+    pub fn language(&self) -> &str {
+        if let Some(language) = &self.language_override {
+            return language;
+        }
+        language::detect(&self.language_id, &self.uri, &self.text)
+    }
+}
+
+/// Applies the changes of a `textDocument/didChange` in order, each replacing its range
+/// or, without one, the whole text. Clients that can only sync full documents send
+/// changes without ranges whatever sync kind was advertised.
+pub fn apply_changes(text: &mut String, changes: Vec<TextDocumentContentChangeEvent>) {
+    for change in changes {
+        let Some(range) = change.range else {
+            *text = change.text;
+            continue;
+        };
+        let line_index = LineIndex::new(text);
+        // Past the last line is the end of the text
+        let start = line_index.offset(range.start).unwrap_or(text.len());
+        let end = line_index.offset(range.end).unwrap_or(text.len());
+        text.replace_range(start..end.max(start), &change.text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, Range};
+
+    fn full(text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn full_text_then_ranges() {
+        let mut text = "old".to_string();
+        let changes = vec![
+            full("hello world"),
+            edit((0, 0), (0, 5), "goodbye"),
+            edit((0, 13), (0, 13), "!"),
+        ];
+        apply_changes(&mut text, changes);
+        assert_eq!(text, "goodbye world!");
+    }
+
+    #[test]
+    fn ranges_then_full_text() {
+        let mut text = "hello world".to_string();
+        let changes = vec![edit((0, 0), (0, 5), "goodbye"), full("replaced\n")];
+        apply_changes(&mut text, changes);
+        assert_eq!(text, "replaced\n");
+    }
+
+    #[test]
+    fn multi_byte_and_crlf() {
+        let mut text = "caf\u{e9} \u{1F600}\r\nna\u{ef}ve\r\n".to_string();
+        let changes = vec![
+            // After the emoji, counted in UTF-16
+            edit((0, 7), (0, 7), "!"),
+            edit((1, 2), (1, 3), "i"),
+            // Past the end of the line, before its `\r\n`
+            edit((1, 40), (1, 40), "."),
+        ];
+        apply_changes(&mut text, changes);
+        assert_eq!(text, "caf\u{e9} \u{1F600}!\r\nnaive.\r\n");
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut text = "ab\ncd".to_string();
+        apply_changes(&mut text, vec![edit((5, 0), (6, 0), "!")]);
+        assert_eq!(text, "ab\ncd!");
+        // An end before the start inserts at the start
+        apply_changes(&mut text, vec![edit((1, 1), (0, 0), "x")]);
+        assert_eq!(text, "ab\ncxd!");
+    }
+}
@@ -0,0 +1,333 @@
+//! Word completion and diagnostics without the language server, for tools embedding
+//! them, like command line autocompleters, editors of their own or pre-commit hooks.
+//!
+//! The engine collects candidates from the same providers as the server and ranks
+//! them with the plugins of its [`Config`]. What the server ranks by from its session,
+//! like dismissed completions, experiments and `completion.git_boost`, is left out.
+//!
+//! ```no_run
+//! use test_lsp::engine::{CompletionEngine, Config, Position, Url};
+//!
+//! let mut engine = CompletionEngine::new(Config::default())?;
+//! engine.index_workspace(std::path::Path::new("."));
+//! let uri = Url::parse("file:///tmp/notes.md")?;
+//! let text = "Completion engines complete com";
+//! engine.update(&uri, text)?;
+//! for candidate in engine.complete(&uri, text, Position::new(0, 31)).candidates {
+//!     println!("{}", candidate.word);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::bibtex::{self, Bibliography};
+use crate::candidates::{Collected, Providers, Query};
+use crate::collation::Collator;
+use crate::config::ContextWindow;
+use crate::consistency::Shape;
+use crate::diagnostics;
+use crate::document::Document;
+use crate::index::{self, Index};
+use crate::line_index::LineIndex;
+use crate::plugin::{self, Worker};
+use crate::rule_pack;
+use crate::session::Session;
+use crate::{context, is_word_char, language, uri, workspace, Token};
+use itertools::Itertools;
+use logos::Logos;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub use crate::candidates::Source;
+pub use crate::config::Config;
+pub use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Url};
+
+/// Completes words from the documents it's given, the workspace it indexed and
+/// plugins.
+pub struct CompletionEngine {
+    config: Config,
+    index: Index,
+    plugins: Vec<Worker>,
+    providers: Providers,
+    bibliography: Bibliography,
+    session: Session,
+}
+
+/// The candidates for the word at a position, best first.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Completions {
+    /// What's typed of the word being completed.
+    pub prefix: String,
+    /// Where the text a candidate replaces starts, usually that of the word, but earlier
+    /// for the completions of more than a word, like markdown link anchors.
+    pub replace_from: Position,
+    pub candidates: Vec<Candidate>,
+    /// Whether slower sources missed `completion.deadline`, or candidates were cut at
+    /// `completion.max_results`.
+    pub incomplete: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+    pub word: String,
+    /// Where it was found, the first deciding its rank. Empty for the words a plugin
+    /// came up with while ranking.
+    pub sources: Vec<Source>,
+    /// What an abbreviation of `completion.snippets` expands to, in LSP snippet syntax.
+    pub snippet: Option<String>,
+}
+
+impl CompletionEngine {
+    /// Loads the plugins and rule packs of `config`, and opens its index. A `disk` index needs
+    /// `index.path`, there being no workspace to keep it for.
+    pub fn new(mut config: Config) -> io::Result<Self> {
+        rule_pack::apply(&mut config);
+        let plugins = spawn_plugins(&config);
+        let providers = Providers::new(&plugins, &config.completion);
+        let context_symbols = match config.completion.uses_symbol_context() {
+            true => config.completion.context_symbols.clone(),
+            false => Vec::new(),
+        };
+        let index = Index::open(&config.index, None)?
+            .with_context_symbols(context_symbols)
+            .with_collator(Collator::new(config.completion.locale.as_deref()));
+        Ok(Self {
+            config,
+            index,
+            plugins,
+            providers,
+            bibliography: Bibliography::default(),
+            session: Session::default(),
+        })
+    }
+
+    /// Indexes the text files under `root` as the server does its workspace, returning
+    /// how many were.
+    pub fn index_workspace(&mut self, root: &Path) -> usize {
+        let context_symbols = self.index.context_symbols().to_vec();
+        let scanned = workspace::scan(root, &self.config.index, |uri, text| {
+            let hash = index::content_hash(&text);
+            let counts = index::count_words(&text, &context_symbols);
+            let citations = bibtex::is_bib(&uri).then(|| bibtex::parse(&text));
+            (uri, hash, counts, citations)
+        });
+        let mut documents = Vec::new();
+        for (uri, hash, counts, citations) in scanned {
+            if let Some(citations) = citations {
+                self.bibliography.set(uri.clone(), citations);
+            }
+            documents.push((uri, hash, counts));
+        }
+        let mut indexed = documents.len();
+        for (uri, err) in self.index.set_many_counts(documents) {
+            log::error!("failed to index {uri}: {err}");
+            indexed -= 1;
+        }
+        indexed
+    }
+
+    /// Indexes `text` as the contents of `uri`, in place of what it had.
+    pub fn update(&mut self, uri: &Url, text: &str) -> io::Result<()> {
+        let uri = uri::normalize(uri);
+        if bibtex::is_bib(&uri) {
+            self.bibliography.set(uri.clone(), bibtex::parse(text));
+        }
+        self.index.update(&uri, text)
+    }
+
+    /// Completes the word at `position` of `text`, the contents of `uri`.
+    pub fn complete(&mut self, uri: &Url, text: &str, position: Position) -> Completions {
+        let uri = uri::normalize(uri);
+        let Some(offset) = LineIndex::new(text).offset(position) else {
+            return Completions::default();
+        };
+        let completion = &self.config.completion;
+        let configured = completion.context_window;
+        let window = context::window(text, offset, configured.unwrap_or(ContextWindow::Line));
+        let window_words = Token::lexer(&text[window.clone()])
+            .filter_map(|token| match token {
+                Ok(Token::Word(word)) => Some(word),
+                _ => None,
+            })
+            .collect_vec();
+        let (before, prefix) = split_word_prefix(position, text);
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(completion.deadline);
+        let word_start = Position::new(
+            position.line,
+            position.character - prefix.encode_utf16().count() as u32,
+        );
+        let clipboard = VecDeque::new();
+        let query = Query {
+            uri: &uri,
+            text,
+            language: language::of_file(&uri, text),
+            position,
+            word_start,
+            before,
+            prefix,
+            window_words: &window_words,
+            window: configured.map(|_| window),
+            symbol_context: completion.symbol_context,
+            context_symbols: &completion.context_symbols,
+            index: &self.index,
+            bibliography: &self.bibliography,
+            clipboard: &clipboard,
+        };
+        let Collected {
+            words: sources,
+            mut incomplete,
+            replacing,
+            ..
+        } = self
+            .providers
+            .collect(&query, started, &mut self.session, &|| false);
+        let only = replacing.is_some();
+        let mut words = sources.keys().cloned().collect_vec();
+        for plugin in self.plugins.iter().filter(|_| !only) {
+            let (prefix, candidates) = (prefix.to_string(), words.clone());
+            let ranked = plugin.call(move |plugin| plugin.rank(&prefix, candidates));
+            match ranked.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Ok(ranked)) => words = ranked,
+                Ok(Err(err)) => log::error!("plugin {} failed to rank: {err}", plugin.name()),
+                Err(_) => incomplete = true,
+            }
+        }
+        incomplete |= words.len() > completion.max_results;
+        words.truncate(completion.max_results);
+
+        let snippets = match only {
+            true => Vec::new(),
+            false => snippets(&completion.snippets, prefix, self.index.collator()),
+        };
+        words.retain(|word| {
+            !snippets
+                .iter()
+                .any(|(abbreviation, _)| *abbreviation == word)
+        });
+        let snippets = snippets.into_iter().map(|(abbreviation, body)| Candidate {
+            word: abbreviation.clone(),
+            sources: [Source::Snippet]
+                .into_iter()
+                .chain(sources.get(abbreviation).into_iter().flatten().copied())
+                .collect(),
+            snippet: Some(body.clone()),
+        });
+        let words = words.into_iter().map(|word| Candidate {
+            sources: sources.get(&word).cloned().unwrap_or_default(),
+            word,
+            snippet: None,
+        });
+        let replace_from = replacing.map_or(word_start, |from| {
+            let character = [before, prefix].concat()[..from].encode_utf16().count() as u32;
+            Position::new(position.line, character)
+        });
+        Completions {
+            prefix: prefix.to_string(),
+            replace_from,
+            candidates: snippets.chain(words).collect(),
+            incomplete,
+        }
+    }
+}
+
+/// Runs the diagnostics of the server over files, the built-in rules and those of the
+/// plugins of its [`Config`].
+pub struct Checker {
+    providers: diagnostics::Providers,
+    config: Config,
+}
+
+impl Checker {
+    pub fn new(mut config: Config) -> Self {
+        rule_pack::apply(&mut config);
+        let plugins = spawn_plugins(&config);
+        Self {
+            providers: diagnostics::Providers::new(&plugins, config.diagnostics.clone()),
+            config,
+        }
+    }
+
+    /// The diagnostics of `text` as the contents of `uri`, suppressed and with the
+    /// severities configured as the server would publish them.
+    pub fn check(&self, uri: &Url, text: &str) -> Vec<Diagnostic> {
+        let uri = uri::normalize(uri);
+        let document = Document {
+            language_id: language::of_file(&uri, text).to_string(),
+            uri: uri.clone(),
+            shape: Shape::of(text),
+            text: text.to_string(),
+            version: 0,
+            language_override: None,
+        };
+        let found = self.providers.check(&uri, &document);
+        diagnostics::configure(diagnostics::suppress(&document, found), &self.config.rules)
+    }
+
+    /// `path` if it's a file, else the text files under it the server would index, none
+    /// without the `workspace` feature.
+    pub fn files(&self, path: &Path) -> Vec<PathBuf> {
+        match path.is_dir() {
+            true => workspace::files(path, &self.config.index),
+            false => vec![path.to_path_buf()],
+        }
+    }
+}
+
+/// The plugins of `config`, and its sidecar unless offline.
+fn spawn_plugins(config: &Config) -> Vec<Worker> {
+    let mut plugins = plugin::load(&config.plugins)
+        .into_iter()
+        .map(Worker::spawn)
+        .collect_vec();
+    if let Some(sidecar) = config.sidecar.as_ref().filter(|_| !config.offline) {
+        match plugin::connect_sidecar(sidecar) {
+            Ok(plugin) => plugins.push(Worker::spawn(plugin)),
+            Err(err) => log::error!("failed to connect to {}: {err}", sidecar.endpoint),
+        }
+    }
+    plugins
+}
+
+/// The abbreviations of `completion.snippets` starting with `prefix`, with the bodies
+/// they expand to, in order.
+pub(crate) fn snippets<'a>(
+    snippets: &'a HashMap<String, String>,
+    prefix: &str,
+    collator: &Collator,
+) -> Vec<(&'a String, &'a String)> {
+    snippets
+        .iter()
+        .filter(|(abbreviation, _)| abbreviation.starts_with(prefix))
+        .sorted_by(|(a, _), (b, _)| collator.compare(a, b))
+        .collect()
+}
+
+/// Splits the line up to the cursor into what comes before the word being typed and
+/// the part of that word already typed.
+pub(crate) fn split_word_prefix(position: Position, text: &str) -> (&str, &str) {
+    let Some(offset) = LineIndex::new(text).offset(position) else {
+        return ("", "");
+    };
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let context = &text[line_start..offset];
+    let start = context
+        .char_indices()
+        .rfind(|&(_, c)| !is_word_char(c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    context.split_at(start)
+}
+
+/// The rest of the word the cursor is in the middle of, empty when it's at its end.
+pub(crate) fn word_suffix(position: Position, text: &str) -> &str {
+    let Some(offset) = LineIndex::new(text).offset(position) else {
+        return "";
+    };
+    let after = &text[offset..];
+    let end = after.find(|c| !is_word_char(c)).unwrap_or(after.len());
+    &after[..end]
+}
@@ -0,0 +1,37 @@
+//! Environment variables, as shell scripts, `.env` files and CI configurations use them.
+
+use itertools::Itertools;
+
+/// The languages expanding `$VAR` and `${VAR}`.
+pub const EXPANDING_LANGUAGES: &[&str] = &["shellscript", "dotenv", "yaml"];
+
+/// Whether `before`, the line up to the word being typed, ends in `$` or `${`.
+pub fn expands(before: &str) -> bool {
+    before.ends_with('$') || before.ends_with("${")
+}
+
+/// The variables defined in `text` before `offset`, in order, and then the ones of the
+/// process environment, sorted.
+pub fn variables(text: &str, offset: usize) -> Vec<String> {
+    let defined = text[..offset].lines().filter_map(definition);
+    let inherited = std::env::vars_os()
+        .filter_map(|(name, _)| name.into_string().ok())
+        .sorted();
+    defined
+        .map(str::to_string)
+        .chain(inherited)
+        .unique()
+        .collect()
+}
+
+/// The name assigned on `line`: `NAME=value`, `export NAME=value` or `NAME: value`.
+fn definition(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+    let len = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(line.len());
+    let name = &line[..len];
+    let named = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    (named && line[len..].starts_with(['=', ':'])).then_some(name)
+}
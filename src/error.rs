@@ -0,0 +1,67 @@
+#[cfg(feature = "server")]
+use lsp_server::ErrorCode;
+use std::error::Error;
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    /// The client sent what the protocol doesn't allow, or went away.
+    #[error("protocol error: {0}")]
+    Protocol(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// A built-in backend, like the index.
+    #[error("{backend} failed: {source}")]
+    Backend {
+        backend: &'static str,
+        source: Box<dyn Error + Sync + Send>,
+    },
+    #[error("plugin {name} failed: {source}")]
+    Plugin {
+        name: String,
+        source: Box<dyn Error + Sync + Send>,
+    },
+    #[error("invalid configuration: {0}")]
+    Config(String),
+}
+
+impl ServerError {
+    pub fn index(source: io::Error) -> Self {
+        Self::Backend {
+            backend: "index",
+            source: source.into(),
+        }
+    }
+
+    /// For the messages that can't be sent, the client being gone.
+    pub fn disconnected<T>(_: T) -> Self {
+        Self::Protocol("the client connection closed".to_string())
+    }
+
+    /// The code to answer a request that failed with it.
+    #[cfg(feature = "server")]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Protocol(_) | Self::Config(_) => ErrorCode::InvalidParams,
+            Self::Io(_) => ErrorCode::InternalError,
+            Self::Backend { .. } | Self::Plugin { .. } => ErrorCode::RequestFailed,
+        }
+    }
+
+    /// What to tell the user, who doesn't need the details of every failure.
+    pub fn message(&self) -> String {
+        match self {
+            Self::Protocol(message) => format!("Invalid request: {message}"),
+            Self::Io(err) => format!("Couldn't read or write a file: {err}"),
+            Self::Backend { backend, .. } => format!("The {backend} isn't working, see the log"),
+            Self::Plugin { name, .. } => format!("Plugin {name} isn't working, see the log"),
+            Self::Config(message) => format!("Invalid settings: {message}"),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ServerError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Protocol(err.to_string())
+    }
+}
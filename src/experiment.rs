@@ -0,0 +1,88 @@
+//! A/B tests of completion ranking. Each document is assigned to one of two arms, by a
+//! hash of the experiment name and its uri, and the completions accepted are counted
+//! per arm.
+
+use crate::config::{ExperimentConfig, RankingConfig};
+use crate::ext::{ArmStats, ExperimentStats};
+use lsp_types::Url;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arm {
+    Control,
+    Treatment,
+}
+
+impl Arm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Control => "control",
+            Self::Treatment => "treatment",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Control, Self::Treatment]
+            .into_iter()
+            .find(|arm| arm.name() == name)
+    }
+}
+
+pub struct Experiment {
+    config: ExperimentConfig,
+    arms: BTreeMap<&'static str, ArmStats>,
+}
+
+impl Experiment {
+    pub fn new(config: ExperimentConfig) -> Self {
+        Self {
+            config,
+            arms: BTreeMap::new(),
+        }
+    }
+
+    /// The arm of the document at `uri`, the same for every session.
+    pub fn assign(&self, uri: &Url) -> (Arm, RankingConfig) {
+        match fnv1a(&[self.config.name.as_bytes(), b"\0", uri.as_str().as_bytes()]) % 2 {
+            0 => (Arm::Control, self.config.control),
+            _ => (Arm::Treatment, self.config.treatment),
+        }
+    }
+
+    pub fn served(&mut self, arm: Arm) {
+        self.arms.entry(arm.name()).or_default().served += 1;
+    }
+
+    pub fn accepted(&mut self, arm: Arm) {
+        self.arms.entry(arm.name()).or_default().accepted += 1;
+    }
+
+    pub fn stats(&self) -> ExperimentStats {
+        let arms = self
+            .arms
+            .iter()
+            .map(|(name, stats)| {
+                let acceptance_rate = stats.accepted as f64 / stats.served.max(1) as f64;
+                let stats = ArmStats {
+                    acceptance_rate,
+                    ..stats.clone()
+                };
+                (name.to_string(), stats)
+            })
+            .collect();
+        ExperimentStats {
+            name: self.config.name.clone(),
+            arms,
+        }
+    }
+}
+
+/// Stable across builds, unlike the std hasher.
+fn fnv1a(parts: &[&[u8]]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+        })
+}
@@ -0,0 +1,437 @@
+//! Protocol extensions, served under the `testLsp/` prefix.
+
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+use lsp_types::{Position, Range, TextDocumentIdentifier, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// `workspace/executeCommand` formatting every text file of the workspace.
+pub const FORMAT_WORKSPACE: &str = "testLsp.formatWorkspace";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FormatWorkspaceArgs {
+    /// Only report what would change, without applying any edit.
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatWorkspaceResult {
+    /// The files that were, or with `dryRun` would be, changed.
+    pub files: Vec<Url>,
+    pub edits: usize,
+    /// The files the client didn't apply the edits of.
+    pub failed: Vec<Url>,
+    pub dry_run: bool,
+}
+
+/// `workspace/executeCommand` replacing the matches of a regex in every text file of the
+/// workspace, answered with a [`FormatWorkspaceResult`] too.
+pub const REPLACE_IN_WORKSPACE: &str = "testLsp.replaceInWorkspace";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaceInWorkspaceArgs {
+    pub pattern: String,
+    /// What each match is replaced with, `$1` or `${name}` standing for its groups.
+    pub replacement: String,
+    /// Gitignore-style globs, relative to the workspace root, of the only files searched.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Gitignore-style globs of files never searched.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Only report what would change, without applying any edit.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// `workspace/executeCommand` overriding the language of an open document, also served
+/// as the [`SetDocumentLanguage`] request.
+pub const SET_DOCUMENT_LANGUAGE: &str = "testLsp.setDocumentLanguage";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetDocumentLanguageParams {
+    pub text_document: TextDocumentIdentifier,
+    /// `None` goes back to the language the client opened the document with.
+    pub language_id: Option<String>,
+}
+
+/// Overrides the language of an open document until it's reopened, returning the
+/// language it's now handled as.
+pub enum SetDocumentLanguage {}
+
+impl Request for SetDocumentLanguage {
+    type Params = SetDocumentLanguageParams;
+    type Result = String;
+    const METHOD: &'static str = "testLsp/setDocumentLanguage";
+}
+
+/// `workspace/executeCommand` the completion items run once inserted, with the source
+/// they were shown as, to count accepted completions.
+pub const COMPLETION_ACCEPTED: &str = "testLsp.completionAccepted";
+
+/// Why an item of the last completion ranks where it does, with `completion.debug`.
+pub enum ExplainRanking {}
+
+impl Request for ExplainRanking {
+    type Params = ExplainRankingParams;
+    type Result = Option<RankingExplanation>;
+    const METHOD: &'static str = "testLsp/explainRanking";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainRankingParams {
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RankingExplanation {
+    pub label: String,
+    /// The providers that found it, the first deciding its base rank.
+    pub sources: Vec<String>,
+    /// Its place among the collected words, `None` for snippets and plugin suggestions.
+    pub base_rank: Option<usize>,
+    /// How often the index has it.
+    pub frequency: Option<u64>,
+    /// The boosts and demotions applied, in order.
+    pub adjustments: Vec<String>,
+    pub final_rank: Option<usize>,
+}
+
+/// Sent by clients with what the user copied, offered first in completions for the
+/// session. Only the latest few strings are kept, in memory.
+pub enum ClipboardHint {}
+
+impl Notification for ClipboardHint {
+    type Params = ClipboardHintParams;
+    const METHOD: &'static str = "testLsp/clipboardHint";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipboardHintParams {
+    pub text: String,
+}
+
+/// Sent by clients when the user dismisses a completion, demoting it in that context
+/// for the session once it happens again.
+pub enum CompletionDismissed {}
+
+impl Notification for CompletionDismissed {
+    type Params = CompletionDismissedParams;
+    const METHOD: &'static str = "testLsp/completionDismissed";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionDismissedParams {
+    pub text_document: TextDocumentIdentifier,
+    /// Where the completion was requested.
+    pub position: Position,
+    pub label: String,
+}
+
+/// `workspace/executeCommand` hard wrapping the paragraphs at the cursor or in the
+/// selection, taking [`ParagraphArgs`].
+pub const REFLOW_PARAGRAPH: &str = "testLsp.reflowParagraph";
+
+/// `workspace/executeCommand` forgetting the word frequencies of the index, then
+/// indexing the workspace and open documents anew.
+pub const RESET_STATISTICS: &str = "testLsp.resetStatistics";
+
+/// `workspace/executeCommand` removing the data directory of the workspace, with the
+/// on-disk index in it, then indexing the workspace and open documents anew.
+pub const CLEAR_WORKSPACE_DATA: &str = "testLsp.clearWorkspaceData";
+
+/// `workspace/executeCommand` putting each paragraph at the cursor or in the selection on
+/// a single line, taking [`ParagraphArgs`].
+pub const JOIN_LINES: &str = "testLsp.joinLines";
+
+/// `workspace/executeCommand` asking the plugins to rewrite the paragraph at the cursor
+/// to read at `diagnostics.reading_level`, taking [`ParagraphArgs`].
+pub const REWRITE_PARAGRAPH: &str = "testLsp.rewriteParagraph";
+
+/// `workspace/executeCommand` asking the plugins to explain the selection, or the word at
+/// the cursor, taking [`ExplainArgs`]. The explanation is shown as a document when the
+/// client can show one, else as a message, and returned either way.
+pub const EXPLAIN: &str = "testLsp.explain";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExplainArgs {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParagraphArgs {
+    pub text_document: TextDocumentIdentifier,
+    /// The cursor, or a selection of paragraphs.
+    pub range: Range,
+    /// Columns to reflow to, by default the language's `diagnostics.line_length`.
+    pub width: Option<usize>,
+}
+
+/// Lexes a document the same way completion does.
+pub enum Tokenize {}
+
+impl Request for Tokenize {
+    type Params = TokenizeParams;
+    type Result = Vec<LexedToken>;
+    const METHOD: &'static str = "testLsp/tokenize";
+}
+
+/// How many times each indexed file uses a word, e.g. for a heatmap.
+pub enum Occurrences {}
+
+impl Request for Occurrences {
+    type Params = OccurrencesParams;
+    type Result = OccurrencesResult;
+    const METHOD: &'static str = "testLsp/occurrences";
+}
+
+/// Scheme of the read-only documents the server makes on demand, read with
+/// [`ReadVirtualDocument`]: `testlsp:explain/<phrase>`, `testlsp:stats` and
+/// `testlsp:index`.
+pub const VIRTUAL_SCHEME: &str = "testlsp";
+
+/// Whether the client reads `testlsp:` documents, under `experimental.testLsp` of its
+/// capabilities.
+pub const VIRTUAL_DOCUMENTS_CAPABILITY: &str = "virtualDocuments";
+
+/// The text of a `testlsp:` document, generated as the server knows things now.
+pub enum ReadVirtualDocument {}
+
+impl Request for ReadVirtualDocument {
+    type Params = ReadVirtualDocumentParams;
+    type Result = VirtualDocument;
+    const METHOD: &'static str = "testLsp/readVirtualDocument";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadVirtualDocumentParams {
+    pub uri: Url,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualDocument {
+    pub text: String,
+    pub language_id: String,
+}
+
+/// Counters of the server, like how often results were cut at their limit.
+pub enum Stats {}
+
+impl Request for Stats {
+    type Params = ();
+    type Result = ServerStats;
+    const METHOD: &'static str = "testLsp/stats";
+}
+
+/// Writes the flamegraph of the server sampled so far, when started with `--profile`.
+pub enum DumpProfile {}
+
+impl Request for DumpProfile {
+    type Params = ();
+    type Result = DumpedProfile;
+    const METHOD: &'static str = "testLsp/dumpProfile";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpedProfile {
+    pub path: PathBuf,
+}
+
+/// Reads and indexes the files the client is about to open, like those of the editor's
+/// last session, so that the first completions after a restart know their words.
+/// Answered once they're indexed.
+pub enum PreloadDocuments {}
+
+impl Request for PreloadDocuments {
+    type Params = PreloadDocumentsParams;
+    type Result = PreloadedDocuments;
+    const METHOD: &'static str = "testLsp/preloadDocuments";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreloadDocumentsParams {
+    pub uris: Vec<Url>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreloadedDocuments {
+    pub indexed: usize,
+    /// The uris that aren't of readable text files.
+    pub skipped: Vec<Url>,
+}
+
+/// The diagnostics last published for every document, as a SARIF 2.1.0 log for code
+/// scanning dashboards, see [`crate::sarif`].
+pub enum ExportDiagnostics {}
+
+impl Request for ExportDiagnostics {
+    type Params = ();
+    type Result = serde_json::Value;
+    const METHOD: &'static str = "testLsp/exportDiagnostics";
+}
+
+/// The last protocol anomalies the server ran into, the oldest first, as many as
+/// `debug.recent_errors` keeps.
+pub enum RecentErrors {}
+
+impl Request for RecentErrors {
+    type Params = ();
+    type Result = Vec<ProtocolError>;
+    const METHOD: &'static str = "testLsp/recentErrors";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtocolError {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub kind: ProtocolErrorKind,
+    pub method: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProtocolErrorKind {
+    /// A request or notification of a method the server doesn't handle.
+    UnknownMethod,
+    /// Params that don't parse as the method's.
+    InvalidParams,
+    /// A change to a document older than, or as old as, the version already synced.
+    StaleVersion,
+    /// A message over `--max-message-size`.
+    TooLarge,
+    /// A change or a request about a document that isn't open.
+    NotOpen,
+}
+
+/// The terms the workspace defines and the configured acronyms, in order, see
+/// [`crate::glossary`].
+pub enum Glossary {}
+
+impl Request for Glossary {
+    type Params = ();
+    type Result = Vec<GlossaryEntry>;
+    const METHOD: &'static str = "testLsp/glossary";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlossaryEntry {
+    pub term: String,
+    /// As configured, or else as most often defined.
+    pub meaning: String,
+    /// The files defining the term, none for those only configured.
+    pub defined_in: Vec<Url>,
+}
+
+/// Lists the built-in diagnostic rules, for building settings UIs.
+pub enum ListRules {}
+
+impl Request for ListRules {
+    type Params = ();
+    type Result = Vec<RuleDescription>;
+    const METHOD: &'static str = "testLsp/listRules";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenizeParams {
+    pub text_document: TextDocumentIdentifier,
+    /// Only return the tokens overlapping this range, defaults to the whole document.
+    pub range: Option<Range>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OccurrencesParams {
+    pub word: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OccurrencesResult {
+    pub total: u64,
+    /// Only the files using the word, open documents under the uri the client opened
+    /// them with.
+    pub files: BTreeMap<Url, u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LexedToken {
+    pub kind: TokenKind,
+    pub text: String,
+    pub byte_range: std::ops::Range<usize>,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenKind {
+    Word,
+    Symbol,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleDescription {
+    pub id: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStats {
+    pub open_documents: usize,
+    /// By feature, e.g. `completion`.
+    pub limits: BTreeMap<String, LimitStats>,
+    pub experiment: Option<ExperimentStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExperimentStats {
+    pub name: String,
+    /// `control` and `treatment`, once they served completions.
+    pub arms: BTreeMap<String, ArmStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArmStats {
+    /// Completion lists.
+    pub served: u64,
+    pub accepted: u64,
+    pub acceptance_rate: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LimitStats {
+    pub limit: usize,
+    pub requests: u64,
+    /// How many of the requests had results dropped.
+    pub truncated: u64,
+    /// How many results the last request had before truncation.
+    pub last_total: usize,
+}
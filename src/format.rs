@@ -0,0 +1,208 @@
+use crate::commit::{self, BODY_WIDTH};
+use crate::config::{FormatConfig, LineEnding};
+use crate::diagnostics::PAIRS;
+use crate::line_index::LineIndex;
+use crate::markdown::Markdown;
+use crate::wrap;
+use lsp_types::TextEdit;
+use std::ops::Range;
+
+/// Edits that strip trailing whitespace, give every line the same ending and settle
+/// the newlines ending a non-empty text as `config` says. Markdown keeps the trailing
+/// double spaces of hard line breaks, and commit messages get their body wrapped.
+///
+/// The languages `config` makes typographic also get [`typographic`] edits.
+pub fn format(text: &str, language: &str, config: &FormatConfig) -> Vec<TextEdit> {
+    let line_index = LineIndex::new(text);
+    let newline = match config.line_ending {
+        LineEnding::Auto => dominant_line_ending(text),
+        LineEnding::Lf => "\n",
+        LineEnding::Crlf => "\r\n",
+    };
+    let wrapped = match language {
+        commit::LANGUAGE => wrap_body(text),
+        _ => Vec::new(),
+    };
+    let mut edits = wrapped
+        .iter()
+        .map(|(range, paragraph)| TextEdit::new(line_index.range(range.clone()), paragraph.clone()))
+        .collect::<Vec<_>>();
+    let body_end = text.trim_end().len();
+    let mut offset = 0;
+    for line in text[..body_end].split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        // Rewritten with the paragraph
+        if wrapped.iter().any(|(range, _)| range.contains(&line_start)) {
+            continue;
+        }
+        let content = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[content.len()..];
+        if ending.is_empty() {
+            continue;
+        }
+        let trimmed = content.trim_end_matches([' ', '\t']);
+        let trailing = &content[trimmed.len()..];
+        let hard_break = language == "markdown"
+            && !trimmed.is_empty()
+            && trailing.starts_with("  ")
+            && !trailing.contains('\t');
+        let keep = if hard_break { content } else { trimmed };
+        if keep.len() < content.len() || ending != newline {
+            edits.push(TextEdit::new(
+                line_index.range(line_start + keep.len()..offset),
+                newline.to_string(),
+            ));
+        }
+    }
+    if body_end > 0 {
+        let tail = &text[body_end..];
+        let mut newlines = tail.matches('\n').count();
+        if config.trim_final_newlines {
+            newlines = newlines.min(1);
+        }
+        if config.insert_final_newline {
+            newlines = newlines.max(1);
+        }
+        let expected = newline.repeat(newlines);
+        if tail != expected {
+            edits.push(TextEdit::new(
+                line_index.range(body_end..text.len()),
+                expected,
+            ));
+        }
+    }
+    if config
+        .typographic
+        .iter()
+        .any(|typographic| typographic == language)
+    {
+        edits.extend(typographic(text));
+    }
+    edits.sort_by_key(|edit| edit.range.start);
+    edits
+}
+
+/// The paragraphs of a commit message body that need wrapping, wrapped. Indented
+/// ones, like quoted code, are left alone.
+fn wrap_body(text: &str) -> Vec<(Range<usize>, String)> {
+    let body = commit::body(text);
+    wrap::paragraphs(text, body.clone())
+        .into_iter()
+        .filter(|paragraph| body.start <= paragraph.start && paragraph.end <= body.end)
+        .filter(|paragraph| !text[paragraph.clone()].starts_with([' ', '\t']))
+        .filter_map(|paragraph| {
+            let wrapped = wrap::wrap(&text[paragraph.clone()], BODY_WIDTH);
+            (wrapped != text[paragraph.clone()]).then_some((paragraph, wrapped))
+        })
+        .collect()
+}
+
+/// `\r\n` when most lines end with it, `\n` otherwise.
+fn dominant_line_ending(text: &str) -> &'static str {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+    if crlf > lf {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Edits turning straight quotes curly and `--` into em dashes, outside of code.
+pub fn typographic(text: &str) -> Vec<TextEdit> {
+    let line_index = LineIndex::new(text);
+    let markdown = Markdown::parse(text);
+    let mut edits = Vec::new();
+    let mut previous = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, next)| next);
+        let opening = previous.is_none_or(|c: char| {
+            c.is_whitespace() || matches!(c, '—' | '-') || PAIRS.iter().any(|&(o, _)| o == c)
+        });
+        let replacement = match c {
+            _ if markdown.in_code(i) => None,
+            '"' if opening => Some("“"),
+            '"' => Some("”"),
+            '\'' if opening => Some("‘"),
+            // Closing quotes and apostrophes alike
+            '\'' => Some("’"),
+            // Not `---` rules, nor HTML comments
+            '-' if next == Some('-')
+                && previous.is_none_or(|c| !matches!(c, '-' | '!'))
+                && !text[i + 2..].starts_with(['-', '>']) =>
+            {
+                chars.next();
+                Some("—")
+            }
+            _ => None,
+        };
+        if let Some(replacement) = replacement {
+            let end = if replacement == "—" { i + 2 } else { i + 1 };
+            edits.push(TextEdit::new(
+                line_index.range(i..end),
+                replacement.to_string(),
+            ));
+            previous = replacement.chars().next();
+        } else {
+            previous = Some(c);
+        }
+    }
+    edits
+}
+
+/// Edits after `typed` was inserted right before `offset`: a bracket gets its closing
+/// one, typing a closing bracket steps over the one already there and a straight
+/// double quote turns curly, opening or closing whichever is open.
+pub fn on_type(text: &str, offset: usize, typed: char) -> Vec<TextEdit> {
+    let Some(start) = offset.checked_sub(typed.len_utf8()) else {
+        return Vec::new();
+    };
+    if !text.is_char_boundary(start) || !text[start..].starts_with(typed) {
+        return Vec::new();
+    }
+    let line_index = LineIndex::new(text);
+    let paragraph_start = text[..start].rfind("\n\n").map_or(0, |i| i + 2);
+    let paragraph_end = text[offset..]
+        .find("\n\n")
+        .map_or(text.len(), |i| offset + i);
+    let before = &text[paragraph_start..start];
+    let previous = before.chars().next_back();
+    let next = text[offset..].chars().next();
+    // Don't pair in front of a word, where the closing one most likely goes elsewhere
+    let closable = next.is_none_or(|c| {
+        c.is_whitespace() || ".,;:!?".contains(c) || PAIRS.iter().any(|&(_, close)| close == c)
+    });
+    if let Some(&(_, close)) = PAIRS.iter().find(|&&(open, _)| open == typed) {
+        if closable {
+            return vec![TextEdit::new(
+                line_index.range(offset..offset),
+                close.to_string(),
+            )];
+        }
+    } else if let Some(&(open, _)) = PAIRS.iter().find(|&&(_, close)| close == typed) {
+        let paragraph = &text[paragraph_start..paragraph_end];
+        let unopened = paragraph.matches(typed).count() > paragraph.matches(open).count();
+        if next == Some(typed) && unopened {
+            return vec![TextEdit::new(
+                line_index.range(offset..offset + typed.len_utf8()),
+                String::new(),
+            )];
+        }
+    } else if typed == '"' {
+        let quoted = before.matches('“').count() > before.matches('”').count();
+        let opening =
+            previous.is_none_or(|c| c.is_whitespace() || PAIRS.iter().any(|&(o, _)| o == c));
+        let curly = match (quoted, opening) {
+            (true, _) => "”",
+            (false, true) => "“",
+            (false, false) => return Vec::new(),
+        };
+        return vec![TextEdit::new(
+            line_index.range(start..offset),
+            curly.to_string(),
+        )];
+    }
+    Vec::new()
+}
@@ -0,0 +1,79 @@
+//! What the working tree changed since the last commit, as `git diff` tells.
+
+use crate::Token;
+use logos::Logos;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// How long the changed words are trusted before asking git again.
+pub const REFRESH: Duration = Duration::from_secs(10);
+
+const DIFF: &[&str] = &["diff", "--no-color", "--no-ext-diff", "--unified=0"];
+
+/// The words of the lines added or changed in the working tree of `root` since `HEAD`.
+pub fn changed_words(root: &Path) -> io::Result<HashSet<String>> {
+    let diff = git(root, &[DIFF, &["HEAD"]].concat())?;
+    Ok(words(&added_lines(&diff)).collect())
+}
+
+/// The words of the lines staged to be committed in `root`, which its commit message
+/// describes.
+pub fn staged_words(root: &Path) -> io::Result<Vec<String>> {
+    let diff = git(root, &[DIFF, &["--cached"]].concat())?;
+    Ok(words(&added_lines(&diff)).collect())
+}
+
+/// The words of the last `count` commit messages of `root`.
+pub fn message_words(root: &Path, count: usize) -> io::Result<Vec<String>> {
+    let log = git(
+        root,
+        &["log", "--no-color", "--format=%B", &format!("-{count}")],
+    )?;
+    Ok(words(&log).collect())
+}
+
+/// The work tree a file belongs to, including the files git keeps in `.git` like
+/// `COMMIT_EDITMSG`.
+pub fn work_tree(path: &Path) -> Option<PathBuf> {
+    let dir = path.parent()?;
+    let in_git_dir = dir.ancestors().find(|dir| dir.ends_with(".git"));
+    Some(
+        in_git_dir
+            .and_then(Path::parent)
+            .unwrap_or(dir)
+            .to_path_buf(),
+    )
+}
+
+fn git(root: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").args(args).current_dir(root).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "git {} failed: {}",
+            args[0],
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn added_lines(diff: &str) -> String {
+    diff.lines()
+        .filter(|line| !line.starts_with("+++"))
+        .filter_map(|line| line.strip_prefix('+'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    Token::lexer(text)
+        .filter_map(Result::ok)
+        .filter_map(|token| match token {
+            Token::Word(word) => Some(word.to_string()),
+            Token::Symbol(_) => None,
+        })
+}
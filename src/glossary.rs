@@ -0,0 +1,256 @@
+//! The terms the workspace defines, learned while indexing from where it introduces
+//! them, like `Language Server Protocol (LSP)`, `LSP (Language Server Protocol)` or
+//! `Language Server Protocol, also known as LSP`, and those configured in `acronyms`.
+
+use crate::ext::GlossaryEntry;
+use crate::Token;
+use itertools::Itertools;
+use logos::Logos;
+use lsp_types::Url;
+use std::collections::{BTreeMap, HashMap};
+
+/// Short words an expansion may have without an initial of its own, like the `of` of
+/// `Bank of America (BoA)`.
+const CONNECTIVES: &[&str] = &["a", "an", "and", "for", "in", "of", "on", "the", "to"];
+
+/// How many words the alias of `X, also known as Y` may have.
+const MAX_ALIAS_WORDS: usize = 6;
+
+/// A term and what it means.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub term: String,
+    pub meaning: String,
+}
+
+/// The definitions of the workspace files, by file, and those configured.
+#[derive(Debug, Default)]
+pub struct Glossary {
+    configured: HashMap<String, String>,
+    files: HashMap<Url, Vec<Definition>>,
+}
+
+impl Glossary {
+    pub fn new(configured: HashMap<String, String>) -> Self {
+        Self {
+            configured,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Learns the definitions `uri` introduces anew.
+    pub fn update(&mut self, uri: &Url, text: &str) {
+        self.set(uri.clone(), learn(text));
+    }
+
+    pub fn set(&mut self, uri: Url, learned: Vec<Definition>) {
+        match learned.is_empty() {
+            true => _ = self.files.remove(&uri),
+            false => _ = self.files.insert(uri, learned),
+        }
+    }
+
+    /// What `term` means, as configured or else as most often defined.
+    pub fn meaning(&self, term: &str) -> Option<&str> {
+        if let Some(meaning) = self.configured.get(term) {
+            return Some(meaning);
+        }
+        most_common(
+            self.files
+                .values()
+                .flatten()
+                .filter(|learned| learned.term == term)
+                .map(|learned| learned.meaning.as_str()),
+        )
+    }
+
+    /// Every term, in order, with what it means and the files defining it.
+    pub fn entries(&self) -> Vec<GlossaryEntry> {
+        let mut defined: BTreeMap<&str, Vec<(&str, &Url)>> = BTreeMap::new();
+        for (uri, learned) in &self.files {
+            for definition in learned {
+                defined
+                    .entry(definition.term.as_str())
+                    .or_default()
+                    .push((definition.meaning.as_str(), uri));
+            }
+        }
+        for term in self.configured.keys() {
+            defined.entry(term.as_str()).or_default();
+        }
+        defined
+            .into_iter()
+            .map(|(term, definitions)| GlossaryEntry {
+                term: term.to_string(),
+                meaning: self
+                    .configured
+                    .get(term)
+                    .map(String::as_str)
+                    .or_else(|| most_common(definitions.iter().map(|(meaning, _)| *meaning)))
+                    .unwrap_or_default()
+                    .to_string(),
+                defined_in: definitions
+                    .into_iter()
+                    .map(|(_, uri)| uri.clone())
+                    .sorted()
+                    .dedup()
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// The meaning given the most, the first in order on ties.
+fn most_common<'a>(meanings: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for meaning in meanings {
+        *counts.entry(meaning).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))
+        .map(|(meaning, _)| meaning)
+}
+
+/// Whether `word` looks like an acronym: two or more letters or digits starting with a
+/// capital, most of them capitals.
+pub fn is_acronym(word: &str) -> bool {
+    let capitals = word.chars().filter(|c| c.is_uppercase()).count();
+    word.chars().count() >= 2
+        && word.starts_with(char::is_uppercase)
+        && word.chars().all(char::is_alphanumeric)
+        && capitals * 2 > word.chars().count()
+}
+
+/// The definitions `text` introduces: acronyms in parentheses after what they stand for,
+/// or the other way around, whose words start with the capitals of the acronym in order,
+/// and aliases after `also known as` or `aka`.
+pub fn learn(text: &str) -> Vec<Definition> {
+    let tokens = Token::lexer(text)
+        .spanned()
+        .filter_map(|(token, span)| Some((token.ok()?, span)))
+        .filter(|(token, _)| !matches!(token, Token::Symbol(" " | "\t")))
+        .collect_vec();
+    let word = |i: usize| match tokens.get(i) {
+        Some((Token::Word(word), span)) => Some((*word, span.start)),
+        _ => None,
+    };
+    let symbol = |i: usize, symbol: &str| matches!(tokens.get(i), Some((Token::Symbol(s), _)) if *s == symbol);
+    let mut learned = Vec::new();
+    // Where the sentence started, as definitions don't span sentences
+    let mut sentence = 0;
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        let before = || (sentence..i).filter_map(word).collect_vec();
+        match token {
+            Token::Symbol("." | "\n" | ":" | ";") => sentence = i + 1,
+            // `Full Name (FN)`
+            Token::Symbol("(") if word(i + 1).is_some() && symbol(i + 2, ")") => {
+                let (acronym, _) = word(i + 1).expect("checked above");
+                if let Some(start) = expansion_start(acronym, &before()) {
+                    learned.push(Definition {
+                        term: acronym.to_string(),
+                        meaning: text[start..span.start].trim_end().to_string(),
+                    });
+                }
+            }
+            // `FN (Full Name)`
+            Token::Symbol("(") if i > sentence && word(i - 1).is_some() => {
+                let (acronym, _) = word(i - 1).expect("checked above");
+                let inside = (i + 1..).map_while(word).collect_vec();
+                let close = i + 1 + inside.len();
+                let Some(&(_, first)) = inside.first() else {
+                    continue;
+                };
+                if symbol(close, ")") && expansion_start(acronym, &inside) == Some(first) {
+                    learned.push(Definition {
+                        term: acronym.to_string(),
+                        meaning: text[first..tokens[close].1.start].trim_end().to_string(),
+                    });
+                }
+            }
+            // `Full Name, also known as FN` and `Full Name, aka FN`
+            Token::Word("also" | "aka") if i > sentence && symbol(i - 1, ",") => {
+                let alias = match token {
+                    Token::Word("also") => {
+                        let known_as = word(i + 1).is_some_and(|(w, _)| w == "known")
+                            && word(i + 2).is_some_and(|(w, _)| w == "as");
+                        if !known_as {
+                            continue;
+                        }
+                        i + 3
+                    }
+                    _ => i + 1,
+                };
+                let Some(start) = name_start(&(sentence..i - 1).filter_map(word).collect_vec())
+                else {
+                    continue;
+                };
+                let words = (alias..)
+                    .map_while(word)
+                    .take(MAX_ALIAS_WORDS)
+                    .collect_vec();
+                let (Some(&(_, alias_start)), Some(&(last, last_start))) =
+                    (words.first(), words.last())
+                else {
+                    continue;
+                };
+                learned.push(Definition {
+                    term: text[alias_start..last_start + last.len()].to_string(),
+                    meaning: text[start..tokens[i - 1].1.start].to_string(),
+                });
+            }
+            _ => {}
+        }
+    }
+    learned
+}
+
+/// Where the words that `acronym` stands for start, matching its capitals to their
+/// initials from the last word back.
+fn expansion_start(acronym: &str, words: &[(&str, usize)]) -> Option<usize> {
+    if !is_acronym(acronym) {
+        return None;
+    }
+    let mut initials = acronym
+        .chars()
+        .filter(|c| c.is_uppercase())
+        .rev()
+        .peekable();
+    let mut start = None;
+    for &(word, offset) in words.iter().rev() {
+        let Some(&initial) = initials.peek() else {
+            break;
+        };
+        let first = word.chars().next()?;
+        if first.to_uppercase().eq(initial.to_uppercase()) {
+            initials.next();
+            start = Some(offset);
+        } else if !CONNECTIVES.contains(&word.to_lowercase().as_str()) || start.is_none() {
+            return None;
+        }
+    }
+    initials.peek().is_none().then_some(start).flatten()
+}
+
+/// Where the name ending `words` starts: its capitalized words and the connectives
+/// between them, or else its last word.
+fn name_start(words: &[(&str, usize)]) -> Option<usize> {
+    let capitalized = |word: &str| word.starts_with(char::is_uppercase);
+    let &(_, mut start) = words.last()?;
+    let mut rest = &words[..words.len() - 1];
+    if !words.last().is_some_and(|(word, _)| capitalized(word)) {
+        return Some(start);
+    }
+    while let Some((&(word, offset), before)) = rest.split_last() {
+        let connects =
+            CONNECTIVES.contains(&word) && before.last().is_some_and(|(word, _)| capitalized(word));
+        if !capitalized(word) && !connects {
+            break;
+        }
+        if capitalized(word) {
+            start = offset;
+        }
+        rest = before;
+    }
+    Some(start)
+}
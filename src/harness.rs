@@ -0,0 +1,220 @@
+//! A client driving a server on an in-memory connection, for the conformance suite and
+//! soak runs.
+
+use crate::server::{self, Server, Stop};
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidOpenTextDocument, Initialized, Notification as _,
+};
+use lsp_types::request::{Initialize, Request as _};
+use lsp_types::{
+    ClientCapabilities, CompletionParams, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+    InitializeParams, InitializedParams, Position, Range, TextDocumentContentChangeEvent,
+    TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams, Url,
+    VersionedTextDocumentIdentifier,
+};
+use serde_json::Value;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long the server has to answer a request before it's taken for hung.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The client side of a server running on a thread of its own.
+pub struct Client {
+    connection: Connection,
+    server: Option<JoinHandle<Result<Stop, String>>>,
+    next_id: i32,
+    /// The methods of the requests the server sent.
+    server_requests: Vec<String>,
+}
+
+impl Client {
+    pub fn start() -> Self {
+        let (connection, client) = Connection::memory();
+        let server = thread::spawn(move || {
+            let capabilities =
+                serde_json::to_value(server::capabilities()).map_err(|err| err.to_string())?;
+            let params = connection
+                .initialize(capabilities)
+                .map_err(|err| err.to_string())?;
+            let params: InitializeParams =
+                serde_json::from_value(params).map_err(|err| err.to_string())?;
+            let runtime = tokio::runtime::Runtime::new().map_err(|err| err.to_string())?;
+            let server =
+                Server::new(connection, params, None, None).map_err(|err| err.to_string())?;
+            let stop = runtime.block_on(server.run(None));
+            runtime.shutdown_background();
+            stop.map_err(|err| err.to_string())
+        });
+        Self {
+            connection: client,
+            server: Some(server),
+            next_id: 0,
+            server_requests: Vec::new(),
+        }
+    }
+
+    /// Initializes the server for a client of no capabilities.
+    pub fn initialize(&mut self) -> Result<Response, String> {
+        self.initialize_with(None)
+    }
+
+    /// Like [`Client::initialize`] with the settings of `initializationOptions`.
+    pub fn initialize_with(&mut self, settings: Option<Value>) -> Result<Response, String> {
+        let params = InitializeParams {
+            capabilities: ClientCapabilities::default(),
+            initialization_options: settings,
+            ..Default::default()
+        };
+        let response = self.request(Initialize::METHOD, params)?;
+        self.notify(Initialized::METHOD, InitializedParams {})?;
+        Ok(response)
+    }
+
+    pub fn request(
+        &mut self,
+        method: &str,
+        params: impl serde::Serialize,
+    ) -> Result<Response, String> {
+        let id = self.send_request(method, params)?;
+        self.response(id)
+    }
+
+    pub fn send_request(
+        &mut self,
+        method: &str,
+        params: impl serde::Serialize,
+    ) -> Result<RequestId, String> {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        let request = Request::new(id.clone(), method.to_string(), params);
+        self.send(Message::Request(request))?;
+        Ok(id)
+    }
+
+    pub fn notify(&mut self, method: &str, params: impl serde::Serialize) -> Result<(), String> {
+        self.send(Message::Notification(Notification::new(
+            method.to_string(),
+            params,
+        )))
+    }
+
+    fn send(&self, message: Message) -> Result<(), String> {
+        self.connection
+            .sender
+            .send(message)
+            .map_err(|_| "the server stopped".to_string())
+    }
+
+    /// The id of the request sent last.
+    pub fn last_id(&self) -> i32 {
+        self.next_id
+    }
+
+    /// The methods of the requests the server sent so far.
+    pub fn server_requests(&self) -> &[String] {
+        &self.server_requests
+    }
+
+    /// Waits for the response to `id`, answering what the server asks meanwhile.
+    pub fn response(&mut self, id: RequestId) -> Result<Response, String> {
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.connection.receiver.recv_timeout(left) {
+                Ok(Message::Response(response)) if response.id == id => return Ok(response),
+                Ok(Message::Response(_) | Message::Notification(_)) => {}
+                Ok(Message::Request(request)) => {
+                    self.server_requests.push(request.method.clone());
+                    self.send(Message::Response(Response::new_ok(request.id, ())))?;
+                }
+                Err(_) => {
+                    return Err(format!(
+                        "no response to request {id} within {}s",
+                        RESPONSE_TIMEOUT.as_secs()
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Disconnects, which the server takes for an exit, and waits for it to stop.
+    pub fn stop(mut self) {
+        let Some(server) = self.server.take() else {
+            return;
+        };
+        drop(self.connection);
+        match server.join() {
+            Ok(Err(err)) => log::error!("the server failed: {err}"),
+            Err(_) => log::error!("the server panicked"),
+            Ok(Ok(_)) => {}
+        }
+    }
+
+    /// Waits for the server to stop on its own.
+    pub fn stopped(&mut self) -> Result<Stop, String> {
+        let server = self.server.take().ok_or("the server was already stopped")?;
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        while !server.is_finished() {
+            if Instant::now() > deadline {
+                self.server = Some(server);
+                return Err(format!(
+                    "still running {}s after exit",
+                    RESPONSE_TIMEOUT.as_secs()
+                ));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        server
+            .join()
+            .map_err(|_| "the server panicked".to_string())?
+    }
+
+    pub fn open(&mut self, uri: &Url, text: &str) -> Result<(), String> {
+        self.notify(
+            DidOpenTextDocument::METHOD,
+            DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(
+                    uri.clone(),
+                    "plaintext".to_string(),
+                    1,
+                    text.to_string(),
+                ),
+            },
+        )
+    }
+
+    /// Replaces `range` of the document with `text`.
+    pub fn change(
+        &mut self,
+        uri: &Url,
+        version: i32,
+        range: Range,
+        text: &str,
+    ) -> Result<(), String> {
+        self.notify(
+            DidChangeTextDocument::METHOD,
+            DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri.clone(), version),
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: Some(range),
+                    range_length: None,
+                    text: text.to_string(),
+                }],
+            },
+        )
+    }
+}
+
+pub fn completion_params(uri: &Url, position: Position) -> CompletionParams {
+    CompletionParams {
+        text_document_position: TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(uri.clone()),
+            position,
+        ),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    }
+}
@@ -0,0 +1,418 @@
+use crate::collation::Collator;
+use crate::config::{IndexConfig, StorageKind};
+use crate::{context, is_word_char, Token};
+use logos::Logos;
+use lsp_types::Url;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod duplicates;
+mod shared;
+mod storage;
+
+use duplicates::Duplicates;
+pub use shared::{SharedIndex, POLL_INTERVAL as SHARED_POLL_INTERVAL};
+#[cfg(feature = "sled")]
+pub use storage::SledStorage;
+pub use storage::{Frequency, IndexStorage, MemoryStorage, WordCounts};
+
+/// Word frequencies of every indexed document.
+///
+/// Occurrences with a [symbol context](crate::context::symbol_context) are also counted
+/// under `{symbol}{word}`, which can't collide with a plain word.
+///
+/// Documents are spread over shards by a hash of their uri, each shard counting the
+/// frequencies of its own documents, so queries and batches of updates run on every
+/// shard at once.
+///
+/// Frequencies decay with the time since a document using the word was last indexed,
+/// so the vocabulary of deleted files fades out of the on-disk index.
+///
+/// Documents of the same content, like vendored copies, are counted once, see
+/// [`Duplicates`].
+pub struct Index {
+    shards: Vec<Box<dyn IndexStorage>>,
+    /// Seconds for a frequency to decay to half of itself, `None` not to decay.
+    half_life: Option<f64>,
+    /// Frequencies published by another instance, see [`SharedIndex`].
+    shared: BTreeMap<String, u64>,
+    context_symbols: Vec<char>,
+    /// Orders the words of equal frequency.
+    collator: Collator,
+    duplicates: Duplicates,
+}
+
+impl Index {
+    pub fn open(config: &IndexConfig, data_dir: Option<&Path>) -> io::Result<Self> {
+        let count = config.shards.max(1);
+        let memory = || {
+            (0..count)
+                .map(|_| Box::new(MemoryStorage::default()) as Box<dyn IndexStorage>)
+                .collect()
+        };
+        let shards = match config.storage {
+            StorageKind::Memory => memory(),
+            #[cfg(feature = "sled")]
+            StorageKind::Disk => {
+                let path = config
+                    .path
+                    .clone()
+                    .or_else(|| data_dir.map(|dir| dir.join("index")))
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no path for the on-disk index")
+                    })?;
+                SledStorage::open_shards(&path, count)?
+                    .into_iter()
+                    .map(|shard| Box::new(shard) as Box<dyn IndexStorage>)
+                    .collect()
+            }
+            #[cfg(not(feature = "sled"))]
+            StorageKind::Disk => {
+                let _ = data_dir;
+                log::warn!("built without the `sled` feature, using in-memory index storage");
+                memory()
+            }
+        };
+        Ok(Self {
+            shards,
+            half_life: config
+                .decay_half_life_days
+                .map(|days| days * 24.0 * 60.0 * 60.0),
+            shared: BTreeMap::new(),
+            context_symbols: Vec::new(),
+            collator: Collator::default(),
+            duplicates: Duplicates::default(),
+        })
+    }
+
+    /// Also count the words used with any of `symbols`, see [`Index::words_after`].
+    pub fn with_context_symbols(mut self, mut symbols: Vec<char>) -> Self {
+        symbols.retain(|&c| !is_word_char(c));
+        self.context_symbols = symbols;
+        self
+    }
+
+    pub fn with_collator(mut self, collator: Collator) -> Self {
+        self.collator = collator;
+        self
+    }
+
+    pub fn collator(&self) -> &Collator {
+        &self.collator
+    }
+
+    pub fn update(&mut self, uri: &Url, text: &str) -> io::Result<()> {
+        let counts = count_words(text, &self.context_symbols);
+        self.set_counts(uri, content_hash(text), counts)
+    }
+
+    /// The counts stored for `uri`, or for the copy of it counted.
+    pub fn document(&self, uri: &Url) -> io::Result<Option<WordCounts>> {
+        let counted = self.duplicates.counted(uri);
+        self.shard(counted.as_str()).document(counted.as_str())
+    }
+
+    pub fn context_symbols(&self) -> &[char] {
+        &self.context_symbols
+    }
+
+    /// Indexed words starting with `prefix`, most frequent first.
+    pub fn words_with_prefix(&self, prefix: &str) -> io::Result<Vec<(String, u64)>> {
+        let mut words = self.entries_with_prefix(prefix)?;
+        // An empty prefix would also match the contextual entries
+        words.retain(|(word, _)| !word.starts_with(|c: char| self.context_symbols.contains(&c)));
+        self.sort_by_frequency(&mut words);
+        Ok(words)
+    }
+
+    /// When a document using each indexed word starting with `prefix` was last indexed,
+    /// in seconds since the Unix epoch, `0` when that isn't known.
+    pub fn last_seen_with_prefix(&self, prefix: &str) -> io::Result<HashMap<String, u64>> {
+        let shards = self.each_shard(|shard| shard.words_with_prefix(prefix))?;
+        let mut last_seen = HashMap::new();
+        for (word, frequency) in shards.into_iter().flatten() {
+            if word.starts_with(|c: char| self.context_symbols.contains(&c)) {
+                continue;
+            }
+            let seen = match frequency.seen {
+                u64::MAX => 0,
+                seen => seen,
+            };
+            let last = last_seen.entry(word).or_default();
+            *last = seen.max(*last);
+        }
+        Ok(last_seen)
+    }
+
+    /// Words starting with `prefix` that were used with `symbol`, most frequent first.
+    pub fn words_after(&self, symbol: char, prefix: &str) -> io::Result<Vec<(String, u64)>> {
+        let mut words = self.entries_with_prefix(&format!("{symbol}{prefix}"))?;
+        for (word, _) in &mut words {
+            word.remove(0);
+        }
+        self.sort_by_frequency(&mut words);
+        Ok(words)
+    }
+
+    /// The indexed documents using `word`, in no particular order.
+    pub fn documents_with(&self, word: &str) -> io::Result<Vec<Url>> {
+        Ok(self.occurrences(word)?.into_keys().collect())
+    }
+
+    /// How many times each indexed document uses `word`, leaving out those that don't.
+    pub fn occurrences(&self, word: &str) -> io::Result<BTreeMap<Url, u32>> {
+        let shards = self.each_shard(|shard| {
+            let mut occurrences = Vec::new();
+            for uri in shard.documents()? {
+                let count = shard
+                    .document(&uri)?
+                    .and_then(|counts| counts.get(word).copied());
+                if let (Some(count), Ok(uri)) = (count, Url::parse(&uri)) {
+                    occurrences.push((uri, count));
+                }
+            }
+            Ok(occurrences)
+        })?;
+        let mut occurrences: BTreeMap<Url, u32> = shards.into_iter().flatten().collect();
+        let copies = occurrences
+            .iter()
+            .flat_map(|(uri, &count)| self.duplicates.copies(uri).map(move |copy| (copy, count)))
+            .map(|(copy, count)| (copy.clone(), count))
+            .collect::<Vec<_>>();
+        occurrences.extend(copies);
+        Ok(occurrences)
+    }
+
+    /// Every entry, contextual ones included, as shared with other instances.
+    pub fn snapshot(&self) -> io::Result<BTreeMap<String, u64>> {
+        Ok(self.entries_with_prefix("")?.into_iter().collect())
+    }
+
+    fn sort_by_frequency(&self, words: &mut [(String, u64)]) {
+        words.sort_by(|(a, a_freq), (b, b_freq)| {
+            b_freq.cmp(a_freq).then_with(|| self.collator.compare(a, b))
+        });
+    }
+
+    fn entries_with_prefix(&self, prefix: &str) -> io::Result<Vec<(String, u64)>> {
+        let now = now();
+        let decayed = |frequency: Frequency| match self.half_life {
+            Some(half_life) if frequency.seen < now => {
+                let age = (now - frequency.seen) as f64;
+                let count = frequency.count as f64 * 0.5_f64.powf(age / half_life);
+                (count.round() as u64).max(1)
+            }
+            _ => frequency.count,
+        };
+        let mut shards = self.each_shard(|shard| {
+            let words = shard.words_with_prefix(prefix)?;
+            Ok(words
+                .into_iter()
+                .map(|(word, frequency)| (word, decayed(frequency)))
+                .collect::<Vec<_>>())
+        })?;
+        if shards.len() == 1 && self.shared.is_empty() {
+            return Ok(shards.remove(0));
+        }
+        let mut merged: BTreeMap<String, u64> = self
+            .shared
+            .range(prefix.to_string()..)
+            .take_while(|(word, _)| word.starts_with(prefix))
+            .map(|(word, freq)| (word.clone(), *freq))
+            .collect();
+        for (word, freq) in shards.into_iter().flatten() {
+            *merged.entry(word).or_default() += freq;
+        }
+        Ok(merged.into_iter().collect())
+    }
+
+    /// Runs `f` on every shard, in parallel when there are several.
+    fn each_shard<T: Send>(
+        &self,
+        f: impl Fn(&dyn IndexStorage) -> io::Result<T> + Sync,
+    ) -> io::Result<Vec<T>> {
+        if let [shard] = &self.shards[..] {
+            return Ok(vec![f(shard.as_ref())?]);
+        }
+        thread::scope(|scope| {
+            let f = &f;
+            let running: Vec<_> = self
+                .shards
+                .iter()
+                .map(|shard| scope.spawn(move || f(shard.as_ref())))
+                .collect();
+            running
+                .into_iter()
+                .map(|shard| shard.join().expect("an index shard panicked"))
+                .collect()
+        })
+    }
+
+    fn shard(&self, uri: &str) -> &dyn IndexStorage {
+        self.shards[shard_of(uri, self.shards.len())].as_ref()
+    }
+
+    pub fn set_shared(&mut self, shared: BTreeMap<String, u64>) {
+        self.shared = shared;
+    }
+
+    /// Forgets every document and frequency, the shared ones too.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.shared.clear();
+        self.duplicates.clear();
+        for shard in &mut self.shards {
+            shard.clear()?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the counts of `uri` with ones from [`count_words`] for content of
+    /// [`content_hash`] `hash`.
+    pub fn set_counts(&mut self, uri: &Url, hash: u64, counts: WordCounts) -> io::Result<()> {
+        let now = now();
+        for (uri, counts) in self.deduplicate(uri, hash, counts)? {
+            let shard = shard_of(uri.as_str(), self.shards.len());
+            replace_counts(self.shards[shard].as_mut(), uri.as_str(), &counts, now)?;
+        }
+        Ok(())
+    }
+
+    /// Like [`Index::set_counts`] for many documents, updating the shards in parallel.
+    /// Returns the documents that couldn't be indexed.
+    pub fn set_many_counts(
+        &mut self,
+        documents: Vec<(Url, u64, WordCounts)>,
+    ) -> Vec<(Url, io::Error)> {
+        let now = now();
+        let mut failed = Vec::new();
+        let mut by_shard: Vec<Vec<(Url, WordCounts)>> =
+            self.shards.iter().map(|_| Vec::new()).collect();
+        for (uri, hash, counts) in documents {
+            let writes = match self.deduplicate(&uri, hash, counts) {
+                Ok(writes) => writes,
+                Err(err) => {
+                    failed.push((uri, err));
+                    continue;
+                }
+            };
+            for (uri, counts) in writes {
+                by_shard[shard_of(uri.as_str(), self.shards.len())].push((uri, counts));
+            }
+        }
+        thread::scope(|scope| {
+            let running: Vec<_> = self
+                .shards
+                .iter_mut()
+                .zip(by_shard)
+                .filter(|(_, documents)| !documents.is_empty())
+                .map(|(shard, documents)| {
+                    scope.spawn(move || {
+                        documents
+                            .into_iter()
+                            .filter_map(|(uri, counts)| {
+                                let done =
+                                    replace_counts(shard.as_mut(), uri.as_str(), &counts, now);
+                                done.err().map(|err| (uri, err))
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            running
+                .into_iter()
+                .flat_map(|shard| shard.join().expect("an index shard panicked"))
+                .chain(failed)
+                .collect()
+        })
+    }
+
+    /// What to store for `uri` now of the content of `hash`: its counts, or none when
+    /// a copy of it is counted already, and the counts it had for the copy counted in
+    /// its place.
+    fn deduplicate(
+        &mut self,
+        uri: &Url,
+        hash: u64,
+        counts: WordCounts,
+    ) -> io::Result<Vec<(Url, WordCounts)>> {
+        let placement = self.duplicates.place(uri, hash);
+        let mut writes = Vec::new();
+        if let Some(successor) = placement.successor {
+            let stored = self.shard(uri.as_str()).document(uri.as_str())?;
+            writes.push((successor, stored.unwrap_or_default()));
+        }
+        let counts = match placement.counted {
+            true => counts,
+            false => WordCounts::new(),
+        };
+        writes.push((uri.clone(), counts));
+        Ok(writes)
+    }
+}
+
+fn replace_counts(
+    shard: &mut dyn IndexStorage,
+    uri: &str,
+    counts: &WordCounts,
+    now: u64,
+) -> io::Result<()> {
+    if let Some(old) = shard.document(uri)? {
+        for (word, count) in old {
+            shard.add_frequency(&word, -i64::from(count), now)?;
+        }
+    }
+    for (word, count) in counts {
+        shard.add_frequency(word, i64::from(*count), now)?;
+    }
+    shard.set_document(uri, counts)
+}
+
+/// Seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// The shard of `uri`, by its FNV-1a hash, which unlike the std hasher stays the same
+/// across builds for the on-disk shards.
+fn shard_of(uri: &str, shards: usize) -> usize {
+    (fnv(uri.as_bytes()) % shards as u64) as usize
+}
+
+/// What [`Index::set_counts`] tells documents of the same content by, for counting off
+/// the thread owning the index.
+pub fn content_hash(text: &str) -> u64 {
+    fnv(text.as_bytes())
+}
+
+/// The FNV-1a hash of `bytes`.
+fn fnv(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The word counts [`Index::update`] stores for `text`, for counting off the thread
+/// owning the index.
+pub fn count_words(text: &str, context_symbols: &[char]) -> WordCounts {
+    let mut counts = WordCounts::new();
+    let mut line_start = 0;
+    for (token, span) in Token::lexer(text).spanned() {
+        match token {
+            Ok(Token::Word(word)) => {
+                *counts.entry(word.to_string()).or_default() += 1;
+                let before = &text[line_start..span.start];
+                if let Some(symbol) = context::symbol_context(before, context_symbols) {
+                    *counts.entry(format!("{symbol}{word}")).or_default() += 1;
+                }
+            }
+            Ok(Token::Symbol("\n")) => line_start = span.end,
+            _ => {}
+        }
+    }
+    counts
+}
@@ -0,0 +1,72 @@
+use lsp_types::Url;
+use std::collections::HashMap;
+
+/// Which documents have the same content, like vendored copies, of which only one is
+/// counted for the frequencies not to be skewed by how many copies there are.
+#[derive(Debug, Default)]
+pub struct Duplicates {
+    hashes: HashMap<Url, u64>,
+    /// The documents of each content, the counted one first.
+    copies: HashMap<u64, Vec<Url>>,
+}
+
+/// What storing a document of some content takes.
+pub struct Placement {
+    /// Whether the document is the counted copy of its content.
+    pub counted: bool,
+    /// The copy counted in place of the document, when the document was counted for
+    /// content it no longer has.
+    pub successor: Option<Url>,
+}
+
+impl Duplicates {
+    /// Files `uri` under the content of `hash`, out of what it had.
+    pub fn place(&mut self, uri: &Url, hash: u64) -> Placement {
+        if self.hashes.get(uri) == Some(&hash) {
+            return Placement {
+                counted: self.counted(uri) == uri,
+                successor: None,
+            };
+        }
+        let mut successor = None;
+        if let Some(old) = self.hashes.insert(uri.clone(), hash) {
+            let copies = self.copies.entry(old).or_default();
+            if copies.first() == Some(uri) {
+                successor = copies.get(1).cloned();
+            }
+            copies.retain(|copy| copy != uri);
+            if copies.is_empty() {
+                self.copies.remove(&old);
+            }
+        }
+        let copies = self.copies.entry(hash).or_default();
+        copies.push(uri.clone());
+        Placement {
+            counted: copies.len() == 1,
+            successor,
+        }
+    }
+
+    /// The copy counted for the content of `uri`, `uri` itself when it has no copies.
+    pub fn counted<'a>(&'a self, uri: &'a Url) -> &'a Url {
+        self.hashes
+            .get(uri)
+            .and_then(|hash| self.copies.get(hash)?.first())
+            .unwrap_or(uri)
+    }
+
+    /// The documents with the same content as `uri`, but for `uri`.
+    pub fn copies<'a>(&'a self, uri: &'a Url) -> impl Iterator<Item = &'a Url> + 'a {
+        self.hashes
+            .get(uri)
+            .and_then(|hash| self.copies.get(hash))
+            .into_iter()
+            .flatten()
+            .filter(move |copy| *copy != uri)
+    }
+
+    pub fn clear(&mut self) {
+        self.hashes.clear();
+        self.copies.clear();
+    }
+}
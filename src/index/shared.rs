@@ -0,0 +1,134 @@
+use super::{count_words, Index};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(30);
+/// How often instances look at the lock and the snapshot.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Coordinates the server instances running on the same workspace.
+///
+/// The instance holding the writer lock indexes the workspace and periodically
+/// publishes its word frequencies to a snapshot. The others only load that snapshot,
+/// and one of them takes over once the writer goes away.
+pub struct SharedIndex {
+    dir: PathBuf,
+    lock: Option<File>,
+    last_sync: Instant,
+    /// When the snapshot loaded was written, and the open documents left out of it.
+    loaded: Option<(SystemTime, Vec<PathBuf>)>,
+    dirty: bool,
+}
+
+impl SharedIndex {
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut shared = Self {
+            dir,
+            lock: None,
+            last_sync: Instant::now(),
+            loaded: None,
+            dirty: false,
+        };
+        shared.try_lock()?;
+        Ok(shared)
+    }
+
+    pub fn is_writer(&self) -> bool {
+        self.lock.is_some()
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Writers publish pending changes, readers pick up new snapshots and try to get
+    /// elected. Returns `true` when this instance just became the writer and has to
+    /// index the workspace itself.
+    ///
+    /// `open` are the files open in this instance, see [`SharedIndex::load`].
+    pub fn tick(&mut self, index: &mut Index, open: &[PathBuf]) -> io::Result<bool> {
+        if self.is_writer() {
+            if self.dirty && self.last_sync.elapsed() >= PUBLISH_INTERVAL {
+                self.publish(index)?;
+            }
+            return Ok(false);
+        }
+        if self.last_sync.elapsed() < POLL_INTERVAL {
+            return Ok(false);
+        }
+        self.last_sync = Instant::now();
+        if self.try_lock()? {
+            log::info!("became the index writer for {}", self.dir.display());
+            index.set_shared(BTreeMap::new());
+            return Ok(true);
+        }
+        self.load(index, open)?;
+        Ok(false)
+    }
+
+    pub fn publish(&mut self, index: &Index) -> io::Result<()> {
+        let tmp = self.dir.join("snapshot.json.tmp");
+        fs::write(&tmp, serde_json::to_vec(&index.snapshot()?)?)?;
+        fs::rename(tmp, self.snapshot_path())?;
+        self.dirty = false;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Loads the snapshot into `index`, but for the words of the `open` files as the
+    /// writer read them from disk, which `index` counts as they're edited here.
+    pub fn load(&mut self, index: &mut Index, open: &[PathBuf]) -> io::Result<()> {
+        let modified = match fs::metadata(self.snapshot_path()) {
+            Ok(metadata) => metadata.modified()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if self
+            .loaded
+            .as_ref()
+            .is_some_and(|(time, loaded)| *time == modified && loaded == open)
+        {
+            return Ok(());
+        }
+        let mut snapshot: BTreeMap<String, u64> =
+            serde_json::from_slice(&fs::read(self.snapshot_path())?)?;
+        for path in open {
+            let Ok(text) = fs::read_to_string(path) else {
+                continue;
+            };
+            for (word, count) in count_words(&text, index.context_symbols()) {
+                if let Some(frequency) = snapshot.get_mut(&word) {
+                    *frequency = frequency.saturating_sub(u64::from(count));
+                }
+            }
+        }
+        snapshot.retain(|_, frequency| *frequency > 0);
+        index.set_shared(snapshot);
+        self.loaded = Some((modified, open.to_vec()));
+        Ok(())
+    }
+
+    fn try_lock(&mut self) -> io::Result<bool> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join("writer.lock"))?;
+        match file.try_lock() {
+            Ok(()) => {
+                self.lock = Some(file);
+                Ok(true)
+            }
+            Err(TryLockError::WouldBlock) => Ok(false),
+            Err(TryLockError::Error(err)) => Err(err),
+        }
+    }
+
+    fn snapshot_path(&self) -> PathBuf {
+        self.dir.join("snapshot.json")
+    }
+}
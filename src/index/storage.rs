@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+pub type WordCounts = HashMap<String, u32>;
+
+/// How often a word is used across the documents of a storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frequency {
+    pub count: u64,
+    /// When a document using the word was last indexed, in seconds since the Unix
+    /// epoch. `u64::MAX` when that isn't known.
+    pub seen: u64,
+}
+
+/// Backing store for the [`Index`](super::Index).
+///
+/// Keeps the word counts of each document plus the total frequency of each word
+/// across all documents, the latter being kept up to date by the index.
+pub trait IndexStorage: Send + Sync {
+    fn document(&self, uri: &str) -> io::Result<Option<WordCounts>>;
+    fn set_document(&mut self, uri: &str, counts: &WordCounts) -> io::Result<()>;
+    /// Also marks the word as seen `now` when `delta` adds to it.
+    fn add_frequency(&mut self, word: &str, delta: i64, now: u64) -> io::Result<()>;
+    fn words_with_prefix(&self, prefix: &str) -> io::Result<Vec<(String, Frequency)>>;
+    /// The uris of every indexed document.
+    fn documents(&self) -> io::Result<Vec<String>>;
+    /// Forgets every document and frequency.
+    fn clear(&mut self) -> io::Result<()>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    documents: HashMap<String, WordCounts>,
+    frequencies: BTreeMap<String, Frequency>,
+}
+
+impl IndexStorage for MemoryStorage {
+    fn document(&self, uri: &str) -> io::Result<Option<WordCounts>> {
+        Ok(self.documents.get(uri).cloned())
+    }
+
+    fn set_document(&mut self, uri: &str, counts: &WordCounts) -> io::Result<()> {
+        self.documents.insert(uri.to_string(), counts.clone());
+        Ok(())
+    }
+
+    fn add_frequency(&mut self, word: &str, delta: i64, now: u64) -> io::Result<()> {
+        let freq = self
+            .frequencies
+            .entry(word.to_string())
+            .or_insert(Frequency {
+                count: 0,
+                seen: now,
+            });
+        freq.count = freq.count.saturating_add_signed(delta);
+        if delta > 0 {
+            freq.seen = now;
+        }
+        if freq.count == 0 {
+            self.frequencies.remove(word);
+        }
+        Ok(())
+    }
+
+    fn words_with_prefix(&self, prefix: &str) -> io::Result<Vec<(String, Frequency)>> {
+        Ok(self
+            .frequencies
+            .range(prefix.to_string()..)
+            .take_while(|(word, _)| word.starts_with(prefix))
+            .map(|(word, freq)| (word.clone(), *freq))
+            .collect())
+    }
+
+    fn documents(&self) -> io::Result<Vec<String>> {
+        Ok(self.documents.keys().cloned().collect())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.documents.clear();
+        self.frequencies.clear();
+        Ok(())
+    }
+}
+
+/// On-disk storage so large corpora don't have to fit in memory.
+#[cfg(feature = "sled")]
+pub struct SledStorage {
+    documents: sled::Tree,
+    frequencies: sled::Tree,
+}
+
+#[cfg(feature = "sled")]
+impl SledStorage {
+    /// The `count` shards of the index at `path`, each in its own trees. Changing the
+    /// count starts over with empty trees.
+    pub fn open_shards(path: &std::path::Path, count: usize) -> io::Result<Vec<Self>> {
+        let db = sled::open(path)?;
+        (0..count)
+            .map(|shard| {
+                let suffix = match count {
+                    1 => String::new(),
+                    _ => format!("-{shard}-of-{count}"),
+                };
+                Ok(Self {
+                    documents: db.open_tree(format!("documents{suffix}"))?,
+                    frequencies: db.open_tree(format!("frequencies{suffix}"))?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "sled")]
+impl IndexStorage for SledStorage {
+    fn document(&self, uri: &str) -> io::Result<Option<WordCounts>> {
+        self.documents
+            .get(uri)?
+            .map(|bytes| serde_json::from_slice(&bytes).map_err(io::Error::from))
+            .transpose()
+    }
+
+    fn set_document(&mut self, uri: &str, counts: &WordCounts) -> io::Result<()> {
+        self.documents.insert(uri, serde_json::to_vec(counts)?)?;
+        Ok(())
+    }
+
+    fn add_frequency(&mut self, word: &str, delta: i64, now: u64) -> io::Result<()> {
+        self.frequencies.fetch_and_update(word, |old| {
+            let old = old.map_or(
+                Frequency {
+                    count: 0,
+                    seen: now,
+                },
+                decode_frequency,
+            );
+            let seen = if delta > 0 { now } else { old.seen };
+            match old.count.saturating_add_signed(delta) {
+                0 => None,
+                count => Some([count.to_be_bytes(), seen.to_be_bytes()].concat()),
+            }
+        })?;
+        Ok(())
+    }
+
+    fn words_with_prefix(&self, prefix: &str) -> io::Result<Vec<(String, Frequency)>> {
+        self.frequencies
+            .scan_prefix(prefix)
+            .map(|entry| {
+                let (word, freq) = entry?;
+                Ok((
+                    String::from_utf8_lossy(&word).into_owned(),
+                    decode_frequency(&freq),
+                ))
+            })
+            .collect()
+    }
+
+    fn documents(&self) -> io::Result<Vec<String>> {
+        self.documents
+            .iter()
+            .keys()
+            .map(|uri| Ok(String::from_utf8_lossy(&uri?).into_owned()))
+            .collect()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.documents.clear()?;
+        self.frequencies.clear()?;
+        Ok(())
+    }
+}
+
+/// Frequencies stored before they kept when they were seen are never decayed.
+#[cfg(feature = "sled")]
+fn decode_frequency(bytes: &[u8]) -> Frequency {
+    let decode = |bytes: &[u8]| bytes.try_into().map_or(0, u64::from_be_bytes);
+    match bytes.len() {
+        16 => Frequency {
+            count: decode(&bytes[..8]),
+            seen: decode(&bytes[8..]),
+        },
+        _ => Frequency {
+            count: decode(bytes),
+            seen: u64::MAX,
+        },
+    }
+}
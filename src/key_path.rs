@@ -0,0 +1,338 @@
+//! The keys of JSON and YAML documents, parsed leniently so that documents broken
+//! mid-edit still have most of their structure.
+
+use itertools::Itertools;
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug)]
+pub struct Key {
+    pub segment: Segment,
+    /// The key itself, or the start of the array item.
+    pub name: Range<usize>,
+    /// From the key to the end of its value.
+    pub span: Range<usize>,
+    pub children: Vec<Key>,
+}
+
+/// The keys of `text`, in the languages whose structure is known.
+pub fn parse(language: &str, text: &str) -> Option<Vec<Key>> {
+    match language {
+        "json" | "jsonc" => Some(Json { text, pos: 0 }.value()),
+        "yaml" => Some(yaml(text)),
+        _ => None,
+    }
+}
+
+/// The keys holding `offset`, outermost first.
+pub fn path_at(keys: &[Key], offset: usize) -> Vec<&Key> {
+    let mut path = Vec::new();
+    let mut level = keys;
+    while let Some(key) = level
+        .iter()
+        .find(|key| key.span.start <= offset && offset <= key.span.end)
+    {
+        path.push(key);
+        level = &key.children;
+    }
+    path
+}
+
+/// `server.completion.max_results` or `plugins[0].path`.
+pub fn format(path: &[&Key]) -> String {
+    path.iter()
+        .enumerate()
+        .map(|(i, key)| match &key.segment {
+            Segment::Key(name) if i == 0 => name.clone(),
+            Segment::Key(name) => format!(".{name}"),
+            Segment::Index(index) => format!("[{index}]"),
+        })
+        .join("")
+}
+
+struct Json<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Json<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(byte) if byte.is_ascii_whitespace() => self.pos += 1,
+                // Comments, as in jsonc
+                Some(b'/') if self.text[self.pos..].starts_with("//") => {
+                    self.pos = self.text[self.pos..]
+                        .find('\n')
+                        .map_or(self.text.len(), |i| self.pos + i);
+                }
+                Some(b'/') if self.text[self.pos..].starts_with("/*") => {
+                    self.pos = self.text[self.pos + 2..]
+                        .find("*/")
+                        .map_or(self.text.len(), |i| self.pos + 2 + i + 2);
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// The keys of the value at the cursor, empty for scalars.
+    fn value(&mut self) -> Vec<Key> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => {
+                self.string();
+                Vec::new()
+            }
+            _ => {
+                self.scalar();
+                Vec::new()
+            }
+        }
+    }
+
+    fn object(&mut self) -> Vec<Key> {
+        self.pos += 1;
+        let mut keys = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some(b'}') => {
+                    self.pos += 1;
+                    break;
+                }
+                // Closes an array the object is in
+                Some(b']') => break,
+                Some(b',') => self.pos += 1,
+                Some(b'"') => {
+                    let start = self.pos;
+                    let name = self.string();
+                    let name_range = start..self.pos;
+                    self.skip_whitespace();
+                    let children = match self.peek() {
+                        Some(b':') => {
+                            self.pos += 1;
+                            self.value()
+                        }
+                        _ => Vec::new(),
+                    };
+                    keys.push(Key {
+                        segment: Segment::Key(name),
+                        name: name_range,
+                        span: start..self.pos,
+                        children,
+                    });
+                }
+                Some(_) => self.scalar(),
+            }
+        }
+        keys
+    }
+
+    fn array(&mut self) -> Vec<Key> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => break,
+                Some(b']') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'}') => break,
+                Some(b',') => self.pos += 1,
+                Some(_) => {
+                    let start = self.pos;
+                    if self.is_key() {
+                        // The array was never closed, the key is of an object around it
+                        break;
+                    }
+                    let children = self.value();
+                    items.push(Key {
+                        segment: Segment::Index(items.len()),
+                        name: start..start + 1,
+                        span: start..self.pos,
+                        children,
+                    });
+                }
+            }
+        }
+        items
+    }
+
+    /// Whether a string and a `:` come next, restoring the position either way.
+    fn is_key(&mut self) -> bool {
+        let start = self.pos;
+        if self.peek() != Some(b'"') {
+            return false;
+        }
+        self.string();
+        self.skip_whitespace();
+        let is_key = self.peek() == Some(b':');
+        self.pos = start;
+        is_key
+    }
+
+    /// Unterminated strings end with their line.
+    fn string(&mut self) -> String {
+        let start = self.pos;
+        self.pos += 1;
+        while let Some(byte) = self.peek() {
+            match byte {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+                    break;
+                }
+                b'\n' => break,
+                _ => self.pos += 1,
+            }
+        }
+        self.pos = self.pos.min(self.text.len());
+        let literal = &self.text[start..self.pos];
+        serde_json::from_str(literal).unwrap_or_else(|_| {
+            literal
+                .trim_start_matches('"')
+                .trim_end_matches('"')
+                .to_string()
+        })
+    }
+
+    /// Numbers, literals and whatever else doesn't parse, always moving on.
+    fn scalar(&mut self) {
+        let rest = &self.text[self.pos..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || ",:{}[]\"".contains(c))
+            .unwrap_or(rest.len());
+        self.pos += len.max(1).min(rest.len());
+    }
+}
+
+/// A key or item of a YAML line, before the tree is built.
+struct Entry {
+    indent: usize,
+    key: Key,
+    /// A key with nothing after its `:`, whose items may be indented as much as it.
+    opens: bool,
+}
+
+fn yaml(text: &str) -> Vec<Key> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let content = line.trim_end();
+        let line_end = line_start + content.len();
+        let mut column = content.len() - content.trim_start_matches(' ').len();
+        let rest = &content[column..];
+        if rest.is_empty() || rest.starts_with('#') || rest.starts_with("---") {
+            continue;
+        }
+        let mut rest = rest;
+        while let Some(item) = rest
+            .strip_prefix('-')
+            .filter(|item| item.is_empty() || item.starts_with(' '))
+        {
+            let start = line_start + column;
+            entries.push(Entry {
+                indent: column,
+                key: Key {
+                    segment: Segment::Index(0),
+                    name: start..start + 1,
+                    span: start..line_end,
+                    children: Vec::new(),
+                },
+                opens: false,
+            });
+            let skipped = 1 + item.len() - item.trim_start_matches(' ').len();
+            column += skipped;
+            rest = &rest[skipped..];
+        }
+        if let Some((name, len)) = yaml_key(rest) {
+            let start = line_start + column;
+            entries.push(Entry {
+                indent: column,
+                key: Key {
+                    segment: Segment::Key(name),
+                    name: start..start + len,
+                    span: start..line_end,
+                    children: Vec::new(),
+                },
+                opens: rest[len + 1..].trim_start().is_empty()
+                    || rest[len + 1..].trim_start().starts_with('#'),
+            });
+        }
+    }
+
+    // Each entry goes under the closest one before it that's less indented, or as
+    // indented but opening a sequence
+    let mut roots = Vec::new();
+    let mut stack: Vec<Entry> = Vec::new();
+    for mut entry in entries {
+        let is_item = matches!(entry.key.segment, Segment::Index(_));
+        while let Some(top) = stack.last() {
+            let parent =
+                top.indent < entry.indent || (top.indent == entry.indent && top.opens && is_item);
+            if parent {
+                break;
+            }
+            let done = stack.pop().expect("the stack isn't empty");
+            attach(&mut stack, &mut roots, done.key);
+        }
+        if is_item {
+            let siblings = stack.last().map_or(&roots, |top| &top.key.children);
+            let index = siblings
+                .iter()
+                .filter(|sibling| matches!(sibling.segment, Segment::Index(_)))
+                .count();
+            entry.key.segment = Segment::Index(index);
+        }
+        stack.push(entry);
+    }
+    while let Some(done) = stack.pop() {
+        attach(&mut stack, &mut roots, done.key);
+    }
+    roots
+}
+
+fn attach(stack: &mut [Entry], roots: &mut Vec<Key>, key: Key) {
+    match stack.last_mut() {
+        Some(parent) => {
+            parent.key.span.end = parent.key.span.end.max(key.span.end);
+            parent.key.children.push(key);
+        }
+        None => roots.push(key),
+    }
+}
+
+/// The key starting `rest` and the length of it as written, quotes included.
+fn yaml_key(rest: &str) -> Option<(String, usize)> {
+    let (name, len) = match rest.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = rest[1..].find(quote)? + 2;
+            (rest[1..end - 1].to_string(), end)
+        }
+        _ => {
+            let len = rest
+                .find(": ")
+                .or_else(|| rest.strip_suffix(':').map(str::len))?;
+            (rest[..len].trim_end().to_string(), len)
+        }
+    };
+    let after = &rest[len..];
+    (after == ":" || after.starts_with(": ") || after.starts_with(":\t")).then_some((name, len))
+}
@@ -0,0 +1,142 @@
+use crate::{commit, log_file};
+use lsp_types::Url;
+use std::path::Path;
+
+/// Language ids clients send when they don't know better.
+const GENERIC: &[&str] = &["", "plaintext", "text"];
+
+/// The files git asks messages to be written in.
+const GIT_MESSAGES: &[&str] = &["COMMIT_EDITMSG", "MERGE_MSG", "SQUASH_MSG", "TAG_EDITMSG"];
+
+/// How many lines at each end of a document are searched for a modeline.
+const MODELINE_LINES: usize = 5;
+
+/// Refines a generic `language_id` from the document itself: its file name, an editor
+/// modeline, a shebang line or front matter, in that order.
+pub fn detect<'a>(language_id: &'a str, uri: &Url, text: &'a str) -> &'a str {
+    if !GENERIC.contains(&language_id) {
+        return language_id;
+    }
+    file_name(uri)
+        .or_else(|| modeline(text))
+        .or_else(|| shebang(text))
+        .or_else(|| front_matter(text))
+        .unwrap_or(language_id)
+}
+
+/// The language of a file the client hasn't opened, going by its extension.
+pub fn of_file<'a>(uri: &'a Url, text: &'a str) -> &'a str {
+    let language = Path::new(uri.path())
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or("plaintext", alias);
+    detect(language, uri, text)
+}
+
+/// `gitcommit` for the files git asks commit, merge and tag messages in, and the
+/// extensions of logs and tables.
+fn file_name(uri: &Url) -> Option<&'static str> {
+    let name = uri.path_segments()?.next_back()?;
+    match Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("log") => return Some(log_file::LANGUAGE),
+        Some("csv") => return Some("csv"),
+        Some("tsv") => return Some("tsv"),
+        _ => {}
+    }
+    GIT_MESSAGES.contains(&name).then_some(commit::LANGUAGE)
+}
+
+/// `vim: set ft=markdown:`, `vi: filetype=python` or `-*- mode: latex -*-`.
+fn modeline(text: &str) -> Option<&str> {
+    let lines: Vec<&str> = text.lines().collect();
+    let tail = lines
+        .len()
+        .saturating_sub(MODELINE_LINES)
+        .max(MODELINE_LINES);
+    lines
+        .iter()
+        .take(MODELINE_LINES)
+        .chain(lines.iter().skip(tail))
+        .find_map(|line| vim_modeline(line).or_else(|| emacs_modeline(line)))
+        .map(alias)
+}
+
+fn vim_modeline(line: &str) -> Option<&str> {
+    let (_, options) = ["vim:", "vi:", "ex:"]
+        .iter()
+        .find_map(|marker| line.split_once(marker))?;
+    options
+        .split([' ', ':', '\t'])
+        .find_map(|option| {
+            option
+                .strip_prefix("ft=")
+                .or_else(|| option.strip_prefix("filetype="))
+        })
+        .filter(|name| !name.is_empty())
+}
+
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+    let variables = variables.trim();
+    // `-*- markdown -*-` names the mode alone
+    if !variables.contains(':') {
+        return Some(variables).filter(|name| !name.is_empty());
+    }
+    variables.split(';').find_map(|variable| {
+        let (key, value) = variable.split_once(':')?;
+        (key.trim().eq_ignore_ascii_case("mode")).then(|| value.trim())
+    })
+}
+
+/// `#!/usr/bin/python3`, `#!/usr/bin/env -S node --flag`
+fn shebang(text: &str) -> Option<&str> {
+    let line = text.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(alias(program))
+}
+
+/// YAML (`---`) or TOML (`+++`) front matter is a markdown convention.
+fn front_matter(text: &str) -> Option<&'static str> {
+    let mut lines = text.lines();
+    let fence = lines.next()?.trim_end();
+    if fence != "---" && fence != "+++" {
+        return None;
+    }
+    lines
+        .any(|line| line.trim_end() == fence || (fence == "---" && line.trim_end() == "..."))
+        .then_some("markdown")
+}
+
+/// Maps editor and interpreter names to LSP language ids.
+fn alias(name: &str) -> &str {
+    match name {
+        "md" | "markdown" | "pandoc" => "markdown",
+        "tex" | "latex" | "plaintex" => "latex",
+        "rst" => "restructuredtext",
+        "adoc" | "asciidoc" => "asciidoc",
+        "text" | "txt" | "text-mode" => "plaintext",
+        "sh" | "bash" | "zsh" | "dash" | "ksh" | "shell-script" => "shellscript",
+        "python" | "py" => "python",
+        "node" | "nodejs" | "deno" | "js" => "javascript",
+        "rb" | "ruby" => "ruby",
+        "pl" | "perl" => "perl",
+        "rs" => "rust",
+        "ts" => "typescript",
+        "h" => "c",
+        "cc" | "cxx" | "hpp" | "c++" => "cpp",
+        "cs" => "csharp",
+        "kt" => "kotlin",
+        "hs" => "haskell",
+        "yml" => "yaml",
+        name => name,
+    }
+}
@@ -0,0 +1,81 @@
+//! A generic language server completing words from the open documents, the workspace
+//! and plugins.
+//!
+//! The completion pipeline is usable without the server through
+//! [`engine::CompletionEngine`]. The server itself, behind the default `server`
+//! feature, is what the `test-lsp` binary runs.
+#![cfg_attr(not(feature = "server"), allow(dead_code, unused_imports))]
+
+use logos::Logos;
+
+mod bibtex;
+mod candidates;
+mod collation;
+mod commit;
+mod config;
+#[cfg(feature = "server")]
+pub mod conformance;
+mod consistency;
+mod context;
+mod data_dir;
+mod diagnostics;
+mod dismissals;
+mod doc;
+mod document;
+pub mod engine;
+mod environment;
+mod error;
+mod experiment;
+pub mod ext;
+mod format;
+mod git;
+mod glossary;
+#[cfg(feature = "server")]
+mod harness;
+mod index;
+mod key_path;
+mod language;
+mod line_index;
+mod log_file;
+mod markdown;
+mod outline;
+mod plugin;
+mod preview;
+#[cfg(feature = "server")]
+pub mod profile;
+mod protocol_errors;
+mod ranking;
+mod readability;
+mod references;
+#[cfg(feature = "workspace")]
+mod replace;
+mod rule_pack;
+pub mod sarif;
+#[cfg(feature = "server")]
+pub mod server;
+mod session;
+mod snippet;
+#[cfg(feature = "server")]
+pub mod soak;
+mod table;
+#[cfg(feature = "server")]
+pub mod transport;
+mod uri;
+#[cfg(feature = "server")]
+pub mod watchdog;
+mod workspace;
+mod wrap;
+
+#[derive(Logos, Debug, PartialEq, Eq, Clone, Copy)]
+enum Token<'s> {
+    /// Letters of any script with their accents, digits and `_`, see [`is_word_char`].
+    #[regex(r#"[\p{Alphabetic}\p{N}\p{M}_]+"#, |lex| lex.slice())]
+    Word(&'s str),
+    #[regex(r#"[^\p{Alphabetic}\p{N}\p{M}_]"#, |lex| lex.slice())]
+    Symbol(&'s str),
+}
+
+/// Whether `c` is part of a [`Token::Word`].
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || unicode_normalization::char::is_combining_mark(c)
+}
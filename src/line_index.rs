@@ -0,0 +1,159 @@
+use lsp_types::{Position, Range};
+
+/// Converts between byte offsets into a text and LSP positions, which count UTF-16
+/// code units.
+///
+/// A line ends before its `\n` or `\r\n`: positions past that, and offsets within the
+/// line break, are the end of the line. A position within a character, like between
+/// the halves of a surrogate pair, is the start of the next character, and an offset
+/// within one that of its start. Combining characters count on their own, the protocol
+/// having no notion of graphemes, and so does a lone `\r`, which no editor this serves
+/// breaks lines at. Documents are plain `String`s rather than ropes, so there are no
+/// char offsets to convert to.
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let offset = offset.min(self.line_end(line));
+        let character = self.text[start..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn range(&self, range: std::ops::Range<usize>) -> Range {
+        Range::new(self.position(range.start), self.position(range.end))
+    }
+
+    /// The byte offset of `position`, clamped to the end of its line.
+    pub fn offset(&self, position: Position) -> Option<usize> {
+        let start = *self.line_starts.get(position.line as usize)?;
+        let end = self.line_end(position.line as usize);
+        let mut utf16 = 0;
+        for (i, c) in self.text[start..end].char_indices() {
+            if utf16 >= position.character as usize {
+                return Some(start + i);
+            }
+            utf16 += c.len_utf16();
+        }
+        Some(end)
+    }
+
+    /// The offset of the line break ending `line`, or of the end of the text.
+    fn line_end(&self, line: usize) -> usize {
+        let Some(next) = self.line_starts.get(line + 1) else {
+            return self.text.len();
+        };
+        let end = next - 1;
+        match self.text[..end].ends_with('\r') {
+            true => end - 1,
+            false => end,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every offset maps to a position mapping back to it, or to where the position
+    /// clamps it to.
+    fn assert_round_trips(text: &str) {
+        let index = LineIndex::new(text);
+        for offset in 0..=text.len() {
+            let position = index.position(offset);
+            let back = index.offset(position).unwrap();
+            assert_eq!(index.position(back), position, "{text:?} at {offset}");
+            if text.is_char_boundary(offset) && !text[offset..].starts_with(['\r', '\n']) {
+                assert_eq!(back, offset, "{text:?} at {offset}");
+            }
+        }
+    }
+
+    #[test]
+    fn crlf() {
+        let text = "ab\r\ncd\r\n";
+        assert_round_trips(text);
+        let index = LineIndex::new(text);
+        assert_eq!(index.position(2), Position::new(0, 2));
+        // Within the line break
+        assert_eq!(index.position(3), Position::new(0, 2));
+        assert_eq!(index.position(4), Position::new(1, 0));
+        assert_eq!(index.offset(Position::new(0, 3)), Some(2));
+        assert_eq!(index.offset(Position::new(1, 2)), Some(6));
+    }
+
+    #[test]
+    fn lone_carriage_return() {
+        let text = "a\rb\nc";
+        assert_round_trips(text);
+        let index = LineIndex::new(text);
+        assert_eq!(index.position(2), Position::new(0, 2));
+        assert_eq!(index.offset(Position::new(0, 2)), Some(2));
+        assert_eq!(index.position(4), Position::new(1, 0));
+    }
+
+    #[test]
+    fn end_of_text() {
+        for text in ["", "ab", "ab\n", "ab\r\n"] {
+            assert_round_trips(text);
+            let index = LineIndex::new(text);
+            let end = index.position(text.len());
+            assert_eq!(index.offset(end), Some(text.len()), "{text:?}");
+            assert_eq!(index.position(text.len() + 10), end, "{text:?}");
+        }
+        let index = LineIndex::new("ab\n");
+        assert_eq!(index.position(3), Position::new(1, 0));
+        assert_eq!(index.offset(Position::new(2, 0)), None);
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        let text = "a\u{1F600}b";
+        assert_round_trips(text);
+        let index = LineIndex::new(text);
+        assert_eq!(index.position(1), Position::new(0, 1));
+        assert_eq!(index.position(5), Position::new(0, 3));
+        // Within the emoji, its start
+        assert_eq!(index.position(3), Position::new(0, 1));
+        // Between the halves, the start of the next character
+        assert_eq!(index.offset(Position::new(0, 2)), Some(5));
+        assert_eq!(index.offset(Position::new(0, 3)), Some(5));
+    }
+
+    #[test]
+    fn combining_marks() {
+        let text = "e\u{301}x";
+        assert_round_trips(text);
+        let index = LineIndex::new(text);
+        assert_eq!(index.position(1), Position::new(0, 1));
+        assert_eq!(index.position(3), Position::new(0, 2));
+        assert_eq!(index.offset(Position::new(0, 1)), Some(1));
+        assert_eq!(index.offset(Position::new(0, 2)), Some(3));
+    }
+
+    #[test]
+    fn past_the_end_of_a_line() {
+        let text = "ab\ncd";
+        assert_round_trips(text);
+        let index = LineIndex::new(text);
+        assert_eq!(index.offset(Position::new(0, 10)), Some(2));
+        assert_eq!(index.offset(Position::new(1, 10)), Some(5));
+        let position = index.position(index.offset(Position::new(0, 10)).unwrap());
+        assert_eq!(position, Position::new(0, 2));
+    }
+}
@@ -0,0 +1,215 @@
+//! Log files: the timestamps, levels and addresses their lines are made of.
+
+use crate::line_index::LineIndex;
+use lsp_types::{FoldingRange, FoldingRangeKind, SemanticToken, SemanticTokenType};
+use std::ops::Range;
+
+/// The language id of log files.
+pub const LANGUAGE: &str = "log";
+
+/// Seconds without a line that start a new fold.
+const FOLD_GAP: i64 = 60;
+
+const LEVELS: &[&str] = &[
+    "TRACE", "DEBUG", "INFO", "NOTICE", "WARN", "WARNING", "ERROR", "FATAL", "CRITICAL",
+];
+
+/// What the semantic tokens of logs are, in the order of [`LEGEND`].
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    Timestamp,
+    Level,
+    Address,
+}
+
+pub const LEGEND: &[SemanticTokenType] = &[
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::STRING,
+];
+
+/// The timestamp, level and IPv4 addresses of every line, encoded as LSP wants them.
+pub fn semantic_tokens(text: &str) -> Vec<SemanticToken> {
+    let line_index = LineIndex::new(text);
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let mut found = Vec::new();
+        if let Some((range, _)) = timestamp(line) {
+            found.push((range, Kind::Timestamp));
+        }
+        found.extend(level(line).map(|range| (range, Kind::Level)));
+        found.extend(addresses(line).map(|range| (range, Kind::Address)));
+        found.sort_by_key(|(range, _)| range.start);
+        for (range, kind) in found {
+            let start = line_index.position(offset + range.start);
+            let length = line[range].encode_utf16().count() as u32;
+            tokens.push((start, length, kind));
+        }
+        offset += line.len();
+    }
+
+    let mut previous = lsp_types::Position::new(0, 0);
+    tokens
+        .into_iter()
+        .map(|(start, length, kind)| {
+            let delta_line = start.line - previous.line;
+            let delta_start = match delta_line {
+                0 => start.character - previous.character,
+                _ => start.character,
+            };
+            previous = start;
+            SemanticToken {
+                delta_line,
+                delta_start,
+                length,
+                token_type: kind as u32,
+                token_modifiers_bitset: 0,
+            }
+        })
+        .collect()
+}
+
+/// Folds the bursts of lines apart from each other by more than [`FOLD_GAP`] seconds,
+/// the lines without a timestamp going with the one before.
+pub fn folding_ranges(text: &str) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    let mut burst: Option<(u32, i64)> = None;
+    let mut last_line = 0;
+    for (i, line) in text.lines().enumerate() {
+        let i = i as u32;
+        let Some((_, seconds)) = timestamp(line) else {
+            last_line = i;
+            continue;
+        };
+        match burst {
+            Some((start, previous)) if seconds - previous <= FOLD_GAP => {
+                burst = Some((start, seconds));
+            }
+            _ => {
+                if let Some((start, _)) = burst {
+                    ranges.extend(fold(start, i - 1));
+                }
+                burst = Some((i, seconds));
+            }
+        }
+        last_line = i;
+    }
+    if let Some((start, _)) = burst {
+        ranges.extend(fold(start, last_line));
+    }
+    ranges
+}
+
+fn fold(start: u32, end: u32) -> Option<FoldingRange> {
+    (end > start).then(|| FoldingRange {
+        start_line: start,
+        end_line: end,
+        kind: Some(FoldingRangeKind::Region),
+        ..Default::default()
+    })
+}
+
+/// The first `2024-01-02T03:04:05` timestamp of `line`, with optional fractions and
+/// offset and a space for the `T`, and its seconds since the epoch in its own offset.
+fn timestamp(line: &str) -> Option<(Range<usize>, i64)> {
+    let bytes = line.as_bytes();
+    (0..bytes.len()).find_map(|start| {
+        if start > 0 && bytes[start - 1].is_ascii_digit() {
+            return None;
+        }
+        let digits = |at: usize, len: usize| -> Option<i64> {
+            let field = bytes.get(at..at + len)?;
+            field
+                .iter()
+                .all(u8::is_ascii_digit)
+                .then(|| std::str::from_utf8(field).ok()?.parse().ok())?
+        };
+        let separated = |at: usize, separator: &[u8]| {
+            bytes.get(at).is_some_and(|byte| separator.contains(byte))
+        };
+        let year = digits(start, 4)?;
+        let month = digits(start + 5, 2).filter(|_| separated(start + 4, b"-"))?;
+        let day = digits(start + 8, 2).filter(|_| separated(start + 7, b"-"))?;
+        let hour = digits(start + 11, 2).filter(|_| separated(start + 10, b"T "))?;
+        let minute = digits(start + 14, 2).filter(|_| separated(start + 13, b":"))?;
+        let second = digits(start + 17, 2).filter(|_| separated(start + 16, b":"))?;
+        let mut end = start + 19;
+        if separated(end, b".,") {
+            let fraction = bytes[end + 1..]
+                .iter()
+                .take_while(|byte| byte.is_ascii_digit())
+                .count();
+            end += 1 + fraction;
+        }
+        if separated(end, b"Z") {
+            end += 1;
+        } else if separated(end, b"+-") && digits(end + 1, 2).is_some() {
+            end += 3;
+            if separated(end, b":") && digits(end + 1, 2).is_some() {
+                end += 3;
+            } else if digits(end, 2).is_some() {
+                end += 2;
+            }
+        }
+        let days = days_from_civil(year, month, day);
+        let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second;
+        Some((start..end, seconds))
+    })
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The first level of `line`, as a whole word in capitals.
+fn level(line: &str) -> Option<Range<usize>> {
+    words(line).find(|range| LEVELS.contains(&&line[range.clone()]))
+}
+
+/// The IPv4 addresses of `line`.
+fn addresses(line: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        while start < line.len() {
+            let rest = &line[start..];
+            let skipped = rest.find(|c: char| c.is_ascii_digit())?;
+            let candidate = start + skipped;
+            let len = line[candidate..]
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(line.len() - candidate);
+            let dotted = line[candidate..candidate + len].trim_end_matches('.');
+            start = candidate + len;
+            let preceded = line[..candidate].ends_with(|c: char| c.is_alphanumeric());
+            let octets = dotted.split('.').collect::<Vec<_>>();
+            let valid = octets.len() == 4
+                && octets.iter().all(|octet| {
+                    !octet.is_empty() && octet.len() <= 3 && octet.parse::<u8>().is_ok()
+                });
+            if valid && !preceded {
+                return Some(candidate..candidate + dotted.len());
+            }
+        }
+        None
+    })
+}
+
+/// The byte ranges of the alphanumeric runs of `line`.
+fn words(line: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = None;
+    line.char_indices()
+        .chain([(line.len(), ' ')])
+        .filter_map(move |(i, c)| match c.is_alphanumeric() {
+            true => {
+                start.get_or_insert(i);
+                None
+            }
+            false => start.take().map(|start| start..i),
+        })
+}
@@ -1,85 +1,120 @@
 #![allow(clippy::print_stderr)]
-use core::panic;
-use indexmap::IndexSet;
-use itertools::Itertools;
-use logos::Logos;
-use lsp_server::{Connection, ExtractError, Message, Request, RequestId, Response};
-use lsp_types::notification::{DidChangeTextDocument, DidOpenTextDocument};
-use lsp_types::request::Completion;
-use lsp_types::{
-    CompletionItem, CompletionItemKind, CompletionOptions, CompletionResponse, Position,
-    TextDocumentItem, Url, VersionedTextDocumentIdentifier,
-};
-use lsp_types::{InitializeParams, ServerCapabilities};
-use pyo3::types::{IntoPyDict, PyAnyMethods};
-use std::collections::HashMap;
+use clap::{Parser, Subcommand};
+use lsp_types::InitializeParams;
 use std::error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use test_lsp::profile::Profiler;
+use test_lsp::server::{self, Server, Stop};
+use test_lsp::transport;
+use test_lsp::watchdog::Watchdog;
+
+mod cli;
+
+/// The exit status when the client doesn't finish initializing within `--init-timeout`.
+const INIT_TIMED_OUT: i32 = 3;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Exit when the client process goes away without shutting the server down.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    parent_watchdog: bool,
+    /// Also write the session summary logged on shutdown to this JSON file.
+    #[arg(long)]
+    session_report: Option<PathBuf>,
+    /// Sample the server's stacks and write them as a flamegraph SVG to this file on
+    /// shutdown or `testLsp/dumpProfile`. Needs the `profile` feature.
+    #[arg(long)]
+    profile: Option<PathBuf>,
+    /// Seconds the client has to finish initializing before the server gives up and
+    /// exits with status 3, 0 to wait forever.
+    #[arg(long, default_value_t = 60)]
+    init_timeout: u64,
+    /// Mebibytes a single message may take, the larger ones being refused unread.
+    #[arg(long, default_value_t = 64)]
+    max_message_size: usize,
+    /// Without one, serves LSP over stdio.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-#[derive(Logos, Debug, PartialEq, Eq, Clone, Copy)]
-enum Token<'s> {
-    #[regex(r#"[a-zA-Z_0-9]+"#, |lex| lex.slice())]
-    Word(&'s str),
-    #[regex(r#"[^a-zA-Z_0-9]"#, |lex| lex.slice())]
-    Symbol(&'s str),
+#[derive(Subcommand)]
+enum Command {
+    /// Print the ranked completions at a position of a file as JSON.
+    Complete(cli::CompleteArgs),
+    /// Print the diagnostics of files, exiting with status 1 if any is an error.
+    Check(cli::CheckArgs),
+    /// Run the protocol conformance suite against an in-memory server, exiting with
+    /// status 1 if any check fails.
+    Conformance,
+    /// Type into a document of an in-memory server at an editor's pace, with completions
+    /// in between, exiting with status 1 if memory or latency go over the bounds.
+    Soak(cli::SoakArgs),
 }
 
 fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let args = Args::parse();
+
     // Start logging
     let _ = {
         use log::LevelFilter::*;
         env_logger::builder()
-            .filter_module("test-lsp", Info)
+            .filter_module("test_lsp", Info)
             .try_init()
     };
 
-    log::info!("starting generic LSP server");
-
-    //     pyo3::Python::with_gil(|py| -> pyo3::PyResult<()> {
-    //         let sys = py.import_bound("sys")?;
-    //         let version: String = sys.getattr("version")?.extract()?;
+    match args.command {
+        Some(Command::Complete(args)) => return cli::complete(args),
+        Some(Command::Check(args)) => {
+            if cli::check(args)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Conformance) => {
+            if cli::conformance() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Soak(args)) => {
+            if cli::soak(args) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
 
-    //         let locals = [
-    //             ("os", py.import_bound("os")?),
-    //             ("tensorflow", py.import_bound("tensorflow")?),
-    //             ("contractions", py.import_bound("contractions")?),
-    //         ]
-    //         .into_py_dict_bound(py);
-    //         let code = r###"
-    // a = 4
-    // ret = a + 4
-    // "###;
-    //         py.run_bound(code, None, Some(&locals))?;
-    //         let ret: usize = locals.get_item("ret")?.extract()?;
+    log::info!("starting generic LSP server");
 
-    //         println!("Hello {}, I'm Python {}", ret, version);
-    //         Ok(())
-    //     })
-    //     .unwrap();
+    let profiler = args.profile.and_then(|path| {
+        Profiler::start(path)
+            .inspect_err(|err| log::error!("failed to start profiling: {err}"))
+            .ok()
+    });
 
     // Create the transport. Includes the stdio (stdin and stdout) versions but this could
     // also be implemented to use sockets or HTTP.
-    let (connection, io_threads) = Connection::stdio();
+    let (connection, io_threads) = transport::stdio(args.max_message_size << 20);
 
     // Run the server and wait for the two threads to end (typically by trigger LSP Exit event).
-    let server_capabilities = serde_json::to_value(ServerCapabilities {
-        text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Kind(
-            lsp_types::TextDocumentSyncKind::FULL,
-        )),
-        completion_provider: Some(CompletionOptions {
-            trigger_characters: Some(
-                [" ", "\t", "\n", "\r"]
-                    .into_iter()
-                    .map(str::to_string)
-                    .collect_vec(),
-            ),
-            ..Default::default()
-        }),
-        ..Default::default()
-    })
-    .unwrap();
+    let server_capabilities = serde_json::to_value(server::capabilities()).unwrap();
 
-    let initialization_params = match connection.initialize(server_capabilities) {
+    let timeout = Duration::from_secs(args.init_timeout);
+    let deadline = (!timeout.is_zero()).then(|| Instant::now() + timeout);
+    let running = || deadline.is_none_or(|deadline| Instant::now() < deadline);
+    let initialization_params = match connection.initialize_while(server_capabilities, running) {
         Ok(it) => it,
+        Err(_) if !running() => {
+            log::error!(
+                "the client didn't finish initializing within {}s, see --init-timeout",
+                timeout.as_secs()
+            );
+            // The IO threads are blocked on a client that's stalled
+            std::process::exit(INIT_TIMED_OUT);
+        }
         Err(e) => {
             if e.channel_is_disconnected() {
                 io_threads.join()?;
@@ -87,145 +122,30 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
             return Err(e.into());
         }
     };
-    main_loop(connection, initialization_params)?;
-    io_threads.join()?;
+    let initialization_params: InitializeParams =
+        serde_json::from_value(initialization_params).unwrap();
+    let watchdog = args
+        .parent_watchdog
+        .then(|| Watchdog::new(initialization_params.process_id));
+    let runtime = tokio::runtime::Runtime::new()?;
+    let stop = runtime.block_on(
+        Server::new(
+            connection,
+            initialization_params,
+            args.session_report,
+            profiler,
+        )?
+        .run(watchdog),
+    )?;
+    // Don't wait on a scan that's still running
+    runtime.shutdown_background();
+    match stop {
+        Stop::Exit => io_threads.join()?,
+        // The IO threads are blocked on the vanished client's pipes
+        Stop::ClientGone => {}
+    }
 
     // Shut down gracefully.
     eprintln!("shutting down server");
     Ok(())
 }
-
-fn main_loop(
-    connection: Connection,
-    params: serde_json::Value,
-) -> Result<(), Box<dyn Error + Sync + Send>> {
-    let _params: InitializeParams = serde_json::from_value(params).unwrap();
-    let mut contents: HashMap<Url, String> = HashMap::new();
-
-    for msg in &connection.receiver {
-        eprintln!("got msg: {msg:?}");
-        match msg {
-            Message::Request(req) => {
-                if connection.handle_shutdown(&req)? {
-                    return Ok(());
-                }
-                eprintln!("got request: {req:?}");
-                match cast_req::<Completion>(req) {
-                    Ok((
-                        id,
-                        lsp_types::CompletionParams {
-                            text_document_position,
-                            ..
-                        },
-                    )) => {
-                        let position = text_document_position.position;
-                        let file = text_document_position.text_document.uri;
-                        let Some(words): Option<IndexSet<&str>> = pos_to_words_of_line(
-                            position,
-                            contents.get(&file).expect("We trust the LSP"),
-                            |token| match token {
-                                Token::Word(w) => Some(w),
-                                Token::Symbol(_) => None,
-                            },
-                        )
-                        .map(|w| w.into_iter().collect()) else {
-                            continue;
-                        };
-
-                        let result = serde_json::to_value(&Some(CompletionResponse::Array(
-                            words
-                                .into_iter()
-                                .map(|v| CompletionItem {
-                                    label: v.to_string(),
-                                    kind: Some(CompletionItemKind::TEXT),
-                                    documentation: Some(lsp_types::Documentation::String(
-                                        "An AI suggested completion".to_string(),
-                                    )),
-                                    ..Default::default()
-                                })
-                                .collect_vec(),
-                        )))
-                        .unwrap();
-
-                        let resp = Response {
-                            id,
-                            result: Some(result),
-                            error: None,
-                        };
-                        connection.sender.send(Message::Response(resp))?;
-                        continue;
-                    }
-                    Err(err @ ExtractError::JsonError { .. }) => panic!("{err:?}"),
-                    Err(ExtractError::MethodMismatch(req)) => req,
-                };
-            }
-            Message::Response(resp) => {
-                eprintln!("got response: {resp:?}");
-            }
-            Message::Notification(not) => {
-                eprintln!("got notification: {not:?}");
-                match cast_not::<DidOpenTextDocument>(not.clone()) {
-                    Ok(lsp_types::DidOpenTextDocumentParams {
-                        text_document: TextDocumentItem { uri, text, .. },
-                    }) => {
-                        eprintln!("{uri} :: {text:?}");
-                        contents.insert(uri, text);
-                        continue;
-                    }
-                    Err(err @ ExtractError::JsonError { .. }) => panic!("{err:?}"),
-                    Err(ExtractError::MethodMismatch(not)) => not,
-                };
-                match cast_not::<DidChangeTextDocument>(not) {
-                    Ok(lsp_types::DidChangeTextDocumentParams {
-                        text_document: VersionedTextDocumentIdentifier { uri, .. },
-                        content_changes,
-                    }) => {
-                        let text = content_changes.first().unwrap().text.to_string();
-                        eprintln!("{uri} :: {text:?}");
-                        contents.insert(uri, text);
-                        continue;
-                    }
-                    Err(err @ ExtractError::JsonError { .. }) => panic!("{err:?}"),
-                    Err(ExtractError::MethodMismatch(not)) => not,
-                };
-            }
-        }
-    }
-    Ok(())
-}
-
-fn pos_to_words_of_line(
-    Position { line, character }: Position,
-    text: &str,
-    mut filter: impl for<'s> FnMut(Token<'s>) -> Option<&'s str>,
-) -> Option<Vec<&str>> {
-    text.lines()
-        .nth(line.try_into().unwrap())
-        .map(|s| &s[..character.try_into().unwrap()])
-        .map(|context| {
-            Token::lexer(context)
-                .filter_map(|a| match a {
-                    Ok(v) => filter(v),
-                    Err(_) => None,
-                })
-                .collect()
-        })
-}
-
-fn cast_req<R>(req: Request) -> Result<(RequestId, R::Params), ExtractError<Request>>
-where
-    R: lsp_types::request::Request,
-    R::Params: serde::de::DeserializeOwned,
-{
-    req.extract(R::METHOD)
-}
-
-fn cast_not<N>(
-    not: lsp_server::Notification,
-) -> Result<N::Params, ExtractError<lsp_server::Notification>>
-where
-    N: lsp_types::notification::Notification,
-    N::Params: serde::de::DeserializeOwned,
-{
-    not.extract(N::METHOD)
-}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The markdown constructs several features need to know about, as byte ranges.
+#[derive(Debug, Default)]
+pub struct Markdown {
+    /// Fenced code blocks and inline code spans, sorted and disjoint.
+    pub code: Vec<Range<usize>>,
+    /// ATX headings outside of code.
+    pub headings: Vec<Heading>,
+    /// `[label]: destination` lines outside of code.
+    pub link_definitions: Vec<LinkDefinition>,
+}
+
+#[derive(Debug)]
+pub struct Heading {
+    pub range: Range<usize>,
+    /// How many `#`s it has.
+    pub level: usize,
+    /// Without the `#`s around it.
+    pub title: Range<usize>,
+    /// What links to the heading put after `#`, as GitHub makes them.
+    pub anchor: String,
+}
+
+#[derive(Debug)]
+pub struct LinkDefinition {
+    pub range: Range<usize>,
+    pub label: String,
+    pub destination: Range<usize>,
+}
+
+/// What the link being typed, or under the cursor, points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkTarget {
+    /// `](#anchor)`
+    Anchor,
+    /// `][label]`
+    Reference,
+}
+
+/// The link target being typed at the end of `line`, and where its text starts.
+pub fn link_target(line: &str) -> Option<(LinkTarget, usize)> {
+    let anchor = line
+        .rfind("](#")
+        .map(|i| (LinkTarget::Anchor, i + 3))
+        .filter(|&(_, start)| !line[start..].contains([')', ' ']));
+    let reference = line
+        .rfind("][")
+        .map(|i| (LinkTarget::Reference, i + 2))
+        .filter(|&(_, start)| !line[start..].contains(']'));
+    // The one opened last is the one being typed
+    anchor
+        .into_iter()
+        .chain(reference)
+        .max_by_key(|&(_, start)| start)
+}
+
+/// The link target around `offset` in `text`, and its text.
+pub fn link_at(text: &str, offset: usize) -> Option<(LinkTarget, &str)> {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+    let line = &text[line_start..line_end];
+    let offset = offset - line_start;
+    [
+        ("](#", ')', LinkTarget::Anchor),
+        ("][", ']', LinkTarget::Reference),
+    ]
+    .into_iter()
+    .flat_map(|(open, close, target)| {
+        line.match_indices(open).filter_map(move |(i, _)| {
+            let start = i + open.len();
+            let end = line[start..]
+                .find(close)
+                .map_or(line.len(), |len| start + len);
+            (start <= offset && offset <= end).then(|| (target, &line[start..end]))
+        })
+    })
+    .next()
+}
+
+impl Markdown {
+    pub fn parse(text: &str) -> Self {
+        let mut markdown = Self::default();
+        let mut fence: Option<(usize, char, usize)> = None;
+        let mut block_start = 0;
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            let line_start = offset;
+            offset += line.len();
+            let trimmed = line.trim_start_matches(' ');
+            let marker = trimmed.chars().next().filter(|&c| c == '`' || c == '~');
+            let len = marker.map_or(0, |c| trimmed.len() - trimmed.trim_start_matches(c).len());
+            match (fence, marker) {
+                (Some((start, c, open_len)), Some(marker))
+                    if marker == c && len >= open_len && trimmed[len..].trim().is_empty() =>
+                {
+                    markdown.code.push(start..offset);
+                    fence = None;
+                    block_start = offset;
+                }
+                (Some(_), _) => {}
+                (None, Some(c)) if len >= 3 && line.len() - trimmed.len() <= 3 => {
+                    markdown.inline_code(text, block_start..line_start);
+                    fence = Some((line_start, c, len));
+                }
+                (None, _) => {}
+            }
+        }
+        match fence {
+            // An unclosed fence runs until the end of the document
+            Some((start, ..)) => markdown.code.push(start..text.len()),
+            None => markdown.inline_code(text, block_start..text.len()),
+        }
+        markdown.code.sort_by_key(|range| range.start);
+        markdown.blocks(text);
+        markdown
+    }
+
+    pub fn heading(&self, anchor: &str) -> Option<&Heading> {
+        self.headings
+            .iter()
+            .find(|heading| heading.anchor == anchor)
+    }
+
+    /// Labels match case-insensitively.
+    pub fn link_definition(&self, label: &str) -> Option<&LinkDefinition> {
+        self.link_definitions
+            .iter()
+            .find(|definition| definition.label.to_lowercase() == label.to_lowercase())
+    }
+
+    pub fn in_code(&self, offset: usize) -> bool {
+        let i = self.code.partition_point(|range| range.end <= offset);
+        self.code.get(i).is_some_and(|range| range.start <= offset)
+    }
+
+    /// Finds the headings and link reference definitions outside of code.
+    fn blocks(&mut self, text: &str) {
+        let mut anchors: HashMap<String, usize> = HashMap::new();
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            let range = offset..offset + line.trim_end().len();
+            offset += line.len();
+            let trimmed = line.trim_start_matches(' ');
+            if line.len() - trimmed.len() > 3 || self.in_code(range.start) {
+                continue;
+            }
+            let hashes = trimmed.len() - trimmed.trim_start_matches('#').len();
+            if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with([' ', '\t']) {
+                let title = trimmed[hashes..].trim().trim_end_matches('#').trim_end();
+                let title_start = range.start + (line.len() - trimmed.len()) + hashes;
+                let title_start = title_start + text[title_start..].len()
+                    - text[title_start..].trim_start_matches([' ', '\t']).len();
+                let title_range = title_start..title_start + title.len();
+                let slug = slug(title);
+                // Later headings of the same title get numbered
+                let seen = anchors.entry(slug.clone()).or_default();
+                let anchor = match *seen {
+                    0 => slug,
+                    n => format!("{slug}-{n}"),
+                };
+                *seen += 1;
+                self.headings.push(Heading {
+                    range,
+                    level: hashes,
+                    title: title_range,
+                    anchor,
+                });
+            } else if let Some(rest) = trimmed.strip_prefix('[') {
+                if let Some((label, after)) = rest.split_once("]:") {
+                    let after = after.trim_start();
+                    let start = range.end - after.trim_end().len();
+                    let len = after
+                        .find(char::is_whitespace)
+                        .unwrap_or(after.trim_end().len());
+                    self.link_definitions.push(LinkDefinition {
+                        range,
+                        label: label.trim().to_string(),
+                        destination: start..start + len,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Finds the code spans in `range`, which holds no fences. A span is closed by the
+    /// next run of as many backticks within the same paragraph.
+    fn inline_code(&mut self, text: &str, range: Range<usize>) {
+        let region = &text[range.clone()];
+        let mut i = 0;
+        while let Some(found) = region[i..].find('`') {
+            let start = i + found;
+            let len = region[start..].len() - region[start..].trim_start_matches('`').len();
+            let paragraph_end = region[start..]
+                .find("\n\n")
+                .map_or(region.len(), |end| start + end);
+            let mut search = start + len;
+            let mut closed = None;
+            while let Some(found) = region[search..paragraph_end].find('`') {
+                let run = search + found;
+                let run_len = region[run..].len() - region[run..].trim_start_matches('`').len();
+                if run_len == len {
+                    closed = Some(run + run_len);
+                    break;
+                }
+                search = run + run_len;
+            }
+            match closed {
+                Some(end) => {
+                    self.code.push(range.start + start..range.start + end);
+                    i = end;
+                }
+                None => i = start + len,
+            }
+        }
+    }
+}
+
+/// Lowercase, with the punctuation but `-` and `_` dropped and spaces turned into `-`.
+pub fn slug(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        .map(|c| match c {
+            ' ' => '-',
+            c => c,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
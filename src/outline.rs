@@ -0,0 +1,185 @@
+//! The sections of prose documents, from the headings of markdown, reStructuredText and
+//! AsciiDoc, for document symbols and folding.
+
+use crate::line_index::LineIndex;
+use crate::markdown::Markdown;
+use lsp_types::{FoldingRange, FoldingRangeKind};
+use std::ops::Range;
+
+/// The characters reStructuredText adorns titles with.
+const RST_ADORNMENTS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+/// The lines of 4 or more of these delimit AsciiDoc blocks, which hold no sections.
+const ASCIIDOC_DELIMITERS: &str = "-./+*_=";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// 1 for the outermost, `#` in markdown and `=` in AsciiDoc.
+    pub level: usize,
+    /// The heading, with the adornment lines of reStructuredText.
+    pub range: Range<usize>,
+    pub title: Range<usize>,
+    /// Where the next section of the same or an outer level starts, or the end of the
+    /// text.
+    pub end: usize,
+}
+
+/// The sections of `text` in order, none for languages without headings.
+pub fn sections(language: &str, text: &str) -> Vec<Section> {
+    let headings = match language {
+        "markdown" => Markdown::parse(text)
+            .headings
+            .into_iter()
+            .map(|heading| (heading.level, heading.range, heading.title))
+            .collect(),
+        "restructuredtext" => rst_headings(text),
+        "asciidoc" => asciidoc_headings(text),
+        _ => Vec::new(),
+    };
+    headings
+        .iter()
+        .enumerate()
+        .map(|(i, (level, range, title))| Section {
+            level: *level,
+            range: range.clone(),
+            title: title.clone(),
+            end: headings[i + 1..]
+                .iter()
+                .find(|(next, ..)| next <= level)
+                .map_or(text.len(), |(_, next, _)| next.start),
+        })
+        .collect()
+}
+
+/// A fold per section spanning more than its heading, without the blank lines ending it.
+pub fn folding_ranges(sections: &[Section], text: &str) -> Vec<FoldingRange> {
+    let line_index = LineIndex::new(text);
+    sections
+        .iter()
+        .filter_map(|section| {
+            let start = line_index.position(section.range.start).line;
+            let end = section.range.start + text[section.range.start..section.end].trim_end().len();
+            let end = line_index.position(end).line;
+            (end > start).then(|| FoldingRange {
+                start_line: start,
+                end_line: end,
+                kind: Some(FoldingRangeKind::Region),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// The lines of `text` with where they start, without their line breaks.
+fn lines(text: &str) -> Vec<(usize, &str)> {
+    let mut offset = 0;
+    text.split_inclusive('\n')
+        .map(|line| {
+            let start = offset;
+            offset += line.len();
+            (start, line.trim_end_matches(['\n', '\r']))
+        })
+        .collect()
+}
+
+/// The adornment character of a line of one repeated punctuation character.
+fn rst_adornment(line: &str) -> Option<char> {
+    let line = line.trim_end();
+    let c = line
+        .chars()
+        .next()
+        .filter(|&c| RST_ADORNMENTS.contains(c))?;
+    (line.chars().count() >= 2 && line.chars().all(|other| other == c)).then_some(c)
+}
+
+/// Titles underlined, or over- and underlined, by adornment lines at least as long. Each
+/// adornment style is a level, in the order they're first used.
+fn rst_headings(text: &str) -> Vec<(usize, Range<usize>, Range<usize>)> {
+    let lines = lines(text);
+    let mut styles: Vec<(char, bool)> = Vec::new();
+    let mut headings = Vec::new();
+    let mut level = |style: (char, bool)| match styles.iter().position(|&known| known == style) {
+        Some(i) => i + 1,
+        None => {
+            styles.push(style);
+            styles.len()
+        }
+    };
+    let is_title = |line: &str| {
+        !line.trim().is_empty() && !line.starts_with([' ', '\t']) && rst_adornment(line).is_none()
+    };
+    let blank_before = |i: usize| i == 0 || lines[i - 1].1.trim().is_empty();
+    let mut i = 0;
+    while i < lines.len() {
+        let (start, line) = lines[i];
+        let width = |title: &str| title.trim_end().chars().count();
+        let overlined = rst_adornment(line).filter(|&c| {
+            lines.get(i + 1).is_some_and(|&(_, title)| {
+                is_title(title.trim_start())
+                    && lines.get(i + 2).is_some_and(|&(_, under)| {
+                        rst_adornment(under) == Some(c) && width(under) >= width(title.trim())
+                    })
+            })
+        });
+        if let Some(c) = overlined.filter(|_| blank_before(i)) {
+            let (title_start, title) = lines[i + 1];
+            let (under_start, under) = lines[i + 2];
+            let indent = title.len() - title.trim_start().len();
+            let title = title_start + indent..title_start + title.trim_end().len();
+            headings.push((level((c, true)), start..under_start + under.len(), title));
+            i += 3;
+            continue;
+        }
+        let underlined = lines.get(i + 1).and_then(|&(under_start, under)| {
+            let c = rst_adornment(under)?;
+            (is_title(line) && blank_before(i) && width(under) >= width(line))
+                .then_some((c, under_start + under.len()))
+        });
+        if let Some((c, end)) = underlined {
+            let title = start..start + line.trim_end().len();
+            headings.push((level((c, false)), start..end, title));
+            i += 2;
+            continue;
+        }
+        i += 1;
+    }
+    headings
+}
+
+/// `=` prefixed titles outside of delimited blocks and comments, the document title
+/// being of level 1.
+fn asciidoc_headings(text: &str) -> Vec<(usize, Range<usize>, Range<usize>)> {
+    let mut headings = Vec::new();
+    // The delimiter line of the block the line is in
+    let mut block: Option<&str> = None;
+    for (start, line) in lines(text) {
+        let trimmed = line.trim_end();
+        let first = trimmed.chars().next();
+        let delimiter = trimmed.len() >= 4
+            && first.is_some_and(|first| ASCIIDOC_DELIMITERS.contains(first))
+            && trimmed.chars().all(|c| Some(c) == first);
+        if delimiter {
+            block = match block {
+                Some(open) if open == trimmed => None,
+                Some(open) => Some(open),
+                None => Some(trimmed),
+            };
+            continue;
+        }
+        if block.is_some() || trimmed.starts_with("//") {
+            continue;
+        }
+        let marks = trimmed.len() - trimmed.trim_start_matches('=').len();
+        if !(1..=6).contains(&marks) || !trimmed[marks..].starts_with([' ', '\t']) {
+            continue;
+        }
+        let title = trimmed[marks..].trim_start();
+        let title_start = start + trimmed.len() - title.len();
+        headings.push((
+            marks,
+            start..start + trimmed.len(),
+            title_start..title_start + title.len(),
+        ));
+    }
+    headings
+}
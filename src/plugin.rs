@@ -0,0 +1,80 @@
+use crate::config::{PluginConfig, PluginKind, SidecarConfig};
+use lsp_types::{Diagnostic, Position};
+use std::error::Error;
+
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "lua")]
+mod lua;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod worker;
+
+pub use worker::{wait, Unanswered, Worker};
+
+pub type PluginResult<T> = Result<T, Box<dyn Error + Sync + Send>>;
+
+/// User-provided source of completions, diagnostics and hovers.
+pub trait Plugin: Send {
+    fn name(&self) -> &str;
+    fn complete(&mut self, text: &str, position: Position) -> PluginResult<Vec<String>>;
+    fn diagnose(&mut self, text: &str) -> PluginResult<Vec<Diagnostic>>;
+    fn hover(&mut self, text: &str, position: Position) -> PluginResult<Option<String>>;
+
+    /// Reorders the final completion candidates for the word starting with `prefix`.
+    fn rank(&mut self, _prefix: &str, candidates: Vec<String>) -> PluginResult<Vec<String>> {
+        Ok(candidates)
+    }
+
+    /// Rewrites `paragraph` to read at a Flesch-Kincaid grade between `min` and `max`,
+    /// for the plugins backed by a language model.
+    fn rewrite(&mut self, _paragraph: &str, _min: f64, _max: f64) -> PluginResult<Option<String>> {
+        Ok(None)
+    }
+
+    /// Explains `phrase` as used in `context`, in markdown.
+    fn explain(&mut self, _phrase: &str, _context: &str) -> PluginResult<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Loads every configured plugin, logging and skipping the ones that fail.
+pub fn load(configs: &[PluginConfig]) -> Vec<Box<dyn Plugin>> {
+    configs
+        .iter()
+        .filter_map(|config| match load_one(config) {
+            Ok(plugin) => {
+                log::info!("loaded plugin {}", plugin.name());
+                Some(plugin)
+            }
+            Err(err) => {
+                log::error!("failed to load plugin {}: {err}", config.path.display());
+                None
+            }
+        })
+        .collect()
+}
+
+/// Connects to the external model server, see `proto/sidecar.proto`.
+pub fn connect_sidecar(config: &SidecarConfig) -> PluginResult<Box<dyn Plugin>> {
+    #[cfg(feature = "grpc")]
+    return Ok(Box::new(grpc::GrpcPlugin::connect(config)?));
+    #[cfg(not(feature = "grpc"))]
+    {
+        let _ = config;
+        Err("built without the `grpc` feature".into())
+    }
+}
+
+fn load_one(config: &PluginConfig) -> PluginResult<Box<dyn Plugin>> {
+    match config.kind {
+        #[cfg(feature = "wasm")]
+        PluginKind::Wasm => Ok(Box::new(wasm::WasmPlugin::load(config)?)),
+        #[cfg(not(feature = "wasm"))]
+        PluginKind::Wasm => Err("built without the `wasm` feature".into()),
+        #[cfg(feature = "lua")]
+        PluginKind::Lua => Ok(Box::new(lua::LuaPlugin::load(config)?)),
+        #[cfg(not(feature = "lua"))]
+        PluginKind::Lua => Err("built without the `lua` feature".into()),
+    }
+}
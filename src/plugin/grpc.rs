@@ -0,0 +1,262 @@
+use super::{Plugin, PluginResult};
+use crate::config::{SidecarConfig, SidecarDeadlines};
+use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+use std::future::Future;
+use std::time::Duration;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Channel, Endpoint};
+
+/// Messages of `proto/sidecar.proto`, written out by hand so building doesn't need `protoc`.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CompleteRequest {
+        #[prost(string, tag = "1")]
+        pub text: String,
+        #[prost(uint32, tag = "2")]
+        pub line: u32,
+        #[prost(uint32, tag = "3")]
+        pub character: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CompleteResponse {
+        #[prost(string, repeated, tag = "1")]
+        pub candidates: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RankRequest {
+        #[prost(string, tag = "1")]
+        pub prefix: String,
+        #[prost(string, repeated, tag = "2")]
+        pub candidates: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RankResponse {
+        #[prost(string, repeated, tag = "1")]
+        pub candidates: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RewriteRequest {
+        #[prost(string, tag = "1")]
+        pub paragraph: String,
+        #[prost(double, tag = "2")]
+        pub min_grade: f64,
+        #[prost(double, tag = "3")]
+        pub max_grade: f64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RewriteResponse {
+        #[prost(string, optional, tag = "1")]
+        pub paragraph: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExplainRequest {
+        #[prost(string, tag = "1")]
+        pub phrase: String,
+        #[prost(string, tag = "2")]
+        pub context: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExplainResponse {
+        #[prost(string, optional, tag = "1")]
+        pub explanation: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DiagnoseRequest {
+        #[prost(string, tag = "1")]
+        pub text: String,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Severity {
+        Unspecified = 0,
+        Error = 1,
+        Warning = 2,
+        Information = 3,
+        Hint = 4,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Diagnostic {
+        #[prost(uint32, tag = "1")]
+        pub line: u32,
+        #[prost(uint32, tag = "2")]
+        pub start: u32,
+        #[prost(uint32, tag = "3")]
+        pub end: u32,
+        #[prost(enumeration = "Severity", tag = "4")]
+        pub severity: i32,
+        #[prost(string, tag = "5")]
+        pub message: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DiagnoseResponse {
+        #[prost(message, repeated, tag = "1")]
+        pub diagnostics: Vec<Diagnostic>,
+    }
+}
+
+/// Client of a long-lived model server implementing `proto/sidecar.proto`.
+///
+/// Every call is bounded by its configured deadline, which is also forwarded to the
+/// sidecar so it can give up early.
+pub struct GrpcPlugin {
+    name: String,
+    runtime: tokio::runtime::Runtime,
+    client: tonic::client::Grpc<Channel>,
+    deadlines: SidecarDeadlines,
+}
+
+impl GrpcPlugin {
+    pub fn connect(config: &SidecarConfig) -> PluginResult<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let endpoint = Endpoint::from_shared(config.endpoint.clone())?
+            .connect_timeout(Duration::from_millis(config.deadlines.complete));
+        // Connect on first use so the server starts even when the sidecar isn't up yet
+        let channel = {
+            let _guard = runtime.enter();
+            endpoint.connect_lazy()
+        };
+        Ok(Self {
+            name: config.endpoint.clone(),
+            runtime,
+            client: tonic::client::Grpc::new(channel),
+            deadlines: config.deadlines.clone(),
+        })
+    }
+
+    fn call<Req, Resp>(
+        &mut self,
+        path: &'static str,
+        message: Req,
+        deadline: u64,
+    ) -> PluginResult<Resp>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Resp: prost::Message + Default + Send + Sync + 'static,
+    {
+        let deadline = Duration::from_millis(deadline);
+        let mut request = tonic::Request::new(message);
+        request.set_timeout(deadline);
+        let client = &mut self.client;
+        let response = with_deadline(&self.runtime, deadline, async move {
+            client
+                .ready()
+                .await
+                .map_err(|err| tonic::Status::unavailable(err.to_string()))?;
+            client
+                .unary(
+                    request,
+                    PathAndQuery::from_static(path),
+                    tonic::codec::ProstCodec::default(),
+                )
+                .await
+        })??;
+        Ok(response.into_inner())
+    }
+}
+
+fn with_deadline<T>(
+    runtime: &tokio::runtime::Runtime,
+    deadline: Duration,
+    future: impl Future<Output = T>,
+) -> PluginResult<T> {
+    Ok(runtime.block_on(async { tokio::time::timeout(deadline, future).await })?)
+}
+
+impl Plugin for GrpcPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn complete(&mut self, text: &str, position: Position) -> PluginResult<Vec<String>> {
+        let request = proto::CompleteRequest {
+            text: text.to_string(),
+            line: position.line,
+            character: position.character,
+        };
+        let deadline = self.deadlines.complete;
+        let response: proto::CompleteResponse =
+            self.call("/testlsp.sidecar.v1.Sidecar/Complete", request, deadline)?;
+        Ok(response.candidates)
+    }
+
+    fn diagnose(&mut self, text: &str) -> PluginResult<Vec<Diagnostic>> {
+        let request = proto::DiagnoseRequest {
+            text: text.to_string(),
+        };
+        let deadline = self.deadlines.diagnose;
+        let response: proto::DiagnoseResponse =
+            self.call("/testlsp.sidecar.v1.Sidecar/Diagnose", request, deadline)?;
+        Ok(response
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| Diagnostic {
+                range: Range::new(
+                    Position::new(diagnostic.line, diagnostic.start),
+                    Position::new(diagnostic.line, diagnostic.end),
+                ),
+                severity: Some(match diagnostic.severity() {
+                    proto::Severity::Error => DiagnosticSeverity::ERROR,
+                    proto::Severity::Warning => DiagnosticSeverity::WARNING,
+                    proto::Severity::Hint => DiagnosticSeverity::HINT,
+                    proto::Severity::Information | proto::Severity::Unspecified => {
+                        DiagnosticSeverity::INFORMATION
+                    }
+                }),
+                source: Some(self.name.clone()),
+                message: diagnostic.message,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    fn hover(&mut self, _text: &str, _position: Position) -> PluginResult<Option<String>> {
+        Ok(None)
+    }
+
+    fn rank(&mut self, prefix: &str, candidates: Vec<String>) -> PluginResult<Vec<String>> {
+        let request = proto::RankRequest {
+            prefix: prefix.to_string(),
+            candidates,
+        };
+        let deadline = self.deadlines.rank;
+        let response: proto::RankResponse =
+            self.call("/testlsp.sidecar.v1.Sidecar/Rank", request, deadline)?;
+        Ok(response.candidates)
+    }
+
+    fn rewrite(&mut self, paragraph: &str, min: f64, max: f64) -> PluginResult<Option<String>> {
+        let request = proto::RewriteRequest {
+            paragraph: paragraph.to_string(),
+            min_grade: min,
+            max_grade: max,
+        };
+        let deadline = self.deadlines.rewrite;
+        let response: proto::RewriteResponse =
+            self.call("/testlsp.sidecar.v1.Sidecar/Rewrite", request, deadline)?;
+        Ok(response.paragraph)
+    }
+
+    fn explain(&mut self, phrase: &str, context: &str) -> PluginResult<Option<String>> {
+        let request = proto::ExplainRequest {
+            phrase: phrase.to_string(),
+            context: context.to_string(),
+        };
+        let deadline = self.deadlines.explain;
+        let response: proto::ExplainResponse =
+            self.call("/testlsp.sidecar.v1.Sidecar/Explain", request, deadline)?;
+        Ok(response.explanation)
+    }
+}
@@ -0,0 +1,136 @@
+use super::{Plugin, PluginResult};
+use crate::config::PluginConfig;
+use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+use mlua::{Function, HookTriggers, Lua, Table};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Instructions executed between fuel checks.
+const FUEL_STEP: u32 = 1000;
+
+/// A Lua script defining any of the global functions
+///
+/// - `complete(text, line, character) -> { string }`
+/// - `diagnose(text) -> { { line, start, end, severity, message } }`
+/// - `hover(text, line, character) -> string?`
+/// - `rank(prefix, candidates) -> { string }`
+///
+/// Missing functions are treated as producing nothing.
+pub struct LuaPlugin {
+    name: String,
+    lua: Lua,
+    fuel: u64,
+    remaining: Arc<AtomicU64>,
+}
+
+impl LuaPlugin {
+    pub fn load(config: &PluginConfig) -> PluginResult<Self> {
+        let name = config.path.display().to_string();
+        let source = std::fs::read_to_string(&config.path)?;
+        let lua = Lua::new();
+        lua.set_memory_limit(config.max_memory)?;
+        let remaining = Arc::new(AtomicU64::new(config.fuel));
+        let fuel = Arc::clone(&remaining);
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(FUEL_STEP),
+            move |_, _| {
+                let step = u64::from(FUEL_STEP);
+                match fuel.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                    left.checked_sub(step)
+                }) {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(mlua::Error::RuntimeError("out of fuel".to_string())),
+                }
+            },
+        );
+        let plugin = Self {
+            name,
+            lua,
+            fuel: config.fuel,
+            remaining,
+        };
+        plugin.refuel();
+        plugin.lua.load(&source).set_name(&plugin.name).exec()?;
+        Ok(plugin)
+    }
+
+    fn refuel(&self) {
+        self.remaining.store(self.fuel, Ordering::Relaxed);
+    }
+
+    fn function(&self, name: &str) -> PluginResult<Option<Function<'_>>> {
+        self.refuel();
+        Ok(self.lua.globals().get(name)?)
+    }
+}
+
+impl Plugin for LuaPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn complete(&mut self, text: &str, position: Position) -> PluginResult<Vec<String>> {
+        let Some(complete) = self.function("complete")? else {
+            return Ok(Vec::new());
+        };
+        Ok(complete.call((text, position.line, position.character))?)
+    }
+
+    fn diagnose(&mut self, text: &str) -> PluginResult<Vec<Diagnostic>> {
+        let Some(diagnose) = self.function("diagnose")? else {
+            return Ok(Vec::new());
+        };
+        let found: Table = diagnose.call(text)?;
+        found
+            .sequence_values::<Table>()
+            .map(|diagnostic| -> PluginResult<Diagnostic> {
+                let diagnostic = diagnostic?;
+                let line = diagnostic.get("line")?;
+                let severity = match diagnostic.get::<_, Option<String>>("severity")?.as_deref() {
+                    Some("error") => DiagnosticSeverity::ERROR,
+                    Some("warning") => DiagnosticSeverity::WARNING,
+                    Some("hint") => DiagnosticSeverity::HINT,
+                    _ => DiagnosticSeverity::INFORMATION,
+                };
+                Ok(Diagnostic {
+                    range: Range::new(
+                        Position::new(line, diagnostic.get("start")?),
+                        Position::new(line, diagnostic.get("end")?),
+                    ),
+                    severity: Some(severity),
+                    source: Some(self.name.clone()),
+                    message: diagnostic.get("message")?,
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    fn hover(&mut self, text: &str, position: Position) -> PluginResult<Option<String>> {
+        let Some(hover) = self.function("hover")? else {
+            return Ok(None);
+        };
+        Ok(hover.call((text, position.line, position.character))?)
+    }
+
+    fn rank(&mut self, prefix: &str, candidates: Vec<String>) -> PluginResult<Vec<String>> {
+        let Some(rank) = self.function("rank")? else {
+            return Ok(candidates);
+        };
+        Ok(rank.call((prefix, candidates))?)
+    }
+
+    fn rewrite(&mut self, paragraph: &str, min: f64, max: f64) -> PluginResult<Option<String>> {
+        let Some(rewrite) = self.function("rewrite")? else {
+            return Ok(None);
+        };
+        Ok(rewrite.call((paragraph, min, max))?)
+    }
+
+    fn explain(&mut self, phrase: &str, context: &str) -> PluginResult<Option<String>> {
+        let Some(explain) = self.function("explain")? else {
+            return Ok(None);
+        };
+        Ok(explain.call((phrase, context))?)
+    }
+}
@@ -0,0 +1,95 @@
+use super::{Plugin, PluginResult};
+use crate::config::PluginConfig;
+use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Engine, Store, StoreLimits, StoreLimitsBuilder};
+
+mod bindings {
+    wasmtime::component::bindgen!({ path: "wit/plugin.wit", world: "plugin" });
+}
+
+use bindings::Severity;
+
+/// A sandboxed plugin implementing the `plugin` world of `wit/plugin.wit`.
+///
+/// Plugins get no imports, a memory cap, and a fresh fuel budget on every call so a
+/// runaway plugin traps instead of hanging the server.
+pub struct WasmPlugin {
+    name: String,
+    fuel: u64,
+    store: Store<StoreLimits>,
+    bindings: bindings::Plugin,
+}
+
+impl WasmPlugin {
+    pub fn load(config: &PluginConfig) -> PluginResult<Self> {
+        let engine = Engine::new(
+            wasmtime::Config::new()
+                .wasm_component_model(true)
+                .consume_fuel(true),
+        )?;
+        let component = Component::from_file(&engine, &config.path)?;
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(config.max_memory)
+            .build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(config.fuel)?;
+        let (bindings, _) =
+            bindings::Plugin::instantiate(&mut store, &component, &Linker::new(&engine))?;
+        Ok(Self {
+            name: config.path.display().to_string(),
+            fuel: config.fuel,
+            store,
+            bindings,
+        })
+    }
+
+    fn refuel(&mut self) -> PluginResult<()> {
+        self.store.set_fuel(self.fuel)?;
+        Ok(())
+    }
+}
+
+impl Plugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn complete(&mut self, text: &str, position: Position) -> PluginResult<Vec<String>> {
+        self.refuel()?;
+        Ok(self
+            .bindings
+            .call_complete(&mut self.store, text, position.line, position.character)?)
+    }
+
+    fn diagnose(&mut self, text: &str) -> PluginResult<Vec<Diagnostic>> {
+        self.refuel()?;
+        let diagnostics = self.bindings.call_diagnose(&mut self.store, text)?;
+        Ok(diagnostics
+            .into_iter()
+            .map(|diagnostic| Diagnostic {
+                range: Range::new(
+                    Position::new(diagnostic.line, diagnostic.start),
+                    Position::new(diagnostic.line, diagnostic.end),
+                ),
+                severity: Some(match diagnostic.severity {
+                    Severity::Error => DiagnosticSeverity::ERROR,
+                    Severity::Warning => DiagnosticSeverity::WARNING,
+                    Severity::Information => DiagnosticSeverity::INFORMATION,
+                    Severity::Hint => DiagnosticSeverity::HINT,
+                }),
+                source: Some(self.name.clone()),
+                message: diagnostic.message,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    fn hover(&mut self, text: &str, position: Position) -> PluginResult<Option<String>> {
+        self.refuel()?;
+        Ok(self
+            .bindings
+            .call_hover(&mut self.store, text, position.line, position.character)?)
+    }
+}
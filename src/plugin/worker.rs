@@ -0,0 +1,96 @@
+use super::{Plugin, PluginResult};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Job = Box<dyn FnOnce(&mut dyn Plugin) + Send>;
+
+/// How often [`wait`] checks whether its request was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(10);
+
+/// Why [`wait`] returned without a result.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Unanswered {
+    Timeout,
+    Disconnected,
+    /// The request waiting was cancelled with `$/cancelRequest`.
+    Cancelled,
+}
+
+/// Runs a plugin on its own thread, so callers can stop waiting on slow calls while
+/// the plugin finishes them in the background.
+#[derive(Clone)]
+pub struct Worker {
+    name: String,
+    jobs: Sender<Job>,
+}
+
+impl Worker {
+    pub fn spawn(mut plugin: Box<dyn Plugin>) -> Self {
+        let name = plugin.name().to_string();
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name(format!("plugin {name}"))
+            .spawn(move || {
+                for job in receiver {
+                    job(plugin.as_mut());
+                }
+            })
+            .expect("failed to spawn a plugin thread");
+        Self { name, jobs }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Queues `job`, whose result arrives on the returned channel. The channel
+    /// disconnects without a result if the plugin thread is gone.
+    pub fn call<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut dyn Plugin) -> PluginResult<T> + Send + 'static,
+    ) -> Receiver<PluginResult<T>> {
+        let (sender, receiver) = mpsc::channel();
+        let _ = self.jobs.send(Box::new(move |plugin| {
+            let _ = sender.send(job(plugin));
+        }));
+        receiver
+    }
+
+    /// Runs `job` and waits for it however long it takes.
+    pub fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut dyn Plugin) -> PluginResult<T> + Send + 'static,
+    ) -> PluginResult<T> {
+        self.call(job)
+            .recv()
+            .unwrap_or_else(|_| Err(format!("plugin {} stopped", self.name).into()))
+    }
+}
+
+/// Waits on `receiver` until `deadline`, or however long it takes without one, giving up
+/// as soon as `cancelled` says the request waiting was.
+pub fn wait<T>(
+    receiver: &Receiver<T>,
+    deadline: Option<Instant>,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<T, Unanswered> {
+    loop {
+        if cancelled() {
+            return Err(Unanswered::Cancelled);
+        }
+        let slice = match deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => CANCEL_POLL,
+        };
+        match receiver.recv_timeout(slice.min(CANCEL_POLL)) {
+            Ok(value) => return Ok(value),
+            Err(RecvTimeoutError::Disconnected) => return Err(Unanswered::Disconnected),
+            Err(RecvTimeoutError::Timeout) => {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return Err(Unanswered::Timeout);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use crate::Token;
+use logos::Logos;
+use std::ops::Range;
+
+/// The byte range of the first use of `word` in `text`, as lexed for completion.
+pub fn first_occurrence(text: &str, word: &str) -> Option<Range<usize>> {
+    Token::lexer(text)
+        .spanned()
+        .find(|(token, _)| *token == Ok(Token::Word(word)))
+        .map(|(_, span)| span)
+}
+
+/// The line holding `range` as a fenced markdown block, with carets under the match.
+pub fn snippet(text: &str, range: Range<usize>, language: &str) -> String {
+    let line_start = text[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[range.end..]
+        .find('\n')
+        .map_or(text.len(), |i| range.end + i);
+    let line = text[line_start..line_end].trim_end_matches('\r');
+    // Keep tabs so the carets line up however the editor renders them
+    let indent: String = text[line_start..range.start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let carets = "^".repeat(text[range].chars().count());
+    code_block(&format!("{line}\n{indent}{carets}"), language)
+}
+
+/// `code` as a fenced markdown block, fenced with more backticks than any run in it.
+pub fn code_block(code: &str, language: &str) -> String {
+    let longest_run = code
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let language = match language {
+        "plaintext" => "text",
+        language => language,
+    };
+    format!("{fence}{language}\n{code}\n{fence}")
+}
@@ -0,0 +1,56 @@
+//! Sampling the server's own stacks for `--profile`, written out as a flamegraph.
+
+use crate::error::ServerError;
+use std::path::{Path, PathBuf};
+
+/// Samples per second, enough to tell where a slow completion spends its time.
+#[cfg(feature = "profile")]
+const FREQUENCY: i32 = 100;
+
+pub struct Profiler {
+    path: PathBuf,
+    #[cfg(feature = "profile")]
+    guard: pprof::ProfilerGuard<'static>,
+}
+
+impl Profiler {
+    /// Starts sampling until the profiler is dropped.
+    #[cfg(feature = "profile")]
+    pub fn start(path: PathBuf) -> Result<Self, ServerError> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(FREQUENCY)
+            .build()
+            .map_err(failed)?;
+        Ok(Self { path, guard })
+    }
+
+    #[cfg(not(feature = "profile"))]
+    pub fn start(_path: PathBuf) -> Result<Self, ServerError> {
+        Err(ServerError::Backend {
+            backend: "profiler",
+            source: "the server was built without the `profile` feature".into(),
+        })
+    }
+
+    /// Writes the flamegraph of everything sampled so far, as an SVG.
+    #[cfg(feature = "profile")]
+    pub fn write(&self) -> Result<&Path, ServerError> {
+        let report = self.guard.report().build().map_err(failed)?;
+        let file = std::fs::File::create(&self.path)?;
+        report.flamegraph(file).map_err(failed)?;
+        Ok(&self.path)
+    }
+
+    #[cfg(not(feature = "profile"))]
+    pub fn write(&self) -> Result<&Path, ServerError> {
+        Ok(&self.path)
+    }
+}
+
+#[cfg(feature = "profile")]
+fn failed(source: pprof::Error) -> ServerError {
+    ServerError::Backend {
+        backend: "profiler",
+        source: source.into(),
+    }
+}
@@ -0,0 +1,52 @@
+//! The last protocol anomalies, like requests the server doesn't know or params that
+//! don't parse, kept for `testLsp/recentErrors` to inspect intermittent issues after the
+//! fact without verbose logging.
+
+use crate::ext::{ProtocolError, ProtocolErrorKind};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A ring buffer of the last `capacity` anomalies, shared by its clones so that work off
+/// the message loop can record them too.
+#[derive(Debug, Clone)]
+pub struct ProtocolErrors {
+    capacity: usize,
+    recent: Arc<Mutex<VecDeque<ProtocolError>>>,
+}
+
+impl ProtocolErrors {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// Logs the anomaly and keeps it, dropping the oldest one when full.
+    pub fn record(&self, kind: ProtocolErrorKind, method: Option<&str>, message: String) {
+        log::warn!("{}: {message}", method.unwrap_or("protocol error"));
+        if self.capacity == 0 {
+            return;
+        }
+        let error = ProtocolError {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+            kind,
+            method: method.map(str::to_string),
+            message,
+        };
+        let mut recent = self.recent.lock().unwrap_or_else(|err| err.into_inner());
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(error);
+    }
+
+    /// The anomalies kept, the oldest first.
+    pub fn recent(&self) -> Vec<ProtocolError> {
+        let recent = self.recent.lock().unwrap_or_else(|err| err.into_inner());
+        recent.iter().cloned().collect()
+    }
+}
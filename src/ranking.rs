@@ -0,0 +1,168 @@
+//! How completions are ranked, by the strategy of `completion.strategy`, and why they
+//! rank where they do, traced with `completion.debug`.
+
+use crate::candidates::Source;
+use crate::config::RankingStrategy;
+use crate::ext::RankingExplanation;
+use crate::index::Index;
+use indexmap::IndexMap;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io;
+
+/// Reorders the candidates the providers collected.
+pub trait Ranker {
+    fn name(&self) -> &'static str;
+    /// Sorts `words`, best first, keeping the order of those it can't tell apart.
+    fn rank(&self, prefix: &str, index: &Index, words: &mut [String]) -> io::Result<()>;
+}
+
+/// The ranker of `strategy`, none for `model-weighted`, which plugins rank for.
+pub fn ranker(strategy: RankingStrategy) -> Option<Box<dyn Ranker>> {
+    match strategy {
+        RankingStrategy::Frequency => Some(Box::new(Frequency)),
+        RankingStrategy::Recency => Some(Box::new(Recency)),
+        RankingStrategy::FuzzyFirst => Some(Box::new(FuzzyFirst)),
+        RankingStrategy::ModelWeighted => None,
+    }
+}
+
+struct Frequency;
+
+impl Ranker for Frequency {
+    fn name(&self) -> &'static str {
+        "frequency"
+    }
+
+    fn rank(&self, prefix: &str, index: &Index, words: &mut [String]) -> io::Result<()> {
+        let frequencies: HashMap<_, _> = index.words_with_prefix(prefix)?.into_iter().collect();
+        words.sort_by_key(|word| Reverse(frequencies.get(word).copied().unwrap_or_default()));
+        Ok(())
+    }
+}
+
+struct Recency;
+
+impl Ranker for Recency {
+    fn name(&self) -> &'static str {
+        "recency"
+    }
+
+    fn rank(&self, prefix: &str, index: &Index, words: &mut [String]) -> io::Result<()> {
+        let last_seen = index.last_seen_with_prefix(prefix)?;
+        words.sort_by_key(|word| Reverse(last_seen.get(word).copied().unwrap_or_default()));
+        Ok(())
+    }
+}
+
+struct FuzzyFirst;
+
+impl Ranker for FuzzyFirst {
+    fn name(&self) -> &'static str {
+        "fuzzy-first"
+    }
+
+    fn rank(&self, prefix: &str, _: &Index, words: &mut [String]) -> io::Result<()> {
+        // Those it doesn't match at all last
+        words.sort_by_cached_key(|word| fuzzy_match(prefix, word).ok_or(()));
+        Ok(())
+    }
+}
+
+/// How loosely `word` holds the letters of `prefix` in order, case-insensitively: where
+/// the first one is and how many letters come between them, `None` when it doesn't.
+fn fuzzy_match(prefix: &str, word: &str) -> Option<(usize, usize)> {
+    let mut letters = prefix.chars().flat_map(char::to_lowercase).peekable();
+    let mut first = None;
+    let mut gaps = 0;
+    for (i, c) in word.chars().flat_map(char::to_lowercase).enumerate() {
+        let Some(&letter) = letters.peek() else {
+            break;
+        };
+        if c == letter {
+            first.get_or_insert(i);
+            letters.next();
+        } else if first.is_some() {
+            gaps += 1;
+        }
+    }
+    letters
+        .peek()
+        .is_none()
+        .then(|| (first.unwrap_or_default(), gaps))
+}
+
+pub struct RankingTrace {
+    explanations: IndexMap<String, RankingExplanation>,
+}
+
+impl RankingTrace {
+    /// Starts from the words as the providers collected them, best first.
+    pub fn collected(
+        sources: &IndexMap<String, Vec<Source>>,
+        frequencies: &HashMap<String, u64>,
+    ) -> Self {
+        let explanations = sources
+            .iter()
+            .enumerate()
+            .map(|(rank, (word, sources))| {
+                let explanation = RankingExplanation {
+                    label: word.clone(),
+                    sources: sources
+                        .iter()
+                        .map(|source| source.name().to_string())
+                        .collect(),
+                    base_rank: Some(rank),
+                    frequency: frequencies.get(word).copied(),
+                    adjustments: Vec::new(),
+                    final_rank: None,
+                };
+                (word.clone(), explanation)
+            })
+            .collect();
+        Self { explanations }
+    }
+
+    pub fn adjusted(&mut self, word: &str, adjustment: impl Into<String>) {
+        self.explanation(word).adjustments.push(adjustment.into());
+    }
+
+    /// Notes where `by` moved each of the words it reordered `before` into.
+    pub fn reordered(&mut self, before: &[String], after: &[String], by: &str) {
+        for (to, word) in after.iter().enumerate() {
+            match before.iter().position(|other| other == word) {
+                Some(from) if from == to => {}
+                Some(from) => self.adjusted(word, format!("moved by {by} from {from} to {to}")),
+                None => self.adjusted(word, format!("suggested by {by}")),
+            }
+        }
+    }
+
+    /// The explanations of the words listed, by label, with their place in the list.
+    pub fn finish<'a>(
+        mut self,
+        listed: impl Iterator<Item = &'a str>,
+    ) -> HashMap<String, RankingExplanation> {
+        listed
+            .enumerate()
+            .map(|(rank, label)| {
+                let mut explanation = self.explanation(label).clone();
+                explanation.final_rank = Some(rank);
+                (label.to_string(), explanation)
+            })
+            .collect()
+    }
+
+    fn explanation(&mut self, word: &str) -> &mut RankingExplanation {
+        self.explanations
+            .entry(word.to_string())
+            .or_insert_with(|| RankingExplanation {
+                label: word.to_string(),
+                sources: Vec::new(),
+                base_rank: None,
+                frequency: None,
+                adjustments: Vec::new(),
+                final_rank: None,
+            })
+    }
+}
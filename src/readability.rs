@@ -0,0 +1,59 @@
+//! How hard prose paragraphs are to read, as the school grade of the Flesch-Kincaid
+//! formula.
+
+use crate::markdown::Markdown;
+use crate::wrap;
+use std::ops::Range;
+
+/// Words a paragraph needs for its grade to mean anything.
+const MIN_WORDS: usize = 20;
+
+/// The grade of each paragraph of `text` long enough to have one, outside of code.
+pub fn paragraphs(text: &str, markdown: &Markdown) -> Vec<(Range<usize>, f64)> {
+    wrap::paragraphs(text, 0..text.len())
+        .into_iter()
+        .filter(|paragraph| !markdown.in_code(paragraph.start))
+        .filter_map(|paragraph| Some((paragraph.clone(), grade(&text[paragraph])?)))
+        .collect()
+}
+
+/// The Flesch-Kincaid grade level of `paragraph`, from its words per sentence and
+/// syllables per word.
+pub fn grade(paragraph: &str) -> Option<f64> {
+    let words = paragraph
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| word.chars().any(char::is_alphabetic))
+        .collect::<Vec<_>>();
+    if words.len() < MIN_WORDS {
+        return None;
+    }
+    let sentences = paragraph
+        .split_terminator(['.', '!', '?'])
+        .filter(|sentence| sentence.chars().any(char::is_alphabetic))
+        .count()
+        .max(1);
+    let syllables: usize = words.iter().map(|word| syllables(word)).sum();
+    let words_per_sentence = words.len() as f64 / sentences as f64;
+    let syllables_per_word = syllables as f64 / words.len() as f64;
+    Some(0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59)
+}
+
+/// The runs of vowels of `word`, but a silent final `e`, at least one.
+fn syllables(word: &str) -> usize {
+    let word = word.to_lowercase();
+    let is_vowel = |c: char| "aeiouy".contains(c);
+    let mut count = 0;
+    let mut previous = false;
+    for c in word.chars() {
+        let vowel = is_vowel(c);
+        if vowel && !previous {
+            count += 1;
+        }
+        previous = vowel;
+    }
+    if word.ends_with('e') && !word.ends_with("le") && count > 1 {
+        count -= 1;
+    }
+    count.max(1)
+}
@@ -0,0 +1,160 @@
+use crate::config::ReferencesConfig;
+#[cfg(feature = "workspace")]
+use crate::uri;
+use crate::Token;
+#[cfg(feature = "workspace")]
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use logos::Logos;
+use lsp_types::Url;
+use std::ops::Range;
+use std::path::Path;
+
+/// The byte ranges where `word` is used in `text`, leaving out the uses inside strings
+/// or comments when `config` asks to and the syntax of `language` is known.
+pub fn occurrences(
+    text: &str,
+    word: &str,
+    language: &str,
+    config: &ReferencesConfig,
+) -> Vec<Range<usize>> {
+    let excluded = Syntax::of(language)
+        .filter(|_| config.exclude_strings || config.exclude_comments)
+        .map(|syntax| syntax.literals(text, config))
+        .unwrap_or_default();
+    Token::lexer(text)
+        .spanned()
+        .filter(|(token, _)| *token == Ok(Token::Word(word)))
+        .map(|(_, span)| span)
+        .filter(|span| {
+            let i = excluded.partition_point(|range| range.end <= span.start);
+            excluded.get(i).is_none_or(|range| range.start > span.start)
+        })
+        .collect()
+}
+
+/// Files left out of references, by gitignore-style globs relative to the workspace
+/// root.
+#[cfg(feature = "workspace")]
+pub struct Exclusions(Gitignore);
+
+/// None, the globs needing the `workspace` feature.
+#[cfg(not(feature = "workspace"))]
+pub struct Exclusions;
+
+impl Exclusions {
+    #[cfg(feature = "workspace")]
+    pub fn new(root: Option<&Path>, globs: &[String]) -> Self {
+        let Some(root) = root.filter(|_| !globs.is_empty()) else {
+            return Self(Gitignore::empty());
+        };
+        let mut builder = GitignoreBuilder::new(root);
+        for glob in globs {
+            if let Err(err) = builder.add_line(None, glob) {
+                log::warn!("invalid references exclude `{glob}`: {err}");
+            }
+        }
+        Self(builder.build().unwrap_or_else(|err| {
+            log::warn!("invalid references excludes: {err}");
+            Gitignore::empty()
+        }))
+    }
+
+    #[cfg(not(feature = "workspace"))]
+    pub fn new(_root: Option<&Path>, _globs: &[String]) -> Self {
+        Self
+    }
+
+    #[cfg(feature = "workspace")]
+    pub fn contains(&self, uri: &Url) -> bool {
+        match uri::to_path(uri) {
+            Some(path) if !self.0.is_empty() && path.starts_with(self.0.path()) => {
+                self.0.matched_path_or_any_parents(&path, false).is_ignore()
+            }
+            _ => false,
+        }
+    }
+
+    #[cfg(not(feature = "workspace"))]
+    pub fn contains(&self, _uri: &Url) -> bool {
+        false
+    }
+}
+
+/// Just enough of a language's syntax to tell its strings and comments apart.
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+impl Syntax {
+    fn of(language: &str) -> Option<Self> {
+        let (line_comments, block_comment, quotes): (_, _, &[char]) = match language {
+            // `'` starts lifetimes as often as chars
+            "rust" => (&["//"][..], Some(("/*", "*/")), &['"']),
+            "c" | "cpp" | "csharp" | "java" | "go" | "kotlin" | "scala" | "swift" | "dart" => {
+                (&["//"][..], Some(("/*", "*/")), &['"', '\''])
+            }
+            "javascript" | "javascriptreact" | "typescript" | "typescriptreact" => {
+                (&["//"][..], Some(("/*", "*/")), &['"', '\'', '`'])
+            }
+            "python" | "shellscript" | "ruby" | "perl" | "r" | "yaml" | "toml" | "makefile"
+            | "dockerfile" => (&["#"][..], None, &['"', '\'']),
+            "lua" | "sql" | "haskell" => (&["--"][..], None, &['"', '\'']),
+            "lisp" | "clojure" | "scheme" => (&[";"][..], None, &['"']),
+            _ => return None,
+        };
+        Some(Self {
+            line_comments,
+            block_comment,
+            quotes,
+        })
+    }
+
+    /// The strings and comments of `text` that `config` excludes, sorted and disjoint.
+    fn literals(&self, text: &str, config: &ReferencesConfig) -> Vec<Range<usize>> {
+        let mut literals = Vec::new();
+        let mut i = 0;
+        while let Some(c) = text[i..].chars().next() {
+            let rest = &text[i..];
+            let (end, excluded) = if self.line_comments.iter().any(|&c| rest.starts_with(c)) {
+                let end = rest.find('\n').map_or(text.len(), |n| i + n);
+                (end, config.exclude_comments)
+            } else if let Some((open, close)) = self
+                .block_comment
+                .filter(|(open, _)| rest.starts_with(open))
+            {
+                let end = rest[open.len()..]
+                    .find(close)
+                    .map_or(text.len(), |n| i + open.len() + n + close.len());
+                (end, config.exclude_comments)
+            } else if self.quotes.contains(&c) {
+                (i + string_len(rest, c), config.exclude_strings)
+            } else {
+                i += c.len_utf8();
+                continue;
+            };
+            if excluded {
+                literals.push(i..end);
+            }
+            i = end;
+        }
+        literals
+    }
+}
+
+/// The length of the string literal `rest` starts with. Strings other than backtick
+/// ones end at the line, closed or not.
+fn string_len(rest: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' if quote != '`' => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    rest.len()
+}
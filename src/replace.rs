@@ -0,0 +1,50 @@
+//! Regex replacements across the workspace, for `testLsp.replaceInWorkspace`.
+
+use crate::line_index::LineIndex;
+use crate::uri;
+#[cfg(feature = "workspace")]
+use ignore::overrides::{Override, OverrideBuilder};
+use lsp_types::{TextEdit, Url};
+use regex::Regex;
+use std::path::Path;
+
+/// The files searched, by gitignore-style globs relative to the workspace root: those
+/// matching `include`, or any when it's empty, but for those matching `exclude`.
+#[cfg(feature = "workspace")]
+pub struct Scope(Override);
+
+#[cfg(feature = "workspace")]
+impl Scope {
+    pub fn new(root: &Path, include: &[String], exclude: &[String]) -> Result<Self, ignore::Error> {
+        let mut builder = OverrideBuilder::new(root);
+        for glob in include {
+            builder.add(glob)?;
+        }
+        for glob in exclude {
+            builder.add(&format!("!{glob}"))?;
+        }
+        Ok(Self(builder.build()?))
+    }
+
+    pub fn contains(&self, uri: &Url) -> bool {
+        uri::to_path(uri).is_some_and(|path| !self.0.matched(&path, false).is_ignore())
+    }
+}
+
+/// An edit per non-empty match of `regex` in `text`, replacing it with `replacement` with
+/// its groups expanded.
+pub fn edits(text: &str, regex: &Regex, replacement: &str) -> Vec<TextEdit> {
+    let line_index = LineIndex::new(text);
+    regex
+        .captures_iter(text)
+        .filter_map(|captures| {
+            let found = captures.get(0)?;
+            if found.is_empty() {
+                return None;
+            }
+            let mut replaced = String::new();
+            captures.expand(replacement, &mut replaced);
+            Some(TextEdit::new(line_index.range(found.range()), replaced))
+        })
+        .collect()
+}
@@ -0,0 +1,163 @@
+//! Rule packs, TOML files bundling a house style for a team to share: regex rules,
+//! a dictionary of words to complete, stop words never to offer and abbreviations.
+//!
+//! ```toml
+//! name = "acme"
+//! dictionary = ["Kubernetes", "PostgreSQL"]
+//! stop_words = ["the", "and"]
+//!
+//! [abbreviations]
+//! k8s = "Kubernetes"
+//!
+//! [[rules]]
+//! id = "acme/utilize"
+//! pattern = "\\butilize\\b"
+//! message = "Prefer `use`"
+//! replacement = "use"
+//! ```
+//!
+//! Packs are read from `rule_packs`, which lists files, directories of `.toml` files or
+//! http(s) URLs. Those fetched are cached, to be used while offline or unreachable.
+
+use crate::config::{Config, PatternRule};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// How long fetching a pack may take before the cached copy is used.
+const FETCH_TIMEOUT_SECS: &str = "10";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RulePack {
+    pub name: Option<String>,
+    pub rules: Vec<PatternRule>,
+    pub dictionary: Vec<String>,
+    pub stop_words: Vec<String>,
+    /// What each abbreviation completes to, in LSP snippet syntax.
+    pub abbreviations: HashMap<String, String>,
+}
+
+/// Reads the packs of `rule_packs` into `config`, the settings of the user winning
+/// over those of packs, logging and skipping the packs that can't be read.
+pub fn apply(config: &mut Config) {
+    for source in config.rule_packs.clone() {
+        let packs = match load(&source, config.offline) {
+            Ok(packs) => packs,
+            Err(err) => {
+                log::error!("failed to load rule pack {source}: {err}");
+                continue;
+            }
+        };
+        for pack in packs {
+            log::info!(
+                "loaded rule pack {}",
+                pack.name.as_deref().unwrap_or(&source)
+            );
+            pack.merge(config);
+        }
+    }
+}
+
+impl RulePack {
+    fn merge(self, config: &mut Config) {
+        let diagnostics = &mut config.diagnostics;
+        for rule in self.rules {
+            if !diagnostics.patterns.iter().any(|known| known.id == rule.id) {
+                diagnostics.patterns.push(rule);
+            }
+        }
+        let completion = &mut config.completion;
+        completion.dictionary.extend(self.dictionary);
+        completion.stop_words.extend(self.stop_words);
+        for (abbreviation, body) in self.abbreviations {
+            completion.snippets.entry(abbreviation).or_insert(body);
+        }
+    }
+}
+
+/// The packs of a file, directory or URL.
+fn load(source: &str, offline: bool) -> io::Result<Vec<RulePack>> {
+    if source.starts_with("https://") || source.starts_with("http://") {
+        return Ok(vec![parse(&fetch(source, offline)?)?]);
+    }
+    let path = Path::new(source);
+    if !path.is_dir() {
+        return Ok(vec![parse(&std::fs::read_to_string(path)?)?]);
+    }
+    let mut files = std::fs::read_dir(path)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    files.retain(|file| {
+        file.extension()
+            .is_some_and(|extension| extension == "toml")
+    });
+    files.sort();
+    files
+        .iter()
+        .map(|file| parse(&std::fs::read_to_string(file)?))
+        .collect()
+}
+
+fn parse(text: &str) -> io::Result<RulePack> {
+    toml::from_str(text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// The pack at `url`, from the cache when offline or when fetching it fails.
+fn fetch(url: &str, offline: bool) -> io::Result<String> {
+    let cached = cache_path(url);
+    if !offline {
+        match curl(url) {
+            Ok(text) => {
+                if let Some(cached) = &cached {
+                    let written = cached
+                        .parent()
+                        .map_or(Ok(()), std::fs::create_dir_all)
+                        .and_then(|()| std::fs::write(cached, &text));
+                    if let Err(err) = written {
+                        log::warn!("failed to cache {url}: {err}");
+                    }
+                }
+                return Ok(text);
+            }
+            Err(err) => log::warn!("failed to fetch {url}, using the cached copy: {err}"),
+        }
+    }
+    match cached {
+        Some(cached) => std::fs::read_to_string(cached),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no cache directory",
+        )),
+    }
+}
+
+/// Downloads `url` with `curl`, which the few packs fetched at startup don't need an
+/// HTTP client of their own for.
+fn curl(url: &str) -> io::Result<String> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--max-time", FETCH_TIMEOUT_SECS, url])
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(stderr.trim().to_string()));
+    }
+    String::from_utf8(output.stdout).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Where the copy of the pack at `url` is kept, named by a hash of it under
+/// `test-lsp/rule-packs` in the platform cache directory.
+fn cache_path(url: &str) -> Option<PathBuf> {
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    Some(
+        dirs::cache_dir()?
+            .join("test-lsp")
+            .join("rule-packs")
+            .join(format!("{hash:016x}.toml")),
+    )
+}
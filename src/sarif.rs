@@ -0,0 +1,82 @@
+//! [SARIF 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html) logs
+//! of diagnostics, which code scanning dashboards take.
+
+use crate::diagnostics;
+use itertools::Itertools;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Url};
+use serde_json::{json, Value};
+
+/// What the uris of the files under the root of a log are relative to.
+const ROOT: &str = "%SRCROOT%";
+
+/// A log of one run over `files`, with the uris of those under the `root` directory
+/// relative to it.
+pub fn log(files: &[(Url, Vec<Diagnostic>)], root: Option<&Url>) -> Value {
+    let root = root.map(|root| format!("{}/", root.as_str().trim_end_matches('/')));
+    let results = files
+        .iter()
+        .flat_map(|(uri, diagnostics)| {
+            diagnostics
+                .iter()
+                .map(|diagnostic| result(uri, diagnostic, root.as_deref()))
+        })
+        .collect_vec();
+    let rules = diagnostics::RULES
+        .iter()
+        .map(|(id, description)| json!({ "id": id, "shortDescription": { "text": description } }))
+        .collect_vec();
+    let mut run = json!({
+        "tool": {
+            "driver": {
+                "name": "test-lsp",
+                "version": env!("CARGO_PKG_VERSION"),
+                "rules": rules,
+            }
+        },
+        // As LSP positions count
+        "columnKind": "utf16CodeUnits",
+        "results": results,
+    });
+    if let Some(root) = root {
+        run["originalUriBaseIds"] = json!({ ROOT: { "uri": root } });
+    }
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [run],
+    })
+}
+
+fn result(uri: &Url, diagnostic: &Diagnostic, root: Option<&str>) -> Value {
+    let level = match diagnostic.severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) | None => "warning",
+        Some(_) => "note",
+    };
+    let artifact = match root.and_then(|root| uri.as_str().strip_prefix(root)) {
+        Some(relative) => json!({ "uri": relative, "uriBaseId": ROOT }),
+        None => json!({ "uri": uri }),
+    };
+    let range = diagnostic.range;
+    let mut result = json!({
+        "level": level,
+        "message": { "text": diagnostic.message },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": artifact,
+                "region": {
+                    "startLine": range.start.line + 1,
+                    "startColumn": range.start.character + 1,
+                    "endLine": range.end.line + 1,
+                    "endColumn": range.end.character + 1,
+                },
+            },
+        }],
+    });
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => result["ruleId"] = json!(code),
+        Some(NumberOrString::Number(code)) => result["ruleId"] = json!(code.to_string()),
+        None => {}
+    }
+    result
+}
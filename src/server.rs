@@ -0,0 +1,4374 @@
+use crate::bibtex::{self, Bibliography};
+use crate::candidates::{Collected, Providers, Query, Source};
+use crate::collation::Collator;
+use crate::config::{
+    Config, ContextWindow, IndexConfig, IndexProfile, RankingConfig, RankingStrategy,
+    ReferencesConfig, StorageKind,
+};
+use crate::consistency::{self, Shape};
+use crate::data_dir;
+use crate::diagnostics::{self, Fix};
+use crate::dismissals::{Dismissals, Shown};
+use crate::doc::{self, DocRenderer};
+use crate::document::{self, Document};
+use crate::engine::{self, split_word_prefix, word_suffix};
+use crate::error::ServerError;
+use crate::experiment::{Arm, Experiment};
+use crate::ext::{
+    self, ClipboardHint, ClipboardHintParams, CompletionDismissed, CompletionDismissedParams,
+    DumpProfile, DumpedProfile, ExplainArgs, ExplainRanking, ExplainRankingParams,
+    ExportDiagnostics, FormatWorkspaceArgs, FormatWorkspaceResult, LexedToken, LimitStats,
+    ListRules, Occurrences, OccurrencesParams, OccurrencesResult, ParagraphArgs, PreloadDocuments,
+    PreloadDocumentsParams, PreloadedDocuments, ProtocolErrorKind, RankingExplanation,
+    ReadVirtualDocument, ReadVirtualDocumentParams, RecentErrors, ReplaceInWorkspaceArgs,
+    RuleDescription, ServerStats, SetDocumentLanguage, SetDocumentLanguageParams, Stats, TokenKind,
+    Tokenize, TokenizeParams, VirtualDocument, CLEAR_WORKSPACE_DATA, COMPLETION_ACCEPTED, EXPLAIN,
+    FORMAT_WORKSPACE, JOIN_LINES, REFLOW_PARAGRAPH, REPLACE_IN_WORKSPACE, RESET_STATISTICS,
+    REWRITE_PARAGRAPH, SET_DOCUMENT_LANGUAGE, VIRTUAL_DOCUMENTS_CAPABILITY, VIRTUAL_SCHEME,
+};
+use crate::glossary::{self, Definition, Glossary};
+use crate::index::{self, Index, SharedIndex, WordCounts};
+use crate::line_index::LineIndex;
+use crate::markdown::{self, LinkTarget, Markdown};
+use crate::plugin::{self, Unanswered, Worker};
+use crate::profile::Profiler;
+use crate::protocol_errors::ProtocolErrors;
+use crate::ranking::{self, RankingTrace};
+use crate::references::{self, Exclusions};
+#[cfg(feature = "workspace")]
+use crate::replace;
+use crate::session::Session;
+use crate::table::{self, Table};
+use crate::transport::{self, MessageTooLarge, TooLarge};
+use crate::watchdog::{self, Watchdog};
+use crate::{
+    context, format, git, key_path, language, log_file, outline, preview, readability, rule_pack,
+    sarif, snippet, uri, workspace, wrap, Token,
+};
+use indexmap::IndexSet;
+use itertools::{Either, Itertools};
+use logos::Logos;
+use lsp_server::{
+    Connection, ErrorCode, ExtractError, Message, Notification, Request, RequestId, Response,
+};
+use lsp_types::notification::{
+    Cancel, DidChangeConfiguration, DidChangeTextDocument, DidChangeWatchedFiles,
+    DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument, Exit, LogMessage,
+    Notification as _, Progress, PublishDiagnostics, ShowMessage,
+};
+use lsp_types::request::{
+    ApplyWorkspaceEdit, CodeActionRequest, Completion, DocumentSymbolRequest, ExecuteCommand,
+    FoldingRangeRequest, Formatting, GotoDefinition, HoverRequest, InlayHintRequest,
+    OnTypeFormatting, PrepareRenameRequest, References, RegisterCapability, Rename, Request as _,
+    ResolveCompletionItem, SemanticTokensFullRequest, ShowDocument, ShowMessageRequest, Shutdown,
+    WillSaveWaitUntil, WorkDoneProgressCreate,
+};
+use lsp_types::{
+    AnnotatedTextEdit, ApplyWorkspaceEditParams, ApplyWorkspaceEditResponse, CancelParams,
+    ChangeAnnotation, CodeAction, CodeActionKind, CodeActionOptions, CodeActionOrCommand,
+    CodeActionParams, CodeActionProviderCapability, CompletionItem, CompletionItemKind,
+    CompletionItemLabelDetails, CompletionList, CompletionOptions, CompletionParams,
+    CompletionResponse, CompletionTextEdit, Diagnostic, DidChangeConfigurationParams,
+    DidChangeWatchedFilesParams, DidChangeWatchedFilesRegistrationOptions, DocumentChanges,
+    DocumentFormattingParams, DocumentOnTypeFormattingOptions, DocumentOnTypeFormattingParams,
+    DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, ExecuteCommandOptions,
+    ExecuteCommandParams, FileChangeType, FileSystemWatcher, FoldingRangeParams,
+    FoldingRangeProviderCapability, GlobPattern, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverContents, HoverParams, HoverProviderCapability, InitializeParams, InlayHint,
+    InlayHintLabel, InlayHintParams, InsertTextFormat, Location, LogMessageParams, MessageType,
+    NumberOrString, OneOf, OptionalVersionedTextDocumentIdentifier, Position,
+    PrepareRenameResponse, ProgressParams, ProgressParamsValue, ProgressToken,
+    PublishDiagnosticsParams, ReferenceParams, Registration, RegistrationParams, RenameOptions,
+    RenameParams, SemanticTokens, SemanticTokensFullOptions, SemanticTokensLegend,
+    SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, ShowDocumentParams, ShowMessageParams,
+    ShowMessageRequestParams, SymbolKind, TextDocumentEdit, TextDocumentItem,
+    TextDocumentPositionParams, TextEdit, Url, VersionedTextDocumentIdentifier,
+    WillSaveTextDocumentParams, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressCreateParams, WorkDoneProgressEnd, WorkDoneProgressReport, WorkspaceEdit,
+};
+#[cfg(feature = "diagnostics")]
+use lsp_types::{Command, TextDocumentIdentifier};
+#[cfg(feature = "workspace")]
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::{AbortHandle, JoinSet};
+
+/// Words the `testlsp:stats` report lists.
+const STATS_REPORT_WORDS: usize = 100;
+
+/// How many files each `workspace/applyEdit` of the workspace formatting changes.
+#[cfg(feature = "workspace")]
+const FORMAT_BATCH: usize = 50;
+/// References gathered before sending them as a partial result.
+const REFERENCES_BATCH: usize = 100;
+/// How long the server has to be idle before the lazy crawl indexes more files.
+const CRAWL_IDLE: Duration = Duration::from_secs(2);
+/// Files each step of the lazy crawl indexes.
+const CRAWL_BATCH: usize = 100;
+/// Copied strings kept for completion.
+const CLIPBOARD_ENTRIES: usize = 20;
+/// Longer copied strings aren't identifiers worth completing.
+const CLIPBOARD_MAX_LEN: usize = 200;
+
+type Result<T> = std::result::Result<T, ServerError>;
+
+pub struct Server {
+    connection: Connection,
+    config: Config,
+    root: Option<PathBuf>,
+    /// Where the index and what else is persisted about the workspace live, see
+    /// [`data_dir`].
+    data_dir: Option<PathBuf>,
+    index: Index,
+    shared: Option<SharedIndex>,
+    plugins: Vec<Worker>,
+    providers: Providers,
+    diagnostic_providers: diagnostics::Providers,
+    contents: HashMap<Url, Document>,
+    /// Changed documents waiting for their edits to settle before being reindexed and
+    /// diagnosed, by when they're due.
+    settling: HashMap<Url, Instant>,
+    /// The diagnostics last published for each document.
+    published: HashMap<Url, Vec<Diagnostic>>,
+    /// Whether the client accepts server-initiated progress.
+    work_done_progress: bool,
+    /// Whether the client watches files for the server once asked to.
+    watches_files: bool,
+    /// Whether the client opens the documents of `window/showDocument`.
+    shows_documents: bool,
+    /// Whether the client reads `testlsp:` documents with `testLsp/readVirtualDocument`.
+    virtual_documents: bool,
+    /// The explanations of `testLsp.explain`, by the slug of their `testlsp:` document.
+    explanations: HashMap<String, String>,
+    /// The completion kinds the client can show, `None` for the base set.
+    completion_kinds: Option<Vec<CompletionItemKind>>,
+    snippet_support: bool,
+    /// Whether the client shows `labelDetails`, where completions say where they're from.
+    label_details: bool,
+    /// Whether the client takes `documentChanges` and change annotations in workspace
+    /// edits.
+    document_changes: bool,
+    change_annotations: bool,
+    completion_docs: DocRenderer,
+    hover_docs: DocRenderer,
+    next_request_id: i32,
+    initial_scan: bool,
+    /// The workspace files `index.lazy_workspace` has yet to index.
+    unindexed: IndexSet<PathBuf>,
+    /// Whether a batch of `unindexed` is being indexed off the message loop.
+    crawling: bool,
+    /// CPU and IO bound work, run off the message loop.
+    tasks: JoinSet<Background>,
+    /// Requests answered once a task finishes, which `$/cancelRequest` can cancel.
+    in_flight: HashMap<RequestId, InFlight>,
+    /// The requests `$/cancelRequest` named, seen as they arrive rather than once the
+    /// message loop gets to them, for the requests it's busy with to give up waiting.
+    cancellations: Arc<Mutex<HashSet<RequestId>>>,
+    /// `workspace/applyEdit` requests by id, until the client answers them.
+    applying: HashMap<RequestId, Applying>,
+    formatting: HashMap<RequestId, WorkspaceFormat>,
+    limits: BTreeMap<String, LimitStats>,
+    reference_exclusions: Exclusions,
+    session: Session,
+    experiment: Option<Experiment>,
+    bibliography: Bibliography,
+    glossary: Glossary,
+    /// What the markdown files tell about each other, open or not.
+    markdown_files: HashMap<Url, MarkdownFile>,
+    /// The broken links of each markdown file as last checked, which are published
+    /// with its other diagnostics.
+    broken_links: HashMap<Url, Vec<Diagnostic>>,
+    /// The words of the lines changed since the last commit, with `completion.git_boost`.
+    changed_words: HashSet<String>,
+    /// When `changed_words` was last asked for.
+    git_refreshed: Option<Instant>,
+    /// Where to write the session summary on shutdown, besides the log.
+    session_report: Option<PathBuf>,
+    /// Started by `--profile`, written on shutdown and `testLsp/dumpProfile`.
+    profiler: Option<Profiler>,
+    dismissals: Dismissals,
+    /// Why the items of the last completion rank where they do, with `completion.debug`.
+    rankings: HashMap<String, RankingExplanation>,
+    /// From `testLsp/clipboardHint`, the latest first.
+    clipboard: VecDeque<String>,
+    /// Documents whose text came in a message over `--max-message-size`, left empty
+    /// until they're opened again.
+    oversized: HashSet<Url>,
+    /// The completion providers the user was told were disabled for being too slow,
+    /// which they're told once.
+    slow_providers: HashSet<String>,
+    protocol_errors: ProtocolErrors,
+}
+
+/// Why [`Server::run`] returned.
+pub enum Stop {
+    Exit,
+    /// The [`Watchdog`] found the client gone.
+    ClientGone,
+}
+
+enum Background {
+    Scanned(Vec<Scanned>),
+    /// The workspace files, listed for the lazy crawl.
+    Listed(Vec<PathBuf>),
+    /// A batch of the lazy crawl.
+    Crawled {
+        paths: Vec<PathBuf>,
+        scanned: Vec<Scanned>,
+    },
+    /// The files of `testLsp/preloadDocuments`.
+    Preloaded {
+        id: RequestId,
+        scanned: Vec<Scanned>,
+        skipped: Vec<Url>,
+    },
+    GitChanged(std::io::Result<HashSet<String>>),
+    /// The broken links of the files affected by a save.
+    CheckedLinks(Vec<(Url, Vec<Diagnostic>)>),
+    #[cfg(feature = "workspace")]
+    FormattedWorkspace {
+        id: RequestId,
+        dry_run: bool,
+        changes: Vec<FileEdits>,
+    },
+    #[cfg(feature = "workspace")]
+    ReplacedInWorkspace {
+        id: RequestId,
+        args: ReplaceInWorkspaceArgs,
+        changes: Vec<FileEdits>,
+    },
+    /// What the first plugin to explain a phrase said about it.
+    Explained {
+        id: RequestId,
+        phrase: String,
+        explanation: Option<String>,
+    },
+    /// The rewrite of a paragraph the first plugin to suggest one gave.
+    Rewritten {
+        id: RequestId,
+        edit: Option<FileEdits>,
+    },
+}
+
+/// A workspace file as the scan found it.
+struct Scanned {
+    uri: Url,
+    /// Of the content, see [`index::content_hash`].
+    hash: u64,
+    counts: WordCounts,
+    /// Only for `.bib` files.
+    citations: Option<Vec<bibtex::Entry>>,
+    markdown: Option<MarkdownFile>,
+    definitions: Vec<Definition>,
+    /// Only with profiles diagnosing the files the client hasn't opened.
+    diagnostics: Option<Vec<Diagnostic>>,
+}
+
+/// Edits of one file, for the version of it they were made for.
+struct FileEdits {
+    uri: Url,
+    /// `None` for files the client hasn't opened.
+    version: Option<i32>,
+    edits: Vec<TextEdit>,
+}
+
+/// A `workspace/applyEdit` the client has yet to answer.
+struct Applying {
+    label: String,
+    files: Vec<FileEdits>,
+    /// The command it was sent for, like a batch of `testLsp.formatWorkspace`.
+    command: RequestId,
+}
+
+/// A `testLsp.formatWorkspace` or `testLsp.replaceInWorkspace` command waiting on the
+/// client to apply its batches.
+struct WorkspaceFormat {
+    pending: usize,
+    batches: usize,
+    applied: Vec<Url>,
+    failed: Vec<Url>,
+    edits: usize,
+    progress: Option<ProgressToken>,
+}
+
+/// A document using a word, open or not.
+struct Using {
+    /// As `contents` and the index key it.
+    key: Url,
+    /// As the client knows it.
+    uri: Url,
+    text: String,
+    language: String,
+    /// `None` for files the client hasn't opened.
+    version: Option<i32>,
+}
+
+struct InFlight {
+    abort: AbortHandle,
+    /// Lets blocking tasks stop early, as aborting them only discards their result.
+    cancelled: Arc<AtomicBool>,
+    progress: Option<ProgressToken>,
+}
+
+/// What the server tells clients it can do.
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Options(
+            lsp_types::TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(lsp_types::TextDocumentSyncKind::INCREMENTAL),
+                will_save_wait_until: Some(true),
+                save: Some(lsp_types::TextDocumentSyncSaveOptions::Supported(true)),
+                ..Default::default()
+            },
+        )),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(
+                [" ", "\t", "\n", "\r", "$"]
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect_vec(),
+            ),
+            resolve_provider: Some(true),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: SemanticTokensLegend {
+                    token_types: log_file::LEGEND.to_vec(),
+                    token_modifiers: Vec::new(),
+                },
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..Default::default()
+            },
+        )),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        inlay_hint_provider: Some(OneOf::Left(true)),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: "(".to_string(),
+            more_trigger_character: Some(
+                ["[", "{", "“", "«", ")", "]", "}", "”", "»", "\""]
+                    .map(str::to_string)
+                    .to_vec(),
+            ),
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![
+                ext::FORMAT_WORKSPACE.to_string(),
+                ext::REPLACE_IN_WORKSPACE.to_string(),
+                ext::SET_DOCUMENT_LANGUAGE.to_string(),
+                ext::COMPLETION_ACCEPTED.to_string(),
+                ext::REFLOW_PARAGRAPH.to_string(),
+                ext::JOIN_LINES.to_string(),
+                ext::REWRITE_PARAGRAPH.to_string(),
+                ext::EXPLAIN.to_string(),
+                ext::RESET_STATISTICS.to_string(),
+                ext::CLEAR_WORKSPACE_DATA.to_string(),
+            ],
+            ..Default::default()
+        }),
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            code_action_kinds: Some(vec![
+                CodeActionKind::QUICKFIX,
+                CodeActionKind::REFACTOR_REWRITE,
+                CodeActionKind::REFACTOR_INLINE,
+                CodeActionKind::from(diagnostics::FIX_ALL),
+            ]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+impl Server {
+    pub fn new(
+        connection: Connection,
+        params: InitializeParams,
+        session_report: Option<PathBuf>,
+        profiler: Option<Profiler>,
+    ) -> Result<Self> {
+        let mut config = Config::from_initialization_options(params.initialization_options);
+        rule_pack::apply(&mut config);
+        let work_done_progress = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        let watches_files = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|watched| watched.dynamic_registration)
+            .unwrap_or(false);
+        let shows_documents = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.show_document.as_ref())
+            .is_some_and(|show_document| show_document.support);
+        let virtual_documents = params
+            .capabilities
+            .experimental
+            .as_ref()
+            .and_then(|experimental| experimental.get("testLsp"))
+            .and_then(|test_lsp| test_lsp.get(VIRTUAL_DOCUMENTS_CAPABILITY))
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(false);
+        let completion = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.completion.as_ref());
+        let completion_kinds = completion
+            .and_then(|completion| completion.completion_item_kind.as_ref())
+            .and_then(|kinds| kinds.value_set.clone());
+        let completion_docs = DocRenderer::new(
+            completion
+                .and_then(|completion| completion.completion_item.as_ref())
+                .and_then(|item| item.documentation_format.as_deref()),
+            config.responses.completion_documentation,
+        );
+        let hover_docs = DocRenderer::new(
+            params
+                .capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| text_document.hover.as_ref())
+                .and_then(|hover| hover.content_format.as_deref()),
+            config.responses.hover,
+        );
+        let workspace_edit = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.workspace_edit.as_ref());
+        let document_changes = workspace_edit
+            .and_then(|workspace_edit| workspace_edit.document_changes)
+            .unwrap_or(false);
+        let change_annotations = workspace_edit
+            .is_some_and(|workspace_edit| workspace_edit.change_annotation_support.is_some());
+        let snippet_support = completion
+            .and_then(|completion| completion.completion_item.as_ref())
+            .and_then(|item| item.snippet_support)
+            .unwrap_or(false);
+        let label_details = completion
+            .and_then(|completion| completion.completion_item.as_ref())
+            .and_then(|item| item.label_details_support)
+            .unwrap_or(false);
+        let root_uri = params
+            .workspace_folders
+            .as_ref()
+            .and_then(|folders| folders.first())
+            .map(|folder| uri::normalize(&folder.uri));
+        let root = root_uri.as_ref().and_then(uri::to_path);
+        let data_dir = root_uri
+            .as_ref()
+            .and_then(|root| data_dir::workspace(config.data_dir.as_deref(), root));
+        let (mut index, mut shared) = open_index(&config, data_dir.as_deref())?;
+        let initial_scan = match &mut shared {
+            Some(shared) if !shared.is_writer() => {
+                if let Err(err) = shared.load(&mut index, &[]) {
+                    log::error!("failed to load the shared index: {err}");
+                }
+                false
+            }
+            _ => root.is_some() && config.index.indexes_workspace(),
+        };
+        let mut plugins = plugin::load(&config.plugins)
+            .into_iter()
+            .map(Worker::spawn)
+            .collect_vec();
+        if let Some(sidecar) = config.sidecar.as_ref().filter(|_| !config.offline) {
+            match plugin::connect_sidecar(sidecar) {
+                Ok(plugin) => plugins.push(Worker::spawn(plugin)),
+                Err(err) => log::error!("failed to connect to {}: {err}", sidecar.endpoint),
+            }
+        }
+
+        let reference_exclusions = Exclusions::new(root.as_deref(), &config.references.exclude);
+        let experiment = config.completion.experiment.clone().map(Experiment::new);
+        let diagnostic_providers =
+            diagnostics::Providers::new(&plugins, config.diagnostics.clone());
+        let providers = Providers::new(&plugins, &config.completion);
+        let protocol_errors = ProtocolErrors::new(config.debug.recent_errors);
+        let glossary = Glossary::new(config.acronyms.clone());
+
+        Ok(Self {
+            connection,
+            config,
+            root,
+            data_dir,
+            index,
+            shared,
+            plugins,
+            providers,
+            diagnostic_providers,
+            contents: HashMap::new(),
+            settling: HashMap::new(),
+            published: HashMap::new(),
+            work_done_progress,
+            watches_files,
+            shows_documents,
+            virtual_documents,
+            explanations: HashMap::new(),
+            completion_kinds,
+            snippet_support,
+            label_details,
+            document_changes,
+            change_annotations,
+            completion_docs,
+            hover_docs,
+            next_request_id: 0,
+            initial_scan,
+            unindexed: IndexSet::new(),
+            crawling: false,
+            tasks: JoinSet::new(),
+            in_flight: HashMap::new(),
+            cancellations: Arc::default(),
+            applying: HashMap::new(),
+            formatting: HashMap::new(),
+            limits: BTreeMap::new(),
+            reference_exclusions,
+            session: Session::default(),
+            experiment,
+            bibliography: Bibliography::default(),
+            glossary,
+            markdown_files: HashMap::new(),
+            broken_links: HashMap::new(),
+            changed_words: HashSet::new(),
+            git_refreshed: None,
+            session_report,
+            profiler,
+            dismissals: Dismissals::default(),
+            rankings: HashMap::new(),
+            clipboard: VecDeque::new(),
+            oversized: HashSet::new(),
+            slow_providers: HashSet::new(),
+            protocol_errors,
+        })
+    }
+
+    pub async fn run(mut self, mut watchdog: Option<Watchdog>) -> Result<Stop> {
+        let (sender, mut messages) = tokio::sync::mpsc::unbounded_channel();
+        let receiver = self.connection.receiver.clone();
+        let cancellations = self.cancellations.clone();
+        // lsp-server's channel blocks, so it's forwarded from a thread of its own
+        std::thread::spawn(move || {
+            for msg in receiver {
+                if let Some(id) = cancelled_id(&msg) {
+                    cancellations.lock().unwrap().insert(id);
+                }
+                if sender.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+        for message in self.config.unsupported() {
+            self.notify::<ShowMessage>(ShowMessageParams {
+                typ: MessageType::WARNING,
+                message,
+            })?;
+        }
+        if self.watches_files {
+            // Links break and heal as files come and go
+            self.request::<RegisterCapability>(RegistrationParams {
+                registrations: vec![Registration {
+                    id: "test-lsp/watched-files".to_string(),
+                    method: DidChangeWatchedFiles::METHOD.to_string(),
+                    register_options: Some(serde_json::to_value(
+                        DidChangeWatchedFilesRegistrationOptions {
+                            watchers: vec![FileSystemWatcher {
+                                glob_pattern: GlobPattern::String("**/*".to_string()),
+                                kind: None,
+                            }],
+                        },
+                    )?),
+                }],
+            })?;
+        }
+        if self.initial_scan {
+            self.scan_workspace();
+        }
+        self.refresh_changed_words();
+        let mut shutdown = false;
+        let mut watch = tokio::time::interval(watchdog::INTERVAL);
+        let consistency_interval = Duration::from_secs(self.config.debug.consistency_interval);
+        let mut consistency =
+            tokio::time::interval(consistency_interval.max(Duration::from_secs(1)));
+        let mut shared_sync = tokio::time::interval(index::SHARED_POLL_INTERVAL);
+        loop {
+            let settled = self.settling.values().min().copied();
+            tokio::select! {
+                msg = messages.recv() => {
+                    let Some(msg) = msg else {
+                        return Ok(Stop::Exit);
+                    };
+                    eprintln!("got msg: {}", transport::abbreviate(&msg));
+                    if let Some(watchdog) = &mut watchdog {
+                        watchdog.heard_from_client();
+                    }
+                    match msg {
+                        Message::Request(req) if req.method == Shutdown::METHOD => {
+                            shutdown = true;
+                            self.report_session();
+                            self.write_profile();
+                            self.respond(req.id, ())?;
+                        }
+                        Message::Request(req) if shutdown => {
+                            self.respond_err(
+                                req.id,
+                                ErrorCode::InvalidRequest,
+                                "shutdown was requested".to_string(),
+                            )?;
+                        }
+                        Message::Request(req) => {
+                            eprintln!("got request: {}", transport::abbreviate(&req));
+                            self.on_request(req)?;
+                        }
+                        Message::Response(resp) => {
+                            eprintln!("got response: {}", transport::abbreviate(&resp));
+                            self.on_response(resp)?;
+                        }
+                        Message::Notification(not) if not.method == Exit::METHOD => {
+                            return Ok(Stop::Exit);
+                        }
+                        Message::Notification(not) => {
+                            eprintln!("got notification: {}", transport::abbreviate(&not));
+                            self.on_notification(not)?;
+                        }
+                    }
+                }
+                _ = watch.tick(), if watchdog.is_some() => {
+                    if let Some(reason) = watchdog.as_ref().and_then(Watchdog::check) {
+                        log::warn!("shutting down, {reason}");
+                        return Ok(Stop::ClientGone);
+                    }
+                }
+                _ = tokio::time::sleep_until(settled.unwrap_or_else(Instant::now).into()),
+                    if settled.is_some() =>
+                {
+                    self.update_settled()?;
+                }
+                _ = tokio::time::sleep(CRAWL_IDLE), if !self.unindexed.is_empty() && !self.crawling => {
+                    self.crawl();
+                }
+                _ = consistency.tick(), if self.config.debug.consistency_check => {
+                    self.check_consistency()?;
+                }
+                _ = shared_sync.tick(), if self.shared.is_some() => {
+                    self.sync_shared_index();
+                }
+                Some(done) = self.tasks.join_next() => match done {
+                    Ok(done) => self.on_background(done)?,
+                    Err(err) if err.is_cancelled() => {}
+                    Err(err) => log::error!("background task failed: {err}"),
+                },
+            }
+        }
+    }
+
+    /// Counts the words of the workspace off the message loop.
+    fn scan_workspace(&mut self) {
+        let Some(root) = self.root.clone() else {
+            return;
+        };
+        let config = self.config.index.clone();
+        if config.lazy_workspace {
+            self.tasks
+                .spawn_blocking(move || Background::Listed(workspace::files(&root, &config)));
+            return;
+        }
+        let scan = self.scanner();
+        self.tasks
+            .spawn_blocking(move || Background::Scanned(workspace::scan(&root, &config, scan)));
+    }
+
+    /// What the scan learns of each workspace file.
+    fn scanner(&self) -> impl Fn(Url, String) -> Scanned + Send + Sync + 'static {
+        let context_symbols = self.index.context_symbols().to_vec();
+        let profile = self.config.index.profile;
+        // Plugins are left to the open documents
+        let providers = diagnostics::Providers::new(&[], self.config.diagnostics.clone());
+        let rules = self.config.rules.clone();
+        move |uri, text| {
+            let hash = index::content_hash(&text);
+            let counts = index::count_words(&text, &context_symbols);
+            let citations = bibtex::is_bib(&uri).then(|| bibtex::parse(&text));
+            let markdown = (language::of_file(&uri, &text) == "markdown")
+                .then(|| MarkdownFile::new(&uri, &text));
+            let definitions = glossary::learn(&text);
+            let diagnostics = profile.diagnose_unopened().then(|| {
+                let document = Document {
+                    language_id: language::of_file(&uri, &text).to_string(),
+                    uri: uri.clone(),
+                    shape: Shape::of(&text),
+                    text,
+                    version: 0,
+                    language_override: None,
+                };
+                let found = providers.check(&uri, &document);
+                diagnostics::configure(diagnostics::suppress(&document, found), &rules)
+            });
+            Scanned {
+                uri,
+                hash,
+                counts,
+                citations,
+                markdown,
+                definitions,
+                diagnostics,
+            }
+        }
+    }
+
+    /// Indexes the next batch of the files the lazy crawl has yet to, off the message
+    /// loop.
+    fn crawl(&mut self) {
+        let paths = self
+            .unindexed
+            .iter()
+            .take(CRAWL_BATCH)
+            .cloned()
+            .collect_vec();
+        let config = self.config.index.clone();
+        let scan = self.scanner();
+        self.crawling = true;
+        self.tasks.spawn_blocking(move || {
+            let scanned = workspace::scan_files(paths.clone().into_iter(), &config, scan);
+            Background::Crawled { paths, scanned }
+        });
+    }
+
+    /// Indexes the files of the lazy crawl a query needs now, on the message loop.
+    fn index_pending(&mut self, needed: impl Fn(&Path) -> bool) -> Result<()> {
+        let paths = self
+            .unindexed
+            .iter()
+            .filter(|path| needed(path))
+            .cloned()
+            .collect_vec();
+        if paths.is_empty() {
+            return Ok(());
+        }
+        for path in &paths {
+            self.unindexed.shift_remove(path);
+        }
+        let scanned = workspace::scan_files(paths.into_iter(), &self.config.index, self.scanner());
+        self.add_scanned(scanned)
+    }
+
+    /// Adds the files a scan read to the index, and to what's known of the workspace.
+    fn add_scanned(&mut self, scanned: Vec<Scanned>) -> Result<()> {
+        let mut indexed = Vec::new();
+        for Scanned {
+            uri,
+            hash,
+            counts,
+            citations,
+            markdown,
+            definitions,
+            diagnostics,
+        } in scanned
+        {
+            // Open documents were indexed as the client has them
+            if self.contents.contains_key(&uri) {
+                continue;
+            }
+            if let Some(citations) = citations {
+                self.bibliography.set(uri.clone(), citations);
+            }
+            if let Some(markdown) = markdown {
+                self.markdown_files.insert(uri.clone(), markdown);
+            }
+            self.glossary.set(uri.clone(), definitions);
+            if let Some(diagnostics) = diagnostics {
+                self.publish_unopened(uri.clone(), diagnostics)?;
+            }
+            indexed.push((uri, hash, counts));
+        }
+        let mut files = indexed.len();
+        for (uri, err) in self.index.set_many_counts(indexed) {
+            log::error!("failed to index {uri}: {err}");
+            files -= 1;
+        }
+        if let Some(root) = &self.root {
+            log::info!("indexed {files} files under {}", root.display());
+        }
+        if let Some(shared) = &mut self.shared {
+            if let Err(err) = shared.publish(&self.index) {
+                log::error!("failed to publish the shared index: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    fn on_background(&mut self, done: Background) -> Result<()> {
+        match done {
+            Background::Scanned(scanned) => self.add_scanned(scanned),
+            Background::Listed(paths) => {
+                let open: HashSet<PathBuf> =
+                    self.contents.keys().filter_map(uri::to_path).collect();
+                self.unindexed = paths
+                    .into_iter()
+                    .filter(|path| !open.contains(path))
+                    .collect();
+                log::info!(
+                    "{} workspace files left to index lazily",
+                    self.unindexed.len()
+                );
+                Ok(())
+            }
+            Background::Crawled { paths, scanned } => {
+                self.crawling = false;
+                for path in &paths {
+                    self.unindexed.shift_remove(path);
+                }
+                self.add_scanned(scanned)
+            }
+            Background::CheckedLinks(checked) => {
+                for (uri, broken) in checked {
+                    self.broken_links.insert(uri.clone(), broken.clone());
+                    if let Some(document) = self.contents.get(&uri).cloned() {
+                        self.publish_diagnostics(&uri, &document)?;
+                        continue;
+                    }
+                    let broken = diagnostics::configure(broken, &self.config.rules);
+                    let others =
+                        self.published
+                            .get(&uri)
+                            .into_iter()
+                            .flatten()
+                            .filter(|diagnostic| {
+                                diagnostic.code
+                                    != Some(NumberOrString::String(
+                                        diagnostics::BROKEN_LINK.to_string(),
+                                    ))
+                            });
+                    let diagnostics = others.cloned().chain(broken).collect();
+                    self.publish_unopened(uri, diagnostics)?;
+                }
+                Ok(())
+            }
+            Background::GitChanged(Ok(words)) => {
+                self.changed_words = words;
+                Ok(())
+            }
+            Background::GitChanged(Err(err)) => {
+                log::warn!("not boosting changed words: {err}");
+                Ok(())
+            }
+            Background::Preloaded {
+                id,
+                scanned,
+                skipped,
+            } => {
+                if self.in_flight.remove(&id).is_none() {
+                    return Ok(());
+                }
+                for scanned in &scanned {
+                    if let Some(path) = uri::to_path(&scanned.uri) {
+                        self.unindexed.shift_remove(&path);
+                    }
+                }
+                let indexed = scanned.len();
+                self.add_scanned(scanned)?;
+                self.respond(id, PreloadedDocuments { indexed, skipped })
+            }
+            #[cfg(feature = "workspace")]
+            Background::FormattedWorkspace {
+                id,
+                dry_run,
+                changes,
+            } => {
+                let Some(in_flight) = self.in_flight.remove(&id) else {
+                    return Ok(());
+                };
+                let progress = in_flight.progress;
+                self.apply_workspace_format(
+                    id,
+                    "Format workspace",
+                    None,
+                    dry_run,
+                    changes,
+                    progress,
+                )
+            }
+            #[cfg(feature = "workspace")]
+            Background::ReplacedInWorkspace { id, args, changes } => {
+                let Some(in_flight) = self.in_flight.remove(&id) else {
+                    return Ok(());
+                };
+                let label = "Replace in workspace";
+                let confirm = format!("Replace `{}` with `{}`", args.pattern, args.replacement);
+                let progress = in_flight.progress;
+                self.apply_workspace_format(
+                    id,
+                    label,
+                    Some(confirm),
+                    args.dry_run,
+                    changes,
+                    progress,
+                )
+            }
+            Background::Explained {
+                id,
+                phrase,
+                explanation,
+            } => {
+                if self.in_flight.remove(&id).is_none() {
+                    return Ok(());
+                }
+                match &explanation {
+                    Some(explanation) => self.show_explanation(&phrase, explanation)?,
+                    None => {
+                        let mut message = format!("No plugin explained `{phrase}`");
+                        if self.config.offline && self.config.sidecar.is_some() {
+                            message.push_str(", the sidecar being off while offline");
+                        }
+                        self.notify::<ShowMessage>(ShowMessageParams {
+                            typ: MessageType::INFO,
+                            message,
+                        })?;
+                    }
+                }
+                self.respond(id, explanation)
+            }
+            Background::Rewritten { id, edit } => {
+                if self.in_flight.remove(&id).is_none() {
+                    return Ok(());
+                }
+                match edit {
+                    Some(file) => {
+                        let label = "Rewrite paragraph";
+                        let files = vec![file];
+                        let request =
+                            self.request::<ApplyWorkspaceEdit>(ApplyWorkspaceEditParams {
+                                label: Some(label.to_string()),
+                                edit: self.workspace_edit(&files, None),
+                            })?;
+                        let applying = Applying {
+                            label: label.to_string(),
+                            files,
+                            command: id.clone(),
+                        };
+                        self.applying.insert(request, applying);
+                    }
+                    None => self.notify::<ShowMessage>(ShowMessageParams {
+                        typ: MessageType::INFO,
+                        message: "No plugin suggested a rewrite".to_string(),
+                    })?,
+                }
+                self.respond(id, ())
+            }
+        }
+    }
+
+    fn on_request(&mut self, req: Request) -> Result<()> {
+        let id = req.id.clone();
+        let req = match cast_req::<Completion>(req) {
+            Ok((id, params)) => return self.completion(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<ResolveCompletionItem>(req) {
+            Ok((id, params)) => return self.resolve_completion(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<HoverRequest>(req) {
+            Ok((id, params)) => return self.hover(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<GotoDefinition>(req) {
+            Ok((id, params)) => return self.definition(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<SemanticTokensFullRequest>(req) {
+            Ok((id, params)) => return self.semantic_tokens(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<FoldingRangeRequest>(req) {
+            Ok((id, params)) => return self.folding_ranges(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<DocumentSymbolRequest>(req) {
+            Ok((id, params)) => return self.document_symbols(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<InlayHintRequest>(req) {
+            Ok((id, params)) => return self.inlay_hints(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<References>(req) {
+            Ok((id, params)) => return self.references(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<PrepareRenameRequest>(req) {
+            Ok((id, params)) => return self.prepare_rename(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<Rename>(req) {
+            Ok((id, params)) => return self.rename(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<CodeActionRequest>(req) {
+            Ok((id, params)) => return self.code_action(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<Tokenize>(req) {
+            Ok((id, params)) => return self.tokenize(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<ExportDiagnostics>(req) {
+            Ok((id, ())) => return self.export_diagnostics(id),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<RecentErrors>(req) {
+            Ok((id, ())) => return self.recent_errors(id),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<ext::Glossary>(req) {
+            Ok((id, ())) => return self.respond(id, self.glossary.entries()),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<ListRules>(req) {
+            Ok((id, ())) => return self.list_rules(id),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<Occurrences>(req) {
+            Ok((id, params)) => return self.occurrences(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<PreloadDocuments>(req) {
+            Ok((id, params)) => return self.preload_documents(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<SetDocumentLanguage>(req) {
+            Ok((id, params)) => return self.set_document_language(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<ReadVirtualDocument>(req) {
+            Ok((id, params)) => return self.read_virtual_document(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<ExplainRanking>(req) {
+            Ok((id, params)) => return self.explain_ranking(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<DumpProfile>(req) {
+            Ok((id, ())) => return self.dump_profile(id),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<Stats>(req) {
+            Ok((id, ())) => return self.stats(id),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<Formatting>(req) {
+            Ok((id, params)) => return self.formatting(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<WillSaveWaitUntil>(req) {
+            Ok((id, params)) => return self.will_save(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<OnTypeFormatting>(req) {
+            Ok((id, params)) => return self.on_type_formatting(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let req = match cast_req::<ExecuteCommand>(req) {
+            Ok((id, params)) => return self.execute_command(id, params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_request_params(id, &method, error)
+            }
+            Err(ExtractError::MethodMismatch(req)) => req,
+        };
+        let message = format!("unknown request {}", req.method);
+        self.protocol_errors.record(
+            ProtocolErrorKind::UnknownMethod,
+            Some(&req.method),
+            message.clone(),
+        );
+        // Left unanswered, the client would wait on it forever
+        self.respond_err(id, ErrorCode::MethodNotFound, message)
+    }
+
+    fn on_notification(&mut self, not: Notification) -> Result<()> {
+        let not = match cast_not::<DidOpenTextDocument>(not) {
+            Ok(lsp_types::DidOpenTextDocumentParams {
+                text_document:
+                    TextDocumentItem {
+                        uri,
+                        language_id,
+                        version,
+                        text,
+                    },
+            }) => {
+                self.session.documents_opened += 1;
+                let key = uri::normalize(&uri);
+                self.oversized.remove(&key);
+                if let Some(path) = uri::to_path(&key) {
+                    self.unindexed.shift_remove(&path);
+                }
+                let document = Document {
+                    uri,
+                    shape: Shape::of(&text),
+                    text,
+                    language_id,
+                    version,
+                    language_override: None,
+                };
+                return self.update_document(key, document);
+            }
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        let not = match cast_not::<DidChangeTextDocument>(not) {
+            Ok(lsp_types::DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier { uri, version },
+                content_changes,
+            }) => {
+                let key = uri::normalize(&uri);
+                if self.oversized.contains(&key) {
+                    return Ok(());
+                }
+                let Some(document) = self.contents.get(&key) else {
+                    let message = format!("{uri} changed, but it isn't open");
+                    self.protocol_errors.record(
+                        ProtocolErrorKind::NotOpen,
+                        Some(DidChangeTextDocument::METHOD),
+                        message,
+                    );
+                    return Ok(());
+                };
+                if version <= document.version {
+                    let message = format!(
+                        "{uri} changed to version {version}, but version {} was synced",
+                        document.version
+                    );
+                    self.protocol_errors.record(
+                        ProtocolErrorKind::StaleVersion,
+                        Some(DidChangeTextDocument::METHOD),
+                        message,
+                    );
+                }
+                let mut text = document.text.clone();
+                document::apply_changes(&mut text, content_changes);
+                let document = Document {
+                    uri,
+                    shape: Shape::of(&text),
+                    text,
+                    version,
+                    ..document.clone()
+                };
+                let debounce = self.config.index.profile.debounce();
+                if debounce.is_zero() {
+                    return self.update_document(key, document);
+                }
+                self.settling.insert(key.clone(), Instant::now() + debounce);
+                self.contents.insert(key, document);
+                return Ok(());
+            }
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        let not = match cast_not::<DidCloseTextDocument>(not) {
+            Ok(params) => return self.close_document(params.text_document.uri),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        let not = match cast_not::<Cancel>(not) {
+            Ok(CancelParams { id }) => {
+                return self.cancel(request_id(id));
+            }
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        let not = match cast_not::<DidSaveTextDocument>(not) {
+            Ok(params) => return self.check_links_to(&[uri::normalize(&params.text_document.uri)]),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        let not = match cast_not::<MessageTooLarge>(not) {
+            Ok(too_large) => return self.message_too_large(too_large),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        let not = match cast_not::<ClipboardHint>(not) {
+            Ok(params) => {
+                self.clipboard_hint(params);
+                return Ok(());
+            }
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        let not = match cast_not::<CompletionDismissed>(not) {
+            Ok(params) => return self.completion_dismissed(params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        let not = match cast_not::<DidChangeWatchedFiles>(not) {
+            Ok(params) => return self.files_changed(params),
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        let not = match cast_not::<DidChangeConfiguration>(not) {
+            Ok(DidChangeConfigurationParams { settings }) => {
+                return self.change_configuration(settings);
+            }
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_notification_params(&method, error)
+            }
+            Err(ExtractError::MethodMismatch(not)) => not,
+        };
+        // Clients may send the optional `$/` notifications whether or not they're handled
+        if not.method.starts_with("$/") {
+            let level = if self.config.debug.log_unhandled {
+                log::Level::Info
+            } else {
+                log::Level::Debug
+            };
+            log::log!(level, "unhandled notification {}", not.method);
+        } else {
+            let message = format!("unknown notification {}", not.method);
+            self.protocol_errors.record(
+                ProtocolErrorKind::UnknownMethod,
+                Some(&not.method),
+                message,
+            );
+        }
+        Ok(())
+    }
+
+    /// Answers a request whose params don't parse as its method's with `InvalidParams`.
+    fn invalid_request_params(
+        &mut self,
+        id: RequestId,
+        method: &str,
+        error: serde_json::Error,
+    ) -> Result<()> {
+        let message = format!("invalid params: {error}");
+        self.protocol_errors.record(
+            ProtocolErrorKind::InvalidParams,
+            Some(method),
+            message.clone(),
+        );
+        self.respond_err(id, ErrorCode::InvalidParams, message)
+    }
+
+    /// Answers a request about a document the client didn't open with `InvalidParams`.
+    fn not_open(&mut self, id: RequestId, method: &str, uri: &Url) -> Result<()> {
+        let message = format!("{uri} isn't open");
+        self.protocol_errors
+            .record(ProtocolErrorKind::NotOpen, Some(method), message.clone());
+        self.respond_err(id, ErrorCode::InvalidParams, message)
+    }
+
+    /// Ignores a notification whose params don't parse as its method's.
+    fn invalid_notification_params(
+        &mut self,
+        method: &str,
+        error: serde_json::Error,
+    ) -> Result<()> {
+        let message = format!("invalid params: {error}");
+        self.protocol_errors
+            .record(ProtocolErrorKind::InvalidParams, Some(method), message);
+        Ok(())
+    }
+
+    fn recent_errors(&mut self, id: RequestId) -> Result<()> {
+        self.respond(id, self.protocol_errors.recent())
+    }
+
+    /// Applies the settings that can change at runtime, currently `index.profile`.
+    fn change_configuration(&mut self, settings: serde_json::Value) -> Result<()> {
+        let Some(profile) = settings.pointer("/index/profile") else {
+            return Ok(());
+        };
+        let profile: IndexProfile = match serde_json::from_value(profile.clone()) {
+            Ok(profile) => profile,
+            Err(err) => {
+                let err = ServerError::Config(format!("index.profile: {err}"));
+                log::warn!("{err}, keeping {:?}", self.config.index.profile);
+                return Ok(());
+            }
+        };
+        let previous = std::mem::replace(&mut self.config.index.profile, profile);
+        log::info!("indexing profile changed from {previous:?} to {profile:?}");
+        if profile.diagnose_unopened() && !previous.diagnose_unopened() {
+            self.scan_workspace();
+        } else if previous.diagnose_unopened() && !profile.diagnose_unopened() {
+            let unopened = self
+                .published
+                .keys()
+                .filter(|uri| !self.contents.contains_key(*uri))
+                .cloned()
+                .collect_vec();
+            for uri in unopened {
+                self.publish_unopened(uri, Vec::new())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Publishes the diagnostics of a workspace file the client hasn't opened, skipping
+    /// the ones with nothing to publish or take back.
+    fn publish_unopened(&mut self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<()> {
+        let previous = if diagnostics.is_empty() {
+            self.published.remove(&uri)
+        } else {
+            self.published.insert(uri.clone(), diagnostics.clone())
+        };
+        if diagnostics.is_empty() && previous.is_none_or(|previous| previous.is_empty()) {
+            return Ok(());
+        }
+        self.session.diagnostics_published += diagnostics.len() as u64;
+        self.publish(PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        })
+    }
+
+    /// Reindexes and diagnoses the changed documents whose edits settled.
+    fn update_settled(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let settled = self
+            .settling
+            .iter()
+            .filter(|(_, due)| **due <= now)
+            .map(|(uri, _)| uri.clone())
+            .collect_vec();
+        for uri in settled {
+            let document = self.contents[&uri].clone();
+            self.update_document(uri, document)?;
+        }
+        Ok(())
+    }
+
+    fn update_document(&mut self, uri: Url, document: Document) -> Result<()> {
+        eprintln!("{uri} :: {}", transport::abbreviate(&document.text));
+        self.settling.remove(&uri);
+        if let Err(err) = self.index.update(&uri, &document.text) {
+            log::error!("failed to index {uri}: {err}");
+        }
+        if let Some(shared) = &mut self.shared {
+            shared.mark_dirty();
+        }
+        self.bibliography.update(&uri, &document.text);
+        self.glossary.update(&uri, &document.text);
+        match document.language() {
+            "markdown" => {
+                let markdown = MarkdownFile::new(&uri, &document.text);
+                self.markdown_files.insert(uri.clone(), markdown);
+            }
+            _ => _ = self.markdown_files.remove(&uri),
+        }
+        self.publish_diagnostics(&uri, &document)?;
+        self.contents.insert(uri, document);
+        self.refresh_changed_words();
+        Ok(())
+    }
+
+    /// Forgets the edits of a closed document, indexing what's on disk of it in their
+    /// place, and takes back its diagnostics.
+    fn close_document(&mut self, uri: Url) -> Result<()> {
+        let key = uri::normalize(&uri);
+        self.settling.remove(&key);
+        self.oversized.remove(&key);
+        self.published.remove(&key);
+        let Some(document) = self.contents.remove(&key) else {
+            return Ok(());
+        };
+        // Nothing, for the documents not on disk or the workspaces not indexed
+        let text = uri::to_path(&key)
+            .filter(|_| self.config.index.indexes_workspace())
+            .and_then(|path| workspace::read_text(&path).ok())
+            .unwrap_or_default();
+        if let Err(err) = self.index.update(&key, &text) {
+            log::error!("failed to index {key}: {err}");
+        }
+        if let Some(shared) = &mut self.shared {
+            shared.mark_dirty();
+        }
+        self.bibliography.update(&key, &text);
+        self.glossary.update(&key, &text);
+        match !text.is_empty() && language::of_file(&key, &text) == "markdown" {
+            true => {
+                let markdown = MarkdownFile::new(&key, &text);
+                self.markdown_files.insert(key.clone(), markdown);
+            }
+            false => _ = self.markdown_files.remove(&key),
+        }
+        self.publish(PublishDiagnosticsParams {
+            uri: document.uri,
+            diagnostics: Vec::new(),
+            version: None,
+        })?;
+        self.check_links_to(&[key])
+    }
+
+    /// Asks git for the changed words off the message loop, unless it was asked lately.
+    fn refresh_changed_words(&mut self) {
+        let Some(root) = self.root.clone() else {
+            return;
+        };
+        let fresh = self
+            .git_refreshed
+            .is_some_and(|refreshed| refreshed.elapsed() < git::REFRESH);
+        if !self.config.completion.git_boost || fresh {
+            return;
+        }
+        self.git_refreshed = Some(Instant::now());
+        self.tasks
+            .spawn_blocking(move || Background::GitChanged(git::changed_words(&root)));
+    }
+
+    /// Logs the open documents that drifted from their edits or the index, and resyncs
+    /// them from the text as last synced.
+    fn check_consistency(&mut self) -> Result<()> {
+        let mut drifted = Vec::new();
+        for (uri, document) in &self.contents {
+            // Not indexed yet on purpose
+            if self.settling.contains_key(uri) {
+                continue;
+            }
+            let indexed = match self.index.document(uri) {
+                Ok(indexed) => indexed,
+                Err(err) => {
+                    log::error!("failed to query the index: {err}");
+                    continue;
+                }
+            };
+            let counted = index::count_words(&document.text, self.index.context_symbols());
+            if let Some(report) = consistency::check(document, indexed.as_ref(), &counted) {
+                log::error!("{uri} is out of sync, resyncing:\n{report}");
+                drifted.push(uri.clone());
+            }
+        }
+        for uri in drifted {
+            let mut document = self.contents[&uri].clone();
+            document.shape = Shape::of(&document.text);
+            self.update_document(uri, document)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps the links of the files created, changed or deleted outside of the editor,
+    /// and checks again the files linking to them.
+    fn files_changed(&mut self, params: DidChangeWatchedFilesParams) -> Result<()> {
+        let mut changed = Vec::new();
+        for event in params.changes {
+            let uri = uri::normalize(&event.uri);
+            if self.contents.contains_key(&uri) {
+                continue;
+            }
+            match event.typ {
+                FileChangeType::DELETED => {
+                    self.markdown_files.remove(&uri);
+                    if self.broken_links.remove(&uri).is_some() {
+                        self.publish_unopened(uri.clone(), Vec::new())?;
+                    }
+                }
+                _ => {
+                    let text = uri::to_path(&uri).and_then(|path| workspace::read_text(&path).ok());
+                    if let Some(text) =
+                        text.filter(|text| language::of_file(&uri, text) == "markdown")
+                    {
+                        self.markdown_files
+                            .insert(uri.clone(), MarkdownFile::new(&uri, &text));
+                    }
+                }
+            }
+            changed.push(uri);
+        }
+        self.check_links_to(&changed)
+    }
+
+    /// Checks the links of the `changed` files, and of the files linking to them, off
+    /// the message loop.
+    fn check_links_to(&mut self, changed: &[Url]) -> Result<()> {
+        let affected = self
+            .markdown_files
+            .iter()
+            .filter(|(from, file)| {
+                changed.contains(from) || file.links.iter().any(|target| changed.contains(target))
+            })
+            .map(|(from, _)| from.clone())
+            .collect_vec();
+        if affected.is_empty() {
+            return Ok(());
+        }
+        let open: HashMap<Url, String> = self
+            .contents
+            .iter()
+            .map(|(uri, document)| (uri.clone(), document.text.clone()))
+            .collect();
+        let parallelism = self.config.index.profile.parallelism();
+        self.tasks.spawn_blocking(move || {
+            let read = |uri: &Url| match open.get(&uri::normalize(uri)) {
+                Some(text) => Some(text.clone()),
+                None => {
+                    let path = uri.to_file_path().ok()?;
+                    match path.is_dir() {
+                        true => Some(String::new()),
+                        false => workspace::read_text(&path).ok(),
+                    }
+                }
+            };
+            let list = |directory: &Url| {
+                let entries = uri::to_path(directory).and_then(|path| std::fs::read_dir(path).ok());
+                entries
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter_map(|entry| entry.file_name().into_string().ok())
+                    .collect()
+            };
+            let chunk = affected.len().div_ceil(parallelism);
+            let checked = std::thread::scope(|scope| {
+                let workers = affected
+                    .chunks(chunk)
+                    .map(|chunk| {
+                        scope.spawn(|| {
+                            chunk
+                                .iter()
+                                .map(|uri| {
+                                    let text = read(uri).unwrap_or_default();
+                                    (
+                                        uri.clone(),
+                                        diagnostics::check_links(uri, &text, read, list),
+                                    )
+                                })
+                                .collect_vec()
+                        })
+                    })
+                    .collect_vec();
+                workers
+                    .into_iter()
+                    .flat_map(|worker| worker.join().expect("a link check panicked"))
+                    .collect()
+            });
+            Background::CheckedLinks(checked)
+        });
+        Ok(())
+    }
+
+    /// The headings of a markdown document of `diagnostics.docs_folder` whose anchors
+    /// another file of the folder has too.
+    fn duplicate_anchors(&self, uri: &Url, document: &Document) -> Vec<Diagnostic> {
+        let folder = self
+            .root
+            .as_ref()
+            .zip(self.config.diagnostics.docs_folder.as_ref());
+        let Some(folder) = folder.map(|(root, folder)| root.join(folder)) else {
+            return Vec::new();
+        };
+        let in_folder = |uri: &Url| uri::to_path(uri).is_some_and(|path| path.starts_with(&folder));
+        if document.language() != "markdown" || !in_folder(uri) {
+            return Vec::new();
+        }
+        let markdown = Markdown::parse(&document.text);
+        diagnostics::check_anchors_across(&document.text, &markdown, |anchor| {
+            self.markdown_files
+                .iter()
+                .filter(|(other, file)| {
+                    *other != uri && in_folder(other) && file.anchors.contains(anchor)
+                })
+                .min_by_key(|(other, _)| other.as_str())
+                .map(|(other, file)| (other.clone(), file.anchors.clone()))
+        })
+    }
+
+    fn publish_diagnostics(&mut self, uri: &Url, document: &Document) -> Result<()> {
+        let mut diagnostics = self.diagnostic_providers.check(&document.uri, document);
+        diagnostics.extend(self.broken_links.get(uri).into_iter().flatten().cloned());
+        diagnostics.extend(self.duplicate_anchors(uri, document));
+        let diagnostics = diagnostics::suppress(document, diagnostics);
+        let diagnostics = diagnostics::configure(diagnostics, &self.config.rules);
+        self.session.diagnostics_published += diagnostics.len() as u64;
+        self.published.insert(uri.clone(), diagnostics.clone());
+        self.publish(PublishDiagnosticsParams {
+            uri: document.uri.clone(),
+            diagnostics,
+            version: Some(document.version),
+        })
+    }
+
+    fn completion(&mut self, id: RequestId, params: CompletionParams) -> Result<()> {
+        let token = params.work_done_progress_params.work_done_token;
+        let progress = self.begin_request_progress(token, "Completing")?;
+        let position = params.text_document_position.position;
+        let file = uri::normalize(&params.text_document_position.text_document.uri);
+        // The words of the neighbouring files are the likeliest to be wanted
+        if let Some(directory) = uri::to_path(&file).as_deref().and_then(Path::parent) {
+            self.index_pending(|path| path.parent() == Some(directory))?;
+        }
+        let Some(document) = self.contents.get(&file) else {
+            if let Some(token) = progress {
+                self.end_progress(token)?;
+            }
+            return self.not_open(id, Completion::METHOD, &file);
+        };
+        // Owned, since plugins complete on their own threads
+        let text = &document.text.clone();
+        let language = document.language();
+        let strategy = self.config.completion.strategy(language);
+        let Some(offset) = LineIndex::new(text).offset(position) else {
+            if let Some(token) = progress {
+                self.end_progress(token)?;
+            }
+            let message = format!("{position:?} is out of {file}");
+            return self.respond_err(id, ErrorCode::InvalidParams, message);
+        };
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(self.config.completion.deadline);
+        let configured = self.config.completion.context_window;
+        let column_window = self.config.completion.column_window;
+        let window = context::window(text, offset, configured.unwrap_or(ContextWindow::Line));
+        let window = context::clamp(text, window, offset, column_window);
+        let window_words = context::words(text, window.clone(), deadline);
+        let (before, prefix) = split_word_prefix(position, text);
+        let before = &before[context::clamp(before, 0..before.len(), before.len(), column_window)];
+        let word_start = Position::new(
+            position.line,
+            position.character - prefix.encode_utf16().count() as u32,
+        );
+        // Edited in the middle, the word is replaced whole rather than left to end twice
+        let word_end = Position::new(
+            position.line,
+            position.character + word_suffix(position, text).encode_utf16().count() as u32,
+        );
+        let completion = &self.config.completion;
+        let (arm, ranking) = match &self.experiment {
+            Some(experiment) => {
+                let (arm, ranking) = experiment.assign(&file);
+                (Some(arm), ranking)
+            }
+            None => (
+                None,
+                RankingConfig {
+                    symbol_context: completion.symbol_context,
+                    plugin_ranking: true,
+                },
+            ),
+        };
+        let query = Query {
+            uri: &file,
+            text,
+            language,
+            position,
+            word_start,
+            before,
+            prefix,
+            window_words: &window_words,
+            window: configured.map(|_| window),
+            symbol_context: ranking.symbol_context,
+            context_symbols: &completion.context_symbols,
+            index: &self.index,
+            bibliography: &self.bibliography,
+            clipboard: &self.clipboard,
+        };
+        let cancellations = self.cancellations.clone();
+        let cancelled = || cancellations.lock().unwrap().contains(&id);
+        let Collected {
+            words,
+            mut incomplete,
+            replacing,
+            disabled,
+        } = self
+            .providers
+            .collect(&query, started, &mut self.session, &cancelled);
+        if cancelled() {
+            if let Some(token) = progress {
+                self.end_progress(token)?;
+            }
+            return self.respond_err(id, ErrorCode::RequestCanceled, "canceled".to_string());
+        }
+        for name in disabled {
+            if !self.slow_providers.insert(name.clone()) {
+                continue;
+            }
+            let cooldown = self.config.completion.cooldown;
+            self.notify::<ShowMessage>(ShowMessageParams {
+                typ: MessageType::WARNING,
+                message: format!(
+                    "Completion provider {name} keeps missing its deadline, and is left out \
+                     for {cooldown}s at a time while it does"
+                ),
+            })?;
+        }
+        let only = replacing.is_some();
+        let sources = words;
+        let mut words = sources.keys().cloned().collect_vec();
+        let mut trace = self.config.completion.debug.then(|| {
+            let frequencies = self.index.words_with_prefix(prefix).unwrap_or_default();
+            RankingTrace::collected(&sources, &frequencies.into_iter().collect())
+        });
+        if let Some(ranker) = ranking::ranker(strategy).filter(|_| !only) {
+            let collected = trace.is_some().then(|| words.clone());
+            if let Err(err) = ranker.rank(prefix, &self.index, &mut words) {
+                log::error!("failed to rank by {}: {err}", ranker.name());
+            }
+            if let (Some(trace), Some(collected)) = (&mut trace, collected) {
+                trace.reordered(&collected, &words, ranker.name());
+            }
+        }
+        if !only {
+            // Stable, so the order within either group is kept
+            words.sort_by_key(|word| !self.changed_words.contains(word));
+            if let Some(trace) = &mut trace {
+                for word in words
+                    .iter()
+                    .filter(|word| self.changed_words.contains(*word))
+                {
+                    trace.adjusted(word, "changed since the last commit");
+                }
+            }
+        }
+        // Keys and anchors hold punctuation, so what's replaced isn't just the word
+        let replaced = replacing.map(|from| {
+            // Counted back from the cursor, as `before` may be clamped to `column_window`
+            let replaced = [before, prefix].concat()[from..].encode_utf16().count() as u32;
+            let character = position.character - replaced;
+            lsp_types::Range::new(Position::new(position.line, character), word_end)
+        });
+        let ranked = self.plugins.iter().filter(|_| {
+            ranking.plugin_ranking && strategy == RankingStrategy::ModelWeighted && !only
+        });
+        for plugin in ranked {
+            if let Some(token) = &progress {
+                let message = format!("Ranking with {}", plugin.name());
+                self.report_progress(token.clone(), message, 50)?;
+            }
+            let (prefix, candidates) = (prefix.to_string(), words.clone());
+            let asked_at = Instant::now();
+            let ranked = plugin.call(move |plugin| plugin.rank(&prefix, candidates));
+            match plugin::wait(&ranked, Some(deadline), &cancelled) {
+                Ok(Ok(ranked)) => {
+                    let backend = format!("{} ranking", plugin.name());
+                    self.session.record_latency(&backend, asked_at.elapsed());
+                    if let Some(trace) = &mut trace {
+                        trace.reordered(&words, &ranked, plugin.name());
+                    }
+                    words = ranked;
+                }
+                Ok(Err(err)) => log::error!("plugin {} failed to rank: {err}", plugin.name()),
+                Err(Unanswered::Cancelled) => {
+                    if let Some(token) = progress {
+                        self.end_progress(token)?;
+                    }
+                    let message = "canceled".to_string();
+                    return self.respond_err(id, ErrorCode::RequestCanceled, message);
+                }
+                // Ranking late is as good as not ranking
+                Err(_) => incomplete = true,
+            }
+        }
+        // Passed over too often in this context, so last whatever ranked them
+        let context = previous_word(before);
+        words.sort_by_key(|word| self.dismissals.is_demoted(&context, word));
+        if let Some(trace) = &mut trace {
+            for word in words
+                .iter()
+                .filter(|word| self.dismissals.is_demoted(&context, word))
+            {
+                trace.adjusted(word, "dismissed before in this context");
+            }
+        }
+        let max_results = self.config.completion.max_results;
+        incomplete |= self.truncate("completion", max_results, &mut words);
+
+        // Abbreviations were typed on purpose, so they come first
+        let snippets = match only {
+            true => Vec::new(),
+            false => engine::snippets(
+                &self.config.completion.snippets,
+                prefix,
+                self.index.collator(),
+            ),
+        };
+        if let Some(trace) = &mut trace {
+            for (abbreviation, _) in &snippets {
+                trace.adjusted(abbreviation, "snippet abbreviation, listed first");
+            }
+        }
+        // A word that's also an abbreviation is listed once, as the snippet
+        words.retain(|word| {
+            !snippets
+                .iter()
+                .any(|(abbreviation, _)| *abbreviation == word)
+        });
+        let snippets =
+            snippets
+                .into_iter()
+                .map(|(abbreviation, body)| {
+                    let (insert_text, format) = match self.snippet_support {
+                        true => (body.clone(), InsertTextFormat::SNIPPET),
+                        false => (snippet::plain_text(body), InsertTextFormat::PLAIN_TEXT),
+                    };
+                    let mut provenance = vec![Source::Snippet];
+                    provenance.extend(sources.get(abbreviation).into_iter().flatten());
+                    CompletionItem {
+                        label: abbreviation.clone(),
+                        label_details: self.provenance(&provenance),
+                        kind: Some(self.completion_kind(Source::Snippet)),
+                        documentation: Some(self.completion_docs.documentation(
+                            &preview::code_block(&snippet::plain_text(body), "text"),
+                        )),
+                        insert_text: Some(insert_text),
+                        insert_text_format: Some(format),
+                        command: Some(Source::Snippet.accepted(arm)),
+                        ..Default::default()
+                    }
+                })
+                .collect_vec();
+        let words = words.into_iter().map(|v| {
+            // Rankers may come up with words of their own
+            let provenance = sources.get(&v).map_or(&[Source::Plugin][..], Vec::as_slice);
+            let source = provenance[0];
+            let label_details = self.provenance(provenance);
+            if let Some(range) = replaced {
+                return CompletionItem {
+                    label_details,
+                    kind: Some(self.completion_kind(source)),
+                    command: Some(source.accepted(arm)),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, v.clone()))),
+                    label: v,
+                    ..Default::default()
+                };
+            }
+            CompletionItem {
+                label_details,
+                kind: Some(self.completion_kind(source)),
+                command: Some(source.accepted(arm)),
+                documentation: Some(
+                    self.completion_docs
+                        .documentation(&self.completion_summary(&v)),
+                ),
+                label: v,
+                // Where to start looking for the first occurrence on resolve
+                data: Some(serde_json::to_value(&file).unwrap()),
+                ..Default::default()
+            }
+        });
+        let mut items = snippets
+            .into_iter()
+            .chain(words)
+            .enumerate()
+            .map(|(i, item)| CompletionItem {
+                sort_text: Some(format!("{i:05}")),
+                ..item
+            })
+            .collect_vec();
+        if word_end != position {
+            let range = lsp_types::Range::new(word_start, word_end);
+            for item in items.iter_mut().filter(|item| item.text_edit.is_none()) {
+                let new_text = item
+                    .insert_text
+                    .take()
+                    .unwrap_or_else(|| item.label.clone());
+                item.text_edit = Some(CompletionTextEdit::Edit(TextEdit::new(range, new_text)));
+            }
+        }
+        if let Some(trace) = trace {
+            self.rankings = trace.finish(items.iter().map(|item| item.label.as_str()));
+            for item in &mut items {
+                let ranking = &self.rankings[&item.label];
+                item.data =
+                    Some(serde_json::json!({ "uri": item.data.take(), "ranking": ranking }));
+            }
+        }
+
+        // Clients filter by the prefix, and the word being typed isn't a suggestion
+        let top = items
+            .iter()
+            .find(|item| item.label.starts_with(prefix) && item.label != prefix);
+        let shown = top.map(|item| Shown {
+            uri: file.clone(),
+            word_start,
+            context,
+            top: item.label.clone(),
+        });
+        let contents = &self.contents;
+        self.dismissals.served(shown, |uri, word_start| {
+            let text = &contents.get(uri)?.text;
+            word_at(word_start, text).map(|range| text[range].to_string())
+        });
+        if let Some(token) = progress {
+            self.end_progress(token)?;
+        }
+        self.session.completions_served += 1;
+        if let (Some(experiment), Some(arm)) = (&mut self.experiment, arm) {
+            experiment.served(arm);
+        }
+        self.respond(
+            id,
+            Some(CompletionResponse::List(CompletionList {
+                is_incomplete: incomplete,
+                items,
+            })),
+        )
+    }
+
+    /// Answers a request too large to read with an error. A document sent too large is
+    /// left empty, and its changes ignored, until it's opened again.
+    fn message_too_large(&mut self, too_large: TooLarge) -> Result<()> {
+        let TooLarge {
+            size,
+            id,
+            method,
+            uri,
+        } = too_large;
+        let message = format!(
+            "Ignored {} of {} MiB, over the limit of --max-message-size",
+            method.as_deref().unwrap_or("a message"),
+            size >> 20
+        );
+        self.protocol_errors.record(
+            ProtocolErrorKind::TooLarge,
+            method.as_deref(),
+            message.clone(),
+        );
+        if let Some(id) = id {
+            return self.respond_err(id, ErrorCode::RequestFailed, message);
+        }
+        let opens = matches!(
+            method.as_deref(),
+            Some(DidOpenTextDocument::METHOD | DidChangeTextDocument::METHOD)
+        );
+        if let Some(uri) = uri.filter(|_| opens) {
+            let key = uri::normalize(&uri);
+            let document = self.contents.get(&key).cloned();
+            let document = Document {
+                uri,
+                text: String::new(),
+                shape: Shape::of(""),
+                ..document.unwrap_or_else(|| Document {
+                    uri: key.clone(),
+                    text: String::new(),
+                    language_id: "plaintext".to_string(),
+                    version: 0,
+                    language_override: None,
+                    shape: Shape::of(""),
+                })
+            };
+            self.update_document(key.clone(), document)?;
+            self.oversized.insert(key);
+        }
+        self.notify::<ShowMessage>(ShowMessageParams {
+            typ: MessageType::WARNING,
+            message,
+        })
+    }
+
+    /// Keeps a copied string for completion, unless it's too long or spans lines.
+    fn clipboard_hint(&mut self, params: ClipboardHintParams) {
+        let text = params.text.trim();
+        if text.is_empty() || text.len() > CLIPBOARD_MAX_LEN || text.contains('\n') {
+            return;
+        }
+        self.clipboard.retain(|copied| copied != text);
+        self.clipboard.push_front(text.to_string());
+        self.clipboard.truncate(CLIPBOARD_ENTRIES);
+    }
+
+    fn explain_ranking(&mut self, id: RequestId, params: ExplainRankingParams) -> Result<()> {
+        if !self.config.completion.debug {
+            log::info!("set `completion.debug` to explain the ranking of completions");
+        }
+        self.respond(id, self.rankings.get(&params.label).cloned())
+    }
+
+    fn completion_dismissed(&mut self, params: CompletionDismissedParams) -> Result<()> {
+        let uri = uri::normalize(&params.text_document.uri);
+        let Some(document) = self.contents.get(&uri) else {
+            return Ok(());
+        };
+        let (before, _) = split_word_prefix(params.position, &document.text);
+        self.dismissals
+            .dismissed(&previous_word(before), &params.label);
+        Ok(())
+    }
+
+    /// Every source a completion came from, when the client shows them.
+    fn provenance(&self, sources: &[Source]) -> Option<CompletionItemLabelDetails> {
+        self.label_details.then(|| CompletionItemLabelDetails {
+            detail: None,
+            description: Some(sources.iter().map(|source| source.name()).join(", ")),
+        })
+    }
+
+    /// The kind of `source`, or Text when the client can't show it.
+    fn completion_kind(&self, source: Source) -> CompletionItemKind {
+        let kind = source.kind();
+        match &self.completion_kinds {
+            Some(kinds) if !kinds.contains(&kind) => CompletionItemKind::TEXT,
+            // The kinds of every source are in the base set, Text to Reference
+            _ => kind,
+        }
+    }
+
+    fn resolve_completion(&mut self, id: RequestId, mut item: CompletionItem) -> Result<()> {
+        let from: Option<Url> = item.data.take().and_then(|data| {
+            // `completion.debug` puts the uri next to the ranking explanation, kept for
+            // the client to show
+            let Some(uri) = data.get("uri").cloned() else {
+                return serde_json::from_value(data).ok();
+            };
+            item.data = Some(data);
+            serde_json::from_value(uri).ok()
+        });
+        if let Some((uri, text, range, language)) =
+            from.and_then(|from| self.first_occurrence(&from, &item.label))
+        {
+            let name = uri
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .unwrap_or(uri.as_str())
+                .to_string();
+            let line = text[..range.start].matches('\n').count() + 1;
+            item.documentation = Some(self.completion_docs.documentation(&format!(
+                "{}\n\nFirst used in `{name}`, line {line}:\n\n{}",
+                self.completion_summary(&item.label),
+                preview::snippet(&text, range, &language)
+            )));
+        }
+        self.respond(id, item)
+    }
+
+    /// What the glossary says `word` means, or else that it's a suggestion.
+    fn completion_summary(&self, word: &str) -> String {
+        match self.glossary.meaning(word) {
+            Some(meaning) => format!("**{word}**: {meaning}"),
+            None => "An AI suggested completion".to_string(),
+        }
+    }
+
+    /// The first use of `word`, looking in `from`, then the other open documents and
+    /// then the indexed files.
+    fn first_occurrence(
+        &self,
+        from: &Url,
+        word: &str,
+    ) -> Option<(Url, String, std::ops::Range<usize>, String)> {
+        let open = self
+            .contents
+            .get_key_value(from)
+            .into_iter()
+            .chain(self.contents.iter().filter(|(uri, _)| *uri != from));
+        for (uri, document) in open {
+            if let Some(range) = preview::first_occurrence(&document.text, word) {
+                let language = document.language().to_string();
+                return Some((uri.clone(), document.text.clone(), range, language));
+            }
+        }
+        let indexed = self
+            .index
+            .documents_with(word)
+            .inspect_err(|err| log::error!("failed to query the index: {err}"))
+            .ok()?;
+        indexed
+            .into_iter()
+            .filter(|uri| !self.contents.contains_key(uri))
+            .sorted()
+            .find_map(|uri| {
+                let text = workspace::read_text(&uri.to_file_path().ok()?).ok()?;
+                let range = preview::first_occurrence(&text, word)?;
+                let language = language::of_file(&uri, &text).to_string();
+                Some((uri, text, range, language))
+            })
+    }
+
+    fn hover(&mut self, id: RequestId, params: HoverParams) -> Result<()> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position_params;
+        let Some(document) = self.contents.get(&uri::normalize(&text_document.uri)) else {
+            return self.not_open(id, HoverRequest::METHOD, &text_document.uri);
+        };
+        let text = &document.text;
+        let citation = Some(document)
+            .filter(|document| bibtex::CITING_LANGUAGES.contains(&document.language()))
+            .and_then(|_| LineIndex::new(text).offset(position))
+            .and_then(|offset| bibtex::citation_at(text, offset))
+            .and_then(|key| self.bibliography.get(key))
+            .map(bibtex::Entry::describe);
+        let column = self.table(document).and_then(|(table, _)| {
+            let offset = LineIndex::new(text).offset(position)?;
+            let (_, column) = table.cell_at(offset)?;
+            Some(column_name(text, &table, column))
+        });
+        let key_path = key_path::parse(document.language(), text).and_then(|keys| {
+            let offset = LineIndex::new(text).offset(position)?;
+            let path = key_path::path_at(&keys, offset);
+            (!path.is_empty()).then(|| format!("`{}`", key_path::format(&path)))
+        });
+        let definition = word_at(position, text).and_then(|range| {
+            let term = &text[range];
+            Some(format!("**{term}**: {}", self.glossary.meaning(term)?))
+        });
+        let mut hover = column.or(key_path).or(citation).or(definition);
+        let cancellations = self.cancellations.clone();
+        let cancelled = || cancellations.lock().unwrap().contains(&id);
+        // Waited on like completions, the message loop being held up meanwhile
+        let deadline = Instant::now() + Duration::from_millis(self.config.completion.deadline);
+        for plugin in self.plugins.iter() {
+            if hover.is_some() {
+                break;
+            }
+            let text = text.clone();
+            let hovered = plugin.call(move |plugin| plugin.hover(&text, position));
+            let source = match plugin::wait(&hovered, Some(deadline), &cancelled) {
+                Ok(Ok(value)) => {
+                    hover = value;
+                    continue;
+                }
+                Ok(Err(source)) => source,
+                Err(Unanswered::Cancelled) => {
+                    let message = "canceled".to_string();
+                    return self.respond_err(id, ErrorCode::RequestCanceled, message);
+                }
+                Err(Unanswered::Timeout) => {
+                    log::warn!("{} took too long to hover", plugin.name());
+                    continue;
+                }
+                Err(Unanswered::Disconnected) => format!("plugin {} stopped", plugin.name()).into(),
+            };
+            let err = ServerError::Plugin {
+                name: plugin.name().to_string(),
+                source,
+            };
+            log::error!("failed to hover: {err}");
+        }
+        self.respond(
+            id,
+            hover.map(|value| Hover {
+                contents: HoverContents::Markup(self.hover_docs.render(&value)),
+                range: None,
+            }),
+        )
+    }
+
+    /// The heading of the `](#anchor)` or the definition of the `][label]` under the cursor,
+    /// in markdown documents.
+    fn definition(&mut self, id: RequestId, params: GotoDefinitionParams) -> Result<()> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position_params;
+        let file = uri::normalize(&text_document.uri);
+        let Some(document) = self.contents.get(&file) else {
+            return self.not_open(id, GotoDefinition::METHOD, &text_document.uri);
+        };
+        let text = &document.text;
+        let line_index = LineIndex::new(text);
+        let target = Some(document)
+            .filter(|document| document.language() == "markdown")
+            .and_then(|_| line_index.offset(position))
+            .and_then(|offset| markdown::link_at(text, offset));
+        let range = target.and_then(|(target, name)| {
+            let markdown = Markdown::parse(text);
+            match target {
+                LinkTarget::Anchor => markdown.heading(name).map(|heading| heading.range.clone()),
+                LinkTarget::Reference => markdown
+                    .link_definition(name)
+                    .map(|definition| definition.range.clone()),
+            }
+        });
+        self.respond(
+            id,
+            range.map(|range| {
+                GotoDefinitionResponse::Scalar(Location::new(
+                    text_document.uri,
+                    line_index.range(range),
+                ))
+            }),
+        )
+    }
+
+    /// The grade of the prose paragraphs in view, with `diagnostics.reading_level`.
+    fn inlay_hints(&mut self, id: RequestId, params: InlayHintParams) -> Result<()> {
+        let Some(document) = self
+            .contents
+            .get(&uri::normalize(&params.text_document.uri))
+        else {
+            return self.not_open(id, InlayHintRequest::METHOD, &params.text_document.uri);
+        };
+        let config = &self.config.diagnostics;
+        let prose = config
+            .prose_languages
+            .iter()
+            .any(|prose| prose == document.language());
+        if config.reading_level.is_none() || !prose {
+            return self.respond(id, Vec::<InlayHint>::new());
+        }
+        let text = &document.text;
+        let line_index = LineIndex::new(text);
+        let hints = readability::paragraphs(text, &Markdown::parse(text))
+            .into_iter()
+            .map(|(paragraph, grade)| (line_index.range(paragraph), grade))
+            .filter(|(range, _)| range.end >= params.range.start && range.start <= params.range.end)
+            .map(|(range, grade)| InlayHint {
+                position: range.end,
+                label: InlayHintLabel::String(format!("grade {grade:.1}")),
+                kind: None,
+                text_edits: None,
+                tooltip: None,
+                padding_left: Some(true),
+                padding_right: None,
+                data: None,
+            })
+            .collect_vec();
+        self.respond(id, hints)
+    }
+
+    /// The sections of prose, the columns of tables, named by their header, and the keys
+    /// of JSON and YAML.
+    fn document_symbols(&mut self, id: RequestId, params: DocumentSymbolParams) -> Result<()> {
+        let Some(document) = self
+            .contents
+            .get(&uri::normalize(&params.text_document.uri))
+        else {
+            return self.not_open(id, DocumentSymbolRequest::METHOD, &params.text_document.uri);
+        };
+        let text = &document.text;
+        let line_index = LineIndex::new(text);
+        if let Some(keys) = key_path::parse(document.language(), text) {
+            let symbols = key_symbols(&keys, &line_index);
+            return self.respond(id, DocumentSymbolResponse::Nested(symbols));
+        }
+        let sections = outline::sections(document.language(), text);
+        if !sections.is_empty() {
+            let symbols = section_symbols(&sections, text, &line_index);
+            return self.respond(id, DocumentSymbolResponse::Nested(symbols));
+        }
+        let symbols = self.table(document).map_or_else(Vec::new, |(table, _)| {
+            table
+                .header()
+                .iter()
+                .enumerate()
+                .map(|(column, cell)| {
+                    let range = line_index.range(cell.clone());
+                    #[allow(deprecated)]
+                    DocumentSymbol {
+                        name: column_name(text, &table, column),
+                        detail: Some(format!("column {}", column + 1)),
+                        kind: SymbolKind::FIELD,
+                        tags: None,
+                        deprecated: None,
+                        range,
+                        selection_range: range,
+                        children: None,
+                    }
+                })
+                .collect()
+        });
+        self.respond(id, DocumentSymbolResponse::Nested(symbols))
+    }
+
+    /// The table `document` holds, unless it's too large to be worth it.
+    fn table(&self, document: &Document) -> Option<(Table, char)> {
+        let delimiter = table::delimiter(document.language())?;
+        if document.text.len() > self.config.tables.max_size {
+            return None;
+        }
+        Some((Table::parse(&document.text, delimiter), delimiter))
+    }
+
+    /// Timestamps, levels and addresses, in logs.
+    fn semantic_tokens(&mut self, id: RequestId, params: SemanticTokensParams) -> Result<()> {
+        let Some(document) = self
+            .contents
+            .get(&uri::normalize(&params.text_document.uri))
+        else {
+            return self.not_open(
+                id,
+                SemanticTokensFullRequest::METHOD,
+                &params.text_document.uri,
+            );
+        };
+        let data = match document.language() {
+            log_file::LANGUAGE => log_file::semantic_tokens(&document.text),
+            _ => Vec::new(),
+        };
+        self.respond(
+            id,
+            SemanticTokensResult::Tokens(SemanticTokens {
+                result_id: None,
+                data,
+            }),
+        )
+    }
+
+    /// The sections of prose and the bursts of lines between time gaps, in logs.
+    fn folding_ranges(&mut self, id: RequestId, params: FoldingRangeParams) -> Result<()> {
+        let Some(document) = self
+            .contents
+            .get(&uri::normalize(&params.text_document.uri))
+        else {
+            return self.not_open(id, FoldingRangeRequest::METHOD, &params.text_document.uri);
+        };
+        let ranges = match document.language() {
+            log_file::LANGUAGE => log_file::folding_ranges(&document.text),
+            language => {
+                let sections = outline::sections(language, &document.text);
+                outline::folding_ranges(&sections, &document.text)
+            }
+        };
+        self.respond(id, ranges)
+    }
+
+    /// Every use of the word under the cursor, in the open documents and the indexed
+    /// files. The declaration is its first use, as in the completion preview.
+    fn references(&mut self, id: RequestId, params: ReferenceParams) -> Result<()> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position;
+        let file = uri::normalize(&text_document.uri);
+        let Some(Document { text, .. }) = self.contents.get(&file) else {
+            return self.not_open(id, References::METHOD, &text_document.uri);
+        };
+        let Some(word) = word_at(position, text).map(|range| text[range].to_string()) else {
+            return self.respond(id, Vec::<Location>::new());
+        };
+        let declaration = match params.context.include_declaration {
+            true => None,
+            false => self
+                .first_occurrence(&file, &word)
+                .map(|(uri, _, range, _)| (uri, range)),
+        };
+        let token = params.work_done_progress_params.work_done_token;
+        let progress = self.begin_request_progress(token, "Finding references")?;
+        let partial = params.partial_result_params.partial_result_token;
+        let max_results = self.config.references.max_results;
+        let mut locations = Vec::new();
+        let mut sent = 0;
+        self.index_pending(|_| true)?;
+        let documents = self.documents_using(&word);
+        let mut reported = 0;
+        for (i, using) in documents.iter().enumerate() {
+            let percentage = (i * 100 / documents.len()) as u32;
+            if let Some(token) = progress.as_ref().filter(|_| percentage > reported) {
+                let message = format!("{i} of {} files", documents.len());
+                self.report_progress(token.clone(), message, percentage)?;
+                reported = percentage;
+            }
+            let line_index = LineIndex::new(&using.text);
+            let config = &self.config.references;
+            for range in references::occurrences(&using.text, &word, &using.language, config) {
+                if declaration == Some((using.key.clone(), range.clone())) {
+                    continue;
+                }
+                locations.push(Location::new(using.uri.clone(), line_index.range(range)));
+            }
+            if let Some(token) = &partial {
+                let batch = &locations[sent..locations.len().min(max_results)];
+                if batch.len() >= REFERENCES_BATCH {
+                    self.send_partial_result(token.clone(), batch)?;
+                    sent += batch.len();
+                }
+            }
+        }
+        if let Some(token) = progress {
+            self.end_progress(token)?;
+        }
+        if partial.is_none() {
+            locations.sort_by(|a, b| (&a.uri, a.range.start).cmp(&(&b.uri, b.range.start)));
+        }
+        let total = locations.len();
+        if self.truncate("references", max_results, &mut locations) {
+            self.notify::<LogMessage>(LogMessageParams {
+                typ: MessageType::INFO,
+                message: format!("showing {max_results} of {total} references to `{word}`"),
+            })?;
+        }
+        if let Some(token) = partial {
+            // The streamed batches are the result
+            if sent < locations.len() {
+                self.send_partial_result(token, &locations[sent..])?;
+            }
+            return self.respond(id, Vec::<Location>::new());
+        }
+        self.respond(id, locations)
+    }
+
+    fn prepare_rename(&mut self, id: RequestId, params: TextDocumentPositionParams) -> Result<()> {
+        let Some(Document { text, .. }) = self
+            .contents
+            .get(&uri::normalize(&params.text_document.uri))
+        else {
+            return self.not_open(id, PrepareRenameRequest::METHOD, &params.text_document.uri);
+        };
+        let range = word_at(params.position, text)
+            .map(|range| PrepareRenameResponse::Range(LineIndex::new(text).range(range)));
+        self.respond(id, range)
+    }
+
+    /// Renames every use of the word under the cursor, in the indexed files too, as
+    /// edits the client can preview.
+    fn rename(&mut self, id: RequestId, params: RenameParams) -> Result<()> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position;
+        let new_name = params.new_name;
+        let single_word = Token::lexer(&new_name).collect_vec() == [Ok(Token::Word(&new_name))];
+        if !single_word {
+            return self.respond_err(
+                id,
+                ErrorCode::InvalidParams,
+                format!("`{new_name}` isn't a single word"),
+            );
+        }
+        let Some(Document { text, .. }) = self.contents.get(&uri::normalize(&text_document.uri))
+        else {
+            return self.not_open(id, Rename::METHOD, &text_document.uri);
+        };
+        let Some(word) = word_at(position, text).map(|range| text[range].to_string()) else {
+            return self.respond_err(
+                id,
+                ErrorCode::InvalidParams,
+                "there's no word to rename here".to_string(),
+            );
+        };
+        let mut files = Vec::new();
+        self.index_pending(|_| true)?;
+        for using in self.documents_using(&word) {
+            let line_index = LineIndex::new(&using.text);
+            // Unlike references, renaming also reaches into strings and comments
+            let edits = references::occurrences(
+                &using.text,
+                &word,
+                &using.language,
+                &ReferencesConfig::default(),
+            )
+            .into_iter()
+            .map(|range| TextEdit::new(line_index.range(range), new_name.clone()))
+            .collect_vec();
+            if !edits.is_empty() {
+                files.push((using.uri, using.version, edits));
+            }
+        }
+        files.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+
+        if !self.document_changes {
+            let changes = files
+                .into_iter()
+                .map(|(uri, _, edits)| (uri, edits))
+                .collect();
+            return self.respond(id, WorkspaceEdit::new(changes));
+        }
+        // Files the user can't see being edited need a look first
+        const OPEN: &str = "rename";
+        const UNOPENED: &str = "rename-unopened";
+        let annotate = self.change_annotations;
+        let mut annotations = HashMap::new();
+        let (open, unopened): (Vec<_>, Vec<_>) =
+            files.iter().partition(|(_, version, _)| version.is_some());
+        for (annotation, files, needs_confirmation, label) in [
+            (
+                OPEN,
+                open,
+                false,
+                format!("Rename `{word}` to `{new_name}`"),
+            ),
+            (
+                UNOPENED,
+                unopened,
+                true,
+                format!("Rename `{word}` to `{new_name}` in files that aren't open"),
+            ),
+        ] {
+            if !annotate || files.is_empty() {
+                continue;
+            }
+            let edits: usize = files.iter().map(|(_, _, edits)| edits.len()).sum();
+            let change = ChangeAnnotation {
+                label,
+                needs_confirmation: Some(needs_confirmation),
+                description: Some(format!(
+                    "{edits} {} in {} {}",
+                    if edits == 1 {
+                        "occurrence"
+                    } else {
+                        "occurrences"
+                    },
+                    files.len(),
+                    if files.len() == 1 { "file" } else { "files" },
+                )),
+            };
+            annotations.insert(annotation.to_string(), change);
+        }
+        let document_changes = files
+            .into_iter()
+            .map(|(uri, version, edits)| {
+                let annotation = if version.is_some() { OPEN } else { UNOPENED };
+                let edits = edits
+                    .into_iter()
+                    .map(|text_edit| match annotate {
+                        true => OneOf::Right(AnnotatedTextEdit {
+                            text_edit,
+                            annotation_id: annotation.to_string(),
+                        }),
+                        false => OneOf::Left(text_edit),
+                    })
+                    .collect();
+                TextDocumentEdit {
+                    text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                    edits,
+                }
+            })
+            .collect();
+        self.respond(
+            id,
+            WorkspaceEdit {
+                changes: None,
+                document_changes: Some(DocumentChanges::Edits(document_changes)),
+                change_annotations: annotate.then_some(annotations),
+            },
+        )
+    }
+
+    /// The open documents and the indexed files that may use `word`, but for the ones
+    /// references exclude.
+    fn documents_using(&self, word: &str) -> Vec<Using> {
+        let indexed = self
+            .index
+            .documents_with(word)
+            .inspect_err(|err| log::error!("failed to query the index: {err}"))
+            .unwrap_or_default();
+        let open = self.contents.iter().map(|(key, document)| Using {
+            key: key.clone(),
+            uri: document.uri.clone(),
+            text: document.text.clone(),
+            language: document.language().to_string(),
+            version: Some(document.version),
+        });
+        let on_disk = indexed
+            .into_iter()
+            .filter(|uri| !self.contents.contains_key(uri))
+            .filter_map(|uri| {
+                let text = workspace::read_text(&uri.to_file_path().ok()?).ok()?;
+                let language = language::of_file(&uri, &text).to_string();
+                Some(Using {
+                    key: uri.clone(),
+                    uri,
+                    text,
+                    language,
+                    version: None,
+                })
+            });
+        open.chain(on_disk)
+            .filter(|using| !self.reference_exclusions.contains(&using.key))
+            .collect()
+    }
+
+    /// Hard wraps the prose paragraph of a line that's too long.
+    #[cfg(feature = "diagnostics")]
+    fn wrap_action(&self, uri: &Url, diagnostic: Diagnostic) -> Option<CodeActionOrCommand> {
+        let document = self.contents.get(&uri::normalize(uri))?;
+        let config = &self.config.diagnostics;
+        let language = document.language();
+        if !config.prose_languages.iter().any(|prose| prose == language) {
+            return None;
+        }
+        let max = *config.line_length.get(language)?;
+        let text = &document.text;
+        let line_index = LineIndex::new(text);
+        let offset = line_index.offset(diagnostic.range.start)?;
+        if Markdown::parse(text).in_code(offset) {
+            return None;
+        }
+        let paragraph = wrap::paragraph(text, offset)?;
+        let wrapped = wrap::wrap(&text[paragraph.clone()], max);
+        if wrapped == text[paragraph.clone()] {
+            return None;
+        }
+        let edit = TextEdit::new(line_index.range(paragraph), wrapped);
+        Some(CodeActionOrCommand::CodeAction(CodeAction {
+            title: format!("Hard wrap paragraph at {max} columns"),
+            kind: Some(CodeActionKind::QUICKFIX),
+            edit: Some(WorkspaceEdit::new(HashMap::from([(
+                uri.clone(),
+                vec![edit],
+            )]))),
+            diagnostics: Some(vec![diagnostic]),
+            ..Default::default()
+        }))
+    }
+
+    fn code_action(&mut self, id: RequestId, params: CodeActionParams) -> Result<()> {
+        let uri = params.text_document.uri;
+        let only = params.context.only.as_deref();
+        let published = self
+            .published
+            .get(&uri::normalize(&uri))
+            .map_or(&[][..], Vec::as_slice);
+        let mut actions = Vec::new();
+        if wants(only, &CodeActionKind::QUICKFIX) {
+            let mut rules = IndexSet::new();
+            for diagnostic in params.context.diagnostics {
+                #[cfg(feature = "diagnostics")]
+                if matches!(&diagnostic.code, Some(NumberOrString::String(code)) if code == diagnostics::READING_LEVEL)
+                {
+                    // Only language models rewrite, which plugins may be backed by
+                    if !self.plugins.is_empty() {
+                        actions.push(rewrite_action(&uri, diagnostic));
+                    }
+                    continue;
+                }
+                #[cfg(feature = "diagnostics")]
+                if matches!(&diagnostic.code, Some(NumberOrString::String(code)) if code == diagnostics::LINE_TOO_LONG)
+                {
+                    actions.extend(self.wrap_action(&uri, diagnostic));
+                    continue;
+                }
+                let Some(fix) = Fix::of(&diagnostic) else {
+                    continue;
+                };
+                if let Some(NumberOrString::String(rule)) = &diagnostic.code {
+                    rules.insert(rule.clone());
+                }
+                let edit = TextEdit::new(fix.range.unwrap_or(diagnostic.range), fix.replacement);
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: fix.title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit::new(HashMap::from([(
+                        uri.clone(),
+                        vec![edit],
+                    )]))),
+                    diagnostics: Some(vec![diagnostic]),
+                    is_preferred: Some(true),
+                    ..Default::default()
+                }));
+            }
+            for rule in rules {
+                let of_rule = published
+                    .iter()
+                    .filter(
+                        |d| matches!(&d.code, Some(NumberOrString::String(code)) if *code == rule),
+                    )
+                    .collect_vec();
+                if of_rule.len() < 2 {
+                    continue;
+                }
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Fix all occurrences of {rule}"),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit::new(HashMap::from([(
+                        uri.clone(),
+                        fix_edits(of_rule.iter().copied()),
+                    )]))),
+                    diagnostics: Some(of_rule.into_iter().cloned().collect()),
+                    ..Default::default()
+                }));
+            }
+        }
+        let document = self.contents.get(&uri::normalize(&uri));
+        if let Some((table, _)) = document
+            .filter(|_| wants(only, &CodeActionKind::REFACTOR_REWRITE))
+            .and_then(|document| self.table(document))
+        {
+            let text = &document.expect("a table is of a document").text;
+            let line_index = LineIndex::new(text);
+            let edits = table
+                .align(text)
+                .into_iter()
+                .map(|(offset, padding)| TextEdit::new(line_index.range(offset..offset), padding))
+                .collect_vec();
+            if !edits.is_empty() {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: "Align columns".to_string(),
+                    kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                    edit: Some(WorkspaceEdit::new(HashMap::from([(uri.clone(), edits)]))),
+                    ..Default::default()
+                }));
+            }
+        }
+        if let Some(document) = document.filter(|_| wants(only, &CodeActionKind::REFACTOR_INLINE)) {
+            let text = &document.text;
+            let expanded = word_at(params.range.start, text).and_then(|range| {
+                let acronym = &text[range.clone()];
+                let expansion = self.glossary.meaning(acronym)?;
+                // Already introduced here
+                let introduced = text[..range.start].trim_end().ends_with('(')
+                    && text[range.end..].starts_with(')');
+                (glossary::is_acronym(acronym) && !introduced)
+                    .then_some((range, acronym, expansion))
+            });
+            if let Some((range, acronym, expansion)) = expanded {
+                let edit = TextEdit::new(
+                    LineIndex::new(text).range(range),
+                    format!("{expansion} ({acronym})"),
+                );
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Expand {acronym} to {expansion}"),
+                    kind: Some(CodeActionKind::REFACTOR_INLINE),
+                    edit: Some(WorkspaceEdit::new(HashMap::from([(
+                        uri.clone(),
+                        vec![edit],
+                    )]))),
+                    ..Default::default()
+                }));
+            }
+        }
+        let fix_all = CodeActionKind::from(diagnostics::FIX_ALL);
+        let edits = fix_edits(published.iter());
+        if wants(only, &fix_all) && !edits.is_empty() {
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: "Fix all auto-fixable problems".to_string(),
+                kind: Some(fix_all),
+                edit: Some(WorkspaceEdit::new(HashMap::from([(uri, edits)]))),
+                ..Default::default()
+            }));
+        }
+        self.respond(id, actions)
+    }
+
+    fn formatting(&mut self, id: RequestId, params: DocumentFormattingParams) -> Result<()> {
+        let Some(document) = self
+            .contents
+            .get(&uri::normalize(&params.text_document.uri))
+        else {
+            return self.not_open(id, Formatting::METHOD, &params.text_document.uri);
+        };
+        let config = self.config.format.with_options(&params.options);
+        let edits = format::format(&document.text, document.language(), &config);
+        self.respond(id, edits)
+    }
+
+    /// Saving makes documents typographic, but leaves the rest of formatting to the user.
+    fn will_save(&mut self, id: RequestId, params: WillSaveTextDocumentParams) -> Result<()> {
+        let Some(document) = self
+            .contents
+            .get(&uri::normalize(&params.text_document.uri))
+        else {
+            return self.not_open(id, WillSaveWaitUntil::METHOD, &params.text_document.uri);
+        };
+        let typographic = self
+            .config
+            .format
+            .typographic
+            .iter()
+            .any(|language| language == document.language());
+        let edits = match typographic {
+            true => format::typographic(&document.text),
+            false => Vec::new(),
+        };
+        self.respond(id, edits)
+    }
+
+    fn on_type_formatting(
+        &mut self,
+        id: RequestId,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<()> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position;
+        let Some(document) = self.contents.get(&uri::normalize(&text_document.uri)) else {
+            return self.not_open(id, OnTypeFormatting::METHOD, &text_document.uri);
+        };
+        let text = &document.text;
+        let prose = self
+            .config
+            .diagnostics
+            .prose_languages
+            .iter()
+            .any(|language| language == document.language());
+        let typed = params.ch.chars().next();
+        let edits = match (self.config.format.auto_pair && prose, typed) {
+            (true, Some(typed)) => LineIndex::new(text)
+                .offset(position)
+                .filter(|&offset| !Markdown::parse(text).in_code(offset.saturating_sub(1)))
+                .map(|offset| format::on_type(text, offset, typed))
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        self.respond(id, edits)
+    }
+
+    fn execute_command(&mut self, id: RequestId, params: ExecuteCommandParams) -> Result<()> {
+        match params.command.as_str() {
+            FORMAT_WORKSPACE => {
+                let args: Option<FormatWorkspaceArgs> = match argument(params.arguments) {
+                    Ok(args) => args,
+                    Err(err) => return self.respond_error(id, err),
+                };
+                let token = params.work_done_progress_params.work_done_token;
+                self.format_workspace(id, args.unwrap_or_default(), token)
+            }
+            REPLACE_IN_WORKSPACE => match argument(params.arguments) {
+                Ok(Some(args)) => {
+                    let token = params.work_done_progress_params.work_done_token;
+                    self.replace_in_workspace(id, args, token)
+                }
+                Ok(None) => {
+                    let err = ServerError::Protocol(format!(
+                        "{REPLACE_IN_WORKSPACE} takes the pattern and replacement"
+                    ));
+                    self.respond_error(id, err)
+                }
+                Err(err) => self.respond_error(id, err),
+            },
+            RESET_STATISTICS => self.reset_statistics(id),
+            CLEAR_WORKSPACE_DATA => self.clear_workspace_data(id),
+            COMPLETION_ACCEPTED => {
+                let mut arguments = params.arguments.iter().map(|argument| argument.as_str());
+                let source = arguments.next().flatten();
+                *self
+                    .session
+                    .completions_accepted
+                    .entry(source.unwrap_or("unknown").to_string())
+                    .or_default() += 1;
+                let arm = arguments.next().flatten().and_then(Arm::from_name);
+                if let (Some(experiment), Some(arm)) = (&mut self.experiment, arm) {
+                    experiment.accepted(arm);
+                }
+                self.respond(id, ())
+            }
+            SET_DOCUMENT_LANGUAGE => match argument(params.arguments) {
+                Ok(Some(params)) => self.set_document_language(id, params),
+                Ok(None) => {
+                    let err = ServerError::Protocol(format!(
+                        "{SET_DOCUMENT_LANGUAGE} takes the document and language"
+                    ));
+                    self.respond_error(id, err)
+                }
+                Err(err) => self.respond_error(id, err),
+            },
+            EXPLAIN => match argument(params.arguments) {
+                Ok(Some(args)) => self.explain(id, args),
+                Ok(None) => {
+                    let err =
+                        ServerError::Protocol(format!("{EXPLAIN} takes the document and range"));
+                    self.respond_error(id, err)
+                }
+                Err(err) => self.respond_error(id, err),
+            },
+            REWRITE_PARAGRAPH => match argument(params.arguments) {
+                Ok(Some(args)) => self.rewrite_paragraph(id, args),
+                Ok(None) => {
+                    let err = ServerError::Protocol(format!(
+                        "{REWRITE_PARAGRAPH} takes the document and range"
+                    ));
+                    self.respond_error(id, err)
+                }
+                Err(err) => self.respond_error(id, err),
+            },
+            command @ (REFLOW_PARAGRAPH | JOIN_LINES) => match argument(params.arguments) {
+                Ok(Some(args)) => self.edit_paragraphs(id, args, command == JOIN_LINES),
+                Ok(None) => {
+                    let err =
+                        ServerError::Protocol(format!("{command} takes the document and range"));
+                    self.respond_error(id, err)
+                }
+                Err(err) => self.respond_error(id, err),
+            },
+            command => self.respond_err(
+                id,
+                ErrorCode::InvalidParams,
+                format!("unknown command {command}"),
+            ),
+        }
+    }
+
+    /// Reflows, or joins the lines of, the prose paragraphs at the cursor or in the
+    /// selection, outside of code.
+    fn edit_paragraphs(&mut self, id: RequestId, args: ParagraphArgs, join: bool) -> Result<()> {
+        let Some(document) = self.contents.get(&uri::normalize(&args.text_document.uri)) else {
+            let err = ServerError::Protocol(format!("{} isn't open", args.text_document.uri));
+            return self.respond_error(id, err);
+        };
+        let text = &document.text;
+        let line_index = LineIndex::new(text);
+        let (Some(start), Some(end)) = (
+            line_index.offset(args.range.start),
+            line_index.offset(args.range.end),
+        ) else {
+            let err = ServerError::Protocol(format!("{:?} is out of the document", args.range));
+            return self.respond_error(id, err);
+        };
+        let width = args
+            .width
+            .or_else(|| {
+                let language = document.language();
+                self.config.diagnostics.line_length.get(language).copied()
+            })
+            .unwrap_or(wrap::DEFAULT_WIDTH);
+        let markdown = Markdown::parse(text);
+        let edits = wrap::paragraphs(text, start..end)
+            .into_iter()
+            .filter(|paragraph| !markdown.in_code(paragraph.start))
+            .filter_map(|paragraph| {
+                let edited = match join {
+                    true => wrap::join(&text[paragraph.clone()]),
+                    false => wrap::wrap(&text[paragraph.clone()], width),
+                };
+                (edited != text[paragraph.clone()])
+                    .then(|| TextEdit::new(line_index.range(paragraph), edited))
+            })
+            .collect_vec();
+        if !edits.is_empty() {
+            let files = vec![FileEdits {
+                uri: document.uri.clone(),
+                version: Some(document.version),
+                edits,
+            }];
+            let label = match join {
+                true => "Join lines",
+                false => "Reflow paragraph",
+            };
+            let request = self.request::<ApplyWorkspaceEdit>(ApplyWorkspaceEditParams {
+                label: Some(label.to_string()),
+                edit: self.workspace_edit(&files, None),
+            })?;
+            let applying = Applying {
+                label: label.to_string(),
+                files,
+                command: id.clone(),
+            };
+            self.applying.insert(request, applying);
+        }
+        self.respond(id, ())
+    }
+
+    /// Asks the plugins, off the message loop, to explain the selection or the word at
+    /// the cursor, with the paragraph around it for context.
+    fn explain(&mut self, id: RequestId, args: ExplainArgs) -> Result<()> {
+        let Some(document) = self.contents.get(&uri::normalize(&args.text_document.uri)) else {
+            let err = ServerError::Protocol(format!("{} isn't open", args.text_document.uri));
+            return self.respond_error(id, err);
+        };
+        let text = &document.text;
+        let line_index = LineIndex::new(text);
+        let selection = line_index
+            .offset(args.range.start)
+            .zip(line_index.offset(args.range.end))
+            .map(|(start, end)| start..end)
+            .filter(|selection| !selection.is_empty())
+            .or_else(|| word_at(args.range.start, text));
+        let Some(selection) = selection else {
+            let err = ServerError::Protocol("there's no word to explain".to_string());
+            return self.respond_error(id, err);
+        };
+        let phrase = text[selection.clone()].trim().to_string();
+        let context = wrap::paragraph(text, selection.start).unwrap_or_else(|| {
+            let start = text[..selection.start].rfind('\n').map_or(0, |i| i + 1);
+            let end = text[selection.end..]
+                .find('\n')
+                .map_or(text.len(), |i| selection.end + i);
+            start..end
+        });
+        let context = text[context].to_string();
+        let plugins = self.plugins.clone();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let abort = self.tasks.spawn_blocking({
+            let (id, cancelled) = (id.clone(), cancelled.clone());
+            move || {
+                let explanation = plugins
+                    .iter()
+                    .take_while(|_| !cancelled.load(Ordering::Relaxed))
+                    .find_map(|plugin| {
+                        let (phrase, context) = (phrase.clone(), context.clone());
+                        plugin
+                            .run(move |plugin| plugin.explain(&phrase, &context))
+                            .inspect_err(|err| {
+                                log::warn!("plugin {} failed to explain: {err}", plugin.name())
+                            })
+                            .ok()
+                            .flatten()
+                    });
+                Background::Explained {
+                    id,
+                    phrase,
+                    explanation,
+                }
+            }
+        });
+        self.in_flight.insert(
+            id,
+            InFlight {
+                abort,
+                cancelled,
+                progress: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Opens `explanation` as a markdown document when the client shows documents, a
+    /// `testlsp:` one if it reads them, else has the client show it as a message.
+    fn show_explanation(&mut self, phrase: &str, explanation: &str) -> Result<()> {
+        let slug = markdown::slug(phrase);
+        let text = format!("# {phrase}\n\n{explanation}\n");
+        if self.shows_documents && self.virtual_documents {
+            let uri = Url::parse(&format!("{VIRTUAL_SCHEME}:explain/{slug}"))
+                .expect("slugs make valid paths");
+            self.explanations.insert(slug, text);
+            self.request::<ShowDocument>(ShowDocumentParams {
+                uri,
+                external: Some(false),
+                take_focus: Some(true),
+                selection: None,
+            })?;
+            return Ok(());
+        }
+        if self.shows_documents {
+            let path = std::env::temp_dir()
+                .join("test-lsp")
+                .join(format!("explain-{slug}.md"));
+            let written = std::fs::create_dir_all(path.parent().expect("the path has a parent"))
+                .and_then(|()| std::fs::write(&path, text));
+            match (written, uri::from_path(&path)) {
+                (Ok(()), Some(uri)) => {
+                    self.request::<ShowDocument>(ShowDocumentParams {
+                        uri,
+                        external: Some(false),
+                        take_focus: Some(true),
+                        selection: None,
+                    })?;
+                    return Ok(());
+                }
+                (Err(err), _) => log::warn!("failed to write {}: {err}", path.display()),
+                (Ok(()), None) => {}
+            }
+        }
+        self.request::<ShowMessageRequest>(ShowMessageRequestParams {
+            typ: MessageType::INFO,
+            message: format!("{phrase}: {explanation}"),
+            actions: None,
+        })?;
+        Ok(())
+    }
+
+    /// Asks the plugins, off the message loop, for the paragraph at the cursor rewritten
+    /// to read at `diagnostics.reading_level`.
+    fn rewrite_paragraph(&mut self, id: RequestId, args: ParagraphArgs) -> Result<()> {
+        let Some(target) = self.config.diagnostics.reading_level.clone() else {
+            let err = ServerError::Protocol("diagnostics.reading_level isn't set".to_string());
+            return self.respond_error(id, err);
+        };
+        let Some(document) = self.contents.get(&uri::normalize(&args.text_document.uri)) else {
+            let err = ServerError::Protocol(format!("{} isn't open", args.text_document.uri));
+            return self.respond_error(id, err);
+        };
+        let text = &document.text;
+        let line_index = LineIndex::new(text);
+        let Some(paragraph) = line_index
+            .offset(args.range.start)
+            .and_then(|offset| wrap::paragraph(text, offset))
+        else {
+            return self.respond(id, ());
+        };
+        let (uri, version) = (document.uri.clone(), document.version);
+        let range = line_index.range(paragraph.clone());
+        let paragraph = text[paragraph].to_string();
+        let plugins = self.plugins.clone();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let abort = self.tasks.spawn_blocking({
+            let (id, cancelled) = (id.clone(), cancelled.clone());
+            move || {
+                let rewritten = plugins
+                    .iter()
+                    .take_while(|_| !cancelled.load(Ordering::Relaxed))
+                    .find_map(|plugin| {
+                        let paragraph = paragraph.clone();
+                        plugin
+                            .run(move |plugin| plugin.rewrite(&paragraph, target.min, target.max))
+                            .inspect_err(|err| {
+                                log::warn!("plugin {} failed to rewrite: {err}", plugin.name())
+                            })
+                            .ok()
+                            .flatten()
+                    });
+                Background::Rewritten {
+                    id,
+                    edit: rewritten.map(|rewritten| FileEdits {
+                        uri,
+                        version: Some(version),
+                        edits: vec![TextEdit::new(range, rewritten)],
+                    }),
+                }
+            }
+        });
+        self.in_flight.insert(
+            id,
+            InFlight {
+                abort,
+                cancelled,
+                progress: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Handles the document as written in another language from now on, reindexing and
+    /// diagnosing it again.
+    fn set_document_language(
+        &mut self,
+        id: RequestId,
+        params: SetDocumentLanguageParams,
+    ) -> Result<()> {
+        let key = uri::normalize(&params.text_document.uri);
+        let Some(document) = self.contents.get(&key) else {
+            return self.respond_err(
+                id,
+                ErrorCode::InvalidParams,
+                format!("{} isn't open", params.text_document.uri),
+            );
+        };
+        let document = Document {
+            language_override: params.language_id,
+            ..document.clone()
+        };
+        let language = document.language().to_string();
+        log::info!("handling {} as {language}", document.uri);
+        self.update_document(key, document)?;
+        self.respond(id, language)
+    }
+
+    /// Formats every text file under the root, open documents as last synced, off the
+    /// message loop.
+    #[cfg(feature = "workspace")]
+    fn format_workspace(
+        &mut self,
+        id: RequestId,
+        args: FormatWorkspaceArgs,
+        token: Option<ProgressToken>,
+    ) -> Result<()> {
+        let Some(root) = self.root.clone() else {
+            return self.respond(
+                id,
+                FormatWorkspaceResult {
+                    files: Vec::new(),
+                    edits: 0,
+                    failed: Vec::new(),
+                    dry_run: args.dry_run,
+                },
+            );
+        };
+        let progress = self.begin_progress(token, "Formatting workspace")?;
+        let open: HashMap<Url, Document> = self.contents.clone();
+        let config = self.config.index.clone();
+        let format = self.config.format.clone();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let abort = self.tasks.spawn_blocking({
+            let (id, cancelled) = (id.clone(), cancelled.clone());
+            move || {
+                let mut changes = Vec::new();
+                for (uri, text) in workspace::text_files(&root, &config) {
+                    if cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let file = match open.get(&uri) {
+                        // Edit open documents as the client knows them
+                        Some(document) => FileEdits {
+                            uri: document.uri.clone(),
+                            version: Some(document.version),
+                            edits: format::format(&document.text, document.language(), &format),
+                        },
+                        None => {
+                            let language = language::of_file(&uri, &text);
+                            let edits = format::format(&text, language, &format);
+                            FileEdits {
+                                uri,
+                                version: None,
+                                edits,
+                            }
+                        }
+                    };
+                    if !file.edits.is_empty() {
+                        changes.push(file);
+                    }
+                }
+                Background::FormattedWorkspace {
+                    id,
+                    dry_run: args.dry_run,
+                    changes,
+                }
+            }
+        });
+        self.in_flight.insert(
+            id,
+            InFlight {
+                abort,
+                cancelled,
+                progress,
+            },
+        );
+        Ok(())
+    }
+
+    #[cfg(not(feature = "workspace"))]
+    fn format_workspace(
+        &mut self,
+        id: RequestId,
+        _args: FormatWorkspaceArgs,
+        _token: Option<ProgressToken>,
+    ) -> Result<()> {
+        let message = format!("{FORMAT_WORKSPACE} needs the `workspace` feature");
+        self.respond_error(id, ServerError::Protocol(message))
+    }
+
+    /// Replaces the matches of a regex in the text files under the root in scope, open
+    /// documents as last synced, off the message loop.
+    #[cfg(feature = "workspace")]
+    fn replace_in_workspace(
+        &mut self,
+        id: RequestId,
+        args: ReplaceInWorkspaceArgs,
+        token: Option<ProgressToken>,
+    ) -> Result<()> {
+        let regex = match Regex::new(&args.pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                let err = ServerError::Protocol(format!("invalid pattern: {err}"));
+                return self.respond_error(id, err);
+            }
+        };
+        let Some(root) = self.root.clone() else {
+            let err = ServerError::Protocol("there's no workspace to replace in".to_string());
+            return self.respond_error(id, err);
+        };
+        let scope = match replace::Scope::new(&root, &args.include, &args.exclude) {
+            Ok(scope) => scope,
+            Err(err) => {
+                let err = ServerError::Protocol(format!("invalid glob: {err}"));
+                return self.respond_error(id, err);
+            }
+        };
+        let progress = self.begin_progress(token, "Replacing in workspace")?;
+        let open: HashMap<Url, Document> = self.contents.clone();
+        let config = self.config.index.clone();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let abort = self.tasks.spawn_blocking({
+            let (id, cancelled) = (id.clone(), cancelled.clone());
+            move || {
+                let mut changes = Vec::new();
+                for (uri, text) in workspace::text_files(&root, &config) {
+                    if cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if !scope.contains(&uri) {
+                        continue;
+                    }
+                    let file = match open.get(&uri) {
+                        // Edit open documents as the client knows them
+                        Some(document) => FileEdits {
+                            uri: document.uri.clone(),
+                            version: Some(document.version),
+                            edits: replace::edits(&document.text, &regex, &args.replacement),
+                        },
+                        None => FileEdits {
+                            edits: replace::edits(&text, &regex, &args.replacement),
+                            uri,
+                            version: None,
+                        },
+                    };
+                    if !file.edits.is_empty() {
+                        changes.push(file);
+                    }
+                }
+                Background::ReplacedInWorkspace { id, args, changes }
+            }
+        });
+        self.in_flight.insert(
+            id,
+            InFlight {
+                abort,
+                cancelled,
+                progress,
+            },
+        );
+        Ok(())
+    }
+
+    #[cfg(not(feature = "workspace"))]
+    fn replace_in_workspace(
+        &mut self,
+        id: RequestId,
+        _args: ReplaceInWorkspaceArgs,
+        _token: Option<ProgressToken>,
+    ) -> Result<()> {
+        let message = format!("{REPLACE_IN_WORKSPACE} needs the `workspace` feature");
+        self.respond_error(id, ServerError::Protocol(message))
+    }
+
+    /// Applies the edits of a workspace command in batches of [`FORMAT_BATCH`] files,
+    /// answering the command `id` once the client has applied them all. With `confirm`,
+    /// the user confirms the edits first.
+    #[cfg(feature = "workspace")]
+    fn apply_workspace_format(
+        &mut self,
+        id: RequestId,
+        label: &str,
+        confirm: Option<String>,
+        dry_run: bool,
+        changes: Vec<FileEdits>,
+        progress: Option<ProgressToken>,
+    ) -> Result<()> {
+        if dry_run || changes.is_empty() {
+            if let Some(token) = progress {
+                self.end_progress(token)?;
+            }
+            let result = FormatWorkspaceResult {
+                edits: changes.iter().map(|file| file.edits.len()).sum(),
+                files: changes.into_iter().map(|file| file.uri).collect(),
+                failed: Vec::new(),
+                dry_run,
+            };
+            return self.respond(id, result);
+        }
+        let batches = changes.len().div_ceil(FORMAT_BATCH);
+        for batch in &changes.into_iter().chunks(FORMAT_BATCH) {
+            let files = batch.collect_vec();
+            let request = self.request::<ApplyWorkspaceEdit>(ApplyWorkspaceEditParams {
+                label: Some(label.to_string()),
+                edit: self.workspace_edit(&files, confirm.as_deref()),
+            })?;
+            let applying = Applying {
+                label: label.to_string(),
+                files,
+                command: id.clone(),
+            };
+            self.applying.insert(request, applying);
+        }
+        let format = WorkspaceFormat {
+            pending: batches,
+            batches,
+            applied: Vec::new(),
+            failed: Vec::new(),
+            edits: 0,
+            progress,
+        };
+        self.formatting.insert(id, format);
+        Ok(())
+    }
+
+    /// `files` as `documentChanges` when the client takes them, so it can tell which
+    /// were left out and reject edits of a document that changed since. With `confirm`,
+    /// and a client taking change annotations, the edits are annotated with it as needing
+    /// the user's confirmation.
+    fn workspace_edit(&self, files: &[FileEdits], confirm: Option<&str>) -> WorkspaceEdit {
+        if !self.document_changes {
+            let changes = files
+                .iter()
+                .map(|file| (file.uri.clone(), file.edits.clone()))
+                .collect();
+            return WorkspaceEdit::new(changes);
+        }
+        const CONFIRM: &str = "confirm";
+        let confirm = confirm.filter(|_| self.change_annotations);
+        let document_changes = files
+            .iter()
+            .map(|file| TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: file.uri.clone(),
+                    version: file.version,
+                },
+                edits: file
+                    .edits
+                    .iter()
+                    .cloned()
+                    .map(|text_edit| match confirm {
+                        Some(_) => OneOf::Right(AnnotatedTextEdit {
+                            text_edit,
+                            annotation_id: CONFIRM.to_string(),
+                        }),
+                        None => OneOf::Left(text_edit),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let edits: usize = files.iter().map(|file| file.edits.len()).sum();
+        let change_annotations = confirm.map(|label| {
+            let annotation = ChangeAnnotation {
+                label: label.to_string(),
+                needs_confirmation: Some(true),
+                description: Some(format!(
+                    "{edits} {} in {} {}",
+                    if edits == 1 { "edit" } else { "edits" },
+                    files.len(),
+                    if files.len() == 1 { "file" } else { "files" },
+                )),
+            };
+            HashMap::from([(CONFIRM.to_string(), annotation)])
+        });
+        WorkspaceEdit {
+            changes: None,
+            document_changes: Some(DocumentChanges::Edits(document_changes)),
+            change_annotations,
+        }
+    }
+
+    fn on_response(&mut self, resp: Response) -> Result<()> {
+        let Some(applying) = self.applying.remove(&resp.id) else {
+            if let Some(err) = resp.error {
+                log::error!("request {} failed: {}", resp.id, err.message);
+            }
+            return Ok(());
+        };
+        let outcome = match resp.error {
+            Some(err) => Err(err.message),
+            None => serde_json::from_value::<ApplyWorkspaceEditResponse>(
+                resp.result.unwrap_or_default(),
+            )
+            .map_err(|err| format!("invalid response: {err}")),
+        };
+        let (applied, reason) = match outcome {
+            Ok(ApplyWorkspaceEditResponse { applied: true, .. }) => (applying.files.len(), None),
+            Ok(response) => {
+                // Only `documentChanges` have an order for `failedChange` to point into
+                let applied = match self.document_changes {
+                    // Something failed, whatever the index says
+                    true => (response.failed_change.unwrap_or_default() as usize)
+                        .min(applying.files.len().saturating_sub(1)),
+                    false => 0,
+                };
+                let reason = response
+                    .failure_reason
+                    .unwrap_or_else(|| "no reason given".to_string());
+                (applied, Some(reason))
+            }
+            Err(reason) => (0, Some(reason)),
+        };
+        let mut files = applying.files;
+        let failed = files.split_off(applied.min(files.len()));
+        if let Some(reason) = reason {
+            log::warn!(
+                "the client applied {} of {} files of `{}`: {reason}",
+                files.len(),
+                files.len() + failed.len(),
+                applying.label,
+            );
+            self.notify::<ShowMessage>(ShowMessageParams {
+                typ: MessageType::WARNING,
+                message: format!(
+                    "{}: {} files were left as they were, {reason}",
+                    applying.label,
+                    failed.len()
+                ),
+            })?;
+        }
+        self.on_format_batch(applying.command, files, failed)
+    }
+
+    /// Counts a batch of the workspace formatting in, answering the command after the
+    /// last one.
+    fn on_format_batch(
+        &mut self,
+        command: RequestId,
+        applied: Vec<FileEdits>,
+        failed: Vec<FileEdits>,
+    ) -> Result<()> {
+        let Some(format) = self.formatting.get_mut(&command) else {
+            return Ok(());
+        };
+        format.pending -= 1;
+        format.edits += applied.iter().map(|file| file.edits.len()).sum::<usize>();
+        format
+            .applied
+            .extend(applied.into_iter().map(|file| file.uri));
+        format
+            .failed
+            .extend(failed.into_iter().map(|file| file.uri));
+        let (done, batches) = (format.batches - format.pending, format.batches);
+        if let Some(token) = format.progress.clone() {
+            self.report_progress(
+                token,
+                format!("{done}/{batches} batches"),
+                (done * 100 / batches) as u32,
+            )?;
+        }
+        if done < batches {
+            return Ok(());
+        }
+        let format = self.formatting.remove(&command).expect("looked up above");
+        if let Some(token) = format.progress {
+            self.end_progress(token)?;
+        }
+        let result = FormatWorkspaceResult {
+            files: format.applied,
+            edits: format.edits,
+            failed: format.failed,
+            dry_run: false,
+        };
+        self.respond(command, result)
+    }
+
+    fn cancel(&mut self, id: RequestId) -> Result<()> {
+        self.cancellations.lock().unwrap().remove(&id);
+        let Some(in_flight) = self.in_flight.remove(&id) else {
+            return Ok(());
+        };
+        in_flight.cancelled.store(true, Ordering::Relaxed);
+        in_flight.abort.abort();
+        if let Some(token) = in_flight.progress {
+            self.end_progress(token)?;
+        }
+        self.respond_err(id, ErrorCode::RequestCanceled, "canceled".to_string())
+    }
+
+    /// Reads and indexes the files of `params` off the message loop.
+    fn preload_documents(&mut self, id: RequestId, params: PreloadDocumentsParams) -> Result<()> {
+        let (files, mut skipped): (Vec<_>, Vec<_>) =
+            params
+                .uris
+                .into_iter()
+                .partition_map(|uri| match uri::to_path(&uri) {
+                    Some(path) => Either::Left((uri, path)),
+                    None => Either::Right(uri),
+                });
+        let config = self.config.index.clone();
+        let scan = self.scanner();
+        let abort = self.tasks.spawn_blocking({
+            let id = id.clone();
+            move || {
+                let paths = files.iter().map(|(_, path)| path.clone()).collect_vec();
+                let scanned = workspace::scan_files(paths.into_iter(), &config, scan);
+                let read: HashSet<&Url> = scanned.iter().map(|scanned| &scanned.uri).collect();
+                skipped.extend(
+                    files
+                        .into_iter()
+                        .filter(|(_, path)| {
+                            uri::from_path(path).is_none_or(|uri| !read.contains(&uri))
+                        })
+                        .map(|(uri, _)| uri),
+                );
+                Background::Preloaded {
+                    id,
+                    scanned,
+                    skipped,
+                }
+            }
+        });
+        self.in_flight.insert(
+            id,
+            InFlight {
+                abort,
+                cancelled: Arc::new(AtomicBool::new(false)),
+                progress: None,
+            },
+        );
+        Ok(())
+    }
+
+    fn occurrences(&mut self, id: RequestId, params: OccurrencesParams) -> Result<()> {
+        let files: BTreeMap<Url, u32> = match self.index.occurrences(&params.word) {
+            Ok(files) => files
+                .into_iter()
+                .map(|(uri, count)| match self.contents.get(&uri) {
+                    Some(document) => (document.uri.clone(), count),
+                    None => (uri, count),
+                })
+                .collect(),
+            Err(err) => return self.respond_error(id, ServerError::index(err)),
+        };
+        let total = files.values().map(|&count| u64::from(count)).sum();
+        self.respond(id, OccurrencesResult { total, files })
+    }
+
+    fn read_virtual_document(
+        &mut self,
+        id: RequestId,
+        params: ReadVirtualDocumentParams,
+    ) -> Result<()> {
+        let uri = params.uri;
+        let path = uri.path();
+        let document = match path.split_once('/') {
+            _ if uri.scheme() != VIRTUAL_SCHEME => None,
+            Some(("explain", slug)) => {
+                self.explanations
+                    .get(slug)
+                    .map(|explanation| VirtualDocument {
+                        text: explanation.clone(),
+                        language_id: "markdown".to_string(),
+                    })
+            }
+            Some(_) => None,
+            None => match path {
+                "stats" => Some(VirtualDocument {
+                    text: self.stats_report()?,
+                    language_id: "markdown".to_string(),
+                }),
+                "index" => Some(VirtualDocument {
+                    text: self
+                        .index
+                        .snapshot()?
+                        .into_iter()
+                        .map(|(word, count)| format!("{word}\t{count}\n"))
+                        .collect(),
+                    language_id: "tsv".to_string(),
+                }),
+                _ => None,
+            },
+        };
+        match document {
+            Some(document) => self.respond(id, document),
+            None => self.respond_err(
+                id,
+                ErrorCode::InvalidParams,
+                format!("{uri} isn't a document of the server"),
+            ),
+        }
+    }
+
+    /// The words of the index, most frequent first, and what the server has open.
+    fn stats_report(&self) -> Result<String> {
+        let words = self.index.snapshot()?;
+        let total: u64 = words.values().sum();
+        let mut report = format!(
+            "# Word stats\n\n{} open documents, {} distinct words, {total} in all.\n\n\
+             | Word | Count |\n| --- | ---: |\n",
+            self.contents.len(),
+            words.len(),
+        );
+        let frequent = words
+            .into_iter()
+            .sorted_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)))
+            .take(STATS_REPORT_WORDS);
+        for (word, count) in frequent {
+            report.push_str(&format!("| {word} | {count} |\n"));
+        }
+        Ok(report)
+    }
+
+    fn stats(&mut self, id: RequestId) -> Result<()> {
+        let stats = ServerStats {
+            open_documents: self.contents.len(),
+            limits: self.limits.clone(),
+            experiment: self.experiment.as_ref().map(Experiment::stats),
+        };
+        self.respond(id, stats)
+    }
+
+    /// Cuts `results` down to `limit`, keeping count for `testLsp/stats`. Returns whether
+    /// anything was dropped.
+    fn truncate<T>(&mut self, feature: &str, limit: usize, results: &mut Vec<T>) -> bool {
+        let stats = self.limits.entry(feature.to_string()).or_default();
+        let truncated = results.len() > limit;
+        stats.limit = limit;
+        stats.requests += 1;
+        stats.truncated += u64::from(truncated);
+        stats.last_total = results.len();
+        results.truncate(limit);
+        truncated
+    }
+
+    /// The diagnostics of `published` as a SARIF log, with the uris of the workspace
+    /// relative to its root.
+    fn export_diagnostics(&mut self, id: RequestId) -> Result<()> {
+        let files = self
+            .published
+            .iter()
+            .filter(|(_, diagnostics)| !diagnostics.is_empty())
+            .map(|(uri, diagnostics)| (uri.clone(), diagnostics.clone()))
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .collect_vec();
+        let root = self.root.as_deref().and_then(uri::from_path);
+        self.respond(id, sarif::log(&files, root.as_ref()))
+    }
+
+    fn list_rules(&mut self, id: RequestId) -> Result<()> {
+        let rules: Vec<RuleDescription> = diagnostics::RULES
+            .iter()
+            .map(|(id, description)| RuleDescription {
+                id: id.to_string(),
+                description: description.to_string(),
+            })
+            .collect();
+        self.respond(id, rules)
+    }
+
+    fn tokenize(&mut self, id: RequestId, params: TokenizeParams) -> Result<()> {
+        let uri = params.text_document.uri;
+        let Some(Document { text, .. }) = self.contents.get(&uri::normalize(&uri)) else {
+            return self.respond_err(id, ErrorCode::InvalidParams, format!("{uri} is not open"));
+        };
+        let line_index = LineIndex::new(text);
+        let bounds = match params.range {
+            Some(range) => match (line_index.offset(range.start), line_index.offset(range.end)) {
+                (Some(start), Some(end)) => start..end,
+                _ => {
+                    let message = format!("{range:?} is out of {uri}");
+                    return self.respond_err(id, ErrorCode::InvalidParams, message);
+                }
+            },
+            None => 0..text.len(),
+        };
+        let tokens = Token::lexer(text)
+            .spanned()
+            // An empty range still selects the token under it
+            .filter(|(_, span)| {
+                span.end > bounds.start && span.start < bounds.end.max(bounds.start + 1)
+            })
+            .map(|(token, span)| LexedToken {
+                kind: match token {
+                    Ok(Token::Word(_)) => TokenKind::Word,
+                    Ok(Token::Symbol(_)) => TokenKind::Symbol,
+                    Err(()) => TokenKind::Error,
+                },
+                text: text[span.clone()].to_string(),
+                range: line_index.range(span.clone()),
+                byte_range: span,
+            })
+            .collect_vec();
+        self.respond(id, tokens)
+    }
+
+    fn sync_shared_index(&mut self) {
+        let open = self.open_paths();
+        let Some(shared) = &mut self.shared else {
+            return;
+        };
+        match shared.tick(&mut self.index, &open) {
+            Ok(true) => {
+                shared.mark_dirty();
+                // Kept in memory while reading, but the writer's is the one configured
+                match open_storage(&self.config, self.data_dir.as_deref(), true) {
+                    Ok(index) => self.index = index,
+                    Err(err) => log::error!("failed to open the index: {err}"),
+                }
+                self.reindex();
+            }
+            Ok(false) => {}
+            Err(err) => log::error!("failed to sync the shared index: {err}"),
+        }
+    }
+
+    /// The files of the open documents, in order.
+    fn open_paths(&self) -> Vec<PathBuf> {
+        self.contents
+            .keys()
+            .filter_map(uri::to_path)
+            .sorted()
+            .collect()
+    }
+
+    /// Forgets the word frequencies, as stale as they may have gotten, and indexes the
+    /// workspace and open documents anew.
+    fn reset_statistics(&mut self, id: RequestId) -> Result<()> {
+        if let Err(err) = self.index.clear() {
+            return self.respond_error(id, ServerError::index(err));
+        }
+        self.reindex();
+        log::info!("reset the word statistics");
+        self.respond(id, ())
+    }
+
+    /// Removes the workspace data directory, with the on-disk index in it, then
+    /// indexes the workspace and open documents anew.
+    fn clear_workspace_data(&mut self, id: RequestId) -> Result<()> {
+        let Some(dir) = self.data_dir.clone() else {
+            let err = ServerError::Protocol("there's no workspace to clear the data of".into());
+            return self.respond_error(id, err);
+        };
+        // Let go of the lock and the on-disk storage before removing them
+        self.shared = None;
+        self.index = Index::open(
+            &IndexConfig {
+                storage: StorageKind::Memory,
+                ..self.config.index.clone()
+            },
+            None,
+        )?;
+        if let Err(err) = data_dir::clear(&dir) {
+            log::error!("failed to remove {}: {err}", dir.display());
+        }
+        match open_index(&self.config, Some(&dir)) {
+            Ok((index, shared)) => (self.index, self.shared) = (index, shared),
+            Err(err) => return self.respond_error(id, err),
+        }
+        self.reindex();
+        log::info!("cleared the workspace data in {}", dir.display());
+        self.respond(id, ())
+    }
+
+    /// Indexes the open documents, and the workspace or the shared snapshot of it.
+    fn reindex(&mut self) {
+        let open = self.open_paths();
+        for (uri, document) in &self.contents {
+            if let Err(err) = self.index.update(uri, &document.text) {
+                log::error!("failed to index {uri}: {err}");
+            }
+        }
+        match &mut self.shared {
+            Some(shared) if !shared.is_writer() => {
+                if let Err(err) = shared.load(&mut self.index, &open) {
+                    log::error!("failed to load the shared index: {err}");
+                }
+            }
+            _ if self.config.index.indexes_workspace() => self.scan_workspace(),
+            _ => {}
+        }
+    }
+
+    /// Logs the session summary, and writes it to the `--session-report` file.
+    fn report_session(&self) {
+        log::info!("session summary: {}", self.session.summary());
+        if let Some(path) = &self.session_report {
+            if let Err(err) = self.session.write(path) {
+                log::error!(
+                    "failed to write the session report to {}: {err}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    /// Writes the flamegraph to the `--profile` file, if profiling.
+    fn write_profile(&self) {
+        if let Some(profiler) = &self.profiler {
+            match profiler.write() {
+                Ok(path) => log::info!("wrote the profile to {}", path.display()),
+                Err(err) => log::error!("failed to write the profile: {err}"),
+            }
+        }
+    }
+
+    fn dump_profile(&mut self, id: RequestId) -> Result<()> {
+        let Some(profiler) = &self.profiler else {
+            let err = ServerError::Protocol("the server wasn't started with --profile".into());
+            return self.respond_error(id, err);
+        };
+        match profiler.write() {
+            Ok(path) => {
+                let path = path.to_path_buf();
+                self.respond(id, DumpedProfile { path })
+            }
+            Err(err) => self.respond_error(id, err),
+        }
+    }
+
+    fn respond(&self, id: RequestId, result: impl serde::Serialize) -> Result<()> {
+        let resp = Response {
+            id,
+            result: Some(serde_json::to_value(result).unwrap()),
+            error: None,
+        };
+        self.connection
+            .sender
+            .send(Message::Response(resp))
+            .map_err(ServerError::disconnected)?;
+        Ok(())
+    }
+
+    /// Answers a request that failed, logging the details the user isn't shown.
+    fn respond_error(&self, id: RequestId, err: ServerError) -> Result<()> {
+        log::error!("{err}");
+        self.respond_err(id, err.code(), err.message())
+    }
+
+    fn respond_err(&self, id: RequestId, code: ErrorCode, message: String) -> Result<()> {
+        let resp = Response::new_err(id, code as i32, message);
+        self.connection
+            .sender
+            .send(Message::Response(resp))
+            .map_err(ServerError::disconnected)?;
+        Ok(())
+    }
+
+    fn request<R>(&mut self, params: R::Params) -> Result<RequestId>
+    where
+        R: lsp_types::request::Request,
+    {
+        self.next_request_id += 1;
+        let id = RequestId::from(format!("test-lsp/{}", self.next_request_id));
+        let req = Request::new(id.clone(), R::METHOD.to_string(), params);
+        self.connection
+            .sender
+            .send(Message::Request(req))
+            .map_err(ServerError::disconnected)?;
+        Ok(id)
+    }
+
+    /// Starts reporting progress on the client's `token`, or on a new one when the
+    /// client supports it.
+    fn begin_progress(
+        &mut self,
+        token: Option<ProgressToken>,
+        title: &str,
+    ) -> Result<Option<ProgressToken>> {
+        let token = match token {
+            Some(token) => token,
+            None if self.work_done_progress => {
+                self.next_request_id += 1;
+                let token = ProgressToken::String(format!("test-lsp/{}", self.next_request_id));
+                self.request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                    token: token.clone(),
+                })?;
+                token
+            }
+            None => return Ok(None),
+        };
+        self.notify::<Progress>(ProgressParams {
+            token: token.clone(),
+            value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                percentage: Some(0),
+                ..Default::default()
+            })),
+        })?;
+        Ok(Some(token))
+    }
+
+    /// Reports the progress of a request on the token the client sent with it, if any.
+    fn begin_request_progress(
+        &mut self,
+        token: Option<ProgressToken>,
+        title: &str,
+    ) -> Result<Option<ProgressToken>> {
+        match token {
+            Some(token) => self.begin_progress(Some(token), title),
+            None => Ok(None),
+        }
+    }
+
+    fn report_progress(
+        &self,
+        token: ProgressToken,
+        message: String,
+        percentage: u32,
+    ) -> Result<()> {
+        self.notify::<Progress>(ProgressParams {
+            token,
+            value: ProgressParamsValue::WorkDone(WorkDoneProgress::Report(
+                WorkDoneProgressReport {
+                    message: Some(message),
+                    percentage: Some(percentage),
+                    ..Default::default()
+                },
+            )),
+        })
+    }
+
+    fn end_progress(&self, token: ProgressToken) -> Result<()> {
+        self.notify::<Progress>(ProgressParams {
+            token,
+            value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
+                message: None,
+            })),
+        })
+    }
+
+    /// Streams part of a request's result on the client's `partial_result_token`.
+    fn send_partial_result(
+        &self,
+        token: ProgressToken,
+        batch: impl serde::Serialize,
+    ) -> Result<()> {
+        let params = serde_json::json!({ "token": token, "value": batch });
+        let not = Notification::new(Progress::METHOD.to_string(), params);
+        self.connection
+            .sender
+            .send(Message::Notification(not))
+            .map_err(ServerError::disconnected)?;
+        Ok(())
+    }
+
+    /// Publishes `params` with the messages over `responses.diagnostic_message` cut
+    /// short.
+    fn publish(&self, mut params: PublishDiagnosticsParams) -> Result<()> {
+        let max_len = self.config.responses.diagnostic_message;
+        for diagnostic in &mut params.diagnostics {
+            if let Cow::Owned(message) = doc::truncate(&diagnostic.message, max_len) {
+                diagnostic.message = message;
+            }
+        }
+        self.notify::<PublishDiagnostics>(params)
+    }
+
+    fn notify<N>(&self, params: N::Params) -> Result<()>
+    where
+        N: lsp_types::notification::Notification,
+    {
+        let not = Notification::new(N::METHOD.to_string(), params);
+        self.connection
+            .sender
+            .send(Message::Notification(not))
+            .map_err(ServerError::disconnected)?;
+        Ok(())
+    }
+}
+
+/// The last word of `before`, lowercased, as the context dismissals are counted in.
+fn previous_word(before: &str) -> String {
+    Token::lexer(before)
+        .filter_map(|token| match token {
+            Ok(Token::Word(word)) => Some(word.to_lowercase()),
+            _ => None,
+        })
+        .last()
+        .unwrap_or_default()
+}
+
+/// The word under the cursor, or right before it.
+fn word_at(position: Position, text: &str) -> Option<std::ops::Range<usize>> {
+    let offset = LineIndex::new(text).offset(position)?;
+    Token::lexer(text)
+        .spanned()
+        .find_map(|(token, span)| match token {
+            Ok(Token::Word(_)) if span.contains(&offset) || span.end == offset => Some(span),
+            _ => None,
+        })
+}
+
+fn cast_req<R>(req: Request) -> std::result::Result<(RequestId, R::Params), ExtractError<Request>>
+where
+    R: lsp_types::request::Request,
+    R::Params: serde::de::DeserializeOwned,
+{
+    req.extract(R::METHOD)
+}
+
+fn cast_not<N>(not: Notification) -> std::result::Result<N::Params, ExtractError<Notification>>
+where
+    N: lsp_types::notification::Notification,
+    N::Params: serde::de::DeserializeOwned,
+{
+    not.extract(N::METHOD)
+}
+
+fn request_id(id: NumberOrString) -> RequestId {
+    match id {
+        NumberOrString::Number(id) => RequestId::from(id),
+        NumberOrString::String(id) => RequestId::from(id),
+    }
+}
+
+/// The request a `$/cancelRequest` cancels.
+fn cancelled_id(msg: &Message) -> Option<RequestId> {
+    let Message::Notification(not) = msg else {
+        return None;
+    };
+    if not.method != Cancel::METHOD {
+        return None;
+    }
+    let CancelParams { id } = serde_json::from_value(not.params.clone()).ok()?;
+    Some(request_id(id))
+}
+
+fn key_symbols(keys: &[key_path::Key], line_index: &LineIndex) -> Vec<DocumentSymbol> {
+    keys.iter()
+        .map(|key| {
+            let (name, kind) = match &key.segment {
+                key_path::Segment::Key(name) => (name.clone(), SymbolKind::PROPERTY),
+                key_path::Segment::Index(index) => (format!("[{index}]"), SymbolKind::OBJECT),
+            };
+            let kind = match key.children.first().map(|child| &child.segment) {
+                Some(key_path::Segment::Index(_)) => SymbolKind::ARRAY,
+                Some(key_path::Segment::Key(_)) => SymbolKind::OBJECT,
+                None => kind,
+            };
+            #[allow(deprecated)]
+            DocumentSymbol {
+                // Clients refuse empty names
+                name: if name.is_empty() {
+                    "\"\"".to_string()
+                } else {
+                    name
+                },
+                detail: None,
+                kind,
+                tags: None,
+                deprecated: None,
+                range: line_index.range(key.span.clone()),
+                selection_range: line_index.range(key.name.clone()),
+                children: Some(key_symbols(&key.children, line_index)),
+            }
+        })
+        .collect()
+}
+
+/// The sections nested in the one before of a lower level, named by their title.
+fn section_symbols(
+    sections: &[outline::Section],
+    text: &str,
+    line_index: &LineIndex,
+) -> Vec<DocumentSymbol> {
+    let mut symbols = Vec::new();
+    let mut rest = sections;
+    while let Some((section, after)) = rest.split_first() {
+        let nested = after
+            .iter()
+            .take_while(|child| child.level > section.level)
+            .count();
+        let title = text[section.title.clone()].trim();
+        #[allow(deprecated)]
+        symbols.push(DocumentSymbol {
+            // Clients refuse empty names
+            name: if title.is_empty() {
+                "\"\"".to_string()
+            } else {
+                title.to_string()
+            },
+            detail: None,
+            kind: SymbolKind::STRING,
+            tags: None,
+            deprecated: None,
+            range: line_index.range(section.range.start..section.end),
+            selection_range: line_index.range(section.title.clone()),
+            children: Some(section_symbols(&after[..nested], text, line_index)),
+        });
+        rest = &after[nested..];
+    }
+    symbols
+}
+
+/// Asks the plugins for a rewrite of the paragraph of a `reading-level` hint.
+#[cfg(feature = "diagnostics")]
+fn rewrite_action(uri: &Url, diagnostic: Diagnostic) -> CodeActionOrCommand {
+    let args = ParagraphArgs {
+        text_document: TextDocumentIdentifier::new(uri.clone()),
+        range: diagnostic.range,
+        width: None,
+    };
+    let title = "Suggest a rewrite at the target grade".to_string();
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        command: Some(Command::new(
+            title,
+            REWRITE_PARAGRAPH.to_string(),
+            Some(vec![serde_json::to_value(args).unwrap()]),
+        )),
+        diagnostics: Some(vec![diagnostic]),
+        ..Default::default()
+    })
+}
+
+/// What other files need to know of a markdown file.
+struct MarkdownFile {
+    /// The files it links to, as the server keys them, to know which to recheck when
+    /// one is saved.
+    links: Vec<Url>,
+    /// The anchors of its headings.
+    anchors: HashSet<String>,
+}
+
+impl MarkdownFile {
+    fn new(uri: &Url, text: &str) -> Self {
+        let links = diagnostics::links(uri, text)
+            .into_iter()
+            .map(|link| uri::normalize(&link.target))
+            .unique()
+            .collect();
+        let anchors = Markdown::parse(text)
+            .headings
+            .into_iter()
+            .map(|heading| heading.anchor)
+            .collect();
+        Self { links, anchors }
+    }
+}
+
+/// The header of `column`, or its number when the header has no such cell.
+fn column_name(text: &str, table: &Table, column: usize) -> String {
+    match table.header().get(column) {
+        Some(cell) if !text[cell.clone()].trim().is_empty() => table::unquote(&text[cell.clone()]),
+        _ => format!("Column {}", column + 1),
+    }
+}
+
+/// Whether a code action of `kind` was asked for, `only` filtering by kind prefixes.
+fn wants(only: Option<&[CodeActionKind]>, kind: &CodeActionKind) -> bool {
+    only.is_none_or(|only| {
+        only.iter().any(|wanted| {
+            kind.as_str() == wanted.as_str()
+                || kind
+                    .as_str()
+                    .strip_prefix(wanted.as_str())
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    })
+}
+
+/// The edits of the automatic fixes of `diagnostics`, skipping fixes that overlap an
+/// earlier one.
+fn fix_edits<'a>(diagnostics: impl Iterator<Item = &'a Diagnostic>) -> Vec<TextEdit> {
+    let edits = diagnostics
+        .filter_map(|diagnostic| {
+            let fix = Fix::of(diagnostic)?;
+            Some(TextEdit::new(
+                fix.range.unwrap_or(diagnostic.range),
+                fix.replacement,
+            ))
+        })
+        .sorted_by_key(|edit| edit.range.start);
+    let mut kept: Vec<TextEdit> = Vec::new();
+    for edit in edits {
+        if kept
+            .last()
+            .is_none_or(|last| last.range.end <= edit.range.start)
+        {
+            kept.push(edit);
+        }
+    }
+    kept
+}
+
+/// Opens the index, and with `index.shared` the snapshot coordinating it with the other
+/// instances on the workspace, in the workspace data directory.
+fn open_index(config: &Config, data_dir: Option<&Path>) -> Result<(Index, Option<SharedIndex>)> {
+    let shared = match (data_dir, config.index.shared) {
+        (Some(dir), true) => SharedIndex::open(dir.join("shared"))
+            .inspect_err(|err| log::error!("failed to open the shared index: {err}"))
+            .ok(),
+        _ => None,
+    };
+    // Only the writer may open the on-disk storage
+    let writer = shared.as_ref().is_none_or(SharedIndex::is_writer);
+    Ok((open_storage(config, data_dir, writer)?, shared))
+}
+
+/// The index `config` asks for, or one in memory for the readers of a shared index.
+fn open_storage(config: &Config, data_dir: Option<&Path>, writer: bool) -> Result<Index> {
+    let context_symbols = match config.completion.uses_symbol_context() {
+        true => config.completion.context_symbols.clone(),
+        false => Vec::new(),
+    };
+    let index = match writer {
+        true => Index::open(&config.index, data_dir)?,
+        false => Index::open(
+            &IndexConfig {
+                storage: StorageKind::Memory,
+                ..config.index.clone()
+            },
+            data_dir,
+        )?,
+    };
+    Ok(index
+        .with_context_symbols(context_symbols)
+        .with_collator(Collator::new(config.completion.locale.as_deref())))
+}
+
+/// The first argument of a `workspace/executeCommand`, if given.
+fn argument<T: DeserializeOwned>(arguments: Vec<serde_json::Value>) -> Result<Option<T>> {
+    arguments
+        .into_iter()
+        .next()
+        .map(|argument| {
+            serde_json::from_value(argument)
+                .map_err(|err| ServerError::Protocol(format!("invalid command arguments: {err}")))
+        })
+        .transpose()
+}
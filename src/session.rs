@@ -0,0 +1,103 @@
+//! What happened during a session, summarized on shutdown to evaluate ranking
+//! experiments.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// Upper bounds, in milliseconds, of the latency buckets but the last one.
+const BUCKETS: [u64; 8] = [1, 5, 10, 25, 50, 100, 250, 1000];
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub documents_opened: u64,
+    pub completions_served: u64,
+    /// Completions the client reported inserting, by the source they were shown as.
+    pub completions_accepted: BTreeMap<String, u64>,
+    pub diagnostics_published: u64,
+    /// By backend, like `index` or a plugin's name.
+    pub latencies: BTreeMap<String, Histogram>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Histogram {
+    pub count: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+    pub buckets: Vec<Bucket>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bucket {
+    /// `None` for the latencies beyond every bound.
+    pub up_to_ms: Option<u64>,
+    pub count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            total_ms: 0.0,
+            max_ms: 0.0,
+            buckets: BUCKETS
+                .into_iter()
+                .map(Some)
+                .chain([None])
+                .map(|up_to_ms| Bucket { up_to_ms, count: 0 })
+                .collect(),
+        }
+    }
+}
+
+impl Histogram {
+    fn record(&mut self, latency: Duration) {
+        let ms = latency.as_secs_f64() * 1000.0;
+        self.count += 1;
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+        let bucket = self
+            .buckets
+            .iter_mut()
+            .find(|bucket| bucket.up_to_ms.is_none_or(|up_to| ms <= up_to as f64))
+            .expect("the last bucket is unbounded");
+        bucket.count += 1;
+    }
+}
+
+impl Session {
+    pub fn record_latency(&mut self, backend: &str, latency: Duration) {
+        self.latencies
+            .entry(backend.to_string())
+            .or_default()
+            .record(latency);
+    }
+
+    /// A few lines for the log.
+    pub fn summary(&self) -> String {
+        let accepted: u64 = self.completions_accepted.values().sum();
+        let mut summary = format!(
+            "{} documents opened, {} completions served and {accepted} accepted, {} \
+             diagnostics published",
+            self.documents_opened, self.completions_served, self.diagnostics_published,
+        );
+        for (backend, histogram) in &self.latencies {
+            let mean = histogram.total_ms / histogram.count.max(1) as f64;
+            let _ = write!(
+                summary,
+                "\n{backend}: {} calls, {mean:.1}ms mean, {:.1}ms max",
+                histogram.count, histogram.max_ms
+            );
+        }
+        summary
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
@@ -0,0 +1,82 @@
+//! LSP snippet syntax, for clients without snippet support.
+
+/// The text `snippet` inserts once its tab stops are left as is: placeholders keep
+/// their default, choices their first option, and variables are dropped.
+pub fn plain_text(snippet: &str) -> String {
+    let chars: Vec<char> = snippet.chars().collect();
+    let mut text = String::new();
+    render(&chars, &mut 0, &mut text, false);
+    text
+}
+
+/// Renders until the end, or until the `}` closing a placeholder when `nested`.
+fn render(chars: &[char], i: &mut usize, text: &mut String, nested: bool) {
+    while let Some(&c) = chars.get(*i) {
+        *i += 1;
+        match c {
+            '\\' if matches!(chars.get(*i), Some('$' | '}' | '\\')) => {
+                text.push(chars[*i]);
+                *i += 1;
+            }
+            '}' if nested => return,
+            '$' => match chars.get(*i) {
+                Some('{') => {
+                    *i += 1;
+                    placeholder(chars, i, text);
+                }
+                Some(&c) if is_name(c) => skip_name(chars, i),
+                _ => text.push('$'),
+            },
+            c => text.push(c),
+        }
+    }
+}
+
+/// Renders what follows a `${`.
+fn placeholder(chars: &[char], i: &mut usize, text: &mut String) {
+    // The tab stop number or variable name
+    skip_name(chars, i);
+    match chars.get(*i) {
+        Some(':') => {
+            *i += 1;
+            render(chars, i, text, true);
+        }
+        Some('|') => {
+            *i += 1;
+            let mut first = true;
+            while let Some(&c) = chars.get(*i) {
+                *i += 1;
+                match c {
+                    '\\' if matches!(chars.get(*i), Some(',' | '|' | '\\')) => {
+                        if first {
+                            text.push(chars[*i]);
+                        }
+                        *i += 1;
+                    }
+                    ',' => first = false,
+                    '|' => {
+                        if chars.get(*i) == Some(&'}') {
+                            *i += 1;
+                        }
+                        return;
+                    }
+                    c if first => text.push(c),
+                    _ => {}
+                }
+            }
+        }
+        Some('}') => *i += 1,
+        // Transforms and the like, whose text is best kept
+        _ => render(chars, i, text, true),
+    }
+}
+
+fn is_name(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn skip_name(chars: &[char], i: &mut usize) {
+    while chars.get(*i).is_some_and(|&c| is_name(c)) {
+        *i += 1;
+    }
+}
@@ -0,0 +1,246 @@
+//! A soak run typing into a document of a server on an in-memory connection for as
+//! long as asked, keystroke by keystroke with completions in between, run by
+//! `test-lsp soak` to catch the memory and latency that creep up over hours of editing.
+
+use crate::harness::{completion_params, Client};
+use lsp_server::Response;
+use lsp_types::request::{Completion, Request as _};
+use lsp_types::{Position, Range, Url};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The lines the document is cut back to when it grows past twice as many.
+const KEPT_LINES: u32 = 1000;
+
+/// The distinct words typed, for the index to see them again.
+const VOCABULARY: usize = 500;
+
+/// How often the progress is logged.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct Options {
+    pub duration: Duration,
+    pub edits_per_second: u32,
+    /// A completion is asked for after this many edits.
+    pub completion_every: u32,
+    /// The resident memory of the process the run fails above, in bytes.
+    pub max_memory: Option<u64>,
+    /// The 99th percentile of the completion latencies the run fails above.
+    pub max_latency: Option<Duration>,
+    pub seed: u64,
+}
+
+pub struct Report {
+    pub edits: u64,
+    pub completions: u64,
+    /// The completion latencies, sorted.
+    pub latencies: Vec<Duration>,
+    /// The peak resident memory of the process, `None` where it can't be read.
+    pub peak_memory: Option<u64>,
+    pub failure: Option<String>,
+}
+
+impl Report {
+    /// The latency `percent` of the completions were at most as slow as.
+    pub fn percentile(&self, percent: usize) -> Option<Duration> {
+        let last = self.latencies.len().checked_sub(1)?;
+        self.latencies.get(last * percent / 100).copied()
+    }
+}
+
+/// Runs for `options.duration` against a server of its own.
+pub fn run(options: &Options) -> Report {
+    let mut report = Report {
+        edits: 0,
+        completions: 0,
+        latencies: Vec::new(),
+        peak_memory: resident_memory(),
+        failure: None,
+    };
+    let mut client = Client::start();
+    report.failure = soak(&mut client, options, &mut report).err();
+    client.stop();
+    report.latencies.sort_unstable();
+    if report.failure.is_none() {
+        report.failure = exceeded(options, &report);
+    }
+    report
+}
+
+fn exceeded(options: &Options, report: &Report) -> Option<String> {
+    if let (Some(max), Some(peak)) = (options.max_memory, report.peak_memory) {
+        if peak > max {
+            return Some(format!("peak memory of {peak} bytes over {max}"));
+        }
+    }
+    if let (Some(max), Some(p99)) = (options.max_latency, report.percentile(99)) {
+        if p99 > max {
+            return Some(format!("p99 completion latency of {p99:?} over {max:?}"));
+        }
+    }
+    None
+}
+
+fn soak(client: &mut Client, options: &Options, report: &mut Report) -> Result<(), String> {
+    expect_result(&client.initialize()?)?;
+    let uri = Url::parse("untitled:soak").expect("a valid uri");
+    client.open(&uri, "")?;
+    let mut rng = XorShift::new(options.seed);
+    let vocabulary: Vec<String> = (0..VOCABULARY).map(|_| rng.word()).collect();
+    let mut document = Document {
+        uri,
+        version: 1,
+        end: Position::new(0, 0),
+    };
+    let interval = Duration::from_secs(1) / options.edits_per_second.max(1);
+    let completion_every = u64::from(options.completion_every.max(1));
+    let start = Instant::now();
+    let mut next = start;
+    let mut progress = start + PROGRESS_INTERVAL;
+    // The rest of the word being typed
+    let mut typing = String::new();
+    while start.elapsed() < options.duration {
+        if typing.is_empty() {
+            typing = vocabulary[rng.below(VOCABULARY as u64) as usize].clone();
+            typing.push(if rng.below(8) == 0 { '\n' } else { ' ' });
+        }
+        match rng.below(40) {
+            // Going back over a typo
+            0 => document.backspace(client)?,
+            // Pasting earlier on
+            1 if document.end.line > 0 => {
+                let line = rng.below(u64::from(document.end.line)) as u32;
+                let word = &vocabulary[rng.below(VOCABULARY as u64) as usize];
+                document.insert_at(client, line, &format!("{word} "))?;
+            }
+            _ => {
+                let c = typing.remove(0);
+                document.type_char(client, c)?;
+            }
+        }
+        if document.end.line >= 2 * KEPT_LINES {
+            document.cut(client, document.end.line - KEPT_LINES)?;
+        }
+        report.edits += 1;
+        if report.edits.is_multiple_of(completion_every) {
+            let asked = Instant::now();
+            let params = completion_params(&document.uri, document.end);
+            expect_result(&client.request(Completion::METHOD, params)?)?;
+            report.latencies.push(asked.elapsed());
+            report.completions += 1;
+        }
+        if let Some(memory) = resident_memory() {
+            report.peak_memory = report.peak_memory.max(Some(memory));
+        }
+        if Instant::now() >= progress {
+            progress += PROGRESS_INTERVAL;
+            log::info!(
+                "{} edits, {} completions, {} bytes resident",
+                report.edits,
+                report.completions,
+                report.peak_memory.unwrap_or_default()
+            );
+        }
+        // Keeping the pace, typing ahead like an editor would when the server fell behind
+        next += interval;
+        thread::sleep(next.saturating_duration_since(Instant::now()));
+    }
+    Ok(())
+}
+
+fn expect_result(response: &Response) -> Result<(), String> {
+    match &response.error {
+        Some(error) => Err(format!("got error {}: {}", error.code, error.message)),
+        None => Ok(()),
+    }
+}
+
+/// The document typed into, of ASCII text only, with the cursor at its end.
+struct Document {
+    uri: Url,
+    version: i32,
+    end: Position,
+}
+
+impl Document {
+    fn change(&mut self, client: &mut Client, range: Range, text: &str) -> Result<(), String> {
+        self.version += 1;
+        client.change(&self.uri, self.version, range, text)
+    }
+
+    fn type_char(&mut self, client: &mut Client, c: char) -> Result<(), String> {
+        let at = self.end;
+        self.change(client, Range::new(at, at), c.encode_utf8(&mut [0; 4]))?;
+        self.end = match c {
+            '\n' => Position::new(at.line + 1, 0),
+            _ => Position::new(at.line, at.character + 1),
+        };
+        Ok(())
+    }
+
+    fn backspace(&mut self, client: &mut Client) -> Result<(), String> {
+        if self.end.character == 0 {
+            return Ok(());
+        }
+        let from = Position::new(self.end.line, self.end.character - 1);
+        self.change(client, Range::new(from, self.end), "")?;
+        self.end = from;
+        Ok(())
+    }
+
+    /// Inserts `text` at the start of a line before the cursor's.
+    fn insert_at(&mut self, client: &mut Client, line: u32, text: &str) -> Result<(), String> {
+        let at = Position::new(line, 0);
+        self.change(client, Range::new(at, at), text)
+    }
+
+    /// Removes the first `lines`, for the document not to grow for as long as it runs.
+    fn cut(&mut self, client: &mut Client, lines: u32) -> Result<(), String> {
+        let range = Range::new(Position::new(0, 0), Position::new(lines, 0));
+        self.change(client, range, "")?;
+        self.end.line -= lines;
+        Ok(())
+    }
+}
+
+/// A generator of the same edits for the same seed.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // Zero would only ever give zero
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn word(&mut self) -> String {
+        let len = 3 + self.below(8);
+        (0..len)
+            .map(|_| (b'a' + self.below(26) as u8) as char)
+            .collect()
+    }
+}
+
+/// The resident memory of the process, the server's included, on Linux.
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
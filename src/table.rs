@@ -0,0 +1,104 @@
+//! Delimiter-separated values: the rows and columns of CSV and TSV files.
+
+use std::ops::Range;
+
+/// The delimiter of the languages holding tables.
+pub fn delimiter(language: &str) -> Option<char> {
+    match language {
+        "csv" => Some(','),
+        "tsv" => Some('\t'),
+        _ => None,
+    }
+}
+
+/// The cells of a table, as byte ranges with their quotes.
+#[derive(Debug)]
+pub struct Table {
+    pub rows: Vec<Vec<Range<usize>>>,
+}
+
+impl Table {
+    /// Quoted cells may hold delimiters, newlines and `""` for a quote.
+    pub fn parse(text: &str, delimiter: char) -> Self {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut cell_start = 0;
+        let mut quoted = false;
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek().is_some_and(|&(_, next)| next == '"') => {
+                    chars.next();
+                }
+                '"' if quoted || i == cell_start => quoted = !quoted,
+                _ if quoted => {}
+                '\n' => {
+                    let end = if text[..i].ends_with('\r') { i - 1 } else { i };
+                    row.push(cell_start..end);
+                    rows.push(std::mem::take(&mut row));
+                    cell_start = i + 1;
+                }
+                c if c == delimiter => {
+                    row.push(cell_start..i);
+                    cell_start = i + c.len_utf8();
+                }
+                _ => {}
+            }
+        }
+        if cell_start < text.len() || !row.is_empty() {
+            row.push(cell_start..text.len());
+            rows.push(row);
+        }
+        Self { rows }
+    }
+
+    /// The row and column of the cell holding `offset`.
+    pub fn cell_at(&self, offset: usize) -> Option<(usize, usize)> {
+        self.rows.iter().enumerate().find_map(|(row, cells)| {
+            let column = cells
+                .iter()
+                .position(|cell| cell.start <= offset && offset <= cell.end)?;
+            Some((row, column))
+        })
+    }
+
+    /// The header cells, which name the columns.
+    pub fn header(&self) -> &[Range<usize>] {
+        self.rows.first().map_or(&[], Vec::as_slice)
+    }
+
+    /// Edits padding every cell but the last of each row to the width of its column.
+    pub fn align(&self, text: &str) -> Vec<(usize, String)> {
+        let width = |cell: &Range<usize>| text[cell.clone()].chars().count();
+        let mut widths: Vec<usize> = Vec::new();
+        for row in &self.rows {
+            // The last cell isn't padded, so it doesn't widen its column
+            for (column, cell) in row.iter().enumerate().rev().skip(1) {
+                if widths.len() <= column {
+                    widths.resize(column + 1, 0);
+                }
+                widths[column] = widths[column].max(width(cell));
+            }
+        }
+        self.rows
+            .iter()
+            .flat_map(|row| row.iter().enumerate().rev().skip(1))
+            .filter_map(|(column, cell)| {
+                let padding = widths[column] - width(cell);
+                (padding > 0).then(|| (cell.end, " ".repeat(padding)))
+            })
+            .collect()
+    }
+}
+
+/// The text of a cell, without its quotes.
+pub fn unquote(cell: &str) -> String {
+    let trimmed = cell.trim();
+    match trimmed
+        .strip_prefix('"')
+        .and_then(|cell| cell.strip_suffix('"'))
+    {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => trimmed.to_string(),
+    }
+}
@@ -0,0 +1,150 @@
+//! The stdio transport, like `lsp_server::Connection::stdio` but refusing the messages
+//! over a size limit before they're read into memory.
+
+use crossbeam_channel::bounded;
+use lsp_server::{Connection, Message, Notification, RequestId};
+use lsp_types::notification::{self, Exit, Notification as _};
+use lsp_types::Url;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::io::{self, BufRead, Read};
+use std::thread;
+
+/// How much of a message too large to read is looked at for its id and method.
+const HEAD_LEN: usize = 4 << 10;
+/// How much of a payload the logs show.
+const LOGGED_LEN: usize = 1000;
+
+/// Stands in for a message over the size limit, sent by the transport itself.
+pub enum MessageTooLarge {}
+
+impl notification::Notification for MessageTooLarge {
+    type Params = TooLarge;
+    const METHOD: &'static str = "$/testLsp/messageTooLarge";
+}
+
+/// What the first bytes of a message too large to read tell of it, going by the keys
+/// clients send before the params.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TooLarge {
+    pub size: usize,
+    pub id: Option<RequestId>,
+    pub method: Option<String>,
+    /// Of the document, for the notifications about one.
+    pub uri: Option<Url>,
+}
+
+pub struct IoThreads {
+    reader: thread::JoinHandle<io::Result<()>>,
+    writer: thread::JoinHandle<io::Result<()>>,
+}
+
+impl IoThreads {
+    pub fn join(self) -> io::Result<()> {
+        self.reader.join().expect("the reader thread panicked")?;
+        self.writer.join().expect("the writer thread panicked")
+    }
+}
+
+/// A connection over stdin and stdout, taking messages of up to `max_size` bytes.
+pub fn stdio(max_size: usize) -> (Connection, IoThreads) {
+    let (writer_sender, writer_receiver) = bounded::<Message>(0);
+    let writer = thread::spawn(move || {
+        let mut stdout = io::stdout().lock();
+        writer_receiver
+            .into_iter()
+            .try_for_each(|message| message.write(&mut stdout))
+    });
+    let (reader_sender, reader_receiver) = bounded::<Message>(0);
+    let reader = thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        while let Some(message) = read(&mut stdin, max_size)? {
+            let is_exit =
+                matches!(&message, Message::Notification(not) if not.method == Exit::METHOD);
+            if reader_sender.send(message).is_err() || is_exit {
+                break;
+            }
+        }
+        Ok(())
+    });
+    let connection = Connection {
+        sender: writer_sender,
+        receiver: reader_receiver,
+    };
+    (connection, IoThreads { reader, writer })
+}
+
+/// `payload` as logged, cut short when long.
+pub fn abbreviate(payload: &impl Debug) -> String {
+    let mut text = format!("{payload:?}");
+    if let Some((end, _)) = text.char_indices().nth(LOGGED_LEN) {
+        let more = text.len() - end;
+        text.truncate(end);
+        text.push_str(&format!("… ({more} more bytes)"));
+    }
+    text
+}
+
+fn read(input: &mut impl BufRead, max_size: usize) -> io::Result<Option<Message>> {
+    let mut size = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let Some(header) = line.strip_suffix("\r\n") else {
+            return Err(invalid_data(format!("malformed header: {line:?}")));
+        };
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(": ") else {
+            return Err(invalid_data(format!("malformed header: {header:?}")));
+        };
+        if name.eq_ignore_ascii_case("Content-Length") {
+            size = Some(value.parse::<usize>().map_err(invalid_data)?);
+        }
+    }
+    let size = size.ok_or_else(|| invalid_data("no Content-Length"))?;
+    if size > max_size {
+        let mut head = Vec::new();
+        input
+            .take(HEAD_LEN.min(size) as u64)
+            .read_to_end(&mut head)?;
+        io::copy(&mut input.take((size - head.len()) as u64), &mut io::sink())?;
+        let too_large = too_large(&String::from_utf8_lossy(&head), size);
+        return Ok(Some(Message::Notification(Notification::new(
+            MessageTooLarge::METHOD.to_string(),
+            too_large,
+        ))));
+    }
+    let mut body = vec![0; size];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn too_large(head: &str, size: usize) -> TooLarge {
+    // Only the keys before the params are the message's own
+    let own = &head[..head.find("\"params\"").unwrap_or(head.len())];
+    TooLarge {
+        size,
+        id: value_of(own, "id").and_then(|id| serde_json::from_value(id).ok()),
+        method: value_of(own, "method").and_then(|method| serde_json::from_value(method).ok()),
+        uri: value_of(head, "uri").and_then(|uri| serde_json::from_value(uri).ok()),
+    }
+}
+
+/// The JSON value of the first `key` in `text`.
+fn value_of(text: &str, key: &str) -> Option<serde_json::Value> {
+    let (_, after) = text.split_once(&format!("\"{key}\""))?;
+    let value = after.trim_start().strip_prefix(':')?;
+    serde_json::Deserializer::from_str(value)
+        .into_iter()
+        .next()?
+        .ok()
+}
+
+fn invalid_data(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
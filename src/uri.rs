@@ -0,0 +1,63 @@
+//! Conversions between file uris and paths that agree on one spelling per file.
+//!
+//! Clients and the filesystem disagree on drive letter casing, percent-encoding and
+//! symlinks, so documents and index entries are keyed by [`normalize`]d uris.
+
+use lsp_types::Url;
+use std::path::{Path, PathBuf};
+
+/// The key of the file `uri` names. Non-file uris, like `untitled:`, are kept as is.
+pub fn normalize(uri: &Url) -> Url {
+    to_path(uri)
+        .and_then(|path| from_path(&path))
+        .unwrap_or_else(|| uri.clone())
+}
+
+/// The canonical path of a `file:` uri, with percent-encoding decoded and symlinks
+/// resolved.
+pub fn to_path(uri: &Url) -> Option<PathBuf> {
+    if uri.scheme() != "file" {
+        return None;
+    }
+    let path = uri.to_file_path().ok()?;
+    Some(canonical(&path))
+}
+
+/// The normalized uri of `path`.
+pub fn from_path(path: &Path) -> Option<Url> {
+    Url::from_file_path(lowercase_drive(&canonical(path))).ok()
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    let Ok(canonical) = path.canonicalize() else {
+        // Unsaved files still get the symlinks of their directory resolved
+        return match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => canonical(parent).join(name),
+            _ => path.to_path_buf(),
+        };
+    };
+    // Windows canonicalizes into verbatim paths, `\\?\C:\` or `\\?\UNC\server\share`
+    match canonical.to_str() {
+        Some(verbatim) if verbatim.starts_with(r"\\?\UNC\") => {
+            PathBuf::from(format!(r"\\{}", &verbatim[r"\\?\UNC\".len()..]))
+        }
+        Some(verbatim) if verbatim.starts_with(r"\\?\") => {
+            PathBuf::from(&verbatim[r"\\?\".len()..])
+        }
+        _ => canonical,
+    }
+}
+
+/// `C:\` and `c:\` are the same drive, and clients differ in which they send.
+fn lowercase_drive(path: &Path) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(drive), Some(':')) if drive.is_ascii_uppercase() => {
+            PathBuf::from(format!("{}{}", drive.to_ascii_lowercase(), &text[1..]))
+        }
+        _ => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+/// How often the watchdog checks on the client.
+pub const INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long without messages means the client is gone, when its process can't be
+/// watched.
+const SILENCE: Duration = Duration::from_secs(60 * 60);
+
+/// Notices a client that went away without sending `exit`, so the server doesn't
+/// linger forever.
+pub struct Watchdog {
+    /// `InitializeParams.process_id`
+    client: Option<u32>,
+    /// Our parent when we started, which may not be the client itself.
+    parent: Option<u32>,
+    last_message: Instant,
+}
+
+impl Watchdog {
+    pub fn new(client: Option<u32>) -> Self {
+        #[cfg(unix)]
+        let parent = Some(std::os::unix::process::parent_id());
+        #[cfg(not(unix))]
+        let parent = None;
+        Self {
+            client,
+            parent,
+            last_message: Instant::now(),
+        }
+    }
+
+    pub fn heard_from_client(&mut self) {
+        self.last_message = Instant::now();
+    }
+
+    /// Why the client seems to be gone, if it does.
+    pub fn check(&self) -> Option<String> {
+        if let Some(client) = self.client {
+            if let Some(alive) = is_alive(client) {
+                return (!alive).then(|| format!("client process {client} exited"));
+            }
+        }
+        #[cfg(unix)]
+        if let Some(parent) = self.parent {
+            // Orphans are adopted by init or a subreaper
+            return (std::os::unix::process::parent_id() != parent)
+                .then(|| format!("parent process {parent} exited"));
+        }
+        let _ = self.parent;
+        (self.last_message.elapsed() >= SILENCE)
+            .then(|| format!("no message for {}s", SILENCE.as_secs()))
+    }
+}
+
+/// `None` when there is no way to tell on this platform.
+fn is_alive(pid: u32) -> Option<bool> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let Ok(stat) = std::fs::read_to_string(format!("/proc/{pid}/stat")) else {
+        return Some(false);
+    };
+    // `pid (comm) state ...`, where comm may hold spaces and parens; zombies have exited
+    let state = stat
+        .rsplit_once(')')
+        .and_then(|(_, rest)| rest.trim_start().chars().next());
+    Some(state != Some('Z'))
+}
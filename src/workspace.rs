@@ -0,0 +1,140 @@
+use crate::config::IndexConfig;
+use crate::uri;
+use itertools::Itertools;
+use lsp_types::Url;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+/// How much of a file is sniffed for binary content.
+const SNIFF_LEN: usize = 8 << 10;
+
+/// Reads a text file as editors show it, without a UTF-8 byte order mark.
+pub fn read_text(path: &Path) -> std::io::Result<String> {
+    std::fs::read_to_string(path).map(without_bom)
+}
+
+/// Text files under `root`, skipping hidden and git-ignored paths, the extensions
+/// `config` denies and, unless allowed, binaries and minified blobs.
+#[cfg(feature = "workspace")]
+pub fn text_files(root: &Path, config: &IndexConfig) -> impl Iterator<Item = (Url, String)> {
+    let config = config.clone();
+    paths(root, &config).filter_map(move |path| read(&path, &config))
+}
+
+/// Maps `f` over the [`text_files`] under `root`, on as many threads as the indexing
+/// profile of `config` allows.
+pub fn scan<T: Send>(
+    root: &Path,
+    config: &IndexConfig,
+    f: impl Fn(Url, String) -> T + Sync,
+) -> Vec<T> {
+    scan_files(paths(root, config), config, f)
+}
+
+/// The paths [`scan`] would read, without reading them.
+pub fn files(root: &Path, config: &IndexConfig) -> Vec<PathBuf> {
+    paths(root, config).collect()
+}
+
+/// Like [`scan`], over the text files among `paths`.
+pub fn scan_files<T: Send>(
+    paths: impl Iterator<Item = PathBuf> + Send,
+    config: &IndexConfig,
+    f: impl Fn(Url, String) -> T + Sync,
+) -> Vec<T> {
+    let paths = Mutex::new(paths);
+    thread::scope(|scope| {
+        let workers = (0..config.profile.parallelism())
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    // Only walking is serialized, reading and `f` run in parallel
+                    while let Some(path) = {
+                        let next = paths.lock().expect("a scan thread panicked").next();
+                        next
+                    } {
+                        if let Some((uri, text)) = read(&path, config) {
+                            done.push(f(uri, text));
+                        }
+                    }
+                    done
+                })
+            })
+            .collect_vec();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("a scan thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(feature = "workspace")]
+fn paths(root: &Path, config: &IndexConfig) -> impl Iterator<Item = PathBuf> + Send {
+    let deny = config.deny_extensions.clone();
+    ignore::WalkBuilder::new(root)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|ty| ty.is_file()))
+        .filter(move |entry| !has_suffix(entry.path(), &deny))
+        .map(ignore::DirEntry::into_path)
+}
+
+/// Nothing, the workspace not being walked without the `workspace` feature.
+#[cfg(not(feature = "workspace"))]
+fn paths(_root: &Path, _config: &IndexConfig) -> impl Iterator<Item = PathBuf> + Send {
+    std::iter::empty()
+}
+
+fn read(path: &Path, config: &IndexConfig) -> Option<(Url, String)> {
+    let allowed = has_suffix(path, &config.allow_extensions);
+    let bytes = std::fs::read(path).ok()?;
+    if !allowed && is_binary(&bytes) {
+        return None;
+    }
+    let text = without_bom(String::from_utf8_lossy(&bytes).into_owned());
+    if !allowed && is_minified(&text) {
+        return None;
+    }
+    let uri = uri::from_path(path)?;
+    Some((uri, text))
+}
+
+fn without_bom(mut text: String) -> String {
+    if text.starts_with('\u{FEFF}') {
+        text.drain(..'\u{FEFF}'.len_utf8());
+    }
+    text
+}
+
+/// Whether the file name of `path` ends with `.` and one of `suffixes`.
+fn has_suffix(path: &Path, suffixes: &[String]) -> bool {
+    let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+        return false;
+    };
+    suffixes.iter().any(|suffix| {
+        name.strip_suffix(suffix.as_str())
+            .is_some_and(|stem| stem.ends_with('.'))
+    })
+}
+
+/// Whether the start of a file has NUL bytes or is mostly invalid UTF-8.
+fn is_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if sample.contains(&0) {
+        return true;
+    }
+    let text = String::from_utf8_lossy(sample);
+    let invalid = text
+        .chars()
+        .filter(|&c| c == char::REPLACEMENT_CHARACTER)
+        .count();
+    invalid * 10 > text.chars().count()
+}
+
+/// Whether `text` looks generated rather than written, going by its line lengths.
+fn is_minified(text: &str) -> bool {
+    let lines = text.lines().count().max(1);
+    let longest = text.lines().map(str::len).max().unwrap_or_default();
+    longest > 10_000 || (text.len() / lines > 500 && longest > 1_000)
+}
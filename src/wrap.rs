@@ -0,0 +1,117 @@
+//! Hard wrapping of prose paragraphs, keeping their indentation and list markers.
+
+use std::ops::Range;
+
+/// Columns to wrap at when neither the user nor the language say.
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// The paragraph around `offset`: the lines between blank lines, list items and
+/// headings starting paragraphs of their own. `None` on blank lines and headings, which
+/// aren't wrapped.
+pub fn paragraph(text: &str, offset: usize) -> Option<Range<usize>> {
+    let lines = lines(text);
+    let current = lines
+        .iter()
+        .position(|line| line.start <= offset && offset <= line.end)?;
+    let line = |i: usize| text[lines[i].clone()].trim_end_matches('\r');
+    let blank = |i: usize| line(i).trim().is_empty();
+    if blank(current) || is_heading(line(current)) {
+        return None;
+    }
+    let starts = |i: usize| list_marker(line(i).trim_start()).is_some() || is_heading(line(i));
+    let mut first = current;
+    while first > 0 && !starts(first) && !blank(first - 1) && !is_heading(line(first - 1)) {
+        first -= 1;
+    }
+    let mut last = current;
+    while last + 1 < lines.len() && !blank(last + 1) && !starts(last + 1) {
+        last += 1;
+    }
+    Some(lines[first].start..lines[last].end)
+}
+
+/// The whole paragraphs with lines in `selection`, which may be empty for a cursor.
+pub fn paragraphs(text: &str, selection: Range<usize>) -> Vec<Range<usize>> {
+    let mut paragraphs: Vec<Range<usize>> = Vec::new();
+    let selected = lines(text).into_iter().filter(|line| {
+        line.end >= selection.start && (line.start < selection.end || line.start == selection.start)
+    });
+    for line in selected {
+        if paragraphs.last().is_some_and(|last| last.end >= line.end) {
+            continue;
+        }
+        paragraphs.extend(paragraph(text, line.start));
+    }
+    paragraphs
+}
+
+/// `paragraph` refilled to lines of up to `width` characters, a word longer than that
+/// getting a line of its own. Continuation lines are indented past the list marker.
+pub fn wrap(paragraph: &str, width: usize) -> String {
+    let newline = match paragraph.contains("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+    let trimmed = paragraph.trim_start();
+    let indent = &paragraph[..paragraph.len() - trimmed.len()];
+    // Selections may start with blank lines
+    let indent = &indent[indent.rfind('\n').map_or(0, |i| i + 1)..];
+    let marker = list_marker(trimmed).unwrap_or("");
+    let continuation = format!("{indent}{}", " ".repeat(marker.chars().count()));
+
+    let mut wrapped = String::new();
+    let mut line = format!("{indent}{marker}");
+    let mut empty = true;
+    for word in trimmed[marker.len()..].split_whitespace() {
+        let len = line.chars().count();
+        if !empty && len + 1 + word.chars().count() > width {
+            wrapped.push_str(&line);
+            wrapped.push_str(newline);
+            line.clone_from(&continuation);
+            empty = true;
+        }
+        if !empty {
+            line.push(' ');
+        }
+        line.push_str(word);
+        empty = false;
+    }
+    wrapped.push_str(&line);
+    wrapped
+}
+
+/// `paragraph` on a single line.
+pub fn join(paragraph: &str) -> String {
+    wrap(paragraph, usize::MAX)
+}
+
+/// The byte ranges of the lines of `text`, without their `\n`.
+fn lines(text: &str) -> Vec<Range<usize>> {
+    let mut start = 0;
+    let mut lines = Vec::new();
+    for (i, _) in text.match_indices('\n') {
+        lines.push(start..i);
+        start = i + 1;
+    }
+    lines.push(start..text.len());
+    lines
+}
+
+/// `- `, `* `, `+ `, `1. ` or `1) ` starting `line`, with the spaces after it.
+fn list_marker(line: &str) -> Option<&str> {
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let len = match line.as_bytes().get(digits)? {
+        b'-' | b'*' | b'+' if digits == 0 => 1,
+        b'.' | b')' if digits > 0 => digits + 1,
+        _ => return None,
+    };
+    let rest = &line[len..];
+    let spaces = rest.len() - rest.trim_start_matches(' ').len();
+    (spaces > 0).then(|| &line[..len + spaces])
+}
+
+fn is_heading(line: &str) -> bool {
+    let line = line.trim_start();
+    let hashes = line.len() - line.trim_start_matches('#').len();
+    (1..=6).contains(&hashes) && line[hashes..].starts_with(' ')
+}